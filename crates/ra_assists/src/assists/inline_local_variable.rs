@@ -46,7 +46,7 @@ pub(crate) fn inline_local_varialbe(ctx: AssistCtx<impl HirDatabase>) -> Option<
         let_stmt.syntax().text_range()
     };
     let analyzer = ctx.source_analyzer(bind_pat.syntax(), None);
-    let refs = analyzer.find_all_refs(&bind_pat);
+    let refs = analyzer.find_all_refs(ctx.db, &bind_pat);
 
     let mut wrap_in_parens = vec![true; refs.len()];
 
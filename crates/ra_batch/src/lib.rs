@@ -5,11 +5,12 @@ use std::{collections::HashSet, error::Error, path::Path};
 use rustc_hash::FxHashMap;
 
 use crossbeam_channel::{unbounded, Receiver};
-use ra_db::{CrateGraph, FileId, SourceRootId};
+use ra_db::{CrateGraph, ExternSourceId, FileId, SourceRootId};
 use ra_ide::{AnalysisChange, AnalysisHost, FeatureFlags};
-use ra_project_model::{get_rustc_cfg_options, PackageRoot, ProjectWorkspace};
+use ra_proc_macro::ProcMacroClient;
+use ra_project_model::{get_rustc_cfg_options, CargoConfig, PackageRoot, ProjectWorkspace};
 use ra_vfs::{RootEntry, Vfs, VfsChange, VfsTask, Watch};
-use ra_vfs_glob::RustPackageFilterBuilder;
+use ra_vfs_glob::{IgnoreFiles, RustPackageFilterBuilder};
 
 type Result<T> = std::result::Result<T, Box<dyn Error + Send + Sync>>;
 
@@ -22,7 +23,8 @@ fn vfs_root_to_id(r: ra_vfs::VfsRoot) -> SourceRootId {
 
 pub fn load_cargo(root: &Path) -> Result<(AnalysisHost, FxHashMap<SourceRootId, PackageRoot>)> {
     let root = std::env::current_dir()?.join(root);
-    let ws = ProjectWorkspace::discover(root.as_ref())?;
+    let cargo_config = CargoConfig::default();
+    let ws = ProjectWorkspace::discover(root.as_ref(), &cargo_config)?;
     let project_roots = ws.to_roots();
     let (sender, receiver) = unbounded();
     let sender = Box::new(move |t| sender.send(t).unwrap());
@@ -30,12 +32,13 @@ pub fn load_cargo(root: &Path) -> Result<(AnalysisHost, FxHashMap<SourceRootId,
         project_roots
             .iter()
             .map(|pkg_root| {
-                RootEntry::new(
-                    pkg_root.path().clone(),
-                    RustPackageFilterBuilder::default()
-                        .set_member(pkg_root.is_member())
-                        .into_vfs_filter(),
-                )
+                let mut filter =
+                    RustPackageFilterBuilder::default().set_member(pkg_root.is_member());
+                for dir in pkg_root.exclude() {
+                    filter = filter.exclude_dir(dir);
+                }
+                filter = filter.respect_ignore_files(IgnoreFiles::new(pkg_root.path().clone()));
+                RootEntry::new(pkg_root.path().clone(), filter.into_vfs_filter())
             })
             .collect(),
         sender,
@@ -44,18 +47,31 @@ pub fn load_cargo(root: &Path) -> Result<(AnalysisHost, FxHashMap<SourceRootId,
 
     // FIXME: cfg options?
     let default_cfg_options = {
-        let mut opts = get_rustc_cfg_options();
+        let mut opts = get_rustc_cfg_options(cargo_config.target.as_ref().map(String::as_str));
         opts.insert_atom("test".into());
         opts.insert_atom("debug_assertion".into());
         opts
     };
 
-    let (crate_graph, _crate_names) =
-        ws.to_crate_graph(&default_cfg_options, &mut |path: &Path| {
+    let out_dirs = ws.out_dirs();
+    let extern_source_roots = roots
+        .iter()
+        .filter(|&&vfs_root| out_dirs.contains(&vfs.root2path(vfs_root)))
+        .map(|&vfs_root| (vfs.root2path(vfs_root), ExternSourceId(vfs_root.0)))
+        .collect::<FxHashMap<_, _>>();
+
+    // FIXME: allow using a proc macro server here
+    let proc_macro_client = ProcMacroClient::dummy();
+    let (crate_graph, _crate_names) = ws.to_crate_graph(
+        &default_cfg_options,
+        &extern_source_roots,
+        &proc_macro_client,
+        &mut |path: &Path| {
             let vfs_file = vfs.load(path);
             log::debug!("vfs file {:?} -> {:?}", path, vfs_file);
             vfs_file.map(vfs_file_to_id)
-        });
+        },
+    );
     log::debug!("crate graph: {:?}", crate_graph);
 
     let source_roots = roots
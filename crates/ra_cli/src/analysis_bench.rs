@@ -1,4 +1,5 @@
-//! FIXME: write short doc here
+//! Measures the latency of a single IDE request, from scratch and after
+//! various kinds of changes, including a scripted text edit.
 
 use std::{
     path::{Path, PathBuf},
@@ -10,29 +11,46 @@ use ra_db::{
     salsa::{Database, Durability},
     FileId, SourceDatabaseExt,
 };
-use ra_ide::{Analysis, AnalysisChange, AnalysisHost, FilePosition, LineCol};
+use ra_ide::{Analysis, AnalysisChange, AnalysisHost, FilePosition};
+use ra_prof::profile;
+use ra_syntax::TextUnit;
 
-use crate::Result;
+use crate::{line_col_offset, Result};
+
+/// A `PATH:LINE:COLUMN` location, with zero-based line and column. The column
+/// counts characters.
+pub(crate) struct Position {
+    pub(crate) path: PathBuf,
+    pub(crate) line: u32,
+    pub(crate) column: u32,
+}
 
 pub(crate) enum Op {
     Highlight { path: PathBuf },
-    Complete { path: PathBuf, line: u32, column: u32 },
+    Complete(Position),
+    GotoDefinition(Position),
+    Hover(Position),
+    FindAllRefs(Position),
+    Diagnostics { path: PathBuf },
+    InlayHints { path: PathBuf },
+}
+
+/// Text inserted at the given position before re-running the request.
+pub(crate) struct Edit {
+    pub(crate) position: Position,
+    pub(crate) text: String,
 }
 
-pub(crate) fn run(verbose: bool, path: &Path, op: Op) -> Result<()> {
+pub(crate) fn run(verbose: bool, path: &Path, op: Op, edit: Option<Edit>) -> Result<()> {
     let start = Instant::now();
     eprint!("loading: ");
     let (mut host, roots) = ra_batch::load_cargo(path)?;
     let db = host.raw_database();
     eprintln!("{:?}\n", start.elapsed());
 
-    let file_id = {
-        let path = match &op {
-            Op::Highlight { path } => path,
-            Op::Complete { path, .. } => path,
-        };
+    let find_file = |path: &Path| -> Result<FileId> {
         let path = std::env::current_dir()?.join(path).canonicalize()?;
-        roots
+        let file_id = roots
             .iter()
             .find_map(|(source_root_id, project_root)| {
                 if project_root.is_member() {
@@ -46,27 +64,86 @@ pub(crate) fn run(verbose: bool, path: &Path, op: Op) -> Result<()> {
                 }
                 None
             })
-            .ok_or_else(|| format!("Can't find {:?}", path))?
+            .ok_or_else(|| format!("Can't find {:?}", path))?;
+        Ok(file_id)
+    };
+    let file_position = |position: &Position| -> Result<FilePosition> {
+        let file_id = find_file(&position.path)?;
+        let text = host.analysis().file_text(file_id)?;
+        let offset = line_col_offset(&text, position.line, position.column).map_err(|e| {
+            format!("{}:{}:{}: {}", position.path.display(), position.line, position.column, e)
+        })?;
+        Ok(FilePosition { file_id, offset })
+    };
+
+    let edit = match edit {
+        Some(Edit { position, text }) => Some((file_position(&position)?, text)),
+        None => None,
+    };
+    // Requests for a whole file only use the `file_id` of the position.
+    let position = match &op {
+        Op::Highlight { path } | Op::Diagnostics { path } | Op::InlayHints { path } => {
+            FilePosition { file_id: find_file(path)?, offset: 0.into() }
+        }
+        Op::Complete(position)
+        | Op::GotoDefinition(position)
+        | Op::Hover(position)
+        | Op::FindAllRefs(position) => file_position(position)?,
     };
 
     match op {
         Op::Highlight { .. } => {
-            let res = do_work(&mut host, file_id, |analysis| {
-                analysis.diagnostics(file_id).unwrap();
-                analysis.highlight_as_html(file_id, false).unwrap()
+            let res = do_work(&mut host, position, edit, |analysis, position| {
+                analysis.diagnostics(position.file_id).unwrap();
+                analysis.highlight_as_html(position.file_id, false).unwrap()
             });
             if verbose {
                 println!("\n{}", res);
             }
         }
-        Op::Complete { line, column, .. } => {
-            let offset = host
-                .analysis()
-                .file_line_index(file_id)?
-                .offset(LineCol { line, col_utf16: column });
-            let file_postion = FilePosition { file_id, offset };
-
-            let res = do_work(&mut host, file_id, |analysis| analysis.completions(file_postion));
+        Op::Complete(_) => {
+            let res = do_work(&mut host, position, edit, |analysis, position| {
+                analysis.completions(position)
+            });
+            if verbose {
+                println!("\n{:#?}", res);
+            }
+        }
+        Op::GotoDefinition(_) => {
+            let res = do_work(&mut host, position, edit, |analysis, position| {
+                analysis.goto_definition(position)
+            });
+            if verbose {
+                println!("\n{:#?}", res);
+            }
+        }
+        Op::Hover(_) => {
+            let res =
+                do_work(&mut host, position, edit, |analysis, position| analysis.hover(position));
+            if verbose {
+                println!("\n{:#?}", res);
+            }
+        }
+        Op::FindAllRefs(_) => {
+            let res = do_work(&mut host, position, edit, |analysis, position| {
+                analysis.find_all_refs(position, None)
+            });
+            if verbose {
+                println!("\n{:#?}", res);
+            }
+        }
+        Op::Diagnostics { .. } => {
+            let res = do_work(&mut host, position, edit, |analysis, position| {
+                analysis.diagnostics(position.file_id)
+            });
+            if verbose {
+                println!("\n{:#?}", res);
+            }
+        }
+        Op::InlayHints { .. } => {
+            let res = do_work(&mut host, position, edit, |analysis, position| {
+                analysis.inlay_hints(position.file_id, None)
+            });
             if verbose {
                 println!("\n{:#?}", res);
             }
@@ -75,27 +152,44 @@ pub(crate) fn run(verbose: bool, path: &Path, op: Op) -> Result<()> {
     Ok(())
 }
 
-fn do_work<F: Fn(&Analysis) -> T, T>(host: &mut AnalysisHost, file_id: FileId, work: F) -> T {
+/// Runs `work` from scratch (cold) and then again after each kind of change,
+/// printing the timings. If profiling is enabled with `--profile`, each run
+/// also prints its own breakdown.
+///
+/// `edit` is applied last: the request is re-run right after the edit, and
+/// once more without changes, to separate recomputation from cache hits. If
+/// the text is inserted before `position`, the position is moved past it.
+fn do_work<F: Fn(&Analysis, FilePosition) -> T, T>(
+    host: &mut AnalysisHost,
+    position: FilePosition,
+    edit: Option<(FilePosition, String)>,
+    work: F,
+) -> T {
+    let file_id = position.file_id;
     {
+        let _p = profile("from scratch");
         let start = Instant::now();
         eprint!("from scratch:   ");
-        work(&host.analysis());
+        work(&host.analysis(), position);
         eprintln!("{:?}", start.elapsed());
     }
     {
+        let _p = profile("no change");
         let start = Instant::now();
         eprint!("no change:      ");
-        work(&host.analysis());
+        work(&host.analysis(), position);
         eprintln!("{:?}", start.elapsed());
     }
     {
+        let _p = profile("trivial change");
         let start = Instant::now();
         eprint!("trivial change: ");
         host.raw_database_mut().salsa_runtime_mut().synthetic_write(Durability::LOW);
-        work(&host.analysis());
+        work(&host.analysis(), position);
         eprintln!("{:?}", start.elapsed());
     }
     {
+        let _p = profile("comment change");
         let start = Instant::now();
         eprint!("comment change: ");
         {
@@ -105,14 +199,48 @@ fn do_work<F: Fn(&Analysis) -> T, T>(host: &mut AnalysisHost, file_id: FileId, w
             change.change_file(file_id, Arc::new(text));
             host.apply_change(change);
         }
-        work(&host.analysis());
+        work(&host.analysis(), position);
         eprintln!("{:?}", start.elapsed());
     }
-    {
+    let res = {
+        let _p = profile("const change");
         let start = Instant::now();
         eprint!("const change:   ");
         host.raw_database_mut().salsa_runtime_mut().synthetic_write(Durability::HIGH);
-        let res = work(&host.analysis());
+        let res = work(&host.analysis(), position);
+        eprintln!("{:?}", start.elapsed());
+        res
+    };
+    let (edit_position, text) = match edit {
+        Some(it) => it,
+        None => return res,
+    };
+    let position =
+        if edit_position.file_id == position.file_id && edit_position.offset <= position.offset {
+            FilePosition { offset: position.offset + TextUnit::of_str(&text), ..position }
+        } else {
+            position
+        };
+    {
+        let _p = profile("scripted edit");
+        let start = Instant::now();
+        eprint!("scripted edit:  ");
+        {
+            let mut file_text =
+                host.analysis().file_text(edit_position.file_id).unwrap().to_string();
+            file_text.insert_str(edit_position.offset.to_usize(), &text);
+            let mut change = AnalysisChange::new();
+            change.change_file(edit_position.file_id, Arc::new(file_text));
+            host.apply_change(change);
+        }
+        work(&host.analysis(), position);
+        eprintln!("{:?}", start.elapsed());
+    }
+    {
+        let _p = profile("after edit");
+        let start = Instant::now();
+        eprint!("after edit:     ");
+        let res = work(&host.analysis(), position);
         eprintln!("{:?}", start.elapsed());
         res
     }
@@ -1,29 +1,147 @@
-//! FIXME: write short doc here
+//! Collects statistics about type inference over a whole project: how many
+//! expressions have an unknown type, and how many type mismatches are found.
+//!
+//! The statistics can be printed as JSON, and compared against a previous run
+//! with `--baseline`, to track inference quality over time.
 
-use std::{collections::HashSet, fmt::Write, path::Path, time::Instant};
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    fmt::Write,
+    fs,
+    path::{Path, PathBuf},
+    time::Instant,
+};
 
 use hir::{
     db::{DefDatabase, HirDatabase},
     AssocItem, Crate, HasSource, HirDisplay, ModuleDef,
 };
-use hir_def::FunctionId;
+use hir_def::{expr::ExprId, FunctionId};
 use hir_ty::{Ty, TypeWalk};
-use ra_db::SourceDatabaseExt;
+use ra_db::{SourceDatabaseExt, SourceRootId};
+use ra_ide::Analysis;
 use ra_syntax::AstNode;
+use serde_json::{json, Value};
 
 use crate::{progress_report::ProgressReport, Result, Verbosity};
 
+/// How to report the statistics.
+pub struct Output {
+    /// Print the statistics as JSON, including per-crate and per-function
+    /// counts, instead of the human readable summary.
+    pub json: bool,
+    /// List the locations of expressions with (partially) unknown types.
+    pub dump_unknowns: bool,
+    /// JSON output of a previous run, to compare against.
+    pub baseline: Option<PathBuf>,
+}
+
+/// Inference statistics of a function, a crate or the whole project.
+#[derive(Debug, Default, Clone, Copy)]
+struct Counts {
+    exprs: u64,
+    unknown: u64,
+    partially_unknown: u64,
+    mismatches: u64,
+}
+
+impl Counts {
+    fn add(&mut self, other: Counts) {
+        self.exprs += other.exprs;
+        self.unknown += other.unknown;
+        self.partially_unknown += other.partially_unknown;
+        self.mismatches += other.mismatches;
+    }
+
+    fn to_json(self) -> Value {
+        json!({
+            "exprs": self.exprs,
+            "unknown": self.unknown,
+            "partially_unknown": self.partially_unknown,
+            "mismatches": self.mismatches,
+        })
+    }
+
+    fn from_json(value: &Value) -> Option<Counts> {
+        Some(Counts {
+            exprs: value.get("exprs")?.as_u64()?,
+            unknown: value.get("unknown")?.as_u64()?,
+            partially_unknown: value.get("partially_unknown")?.as_u64()?,
+            mismatches: value.get("mismatches")?.as_u64()?,
+        })
+    }
+
+    /// Lists the metrics which got worse compared to `baseline`. Ratios are
+    /// compared, rather than the absolute numbers, so that growing code base
+    /// doesn't count as a regression.
+    fn regressions(self, baseline: Counts) -> Vec<String> {
+        let metrics = [
+            ("unknown", self.unknown, baseline.unknown),
+            ("partially unknown", self.partially_unknown, baseline.partially_unknown),
+            ("mismatches", self.mismatches, baseline.mismatches),
+        ];
+        metrics
+            .iter()
+            .filter(|(_, new, old)| ratio(*new, self.exprs) > ratio(*old, baseline.exprs))
+            .map(|(name, new, old)| {
+                format!(
+                    "{}: {:.2}% ({}/{}), was {:.2}% ({}/{})",
+                    name,
+                    ratio(*new, self.exprs) * 100.0,
+                    new,
+                    self.exprs,
+                    ratio(*old, baseline.exprs) * 100.0,
+                    old,
+                    baseline.exprs,
+                )
+            })
+            .collect()
+    }
+}
+
+fn ratio(n: u64, total: u64) -> f64 {
+    if total == 0 {
+        0.0
+    } else {
+        n as f64 / total as f64
+    }
+}
+
+fn percentage(n: u64, total: u64) -> u64 {
+    if total > 0 {
+        n * 100 / total
+    } else {
+        100
+    }
+}
+
 pub fn run(
     verbosity: Verbosity,
     memory_usage: bool,
     path: &Path,
     only: Option<&str>,
     with_deps: bool,
+    output: Output,
 ) -> Result<()> {
+    // With `--json`, stdout is reserved for the report.
+    let verbosity = if output.json { Verbosity::Quiet } else { verbosity };
+    macro_rules! report {
+        ($($arg:tt)*) => {
+            if !output.json {
+                println!($($arg)*);
+            }
+        };
+    }
+
     let db_load_time = Instant::now();
     let (mut host, roots) = ra_batch::load_cargo(path)?;
     let db = host.raw_database();
-    println!("Database loaded, {} roots, {:?}", roots.len(), db_load_time.elapsed());
+    let analysis = host.analysis();
+    report!("Database loaded, {} roots, {:?}", roots.len(), db_load_time.elapsed());
+    let root_paths = roots
+        .iter()
+        .map(|(source_root_id, project_root)| (*source_root_id, project_root.path().clone()))
+        .collect::<HashMap<_, _>>();
     let analysis_time = Instant::now();
     let mut num_crates = 0;
     let mut visited_modules = HashSet::new();
@@ -50,7 +168,7 @@ pub fn run(
         }
     }
 
-    println!("Crates in this dir: {}", num_crates);
+    report!("Crates in this dir: {}", num_crates);
     let mut num_decls = 0;
     let mut funcs = Vec::new();
     while let Some(module) = visit_queue.pop() {
@@ -74,10 +192,10 @@ pub fn run(
             }
         }
     }
-    println!("Total modules found: {}", visited_modules.len());
-    println!("Total declarations: {}", num_decls);
-    println!("Total functions: {}", funcs.len());
-    println!("Item Collection: {:?}, {}", analysis_time.elapsed(), ra_prof::memory_usage());
+    report!("Total modules found: {}", visited_modules.len());
+    report!("Total declarations: {}", num_decls);
+    report!("Total functions: {}", funcs.len());
+    report!("Item Collection: {:?}, {}", analysis_time.elapsed(), ra_prof::memory_usage());
 
     let inference_time = Instant::now();
     let mut bar = match verbosity {
@@ -85,18 +203,26 @@ pub fn run(
         Verbosity::Quiet => ProgressReport::hidden(),
     };
 
+    let project_root = fs::canonicalize(path)?;
+    let project_root = if project_root.is_file() {
+        project_root.parent().map(Path::to_path_buf).unwrap_or(project_root)
+    } else {
+        project_root
+    };
+    let crate_names = crate_names(db, &root_paths, &project_root);
+    let mut total = Counts::default();
+    let mut per_crate = BTreeMap::new();
+    let mut per_function = Vec::new();
+    let mut unknowns = Vec::new();
+
     bar.tick();
-    let mut num_exprs = 0;
-    let mut num_exprs_unknown = 0;
-    let mut num_exprs_partially_unknown = 0;
-    let mut num_type_mismatches = 0;
     for f in funcs {
         let name = f.name(db);
+        let src = f.source(db);
+        let original_file = src.file_id.original_file(db);
+        let path = db.file_relative_path(original_file);
         let mut msg = format!("processing: {}", name);
         if verbosity.is_verbose() {
-            let src = f.source(db);
-            let original_file = src.file_id.original_file(db);
-            let path = db.file_relative_path(original_file);
             let syntax_range = src.value.syntax().text_range();
             write!(msg, " ({:?} {})", path, syntax_range).unwrap();
         }
@@ -106,14 +232,17 @@ pub fn run(
                 continue;
             }
         }
+        let mut counts = Counts::default();
         let f_id = FunctionId::from(f);
         let body = db.body(f_id.into());
         let inference_result = db.infer(f_id.into());
         for (expr_id, _) in body.exprs.iter() {
             let ty = &inference_result[expr_id];
-            num_exprs += 1;
+            counts.exprs += 1;
+            let mut is_unknown = false;
             if let Ty::Unknown = ty {
-                num_exprs_unknown += 1;
+                counts.unknown += 1;
+                is_unknown = true;
             } else {
                 let mut is_partially_unknown = false;
                 ty.walk(&mut |ty| {
@@ -122,75 +251,296 @@ pub fn run(
                     }
                 });
                 if is_partially_unknown {
-                    num_exprs_partially_unknown += 1;
+                    counts.partially_unknown += 1;
+                    is_unknown = true;
+                }
+            }
+            if is_unknown && output.dump_unknowns {
+                let location =
+                    expr_location(db, &analysis, f_id, expr_id).unwrap_or_else(|| name.to_string());
+                let ty = ty.display(db).to_string();
+                if output.json {
+                    unknowns.push(json!({ "location": location, "type": ty }));
+                } else {
+                    bar.println(format!("{}: unknown type {}", location, ty));
                 }
             }
             if let Some(mismatch) = inference_result.type_mismatch_for_expr(expr_id) {
-                num_type_mismatches += 1;
+                counts.mismatches += 1;
                 if verbosity.is_verbose() {
-                    let (_, sm) = db.body_with_source_map(f_id.into());
-                    let src = sm.expr_syntax(expr_id);
-                    if let Some(src) = src {
-                        // FIXME: it might be nice to have a function (on Analysis?) that goes from Source<T> -> (LineCol, LineCol) directly
-                        let original_file = src.file_id.original_file(db);
-                        let path = db.file_relative_path(original_file);
-                        let line_index = host.analysis().file_line_index(original_file).unwrap();
-                        let text_range = src.value.either(
-                            |it| it.syntax_node_ptr().range(),
-                            |it| it.syntax_node_ptr().range(),
-                        );
-                        let (start, end) = (
-                            line_index.line_col(text_range.start()),
-                            line_index.line_col(text_range.end()),
-                        );
-                        bar.println(format!(
-                            "{} {}:{}-{}:{}: Expected {}, got {}",
-                            path,
-                            start.line + 1,
-                            start.col_utf16,
-                            end.line + 1,
-                            end.col_utf16,
-                            mismatch.expected.display(db),
-                            mismatch.actual.display(db)
-                        ));
-                    } else {
-                        bar.println(format!(
-                            "{}: Expected {}, got {}",
-                            name,
-                            mismatch.expected.display(db),
-                            mismatch.actual.display(db)
-                        ));
-                    }
+                    let location = expr_location(db, &analysis, f_id, expr_id)
+                        .unwrap_or_else(|| name.to_string());
+                    bar.println(format!(
+                        "{}: Expected {}, got {}",
+                        location,
+                        mismatch.expected.display(db),
+                        mismatch.actual.display(db)
+                    ));
                 }
             }
         }
+        total.add(counts);
+        let crate_name = crate_names[&f.module(db).krate()].clone();
+        per_crate.entry(crate_name.clone()).or_insert_with(Counts::default).add(counts);
+        if output.json {
+            let line_index = analysis.file_line_index(original_file)?;
+            let line = line_index.line_col(src.value.syntax().text_range().start()).line + 1;
+            let mut function = counts.to_json();
+            function["name"] = name.to_string().into();
+            function["crate"] = crate_name.into();
+            function["location"] = format!("{}:{}", path, line).into();
+            per_function.push(function);
+        }
         bar.inc(1);
     }
     bar.finish_and_clear();
-    println!("Total expressions: {}", num_exprs);
-    println!(
+    report!("Total expressions: {}", total.exprs);
+    report!(
         "Expressions of unknown type: {} ({}%)",
-        num_exprs_unknown,
-        if num_exprs > 0 { (num_exprs_unknown * 100 / num_exprs) } else { 100 }
+        total.unknown,
+        percentage(total.unknown, total.exprs)
     );
-    println!(
+    report!(
         "Expressions of partially unknown type: {} ({}%)",
-        num_exprs_partially_unknown,
-        if num_exprs > 0 { (num_exprs_partially_unknown * 100 / num_exprs) } else { 100 }
+        total.partially_unknown,
+        percentage(total.partially_unknown, total.exprs)
     );
-    println!("Type mismatches: {}", num_type_mismatches);
-    println!("Inference: {:?}, {}", inference_time.elapsed(), ra_prof::memory_usage());
-    println!("Total: {:?}, {}", analysis_time.elapsed(), ra_prof::memory_usage());
+    report!("Type mismatches: {}", total.mismatches);
+    report!("Inference: {:?}, {}", inference_time.elapsed(), ra_prof::memory_usage());
+    report!("Total: {:?}, {}", analysis_time.elapsed(), ra_prof::memory_usage());
+
+    if output.json {
+        let mut report = json!({
+            "total": total.to_json(),
+            "crates": per_crate
+                .iter()
+                .map(|(name, counts)| (name.clone(), counts.to_json()))
+                .collect::<serde_json::Map<_, _>>(),
+            "functions": per_function,
+        });
+        if output.dump_unknowns {
+            report["unknowns"] = unknowns.into();
+        }
+        println!("{}", serde_json::to_string_pretty(&report)?);
+    }
 
     if memory_usage {
+        drop(analysis);
         drop(db);
+        // With `--json`, the memory usage goes to stderr, after the report.
+        macro_rules! report_memory {
+            ($($arg:tt)*) => {
+                if output.json {
+                    eprintln!($($arg)*);
+                } else {
+                    println!($($arg)*);
+                }
+            };
+        }
         for (name, bytes) in host.per_query_memory_usage() {
-            println!("{:>8} {}", bytes, name)
+            report_memory!("{:>8} {}", bytes, name)
         }
         let before = ra_prof::memory_usage();
         drop(host);
-        println!("leftover: {}", before.allocated - ra_prof::memory_usage().allocated)
+        report_memory!("leftover: {}", before.allocated - ra_prof::memory_usage().allocated)
+    }
+
+    if let Some(baseline) = &output.baseline {
+        compare_with_baseline(baseline, total, &per_crate)?;
     }
 
     Ok(())
 }
+
+/// Fails if the inference got worse, overall or for any crate, compared to
+/// the JSON report at `path`.
+fn compare_with_baseline(
+    path: &Path,
+    total: Counts,
+    per_crate: &BTreeMap<String, Counts>,
+) -> Result<()> {
+    let baseline: Value = serde_json::from_str(&fs::read_to_string(path)?)?;
+    let regressions = baseline_regressions(&baseline, total, per_crate)
+        .ok_or_else(|| format!("invalid baseline {}", path.display()))?;
+
+    if regressions.is_empty() {
+        eprintln!("No regressions compared to {}", path.display());
+        return Ok(());
+    }
+    for regression in regressions.iter() {
+        eprintln!("regression: {}", regression);
+    }
+    Err(format!("type inference regressed compared to {}", path.display()).into())
+}
+
+/// Lists the regressions compared to `baseline`. Crates of the baseline which
+/// weren't analyzed count as regressions, so that they aren't missed silently.
+/// Returns `None` if `baseline` isn't a valid report.
+fn baseline_regressions(
+    baseline: &Value,
+    total: Counts,
+    per_crate: &BTreeMap<String, Counts>,
+) -> Option<Vec<String>> {
+    let baseline_total = Counts::from_json(baseline.get("total")?)?;
+    let baseline_crates = baseline.get("crates")?.as_object()?;
+
+    let mut regressions = Vec::new();
+    for regression in total.regressions(baseline_total) {
+        regressions.push(format!("total {}", regression));
+    }
+    for (name, baseline_counts) in baseline_crates {
+        let baseline_counts = Counts::from_json(baseline_counts)?;
+        let counts = match per_crate.get(name) {
+            Some(&it) => it,
+            None => {
+                regressions.push(format!("crate {} is missing", name));
+                continue;
+            }
+        };
+        for regression in counts.regressions(baseline_counts) {
+            regressions.push(format!("crate {} {}", name, regression));
+        }
+    }
+    for name in per_crate.keys().filter(|it| !baseline_crates.contains_key(*it)) {
+        eprintln!("crate {} is not in the baseline", name);
+    }
+    Some(regressions)
+}
+
+/// Names crates after the name they are imported with, if they are some
+/// crate's dependency, and the path of their root file. Crates of different
+/// packages, or different versions of a package, can share a name, but not
+/// the root file. See `crate_key` for the paths.
+fn crate_names(
+    db: &(impl DefDatabase + SourceDatabaseExt),
+    root_paths: &HashMap<SourceRootId, PathBuf>,
+    project_root: &Path,
+) -> HashMap<Crate, String> {
+    let mut dep_names = HashMap::new();
+    for krate in Crate::all(db) {
+        for dep in krate.dependencies(db) {
+            dep_names.insert(dep.krate, dep.name.to_string());
+        }
+    }
+    let mut res = HashMap::new();
+    for krate in Crate::all(db) {
+        let root_file = krate.root_file(db);
+        let path = db.file_relative_path(root_file);
+        let path = match root_paths.get(&db.file_source_root(root_file)) {
+            Some(root_path) => crate_key(&path.to_path(root_path), root_path, project_root),
+            None => path.to_string(),
+        };
+        let name = match dep_names.get(&krate) {
+            Some(name) => format!("{} ({})", name, path),
+            None => path,
+        };
+        res.insert(krate, name);
+    }
+    res
+}
+
+/// The path of a crate's `root_file`, which doesn't depend on where the
+/// project is checked out, so that reports of different machines can be
+/// compared. Crates of the project are identified by the path relative to the
+/// `project_root`, other crates by the path relative to the parent of their
+/// `source_root`, like `serde-1.0.104/src/lib.rs`.
+fn crate_key(root_file: &Path, source_root: &Path, project_root: &Path) -> String {
+    let path = match root_file.strip_prefix(project_root) {
+        Ok(it) => it,
+        Err(_) => {
+            let base = source_root.parent().unwrap_or(source_root);
+            root_file.strip_prefix(base).unwrap_or(root_file)
+        }
+    };
+    let components =
+        path.components().map(|it| it.as_os_str().to_string_lossy()).collect::<Vec<_>>();
+    components.join("/")
+}
+
+fn expr_location(
+    db: &(impl HirDatabase + SourceDatabaseExt),
+    analysis: &Analysis,
+    f_id: FunctionId,
+    expr_id: ExprId,
+) -> Option<String> {
+    let (_, sm) = db.body_with_source_map(f_id.into());
+    let src = sm.expr_syntax(expr_id)?;
+    // FIXME: it might be nice to have a function (on Analysis?) that goes from Source<T> -> (LineCol, LineCol) directly
+    let original_file = src.file_id.original_file(db);
+    let path = db.file_relative_path(original_file);
+    let line_index = analysis.file_line_index(original_file).unwrap();
+    let text_range =
+        src.value.either(|it| it.syntax_node_ptr().range(), |it| it.syntax_node_ptr().range());
+    let (start, end) =
+        (line_index.line_col(text_range.start()), line_index.line_col(text_range.end()));
+    Some(format!(
+        "{} {}:{}-{}:{}",
+        path,
+        start.line + 1,
+        start.col_utf16,
+        end.line + 1,
+        end.col_utf16
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn counts(exprs: u64, unknown: u64) -> Counts {
+        Counts { exprs, unknown, ..Counts::default() }
+    }
+
+    #[test]
+    fn crate_keys_dont_depend_on_the_checkout() {
+        let key = |checkout: &str, registry: &str| {
+            let checkout = Path::new(checkout);
+            let registry = Path::new(registry);
+            let serde = registry.join("serde-1.0.104");
+            vec![
+                crate_key(&checkout.join("crates/foo/src/lib.rs"), checkout, checkout),
+                crate_key(&serde.join("src/lib.rs"), &serde, checkout),
+            ]
+        };
+        let local = key("/home/me/project", "/home/me/.cargo/registry/src/github.com-1ecc");
+        let ci = key("/builds/project", "/usr/local/cargo/registry/src/github.com-1ecc");
+        assert_eq!(local, vec!["crates/foo/src/lib.rs", "serde-1.0.104/src/lib.rs"]);
+        assert_eq!(local, ci);
+    }
+
+    #[test]
+    fn baseline_of_another_checkout() {
+        let key = |checkout: &str| {
+            let checkout = Path::new(checkout);
+            let foo = crate_key(&checkout.join("foo/src/lib.rs"), checkout, checkout);
+            let bar = crate_key(&checkout.join("bar/src/lib.rs"), checkout, checkout);
+            (format!("foo ({})", foo), format!("bar ({})", bar))
+        };
+        let (foo, bar) = key("/home/me/project");
+        let baseline = json!({
+            "total": counts(200, 10).to_json(),
+            "crates": {
+                foo: counts(100, 0).to_json(),
+                bar: counts(100, 10).to_json(),
+            },
+        });
+
+        let (foo, bar) = key("/builds/project");
+        let mut per_crate = BTreeMap::new();
+        per_crate.insert(foo, counts(100, 5));
+        per_crate.insert(bar.clone(), counts(100, 5));
+        assert_eq!(
+            baseline_regressions(&baseline, counts(200, 10), &per_crate).unwrap(),
+            vec!["crate foo (foo/src/lib.rs) unknown: 5.00% (5/100), was 0.00% (0/100)"]
+        );
+
+        per_crate.remove(&bar);
+        assert_eq!(
+            baseline_regressions(&baseline, counts(200, 10), &per_crate).unwrap(),
+            vec![
+                "crate bar (bar/src/lib.rs) is missing".to_string(),
+                "crate foo (foo/src/lib.rs) unknown: 5.00% (5/100), was 0.00% (0/100)".to_string(),
+            ]
+        );
+    }
+}
@@ -0,0 +1,129 @@
+//! Runs rust-analyzer diagnostics over a whole workspace, for use in CI.
+
+use std::{collections::HashSet, path::Path};
+
+use ra_db::SourceDatabaseExt;
+use ra_ide::{Diagnostic, Severity};
+use serde_json::json;
+
+use crate::Result;
+
+pub(crate) struct Filter {
+    /// Report only errors, and not weak warnings.
+    pub(crate) errors_only: bool,
+    /// If not empty, report only diagnostics with these codes.
+    pub(crate) codes: Vec<String>,
+    pub(crate) skip_codes: Vec<String>,
+}
+
+impl Filter {
+    fn accepts(&self, d: &Diagnostic) -> bool {
+        if self.errors_only && !is_error(d) {
+            return false;
+        }
+        if !self.codes.is_empty() && !self.codes.iter().any(|it| it == d.code) {
+            return false;
+        }
+        !self.skip_codes.iter().any(|it| it == d.code)
+    }
+}
+
+/// Prints diagnostics for all files in the workspace at `path`, returns `true`
+/// if at least one error was reported.
+pub(crate) fn run(path: &Path, filter: &Filter, json: bool, with_deps: bool) -> Result<bool> {
+    let (host, roots) = ra_batch::load_cargo(path)?;
+    let db = host.raw_database();
+    let analysis = host.analysis();
+    let cwd = std::env::current_dir()?;
+
+    let mut files = Vec::new();
+    let mut visited = HashSet::new();
+    for (source_root_id, project_root) in roots.iter() {
+        if !(with_deps || project_root.is_member()) {
+            continue;
+        }
+        for file_id in db.source_root(*source_root_id).walk() {
+            if !visited.insert(file_id) {
+                continue;
+            }
+            let path = db.file_relative_path(file_id).to_path(project_root.path());
+            let path = path.strip_prefix(&cwd).unwrap_or(&path).display().to_string();
+            files.push((path, file_id));
+        }
+    }
+    files.sort();
+
+    let mut num_errors = 0;
+    let mut num_warnings = 0;
+    for (path, file_id) in files {
+        let diagnostics = analysis.diagnostics(file_id)?;
+        if diagnostics.is_empty() {
+            continue;
+        }
+        let text = analysis.file_text(file_id)?;
+        let line_index = analysis.file_line_index(file_id)?;
+        for d in diagnostics.iter().filter(|d| filter.accepts(d)) {
+            if is_error(d) {
+                num_errors += 1;
+            } else {
+                num_warnings += 1;
+            }
+            let start = line_index.line_col(d.range.start());
+            let end = line_index.line_col(d.range.end());
+            if json {
+                let value = json!({
+                    "file": path,
+                    "code": d.code,
+                    "severity": severity_name(d),
+                    "message": d.message,
+                    "range": {
+                        "start": { "line": start.line + 1, "column": start.col_utf16 + 1 },
+                        "end": { "line": end.line + 1, "column": end.col_utf16 + 1 },
+                    },
+                });
+                println!("{}", value);
+            } else {
+                println!("{}[{}]: {}", severity_name(d), d.code, d.message.trim_end());
+                println!("  --> {}:{}:{}", path, start.line + 1, start.col_utf16 + 1);
+                print_snippet(&text, d, start.line + 1);
+                println!();
+            }
+        }
+    }
+
+    if !json {
+        eprintln!("{} errors, {} warnings", num_errors, num_warnings);
+    }
+    Ok(num_errors > 0)
+}
+
+/// Prints the first line of the diagnostic's range, underlined, like rustc.
+fn print_snippet(text: &str, d: &Diagnostic, line_number: u32) {
+    let start = d.range.start().to_usize();
+    let end = d.range.end().to_usize();
+    let line_start = text[..start].rfind('\n').map_or(0, |it| it + 1);
+    let line_end = text[start..].find('\n').map_or(text.len(), |it| start + it);
+    let line = &text[line_start..line_end];
+
+    let padding = " ".repeat(text[line_start..start].chars().count());
+    let len = text[start..end.min(line_end)].chars().count().max(1);
+    let gutter = " ".repeat(line_number.to_string().len());
+    println!("{} |", gutter);
+    println!("{} | {}", line_number, line);
+    println!("{} | {}{}", gutter, padding, "^".repeat(len));
+}
+
+fn is_error(d: &Diagnostic) -> bool {
+    match d.severity {
+        Severity::Error => true,
+        Severity::WeakWarning => false,
+    }
+}
+
+fn severity_name(d: &Diagnostic) -> &'static str {
+    if is_error(d) {
+        "error"
+    } else {
+        "warning"
+    }
+}
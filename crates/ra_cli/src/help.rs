@@ -11,8 +11,12 @@ FLAGS:
 SUBCOMMANDS:
     analysis-bench
     analysis-stats
+    assist
+    diagnostics
     highlight
+    lsif
     parse
+    rename
     symbols";
 
 pub const ANALYSIS_BENCH_HELP: &str = "ra_cli-analysis-bench
@@ -25,8 +29,20 @@ FLAGS:
     -v, --verbose
 
 OPTIONS:
-    --complete <PATH:LINE:COLUMN>    Compute completions at this location
-    --highlight <PATH>               Hightlight this file
+    --complete <PATH:LINE:COLUMN>     Compute completions at this location
+    --highlight <PATH>                Hightlight this file
+    --goto-def <PATH:LINE:COLUMN>     Compute goto definition at this location
+    --hover <PATH:LINE:COLUMN>        Compute hover at this location
+    --find-refs <PATH:LINE:COLUMN>    Find all references of the item at this location
+    --diagnostics <PATH>              Compute diagnostics for this file
+    --inlay-hints <PATH>              Compute inlay hints for this file
+    --edit <PATH:LINE:COLUMN>         After the other changes, insert `--edit-text` at
+                                      this location and measure again
+    --edit-text <TEXT>                Text to insert with `--edit`
+    --profile <SPEC>                  Print a `ra_prof` breakdown of each run, like `*>10`
+                                      (see `RA_PROFILE`)
+
+LINE and COLUMN are zero-based, COLUMN counts characters.
 
 ARGS:
     <PATH>    Project to analyse";
@@ -41,21 +57,103 @@ FLAGS:
         --memory-usage
     -v, --verbose
     -q, --quiet
+        --with-deps       Also analyzes dependencies
+        --json            Prints the statistics as JSON, with per-crate and per-function counts
+        --dump-unknowns   Lists expressions of (partially) unknown types
 
 OPTIONS:
     -o <ONLY>
+    --baseline <FILE>     Fails if inference regressed compared to this output of `--json`
 
 ARGS:
     <PATH>";
 
+pub const DIAGNOSTICS_HELP: &str = "ra_cli-diagnostics
+
+USAGE:
+    ra_cli diagnostics [FLAGS] [OPTIONS] <PATH>
+
+FLAGS:
+    -h, --help           Prints help information
+        --json           Prints one JSON object per diagnostic
+        --with-deps      Also checks files of dependencies, which are excluded by default
+
+OPTIONS:
+    --severity <error|warning>    Minimal severity of reported diagnostics, `warning` by default
+    --code <CODE,...>             Reports only diagnostics with these codes
+    --skip-code <CODE,...>        Doesn't report diagnostics with these codes
+
+ARGS:
+    <PATH>    Project to check
+
+Exits with a non-zero status if any errors were reported.";
+
 pub const HIGHLIGHT_HELP: &str = "ra-cli-highlight
 
 USAGE:
     ra_cli highlight [FLAGS]
+    ra_cli highlight [FLAGS] --output <DIR> <PATH>
+
+Highlights a file from stdin, or, with `--output`, renders every file of the
+project at <PATH> to a browsable set of HTML pages.
+
+FLAGS:
+    -h, --help         Prints help information
+    -r, --rainbow
+        --with-deps    Also renders files of dependencies
+
+OPTIONS:
+    -o, --output <DIR>    Directory for the HTML pages
+
+ARGS:
+    <PATH>    Project to render";
+
+pub const LSIF_HELP: &str = "ra_cli-lsif
+
+USAGE:
+    ra_cli lsif [FLAGS] <PATH>
+
+FLAGS:
+    -h, --help    Prints help information
+
+ARGS:
+    <PATH>    Project to export, the LSIF dump is printed to stdout";
+
+pub const RENAME_HELP: &str = "ra_cli-rename
+
+USAGE:
+    ra_cli rename [FLAGS] [OPTIONS] <PATH:LINE:COLUMN> <NEW_NAME>
 
 FLAGS:
     -h, --help       Prints help information
-    -r, --rainbow";
+        --dry-run    Prints the changes as a unified diff instead of applying them
+
+OPTIONS:
+    --path <PATH>    Project to load, defaults to the current directory
+
+ARGS:
+    <PATH:LINE:COLUMN>    Location of the item to rename
+    <NEW_NAME>            New name of the item
+
+LINE and COLUMN are zero-based, COLUMN counts characters.";
+
+pub const ASSIST_HELP: &str = "ra_cli-assist
+
+USAGE:
+    ra_cli assist [FLAGS] [OPTIONS] <ID> <LOCATION>
+
+FLAGS:
+    -h, --help       Prints help information
+        --dry-run    Prints the changes as a unified diff instead of applying them
+
+OPTIONS:
+    --path <PATH>    Project to load, defaults to the current directory
+
+ARGS:
+    <ID>          Id of the assist, like `add_derive`
+    <LOCATION>    `PATH:LINE:COLUMN` or `PATH:LINE:COLUMN-LINE:COLUMN`
+
+LINE and COLUMN are zero-based, COLUMN counts characters.";
 
 pub const SYMBOLS_HELP: &str = "ra-cli-symbols
 
@@ -0,0 +1,167 @@
+//! Renders every file of a workspace to HTML, producing a static code browser.
+//!
+//! Identifiers link to their definitions and show their types on hover. Each
+//! crate gets an index page, listing its files.
+
+use std::{
+    collections::{BTreeSet, HashMap},
+    fs,
+    path::Path,
+};
+
+use hir::{db::DefDatabase, Crate};
+use ra_db::SourceDatabaseExt;
+use ra_ide::{Analysis, FileId, FilePosition, FileRange, HtmlAnnotation};
+use ra_syntax::{AstNode, SyntaxKind};
+
+use crate::Result;
+
+pub(crate) fn run(path: &Path, output: &Path, rainbow: bool, with_deps: bool) -> Result<()> {
+    let (host, roots) = ra_batch::load_cargo(path)?;
+    let db = host.raw_database();
+    let analysis = host.analysis();
+    let workspace_root = std::env::current_dir()?.join(path).canonicalize()?;
+
+    // Path of the page of each rendered file, relative to `output`.
+    let mut pages = HashMap::new();
+    for (source_root_id, project_root) in roots.iter() {
+        if !(with_deps || project_root.is_member()) {
+            continue;
+        }
+        for file_id in db.source_root(*source_root_id).walk() {
+            let rel_path = db.file_relative_path(file_id);
+            let path = rel_path.to_path(project_root.path());
+            let page = match path.strip_prefix(&workspace_root) {
+                Ok(path) => format!("src/{}.html", path.display()),
+                Err(_) => format!("deps/{}/{}.html", source_root_id.0, rel_path),
+            };
+            pages.insert(file_id, page.replace('\\', "/"));
+        }
+    }
+
+    for (&file_id, page) in pages.iter() {
+        let annotations = annotations(&analysis, file_id, page, &pages)?;
+        let html = analysis.highlight_as_html_with_annotations(file_id, rainbow, &annotations)?;
+        let title = page.trim_end_matches(".html");
+        write_page(&output.join(page), title, &html)?;
+    }
+
+    let mut crate_names = HashMap::new();
+    for krate in Crate::all(db) {
+        for dep in krate.dependencies(db) {
+            crate_names.insert(dep.krate, dep.name.to_string());
+        }
+    }
+    let mut index = String::from("<ul>\n");
+    for (idx, krate) in Crate::all(db).into_iter().enumerate() {
+        let root_page = match pages.get(&krate.root_file(db)) {
+            Some(it) => it,
+            None => continue,
+        };
+        let name = match crate_names.get(&krate) {
+            Some(it) => it.as_str(),
+            None => root_page.trim_end_matches(".html"),
+        };
+        let crate_page = format!("crates/{}-{}.html", idx, name.replace('/', "-"));
+
+        let mut list = String::from("<ul>\n");
+        for file_id in crate_files(db, krate) {
+            if let Some(page) = pages.get(&file_id) {
+                let url = relative_url(&crate_page, page);
+                let title = page.trim_end_matches(".html");
+                list.push_str(&format!("<li><a href=\"{}\">{}</a></li>\n", url, title));
+            }
+        }
+        list.push_str("</ul>");
+        write_page(&output.join(&crate_page), name, &list)?;
+        index.push_str(&format!("<li><a href=\"{}\">{}</a></li>\n", crate_page, name));
+    }
+    index.push_str("</ul>");
+    write_page(&output.join("index.html"), "crates", &index)?;
+    eprintln!("rendered {} files to {}", pages.len(), output.display());
+    Ok(())
+}
+
+/// Computes links to definitions and types of all the identifiers in the file.
+fn annotations(
+    analysis: &Analysis,
+    file_id: FileId,
+    page: &str,
+    pages: &HashMap<FileId, String>,
+) -> Result<Vec<HtmlAnnotation>> {
+    let idents = analysis
+        .parse(file_id)?
+        .syntax()
+        .descendants_with_tokens()
+        .filter_map(|it| it.into_token())
+        .filter(|it| it.kind() == SyntaxKind::IDENT)
+        .map(|it| it.text_range())
+        .collect::<Vec<_>>();
+
+    let mut res = Vec::new();
+    for range in idents {
+        let position = FilePosition { file_id, offset: range.start() };
+        let nav = analysis.goto_definition(position)?.and_then(|it| it.info.into_iter().next());
+        let mut title = analysis.type_of(FileRange { file_id, range })?;
+        let mut id = None;
+        let mut href = None;
+        if let Some(nav) = nav {
+            if title.is_none() {
+                title = nav.description().map(|it| it.to_string());
+            }
+            if nav.file_id() == file_id && nav.focus_range() == Some(range) {
+                id = Some(range.start().to_string());
+            } else if let Some(target_page) = pages.get(&nav.file_id()) {
+                let url = relative_url(page, target_page);
+                href = Some(match nav.focus_range() {
+                    Some(focus_range) => format!("{}#{}", url, focus_range.start()),
+                    None => url,
+                });
+            }
+        }
+        if id.is_some() || href.is_some() || title.is_some() {
+            res.push(HtmlAnnotation { range, id, href, title });
+        }
+    }
+    Ok(res)
+}
+
+/// Returns all files of the crate's module tree.
+fn crate_files(db: &impl DefDatabase, krate: Crate) -> BTreeSet<FileId> {
+    let mut res = BTreeSet::new();
+    let mut queue = krate.root_module(db).into_iter().collect::<Vec<_>>();
+    while let Some(module) = queue.pop() {
+        res.insert(module.definition_source(db).file_id.original_file(db));
+        queue.extend(module.children(db));
+    }
+    res
+}
+
+/// Computes the URL of `to` relative to `from`, both relative to the output
+/// directory.
+fn relative_url(from: &str, to: &str) -> String {
+    let depth = from.matches('/').count();
+    format!("{}{}", "../".repeat(depth), to)
+}
+
+fn write_page(path: &Path, title: &str, body: &str) -> Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let html = format!(
+        "<!DOCTYPE html>
+<html>
+<head>
+<meta charset=\"utf-8\">
+<title>{}</title>
+</head>
+<body>
+{}
+</body>
+</html>
+",
+        title, body
+    );
+    fs::write(path, html)?;
+    Ok(())
+}
@@ -0,0 +1,259 @@
+//! Exports the semantic data of a workspace as an LSIF dump.
+//!
+//! LSIF (Language Server Index Format) is a graph of vertices (documents,
+//! ranges, result sets and results) and edges, serialized as JSON lines. See
+//! https://github.com/Microsoft/language-server-protocol/blob/master/indexFormat/specification.md
+//!
+//! Every identifier in the member crates is resolved with `goto_definition`.
+//! Identifiers which resolve to the same definition share a result set, which
+//! holds the hover, definition and references results, computed once per
+//! definition.
+
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    path::{Path, PathBuf},
+};
+
+use ra_db::SourceDatabaseExt;
+use ra_ide::{Analysis, FileId, FilePosition, FileRange, NavigationTarget};
+use ra_syntax::{AstNode, SyntaxKind, TextRange};
+use serde_json::{json, Value};
+use url::Url;
+
+use crate::Result;
+
+/// Uniquely identifies a definition, `NavigationTarget` itself is not `Hash`.
+type DefKey = (FileId, TextRange, Option<TextRange>);
+
+pub(crate) fn run(path: &Path) -> Result<()> {
+    let (host, roots) = ra_batch::load_cargo(path)?;
+    let db = host.raw_database();
+    let root_paths = roots
+        .iter()
+        .map(|(source_root_id, project_root)| (*source_root_id, project_root.path().clone()))
+        .collect::<HashMap<_, _>>();
+    let project_root = std::env::current_dir()?.join(path).canonicalize()?;
+
+    let mut files = Vec::new();
+    for (source_root_id, project_root) in roots.iter() {
+        if project_root.is_member() {
+            for file_id in db.source_root(*source_root_id).walk() {
+                files.push((db.file_relative_path(file_id).to_path(project_root.path()), file_id));
+            }
+        }
+    }
+    files.sort();
+
+    let mut lsif = Lsif {
+        analysis: host.analysis(),
+        file_path: &|file_id| {
+            let root_path = &root_paths[&db.file_source_root(file_id)];
+            db.file_relative_path(file_id).to_path(root_path)
+        },
+        next_id: 0,
+        documents: HashMap::new(),
+        ranges: HashMap::new(),
+        linked_ranges: HashSet::new(),
+        result_sets: HashMap::new(),
+        contains: BTreeMap::new(),
+    };
+    lsif.emit_vertex(json!({
+        "label": "metaData",
+        "version": "0.4.3",
+        "projectRoot": file_uri(&project_root)?,
+        "positionEncoding": "utf-16",
+        "toolInfo": { "name": "ra_cli", "args": ["lsif"] },
+    }));
+    for (_, file_id) in files {
+        lsif.add_file(file_id)?;
+    }
+    lsif.finish();
+    Ok(())
+}
+
+struct Lsif<'a> {
+    analysis: Analysis,
+    file_path: &'a dyn Fn(FileId) -> PathBuf,
+    next_id: u64,
+    documents: HashMap<FileId, u64>,
+    ranges: HashMap<(FileId, TextRange), u64>,
+    /// Ranges which already have a `next` edge to their result set.
+    linked_ranges: HashSet<u64>,
+    result_sets: HashMap<DefKey, u64>,
+    /// Ranges of each document, emitted as `contains` edges at the end.
+    contains: BTreeMap<u64, Vec<u64>>,
+}
+
+impl Lsif<'_> {
+    fn add_file(&mut self, file_id: FileId) -> Result<()> {
+        self.document(file_id)?;
+        let tokens = self
+            .analysis
+            .parse(file_id)?
+            .syntax()
+            .descendants_with_tokens()
+            .filter_map(|it| it.into_token())
+            .filter(|it| it.kind() == SyntaxKind::IDENT)
+            .map(|it| it.text_range())
+            .collect::<Vec<_>>();
+        for range in tokens {
+            let position = FilePosition { file_id, offset: range.start() };
+            let navs = match self.analysis.goto_definition(position)? {
+                Some(it) => it.info,
+                None => continue,
+            };
+            let nav = match navs.into_iter().next() {
+                Some(it) => it,
+                None => continue,
+            };
+            let result_set = self.result_set(&nav)?;
+            let range = self.range(FileRange { file_id, range })?;
+            self.link(range, result_set);
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self) {
+        for (document, ranges) in std::mem::replace(&mut self.contains, BTreeMap::new()) {
+            self.emit(json!({
+                "type": "edge",
+                "label": "contains",
+                "outV": document,
+                "inVs": ranges,
+            }));
+        }
+    }
+
+    fn document(&mut self, file_id: FileId) -> Result<u64> {
+        if let Some(&id) = self.documents.get(&file_id) {
+            return Ok(id);
+        }
+        let path = (self.file_path)(file_id);
+        let id = self.emit_vertex(json!({
+            "label": "document",
+            "uri": file_uri(&path)?,
+            "languageId": "rust",
+        }));
+        self.documents.insert(file_id, id);
+        self.contains.insert(id, Vec::new());
+        Ok(id)
+    }
+
+    fn range(&mut self, frange: FileRange) -> Result<u64> {
+        if let Some(&id) = self.ranges.get(&(frange.file_id, frange.range)) {
+            return Ok(id);
+        }
+        let document = self.document(frange.file_id)?;
+        let line_index = self.analysis.file_line_index(frange.file_id).unwrap();
+        let start = line_index.line_col(frange.range.start());
+        let end = line_index.line_col(frange.range.end());
+        let id = self.emit_vertex(json!({
+            "label": "range",
+            "start": { "line": start.line, "character": start.col_utf16 },
+            "end": { "line": end.line, "character": end.col_utf16 },
+        }));
+        self.ranges.insert((frange.file_id, frange.range), id);
+        self.contains.get_mut(&document).unwrap().push(id);
+        Ok(id)
+    }
+
+    /// Returns the result set of the definition, computing its results the
+    /// first time the definition is seen.
+    fn result_set(&mut self, nav: &NavigationTarget) -> Result<u64> {
+        let key = (nav.file_id(), nav.full_range(), nav.focus_range());
+        if let Some(&id) = self.result_sets.get(&key) {
+            return Ok(id);
+        }
+        let result_set = self.emit_vertex(json!({ "label": "resultSet" }));
+        self.result_sets.insert(key, result_set);
+
+        let def_range = nav.focus_range().unwrap_or_else(|| nav.full_range());
+        let def_frange = FileRange { file_id: nav.file_id(), range: def_range };
+        let def_position = FilePosition { file_id: nav.file_id(), offset: def_range.start() };
+        let def = self.range(def_frange)?;
+        self.link(def, result_set);
+
+        let definition_result = self.emit_vertex(json!({ "label": "definitionResult" }));
+        self.emit_edge("textDocument/definition", result_set, definition_result);
+        self.emit_item(definition_result, vec![def], nav.file_id(), None)?;
+
+        if let Some(hover) = self.analysis.hover(def_position)? {
+            let hover_result = self.emit_vertex(json!({
+                "label": "hoverResult",
+                "result": {
+                    "contents": { "kind": "markdown", "value": hover.info.to_markup() },
+                },
+            }));
+            self.emit_edge("textDocument/hover", result_set, hover_result);
+        }
+
+        let reference_result = self.emit_vertex(json!({ "label": "referenceResult" }));
+        self.emit_edge("textDocument/references", result_set, reference_result);
+        self.emit_item(reference_result, vec![def], nav.file_id(), Some("definitions"))?;
+        if let Some(refs) = self.analysis.find_all_refs(def_position, None)? {
+            let mut by_file = BTreeMap::new();
+            for frange in refs.references() {
+                if frange.file_id == def_frange.file_id && frange.range == def_frange.range {
+                    continue;
+                }
+                by_file.entry(frange.file_id).or_insert_with(Vec::new).push(*frange);
+            }
+            for (file_id, franges) in by_file {
+                let ranges =
+                    franges.into_iter().map(|it| self.range(it)).collect::<Result<Vec<_>>>()?;
+                self.emit_item(reference_result, ranges, file_id, Some("references"))?;
+            }
+        }
+        Ok(result_set)
+    }
+
+    fn link(&mut self, range: u64, result_set: u64) {
+        if self.linked_ranges.insert(range) {
+            self.emit_edge("next", range, result_set);
+        }
+    }
+
+    fn emit_item(
+        &mut self,
+        out_v: u64,
+        in_vs: Vec<u64>,
+        file_id: FileId,
+        property: Option<&str>,
+    ) -> Result<()> {
+        let document = self.document(file_id)?;
+        let mut edge = json!({
+            "type": "edge",
+            "label": "item",
+            "outV": out_v,
+            "inVs": in_vs,
+            "document": document,
+        });
+        if let Some(property) = property {
+            edge["property"] = property.into();
+        }
+        self.emit(edge);
+        Ok(())
+    }
+
+    fn emit_vertex(&mut self, mut vertex: Value) -> u64 {
+        vertex["type"] = "vertex".into();
+        self.emit(vertex)
+    }
+
+    fn emit_edge(&mut self, label: &str, out_v: u64, in_v: u64) -> u64 {
+        self.emit(json!({ "type": "edge", "label": label, "outV": out_v, "inV": in_v }))
+    }
+
+    fn emit(&mut self, mut element: Value) -> u64 {
+        self.next_id += 1;
+        element["id"] = self.next_id.into();
+        println!("{}", element);
+        self.next_id
+    }
+}
+
+fn file_uri(path: &Path) -> Result<String> {
+    let uri = Url::from_file_path(path)
+        .map_err(|()| format!("can't convert {} to a URI", path.display()))?;
+    Ok(String::from(uri))
+}
@@ -2,15 +2,19 @@
 
 mod analysis_stats;
 mod analysis_bench;
+mod diagnostics;
 mod help;
+mod highlight;
+mod lsif;
 mod progress_report;
+mod refactor;
 
 use std::{error::Error, fmt::Write, io::Read};
 
 use pico_args::Arguments;
 use ra_ide::{file_structure, Analysis};
 use ra_prof::profile;
-use ra_syntax::{AstNode, SourceFile};
+use ra_syntax::{AstNode, SourceFile, TextUnit};
 
 type Result<T> = std::result::Result<T, Box<dyn Error + Send + Sync>>;
 
@@ -75,10 +79,27 @@ fn main() -> Result<()> {
                 return Ok(());
             }
             let rainbow_opt = matches.contains(["-r", "--rainbow"]);
-            matches.finish().or_else(handle_extra_flags)?;
-            let (analysis, file_id) = Analysis::from_single_file(read_stdin()?);
-            let html = analysis.highlight_as_html(file_id, rainbow_opt).unwrap();
-            println!("{}", html);
+            let with_deps = matches.contains("--with-deps");
+            let output: Option<String> = matches.opt_value_from_str(["-o", "--output"])?;
+            match output {
+                Some(output) => {
+                    let path = {
+                        let mut trailing = matches.free()?;
+                        if trailing.len() != 1 {
+                            eprintln!("{}", help::HIGHLIGHT_HELP);
+                            Err("Invalid flags")?;
+                        }
+                        trailing.pop().unwrap()
+                    };
+                    highlight::run(path.as_ref(), output.as_ref(), rainbow_opt, with_deps)?;
+                }
+                None => {
+                    matches.finish().or_else(handle_extra_flags)?;
+                    let (analysis, file_id) = Analysis::from_single_file(read_stdin()?);
+                    let html = analysis.highlight_as_html(file_id, rainbow_opt).unwrap();
+                    println!("{}", html);
+                }
+            }
         }
         "analysis-stats" => {
             if matches.contains(["-h", "--help"]) {
@@ -97,6 +118,9 @@ fn main() -> Result<()> {
             let memory_usage = matches.contains("--memory-usage");
             let only: Option<String> = matches.opt_value_from_str(["-o", "--only"])?;
             let with_deps: bool = matches.contains("--with-deps");
+            let json = matches.contains("--json");
+            let dump_unknowns = matches.contains("--dump-unknowns");
+            let baseline: Option<String> = matches.opt_value_from_str("--baseline")?;
             let path = {
                 let mut trailing = matches.free()?;
                 if trailing.len() != 1 {
@@ -111,8 +135,55 @@ fn main() -> Result<()> {
                 path.as_ref(),
                 only.as_ref().map(String::as_ref),
                 with_deps,
+                analysis_stats::Output { json, dump_unknowns, baseline: baseline.map(Into::into) },
             )?;
         }
+        "diagnostics" => {
+            if matches.contains(["-h", "--help"]) {
+                eprintln!("{}", help::DIAGNOSTICS_HELP);
+                return Ok(());
+            }
+            let json = matches.contains("--json");
+            let with_deps = matches.contains("--with-deps");
+            let severity: Option<String> = matches.opt_value_from_str("--severity")?;
+            let errors_only = match severity {
+                None => false,
+                Some(severity) => match severity.as_str() {
+                    "error" => true,
+                    "warning" => false,
+                    _ => Err(format!("Invalid severity: {}", severity))?,
+                },
+            };
+            let codes = comma_separated(matches.opt_value_from_str("--code")?);
+            let skip_codes = comma_separated(matches.opt_value_from_str("--skip-code")?);
+            let path = {
+                let mut trailing = matches.free()?;
+                if trailing.len() != 1 {
+                    eprintln!("{}", help::DIAGNOSTICS_HELP);
+                    Err("Invalid flags")?;
+                }
+                trailing.pop().unwrap()
+            };
+            let filter = diagnostics::Filter { errors_only, codes, skip_codes };
+            if diagnostics::run(path.as_ref(), &filter, json, with_deps)? {
+                std::process::exit(1);
+            }
+        }
+        "lsif" => {
+            if matches.contains(["-h", "--help"]) {
+                eprintln!("{}", help::LSIF_HELP);
+                return Ok(());
+            }
+            let path = {
+                let mut trailing = matches.free()?;
+                if trailing.len() != 1 {
+                    eprintln!("{}", help::LSIF_HELP);
+                    Err("Invalid flags")?;
+                }
+                trailing.pop().unwrap()
+            };
+            lsif::run(path.as_ref())?;
+        }
         "analysis-bench" => {
             if matches.contains(["-h", "--help"]) {
                 eprintln!("{}", help::ANALYSIS_BENCH_HELP);
@@ -120,28 +191,96 @@ fn main() -> Result<()> {
             }
             let verbose = matches.contains(["-v", "--verbose"]);
             let path: String = matches.opt_value_from_str("--path")?.unwrap_or_default();
-            let highlight_path = matches.opt_value_from_str("--highlight")?;
-            let complete_path = matches.opt_value_from_str("--complete")?;
-            if highlight_path.is_some() && complete_path.is_some() {
-                panic!("either --highlight or --complete must be set, not both")
-            }
-            let op = if let Some(path) = highlight_path {
-                let path: String = path;
-                analysis_bench::Op::Highlight { path: path.into() }
-            } else if let Some(path_line_col) = complete_path {
-                let path_line_col: String = path_line_col;
-                let (path_line, column) = rsplit_at_char(path_line_col.as_str(), ':')?;
-                let (path, line) = rsplit_at_char(path_line, ':')?;
-                analysis_bench::Op::Complete {
-                    path: path.into(),
-                    line: line.parse()?,
-                    column: column.parse()?,
+            let profile_spec: Option<String> = matches.opt_value_from_str("--profile")?;
+            let highlight_path: Option<String> = matches.opt_value_from_str("--highlight")?;
+            let complete_path: Option<String> = matches.opt_value_from_str("--complete")?;
+            let goto_def_path: Option<String> = matches.opt_value_from_str("--goto-def")?;
+            let hover_path: Option<String> = matches.opt_value_from_str("--hover")?;
+            let find_refs_path: Option<String> = matches.opt_value_from_str("--find-refs")?;
+            let diagnostics_path: Option<String> = matches.opt_value_from_str("--diagnostics")?;
+            let inlay_hints_path: Option<String> = matches.opt_value_from_str("--inlay-hints")?;
+            let edit_path: Option<String> = matches.opt_value_from_str("--edit")?;
+            let edit_text: Option<String> = matches.opt_value_from_str("--edit-text")?;
+
+            let mut ops = Vec::new();
+            if let Some(path) = highlight_path {
+                ops.push(analysis_bench::Op::Highlight { path: path.into() });
+            }
+            if let Some(path_line_col) = complete_path {
+                ops.push(analysis_bench::Op::Complete(parse_position(&path_line_col)?));
+            }
+            if let Some(path_line_col) = goto_def_path {
+                ops.push(analysis_bench::Op::GotoDefinition(parse_position(&path_line_col)?));
+            }
+            if let Some(path_line_col) = hover_path {
+                ops.push(analysis_bench::Op::Hover(parse_position(&path_line_col)?));
+            }
+            if let Some(path_line_col) = find_refs_path {
+                ops.push(analysis_bench::Op::FindAllRefs(parse_position(&path_line_col)?));
+            }
+            if let Some(path) = diagnostics_path {
+                ops.push(analysis_bench::Op::Diagnostics { path: path.into() });
+            }
+            if let Some(path) = inlay_hints_path {
+                ops.push(analysis_bench::Op::InlayHints { path: path.into() });
+            }
+            if ops.len() != 1 {
+                eprintln!("{}", help::ANALYSIS_BENCH_HELP);
+                Err("Exactly one operation to benchmark must be set")?;
+            }
+            let op = ops.pop().unwrap();
+            let edit = match (edit_path, edit_text) {
+                (Some(path_line_col), Some(text)) => {
+                    Some(analysis_bench::Edit { position: parse_position(&path_line_col)?, text })
                 }
-            } else {
-                panic!("either --highlight or --complete must be set")
+                (None, None) => None,
+                _ => Err("--edit and --edit-text must be set together")?,
             };
             matches.finish().or_else(handle_extra_flags)?;
-            analysis_bench::run(verbose, path.as_ref(), op)?;
+            if let Some(spec) = profile_spec {
+                ra_prof::set_filter(ra_prof::Filter::from_spec(&spec));
+            }
+            analysis_bench::run(verbose, path.as_ref(), op, edit)?;
+        }
+        "rename" => {
+            if matches.contains(["-h", "--help"]) {
+                eprintln!("{}", help::RENAME_HELP);
+                return Ok(());
+            }
+            let dry_run = matches.contains("--dry-run");
+            let path: String = matches.opt_value_from_str("--path")?.unwrap_or_default();
+            let (location, new_name) = {
+                let mut trailing = matches.free()?;
+                if trailing.len() != 2 {
+                    eprintln!("{}", help::RENAME_HELP);
+                    Err("Invalid flags")?;
+                }
+                let new_name = trailing.pop().unwrap();
+                (trailing.pop().unwrap(), new_name)
+            };
+            let location = refactor::Location::parse(&location)?;
+            let refactoring = refactor::Refactoring::Rename { new_name };
+            refactor::run(path.as_ref(), &location, refactoring, dry_run)?;
+        }
+        "assist" => {
+            if matches.contains(["-h", "--help"]) {
+                eprintln!("{}", help::ASSIST_HELP);
+                return Ok(());
+            }
+            let dry_run = matches.contains("--dry-run");
+            let path: String = matches.opt_value_from_str("--path")?.unwrap_or_default();
+            let (id, location) = {
+                let mut trailing = matches.free()?;
+                if trailing.len() != 2 {
+                    eprintln!("{}", help::ASSIST_HELP);
+                    Err("Invalid flags")?;
+                }
+                let location = trailing.pop().unwrap();
+                (trailing.pop().unwrap(), location)
+            };
+            let location = refactor::Location::parse(&location)?;
+            let refactoring = refactor::Refactoring::Assist { id };
+            refactor::run(path.as_ref(), &location, refactoring, dry_run)?;
         }
         _ => eprintln!("{}", help::GLOBAL_HELP),
     }
@@ -172,7 +311,62 @@ fn read_stdin() -> Result<String> {
     Ok(buff)
 }
 
+fn comma_separated(s: Option<String>) -> Vec<String> {
+    match s {
+        None => Vec::new(),
+        Some(s) => s.split(',').map(|it| it.trim().to_string()).collect(),
+    }
+}
+
+fn parse_position(path_line_col: &str) -> Result<analysis_bench::Position> {
+    let (path_line, column) = rsplit_at_char(path_line_col, ':')?;
+    let (path, line) = rsplit_at_char(path_line, ':')?;
+    Ok(analysis_bench::Position { path: path.into(), line: line.parse()?, column: column.parse()? })
+}
+
+/// Converts a zero-based `line` and `column` given on the command line to an
+/// offset into `text`. The column counts characters (Unicode scalar values),
+/// not bytes or UTF-16 code units. Fails if the line or the column is out of
+/// range.
+fn line_col_offset(text: &str, line: u32, column: u32) -> Result<TextUnit> {
+    let n_lines = text.split('\n').count();
+    let line_text = text
+        .split('\n')
+        .nth(line as usize)
+        .ok_or_else(|| format!("line {} is out of range, the file has {} lines", line, n_lines))?;
+    let line_start: usize = text.split('\n').take(line as usize).map(|it| it.len() + 1).sum();
+    let col = line_text
+        .char_indices()
+        .map(|(idx, _)| idx)
+        .chain(std::iter::once(line_text.len()))
+        .nth(column as usize)
+        .ok_or_else(|| {
+            format!(
+                "column {} is out of range, line {} has {} characters",
+                column,
+                line,
+                line_text.chars().count()
+            )
+        })?;
+    Ok(TextUnit::from_usize(line_start + col))
+}
+
 fn rsplit_at_char(s: &str, c: char) -> Result<(&str, &str)> {
     let idx = s.rfind(':').ok_or_else(|| format!("no `{}` in {}", c, s))?;
     Ok((&s[..idx], &s[idx + 1..]))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn columns_count_characters() {
+        // The crab is one character, four bytes and two UTF-16 code units.
+        let text = "fn a() {}\nlet 🦀 = crab;";
+        assert_eq!(line_col_offset(text, 1, 4).unwrap(), 14.into());
+        assert_eq!(line_col_offset(text, 1, 5).unwrap(), 18.into());
+        assert_eq!(line_col_offset(text, 1, 8).unwrap(), 21.into());
+        assert!(line_col_offset(text, 1, 17).is_err());
+    }
+}
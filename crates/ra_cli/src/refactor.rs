@@ -0,0 +1,523 @@
+//! Applies refactorings (renames and assists) from the command line, for large
+//! mechanical migrations which are impractical to do in an editor.
+//!
+//! The resulting `SourceChange` is either written to disk, or, with
+//! `--dry-run`, printed as a unified diff.
+
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use ra_db::{SourceDatabaseExt, SourceRootId};
+use ra_ide::{AnalysisHost, FileId, FilePosition, FileRange, FileSystemEdit, SourceChange};
+use ra_syntax::TextRange;
+
+use crate::{line_col_offset, rsplit_at_char, Result};
+
+/// A location given on the command line: `PATH:LINE:COLUMN`, or
+/// `PATH:LINE:COLUMN-LINE:COLUMN` for a range. Lines and columns are
+/// zero-based, and columns count characters.
+pub(crate) struct Location {
+    path: PathBuf,
+    start: (u32, u32),
+    end: Option<(u32, u32)>,
+}
+
+impl Location {
+    pub(crate) fn parse(s: &str) -> Result<Location> {
+        let (rest, column) = rsplit_at_char(s, ':')?;
+        let (rest, line) = rsplit_at_char(rest, ':')?;
+        let (path, start, end) = match line.find('-') {
+            Some(idx) => {
+                let (path, start_line) = rsplit_at_char(rest, ':')?;
+                let start = line_col(start_line, &line[..idx])?;
+                let end = line_col(&line[idx + 1..], column)?;
+                (path, start, Some(end))
+            }
+            None => (rest, line_col(line, column)?, None),
+        };
+        Ok(Location { path: path.into(), start, end })
+    }
+}
+
+fn line_col(line: &str, column: &str) -> Result<(u32, u32)> {
+    Ok((line.parse()?, column.parse()?))
+}
+
+pub(crate) enum Refactoring {
+    Rename { new_name: String },
+    Assist { id: String },
+}
+
+pub(crate) fn run(
+    path: &Path,
+    location: &Location,
+    refactoring: Refactoring,
+    dry_run: bool,
+) -> Result<()> {
+    let (host, roots) = ra_batch::load_cargo(path)?;
+    let workspace = Workspace {
+        host: &host,
+        root_paths: roots
+            .iter()
+            .map(|(source_root_id, project_root)| (*source_root_id, project_root.path().clone()))
+            .collect(),
+    };
+    let analysis = host.analysis();
+
+    let file_id = workspace.file_id(&location.path)?;
+    let text = analysis.file_text(file_id)?;
+    let offset = |(line, column): (u32, u32)| {
+        line_col_offset(&text, line, column)
+            .map_err(|e| format!("{}:{}:{}: {}", location.path.display(), line, column, e))
+    };
+    let start = offset(location.start)?;
+    let end = match location.end {
+        Some(it) => offset(it)?,
+        None => start,
+    };
+    if end < start {
+        Err("the end of the range is before its start")?;
+    }
+
+    let change = match refactoring {
+        Refactoring::Rename { new_name } => {
+            let position = FilePosition { file_id, offset: start };
+            match analysis.rename(position, &new_name)? {
+                Some(it) => it.info,
+                None => Err("nothing to rename at the given location")?,
+            }
+        }
+        Refactoring::Assist { id } => {
+            let frange = FileRange { file_id, range: TextRange::from_to(start, end) };
+            let assists = analysis.assists(frange)?;
+            let available = assists.iter().map(|it| it.id.0).collect::<Vec<_>>().join(", ");
+            match assists.into_iter().find(|it| it.id.0 == id) {
+                Some(it) => it.change,
+                None => Err(format!(
+                    "assist `{}` is not applicable here, available assists: [{}]",
+                    id, available
+                ))?,
+            }
+        }
+    };
+
+    eprintln!("{}", change.label);
+    if dry_run {
+        print!("{}", workspace.diff(&change)?);
+    } else {
+        workspace.apply(&change)?;
+    }
+    Ok(())
+}
+
+struct Workspace<'a> {
+    host: &'a AnalysisHost,
+    root_paths: HashMap<SourceRootId, PathBuf>,
+}
+
+impl Workspace<'_> {
+    fn file_id(&self, path: &Path) -> Result<FileId> {
+        let db = self.host.raw_database();
+        let path = std::env::current_dir()?.join(path).canonicalize()?;
+        for (source_root_id, root_path) in self.root_paths.iter() {
+            for file_id in db.source_root(*source_root_id).walk() {
+                if db.file_relative_path(file_id).to_path(root_path) == path {
+                    return Ok(file_id);
+                }
+            }
+        }
+        Err(format!("Can't find {:?}", path).into())
+    }
+
+    fn file_path(&self, file_id: FileId) -> PathBuf {
+        let db = self.host.raw_database();
+        let root_path = &self.root_paths[&db.file_source_root(file_id)];
+        db.file_relative_path(file_id).to_path(root_path)
+    }
+
+    fn apply(&self, change: &SourceChange) -> Result<()> {
+        let analysis = self.host.analysis();
+        for edit in change.source_file_edits.iter() {
+            let text = analysis.file_text(edit.file_id)?;
+            let path = self.file_path(edit.file_id);
+            fs::write(&path, edit.edit.apply(&text))?;
+            eprintln!("modified {}", path.display());
+        }
+        // File system edits come last, as the text edits refer to the
+        // original file paths.
+        for edit in change.file_system_edits.iter() {
+            match edit {
+                FileSystemEdit::CreateFile { source_root, path } => {
+                    let path = path.to_path(&self.root_paths[source_root]);
+                    if let Some(dir) = path.parent() {
+                        fs::create_dir_all(dir)?;
+                    }
+                    fs::OpenOptions::new().write(true).create_new(true).open(&path)?;
+                    eprintln!("created {}", path.display());
+                }
+                FileSystemEdit::MoveFile { src, dst_source_root, dst_path } => {
+                    let src = self.file_path(*src);
+                    let dst = dst_path.to_path(&self.root_paths[dst_source_root]);
+                    if let Some(dir) = dst.parent() {
+                        fs::create_dir_all(dir)?;
+                    }
+                    fs::rename(&src, &dst)?;
+                    eprintln!("moved {} to {}", src.display(), dst.display());
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn diff(&self, change: &SourceChange) -> Result<String> {
+        let analysis = self.host.analysis();
+        let mut buf = String::new();
+        for edit in change.source_file_edits.iter() {
+            let text = analysis.file_text(edit.file_id)?;
+            let path = display_path(&self.file_path(edit.file_id));
+            let atoms = edit
+                .edit
+                .as_atoms()
+                .iter()
+                .map(|it| (it.delete, it.insert.as_str()))
+                .collect::<Vec<_>>();
+            buf.push_str(&format!("--- a/{}\n+++ b/{}\n", path, path));
+            buf.push_str(&unified_diff(&text, atoms));
+        }
+        for edit in change.file_system_edits.iter() {
+            match edit {
+                FileSystemEdit::CreateFile { source_root, path } => {
+                    let path = display_path(&path.to_path(&self.root_paths[source_root]));
+                    buf.push_str(&format!("new file {}\n", path));
+                }
+                FileSystemEdit::MoveFile { src, dst_source_root, dst_path } => {
+                    let src = display_path(&self.file_path(*src));
+                    let dst = display_path(&dst_path.to_path(&self.root_paths[dst_source_root]));
+                    buf.push_str(&format!("rename from {}\nrename to {}\n", src, dst));
+                }
+            }
+        }
+        Ok(buf)
+    }
+}
+
+/// Shows paths relative to the current directory, when possible.
+fn display_path(path: &Path) -> String {
+    let cwd = std::env::current_dir().unwrap_or_default();
+    path.strip_prefix(&cwd).unwrap_or(path).display().to_string()
+}
+
+const CONTEXT_LINES: usize = 3;
+
+/// Produces the hunks of a unified diff between `text` and `text` with the
+/// (non-overlapping) `atoms` applied.
+fn unified_diff(text: &str, mut atoms: Vec<(TextRange, &str)>) -> String {
+    atoms.sort_by_key(|(range, _)| range.start());
+    let mut line_starts = Vec::new();
+    let mut offset = 0;
+    for line in split_lines(text) {
+        line_starts.push(offset);
+        offset += line.len();
+    }
+    // The start of the line, or the end of the text past the last line.
+    let line_start = |line: usize| line_starts.get(line).copied().unwrap_or_else(|| text.len());
+    let line_of = |offset: usize| match line_starts.binary_search(&offset) {
+        Ok(idx) => idx,
+        Err(idx) => idx.saturating_sub(1),
+    };
+    // The last line changed by replacing `range` with `insert`. Insertions
+    // change the line they are on, and deleting a line break also changes the
+    // line which gets joined to it.
+    let last_line_of = |range: TextRange, insert: &str| {
+        let (start, end) = (range.start().to_usize(), range.end().to_usize());
+        let line = line_of(end.max(start + 1) - 1);
+        let joins_next_line = end > start
+            && line_starts.binary_search(&end).is_ok()
+            && !insert.ends_with('\n')
+            && !(insert.is_empty() && line_starts.binary_search(&start).is_ok());
+        if joins_next_line {
+            line + 1
+        } else {
+            line
+        }
+    };
+    let last_line = line_starts.len().saturating_sub(1);
+
+    // Groups atoms, whose changed lines (with context) overlap, into hunks of
+    // `(first line, last line, atoms)`.
+    let mut hunks: Vec<(usize, usize, Vec<(TextRange, &str)>)> = Vec::new();
+    for (range, insert) in atoms {
+        let first = line_of(range.start().to_usize()).saturating_sub(CONTEXT_LINES);
+        let last = (last_line_of(range, insert) + CONTEXT_LINES).min(last_line);
+        match hunks.last_mut() {
+            Some(hunk) if first <= hunk.1 + 1 => {
+                hunk.1 = hunk.1.max(last);
+                hunk.2.push((range, insert));
+            }
+            _ => hunks.push((first, last, vec![(range, insert)])),
+        }
+    }
+
+    let mut buf = String::new();
+    let mut line_delta = 0isize;
+    for (first, last, atoms) in hunks {
+        let mut body = String::new();
+        let (mut old_len, mut new_len) = (0, 0);
+        let mut next_line = first;
+        let mut atoms = atoms.into_iter().peekable();
+        while let Some(atom) = atoms.next() {
+            // Atoms touching the same lines are shown as a single change.
+            let change_first = line_of(atom.0.start().to_usize());
+            let mut change_last = last_line_of(atom.0, atom.1);
+            let mut change = vec![atom];
+            while let Some(&(range, insert)) = atoms.peek() {
+                if line_of(range.start().to_usize()) > change_last {
+                    break;
+                }
+                change_last = change_last.max(last_line_of(range, insert));
+                change.extend(atoms.next());
+            }
+
+            for line in split_lines(&text[line_start(next_line)..line_start(change_first)]) {
+                push_line(&mut body, ' ', line);
+                old_len += 1;
+                new_len += 1;
+            }
+
+            let (start, end) = (line_start(change_first), line_start(change_last + 1));
+            let mut new = String::new();
+            let mut prev = start;
+            for (range, insert) in change {
+                new.push_str(&text[prev..range.start().to_usize()]);
+                new.push_str(insert);
+                prev = range.end().to_usize();
+            }
+            new.push_str(&text[prev..end]);
+
+            let old_lines = split_lines(&text[start..end]);
+            let new_lines = split_lines(&new);
+            let prefix =
+                old_lines.iter().zip(new_lines.iter()).take_while(|(old, new)| old == new).count();
+            let suffix = old_lines[prefix..]
+                .iter()
+                .rev()
+                .zip(new_lines[prefix..].iter().rev())
+                .take_while(|(old, new)| old == new)
+                .count();
+            for line in &old_lines[..prefix] {
+                push_line(&mut body, ' ', line);
+            }
+            for line in &old_lines[prefix..old_lines.len() - suffix] {
+                push_line(&mut body, '-', line);
+            }
+            for line in &new_lines[prefix..new_lines.len() - suffix] {
+                push_line(&mut body, '+', line);
+            }
+            for line in &old_lines[old_lines.len() - suffix..] {
+                push_line(&mut body, ' ', line);
+            }
+            old_len += old_lines.len();
+            new_len += new_lines.len();
+            next_line = change_last + 1;
+        }
+        for line in split_lines(&text[line_start(next_line)..line_start(last + 1)]) {
+            push_line(&mut body, ' ', line);
+            old_len += 1;
+            new_len += 1;
+        }
+
+        let new_first = (first as isize + line_delta) as usize;
+        buf.push_str(&format!(
+            "@@ -{} +{} @@\n",
+            hunk_range(first, old_len),
+            hunk_range(new_first, new_len)
+        ));
+        buf.push_str(&body);
+        line_delta += new_len as isize - old_len as isize;
+    }
+    buf
+}
+
+/// Formats the one-based line range of a hunk. Empty ranges refer to the line
+/// before them, as in `diff -u`.
+fn hunk_range(first: usize, len: usize) -> String {
+    if len == 0 {
+        format!("{},0", first)
+    } else {
+        format!("{},{}", first + 1, len)
+    }
+}
+
+/// Splits `text` into lines, keeping the line terminators.
+fn split_lines(text: &str) -> Vec<&str> {
+    let mut lines = Vec::new();
+    let mut start = 0;
+    for (idx, _) in text.match_indices('\n') {
+        lines.push(&text[start..idx + 1]);
+        start = idx + 1;
+    }
+    if start < text.len() {
+        lines.push(&text[start..]);
+    }
+    lines
+}
+
+fn push_line(buf: &mut String, prefix: char, line: &str) {
+    buf.push(prefix);
+    buf.push_str(line);
+    if !line.ends_with('\n') {
+        buf.push_str("\n\\ No newline at end of file\n");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn check_diff(text: &str, atoms: &[(u32, u32, &str)], expected: &str) {
+        let atoms = atoms
+            .iter()
+            .map(|&(start, end, insert)| (TextRange::from_to(start.into(), end.into()), insert))
+            .collect();
+        assert_eq!(unified_diff(text, atoms), expected.trim_start());
+    }
+
+    const TEXT: &str = "fn a() {}\nfn b() {}\nfn c() {}\nfn d() {}\n";
+
+    #[test]
+    fn diff_merges_atoms_into_one_hunk() {
+        check_diff(
+            TEXT,
+            &[(33, 34, "y"), (3, 4, "x")],
+            r"
+@@ -1,4 +1,4 @@
+-fn a() {}
++fn x() {}
+ fn b() {}
+ fn c() {}
+-fn d() {}
++fn y() {}
+",
+        );
+    }
+
+    #[test]
+    fn diff_splits_distant_atoms_into_hunks() {
+        let text: String = (0..20).map(|it| format!("{}\n", it)).collect();
+        check_diff(
+            &text,
+            &[(0, 1, "x"), (35, 35, "new\n")],
+            r"
+@@ -1,4 +1,4 @@
+-0
++x
+ 1
+ 2
+ 3
+@@ -13,7 +13,8 @@
+ 12
+ 13
+ 14
++new
+ 15
+ 16
+ 17
+ 18
+",
+        );
+    }
+
+    #[test]
+    fn diff_insertion_at_eof() {
+        check_diff(
+            TEXT,
+            &[(40, 40, "fn e() {}\n")],
+            r"
+@@ -1,4 +1,5 @@
+ fn a() {}
+ fn b() {}
+ fn c() {}
+ fn d() {}
++fn e() {}
+",
+        );
+        check_diff(
+            "",
+            &[(0, 0, "fn a() {}\n")],
+            r"
+@@ -0,0 +1,1 @@
++fn a() {}
+",
+        );
+    }
+
+    #[test]
+    fn diff_without_trailing_newline() {
+        check_diff(
+            "a\nb",
+            &[(2, 3, "c")],
+            r"
+@@ -1,2 +1,2 @@
+ a
+-b
+\ No newline at end of file
++c
+\ No newline at end of file
+",
+        );
+        check_diff(
+            "a\nb",
+            &[(3, 3, "\n")],
+            r"
+@@ -1,2 +1,2 @@
+ a
+-b
+\ No newline at end of file
++b
+",
+        );
+    }
+
+    #[test]
+    fn diff_joined_lines() {
+        check_diff(
+            TEXT,
+            &[(9, 10, "")],
+            r"
+@@ -1,4 +1,3 @@
+-fn a() {}
+-fn b() {}
++fn a() {}fn b() {}
+ fn c() {}
+ fn d() {}
+",
+        );
+    }
+
+    #[test]
+    fn parse_location() {
+        let location = Location::parse("src/lib.rs:1:2").unwrap();
+        assert_eq!(location.path, Path::new("src/lib.rs"));
+        assert_eq!(location.start, (1, 2));
+        assert_eq!(location.end, None);
+
+        let location = Location::parse("src/lib.rs:1:2-3:4").unwrap();
+        assert_eq!(location.path, Path::new("src/lib.rs"));
+        assert_eq!(location.start, (1, 2));
+        assert_eq!(location.end, Some((3, 4)));
+
+        assert!(Location::parse("src/lib.rs:1").is_err());
+        assert!(Location::parse("src/lib.rs:1:2-3").is_err());
+    }
+
+    #[test]
+    fn location_out_of_range() {
+        let offset = |line, column| line_col_offset("fn a() {}\nfn b", line, column);
+        assert_eq!(offset(1, 4).unwrap(), 14.into());
+        assert!(offset(1, 5).is_err());
+        assert!(offset(2, 0).is_err());
+    }
+}
@@ -58,6 +58,8 @@ fn with_single_file(db: &mut dyn SourceDatabaseExt, text: &str) -> FileId {
         Edition::Edition2018,
         CfgOptions::default(),
         Env::default(),
+        Default::default(),
+        Vec::new(),
     );
 
     db.set_file_text(file_id, Arc::new(text.to_string()));
@@ -98,8 +100,14 @@ fn with_files(db: &mut dyn SourceDatabaseExt, fixture: &str) -> Option<FilePosit
         assert!(meta.path.starts_with(&source_root_prefix));
 
         if let Some(krate) = meta.krate {
-            let crate_id =
-                crate_graph.add_crate_root(file_id, meta.edition, meta.cfg, Env::default());
+            let crate_id = crate_graph.add_crate_root(
+                file_id,
+                meta.edition,
+                meta.cfg,
+                meta.env,
+                Default::default(),
+                Vec::new(),
+            );
             let prev = crates.insert(krate.clone(), crate_id);
             assert!(prev.is_none());
             for dep in meta.deps {
@@ -134,6 +142,8 @@ fn with_files(db: &mut dyn SourceDatabaseExt, fixture: &str) -> Option<FilePosit
             Edition::Edition2018,
             CfgOptions::default(),
             Env::default(),
+            Default::default(),
+            Vec::new(),
         );
     } else {
         for (from, to) in crate_deps {
@@ -160,9 +170,10 @@ struct FileMeta {
     deps: Vec<String>,
     cfg: CfgOptions,
     edition: Edition,
+    env: Env,
 }
 
-//- /lib.rs crate:foo deps:bar,baz
+//- /lib.rs crate:foo deps:bar,baz cfg:foo=a,bar=b env:OUTDIR=path/to,OTHER=foo
 fn parse_meta(meta: &str) -> ParsedMeta {
     let components = meta.split_ascii_whitespace().collect::<Vec<_>>();
 
@@ -179,6 +190,7 @@ fn parse_meta(meta: &str) -> ParsedMeta {
     let mut deps = Vec::new();
     let mut edition = Edition::Edition2018;
     let mut cfg = CfgOptions::default();
+    let mut env = Env::default();
     for component in components[1..].iter() {
         let (key, value) = split1(component, ':').unwrap();
         match key {
@@ -193,11 +205,18 @@ fn parse_meta(meta: &str) -> ParsedMeta {
                     }
                 }
             }
+            "env" => {
+                for key in value.split(',') {
+                    if let Some((k, v)) = split1(key, '=') {
+                        env.set(k, v.into());
+                    }
+                }
+            }
             _ => panic!("bad component: {:?}", component),
         }
     }
 
-    ParsedMeta::File(FileMeta { path, krate, deps, edition, cfg })
+    ParsedMeta::File(FileMeta { path, krate, deps, edition, cfg, env })
 }
 
 fn split1(haystack: &str, delim: char) -> Option<(&str, &str)> {
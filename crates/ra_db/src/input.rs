@@ -6,12 +6,13 @@
 //! actual IO. See `vfs` and `project_model` in the `ra_lsp_server` crate for how
 //! actual IO is done and lowered to input.
 
-use std::{fmt, str::FromStr};
+use std::{fmt, path::PathBuf, str::FromStr, sync::Arc, time::SystemTime};
 
 use ra_cfg::CfgOptions;
 use ra_syntax::SmolStr;
 use rustc_hash::FxHashMap;
 use rustc_hash::FxHashSet;
+use tt::TokenExpander;
 
 use crate::{RelativePath, RelativePathBuf};
 
@@ -89,7 +90,9 @@ struct CrateData {
     edition: Edition,
     cfg_options: CfgOptions,
     env: Env,
+    extern_source: ExternSource,
     dependencies: Vec<Dependency>,
+    proc_macros: Vec<ProcMacro>,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
@@ -103,6 +106,59 @@ pub struct Env {
     entries: FxHashMap<String, String>,
 }
 
+/// Identifies a source root outside of the crate's own files, like the
+/// `OUT_DIR` of a build script. It is the same number as the `SourceRootId`
+/// of that root.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ExternSourceId(pub u32);
+
+/// Absolute paths which a crate can refer to via `include!` and friends,
+/// mapped to the source roots containing their files.
+#[derive(Default, Debug, Clone, PartialEq, Eq)]
+pub struct ExternSource {
+    extern_paths: FxHashMap<String, ExternSourceId>,
+}
+
+/// Index of a procedural macro in `CrateGraph::proc_macros` of its crate.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ProcMacroId(pub u32);
+
+/// How a procedural macro is invoked.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ProcMacroKind {
+    /// `#[derive(Name)]`. `helpers` are the inert attributes, like
+    /// `#[serde(...)]`, which the derive accepts on the item.
+    CustomDerive { helpers: Vec<SmolStr> },
+    /// `name!(...)`.
+    FuncLike,
+    /// `#[name(...)]`.
+    Attr,
+}
+
+/// A procedural macro exported by a `proc-macro` crate, expanded by `expander`.
+#[derive(Debug, Clone)]
+pub struct ProcMacro {
+    pub name: SmolStr,
+    pub kind: ProcMacroKind,
+    /// The compiled `proc-macro` crate, which exports the macro.
+    pub dylib_path: PathBuf,
+    /// When the dylib was built. Cargo rebuilds a `proc-macro` crate into the
+    /// same path, so this tells the builds apart.
+    pub dylib_modified: Option<SystemTime>,
+    pub expander: Arc<dyn TokenExpander>,
+}
+
+// Reloading the project creates new expanders for the same macros, so they
+// are identified by where they come from instead.
+impl Eq for ProcMacro {}
+impl PartialEq for ProcMacro {
+    fn eq(&self, other: &ProcMacro) -> bool {
+        self.dylib_path == other.dylib_path
+            && self.dylib_modified == other.dylib_modified
+            && self.name == other.name
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Dependency {
     pub crate_id: CrateId,
@@ -116,8 +172,10 @@ impl CrateGraph {
         edition: Edition,
         cfg_options: CfgOptions,
         env: Env,
+        extern_source: ExternSource,
+        proc_macros: Vec<ProcMacro>,
     ) -> CrateId {
-        let data = CrateData::new(file_id, edition, cfg_options, env);
+        let data = CrateData::new(file_id, edition, cfg_options, env, extern_source, proc_macros);
         let crate_id = CrateId(self.arena.len() as u32);
         let prev = self.arena.insert(crate_id, data);
         assert!(prev.is_none());
@@ -128,6 +186,18 @@ impl CrateGraph {
         &self.arena[&crate_id].cfg_options
     }
 
+    pub fn env(&self, crate_id: CrateId) -> &Env {
+        &self.arena[&crate_id].env
+    }
+
+    pub fn extern_source(&self, crate_id: CrateId) -> &ExternSource {
+        &self.arena[&crate_id].extern_source
+    }
+
+    pub fn proc_macros(&self, crate_id: CrateId) -> &[ProcMacro] {
+        &self.arena[&crate_id].proc_macros
+    }
+
     pub fn add_dep(
         &mut self,
         from: CrateId,
@@ -213,8 +283,23 @@ impl CrateId {
 }
 
 impl CrateData {
-    fn new(file_id: FileId, edition: Edition, cfg_options: CfgOptions, env: Env) -> CrateData {
-        CrateData { file_id, edition, dependencies: Vec::new(), cfg_options, env }
+    fn new(
+        file_id: FileId,
+        edition: Edition,
+        cfg_options: CfgOptions,
+        env: Env,
+        extern_source: ExternSource,
+        proc_macros: Vec<ProcMacro>,
+    ) -> CrateData {
+        CrateData {
+            file_id,
+            edition,
+            dependencies: Vec::new(),
+            cfg_options,
+            env,
+            extern_source,
+            proc_macros,
+        }
     }
 
     fn add_dep(&mut self, name: SmolStr, crate_id: CrateId) {
@@ -244,6 +329,41 @@ impl fmt::Display for Edition {
     }
 }
 
+impl Env {
+    pub fn set(&mut self, env: &str, value: String) {
+        self.entries.insert(env.to_owned(), value);
+    }
+
+    pub fn get(&self, env: &str) -> Option<String> {
+        self.entries.get(env).cloned()
+    }
+}
+
+impl ExternSource {
+    /// Splits an absolute `path` into the extern source root containing it and
+    /// the path relative to that root.
+    pub fn extern_path(&self, path: &str) -> Option<(ExternSourceId, RelativePathBuf)> {
+        self.extern_paths.iter().find_map(|(root_path, id)| {
+            if !path.starts_with(root_path.as_str()) {
+                return None;
+            }
+            let rel_path = &path[root_path.len()..];
+            let is_separator = |c| c == '/' || c == '\\';
+            if !rel_path.is_empty() && !rel_path.starts_with(is_separator) {
+                // `path` is in a sibling directory with a longer name
+                return None;
+            }
+            let rel_path =
+                RelativePathBuf::from_path(rel_path.trim_start_matches(is_separator)).ok()?;
+            Some((*id, rel_path))
+        })
+    }
+
+    pub fn set_extern_path(&mut self, root_path: &str, root: ExternSourceId) {
+        self.extern_paths.insert(root_path.to_owned(), root);
+    }
+}
+
 impl Dependency {
     pub fn crate_id(&self) -> CrateId {
         self.crate_id
@@ -268,17 +388,38 @@ pub struct CyclicDependenciesError;
 
 #[cfg(test)]
 mod tests {
-    use super::{CfgOptions, CrateGraph, Edition::Edition2018, Env, FileId, SmolStr};
+    use super::{
+        CfgOptions, CrateGraph, Edition::Edition2018, Env, ExternSource, ExternSourceId, FileId,
+        SmolStr,
+    };
 
     #[test]
     fn it_should_panic_because_of_cycle_dependencies() {
         let mut graph = CrateGraph::default();
-        let crate1 =
-            graph.add_crate_root(FileId(1u32), Edition2018, CfgOptions::default(), Env::default());
-        let crate2 =
-            graph.add_crate_root(FileId(2u32), Edition2018, CfgOptions::default(), Env::default());
-        let crate3 =
-            graph.add_crate_root(FileId(3u32), Edition2018, CfgOptions::default(), Env::default());
+        let crate1 = graph.add_crate_root(
+            FileId(1u32),
+            Edition2018,
+            CfgOptions::default(),
+            Env::default(),
+            ExternSource::default(),
+            Vec::new(),
+        );
+        let crate2 = graph.add_crate_root(
+            FileId(2u32),
+            Edition2018,
+            CfgOptions::default(),
+            Env::default(),
+            ExternSource::default(),
+            Vec::new(),
+        );
+        let crate3 = graph.add_crate_root(
+            FileId(3u32),
+            Edition2018,
+            CfgOptions::default(),
+            Env::default(),
+            ExternSource::default(),
+            Vec::new(),
+        );
         assert!(graph.add_dep(crate1, SmolStr::new("crate2"), crate2).is_ok());
         assert!(graph.add_dep(crate2, SmolStr::new("crate3"), crate3).is_ok());
         assert!(graph.add_dep(crate3, SmolStr::new("crate1"), crate1).is_err());
@@ -287,13 +428,45 @@ mod tests {
     #[test]
     fn it_works() {
         let mut graph = CrateGraph::default();
-        let crate1 =
-            graph.add_crate_root(FileId(1u32), Edition2018, CfgOptions::default(), Env::default());
-        let crate2 =
-            graph.add_crate_root(FileId(2u32), Edition2018, CfgOptions::default(), Env::default());
-        let crate3 =
-            graph.add_crate_root(FileId(3u32), Edition2018, CfgOptions::default(), Env::default());
+        let crate1 = graph.add_crate_root(
+            FileId(1u32),
+            Edition2018,
+            CfgOptions::default(),
+            Env::default(),
+            ExternSource::default(),
+            Vec::new(),
+        );
+        let crate2 = graph.add_crate_root(
+            FileId(2u32),
+            Edition2018,
+            CfgOptions::default(),
+            Env::default(),
+            ExternSource::default(),
+            Vec::new(),
+        );
+        let crate3 = graph.add_crate_root(
+            FileId(3u32),
+            Edition2018,
+            CfgOptions::default(),
+            Env::default(),
+            ExternSource::default(),
+            Vec::new(),
+        );
         assert!(graph.add_dep(crate1, SmolStr::new("crate2"), crate2).is_ok());
         assert!(graph.add_dep(crate2, SmolStr::new("crate3"), crate3).is_ok());
     }
+
+    #[test]
+    fn extern_path_is_relative_to_extern_root() {
+        let mut extern_source = ExternSource::default();
+        extern_source.set_extern_path("/target/debug/build/foo/out", ExternSourceId(92));
+
+        let (id, path) =
+            extern_source.extern_path("/target/debug/build/foo/out/gen/bindings.rs").unwrap();
+        assert_eq!(id, ExternSourceId(92));
+        assert_eq!(path.as_str(), "gen/bindings.rs");
+
+        assert!(extern_source.extern_path("/target/debug/build/foo/out2/bindings.rs").is_none());
+        assert!(extern_source.extern_path("/src/lib.rs").is_none());
+    }
 }
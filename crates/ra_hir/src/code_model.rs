@@ -11,6 +11,7 @@ use hir_def::{
     per_ns::PerNs,
     resolver::HasResolver,
     type_ref::{Mutability, TypeRef},
+    visibility::Visibility,
     AdtId, AstItemDef, ConstId, ContainerId, DefWithBodyId, EnumId, FunctionId, HasModule, ImplId,
     LocalEnumVariantId, LocalImportId, LocalModuleId, LocalStructFieldId, Lookup, ModuleId,
     StaticId, StructId, TraitId, TypeAliasId, TypeParamId, UnionId,
@@ -21,8 +22,8 @@ use hir_expand::{
     MacroDefId,
 };
 use hir_ty::{
-    autoderef, display::HirFormatter, expr::ExprValidator, ApplicationTy, Canonical, InEnvironment,
-    TraitEnvironment, Ty, TyDefId, TypeCtor, TypeWalk,
+    autoderef, display::HirFormatter, expr::ExprValidator, ApplicationTy, Canonical, CaptureMode,
+    InEnvironment, TraitEnvironment, Ty, TyDefId, TypeCtor, TypeWalk,
 };
 use ra_db::{CrateId, Edition, FileId};
 use ra_syntax::ast;
@@ -122,12 +123,12 @@ pub use hir_def::attr::Attrs;
 
 impl Module {
     pub(crate) fn new(krate: Crate, crate_module_id: LocalModuleId) -> Module {
-        Module { id: ModuleId { krate: krate.id, local_id: crate_module_id } }
+        Module { id: ModuleId { krate: krate.id, block: None, local_id: crate_module_id } }
     }
 
     /// Name of this module.
     pub fn name(self, db: &impl DefDatabase) -> Option<Name> {
-        let def_map = db.crate_def_map(self.id.krate);
+        let def_map = self.id.def_map(db);
         let parent = def_map[self.id.local_id].parent?;
         def_map[parent].children.iter().find_map(|(name, module_id)| {
             if *module_id == self.id.local_id {
@@ -148,12 +149,12 @@ impl Module {
     /// in the module tree of any target in `Cargo.toml`.
     pub fn crate_root(self, db: &impl DefDatabase) -> Module {
         let def_map = db.crate_def_map(self.id.krate);
-        self.with_module_id(def_map.root)
+        Module::new(self.krate(), def_map.root)
     }
 
     /// Iterates over all child modules.
     pub fn children(self, db: &impl DefDatabase) -> impl Iterator<Item = Module> {
-        let def_map = db.crate_def_map(self.id.krate);
+        let def_map = self.id.def_map(db);
         let children = def_map[self.id.local_id]
             .children
             .iter()
@@ -164,9 +165,7 @@ impl Module {
 
     /// Finds a parent module.
     pub fn parent(self, db: &impl DefDatabase) -> Option<Module> {
-        let def_map = db.crate_def_map(self.id.krate);
-        let parent_id = def_map[self.id.local_id].parent?;
-        Some(self.with_module_id(parent_id))
+        Some(Module { id: self.id.parent(db)? })
     }
 
     pub fn path_to_root(self, db: &impl HirDatabase) -> Vec<Module> {
@@ -180,18 +179,32 @@ impl Module {
     }
 
     /// Returns a `ModuleScope`: a set of items, visible in this module.
-    pub fn scope(self, db: &impl HirDatabase) -> Vec<(Name, ScopeDef, Option<Import>)> {
-        db.crate_def_map(self.id.krate)[self.id.local_id]
+    ///
+    /// If `visible_from` is given, only items visible from that module are
+    /// returned.
+    pub fn scope(
+        self,
+        db: &impl HirDatabase,
+        visible_from: Option<Module>,
+    ) -> Vec<(Name, ScopeDef, Option<Import>)> {
+        self.id.def_map(db)[self.id.local_id]
             .scope
             .entries()
-            .map(|(name, res)| {
-                (name.clone(), res.def.into(), res.import.map(|id| Import { parent: self, id }))
+            .filter_map(|(name, res)| {
+                let def = match visible_from {
+                    Some(from) => res.def.filter_visibility(|vis| vis.is_visible_from(db, from.id)),
+                    None => res.def,
+                };
+                if def.is_none() {
+                    return None;
+                }
+                Some((name.clone(), def.into(), res.import.map(|id| Import { parent: self, id })))
             })
             .collect()
     }
 
     pub fn diagnostics(self, db: &impl HirDatabase, sink: &mut DiagnosticSink) {
-        db.crate_def_map(self.id.krate).add_diagnostics(db, self.id.local_id, sink);
+        self.id.def_map(db).add_diagnostics(db, self.id.local_id, sink);
         for decl in self.declarations(db) {
             match decl {
                 crate::ModuleDef::Function(f) => f.diagnostics(db, sink),
@@ -215,17 +228,17 @@ impl Module {
     }
 
     pub fn declarations(self, db: &impl DefDatabase) -> Vec<ModuleDef> {
-        let def_map = db.crate_def_map(self.id.krate);
+        let def_map = self.id.def_map(db);
         def_map[self.id.local_id].scope.declarations().map(ModuleDef::from).collect()
     }
 
     pub fn impl_blocks(self, db: &impl DefDatabase) -> Vec<ImplBlock> {
-        let def_map = db.crate_def_map(self.id.krate);
+        let def_map = self.id.def_map(db);
         def_map[self.id.local_id].impls.iter().copied().map(ImplBlock::from).collect()
     }
 
     pub(crate) fn with_module_id(self, module_id: LocalModuleId) -> Module {
-        Module::new(self.krate(), module_id)
+        Module { id: ModuleId { local_id: module_id, ..self.id } }
     }
 }
 
@@ -269,7 +282,7 @@ pub struct Struct {
 
 impl Struct {
     pub fn module(self, db: &impl DefDatabase) -> Module {
-        Module { id: self.id.module(db) }
+        Module { id: self.id.module(db).containing_module(db) }
     }
 
     pub fn krate(self, db: &impl DefDatabase) -> Option<Crate> {
@@ -309,7 +322,7 @@ impl Union {
     }
 
     pub fn module(self, db: &impl DefDatabase) -> Module {
-        Module { id: self.id.module(db) }
+        Module { id: self.id.module(db).containing_module(db) }
     }
 
     pub fn ty(self, db: &impl HirDatabase) -> Type {
@@ -337,7 +350,7 @@ pub struct Enum {
 
 impl Enum {
     pub fn module(self, db: &impl DefDatabase) -> Module {
-        Module { id: self.id.module(db) }
+        Module { id: self.id.module(db).containing_module(db) }
     }
 
     pub fn krate(self, db: &impl DefDatabase) -> Option<Crate> {
@@ -485,7 +498,7 @@ pub struct Function {
 
 impl Function {
     pub fn module(self, db: &impl DefDatabase) -> Module {
-        self.id.lookup(db).module(db).into()
+        self.id.lookup(db).module(db).containing_module(db).into()
     }
 
     pub fn krate(self, db: &impl DefDatabase) -> Option<Crate> {
@@ -519,7 +532,7 @@ pub struct Const {
 
 impl Const {
     pub fn module(self, db: &impl DefDatabase) -> Module {
-        Module { id: self.id.lookup(db).module(db) }
+        Module { id: self.id.lookup(db).module(db).containing_module(db) }
     }
 
     pub fn krate(self, db: &impl DefDatabase) -> Option<Crate> {
@@ -562,7 +575,7 @@ pub struct Static {
 
 impl Static {
     pub fn module(self, db: &impl DefDatabase) -> Module {
-        Module { id: self.id.lookup(db).module(db) }
+        Module { id: self.id.lookup(db).module(db).containing_module(db) }
     }
 
     pub fn krate(self, db: &impl DefDatabase) -> Option<Crate> {
@@ -577,7 +590,7 @@ pub struct Trait {
 
 impl Trait {
     pub fn module(self, db: &impl DefDatabase) -> Module {
-        Module { id: self.id.module(db) }
+        Module { id: self.id.module(db).containing_module(db) }
     }
 
     pub fn name(self, db: &impl DefDatabase) -> Name {
@@ -605,7 +618,7 @@ impl TypeAlias {
     }
 
     pub fn module(self, db: &impl DefDatabase) -> Module {
-        Module { id: self.id.lookup(db).module(db) }
+        Module { id: self.id.lookup(db).module(db).containing_module(db) }
     }
 
     pub fn krate(self, db: &impl DefDatabase) -> Option<Crate> {
@@ -765,6 +778,29 @@ impl Local {
     }
 }
 
+/// A place that a closure captures from its environment: a local variable,
+/// possibly projected through some fields.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ClosureCapture {
+    pub(crate) local: Local,
+    pub(crate) fields: Vec<Name>,
+    pub(crate) mode: CaptureMode,
+}
+
+impl ClosureCapture {
+    pub fn local(&self) -> Local {
+        self.local
+    }
+
+    pub fn fields(&self) -> &[Name] {
+        &self.fields
+    }
+
+    pub fn mode(&self) -> CaptureMode {
+        self.mode
+    }
+}
+
 #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
 pub struct TypeParam {
     pub(crate) id: TypeParamId,
@@ -777,7 +813,7 @@ impl TypeParam {
     }
 
     pub fn module(self, db: &impl HirDatabase) -> Module {
-        self.id.parent.module(db).into()
+        self.id.parent.module(db).containing_module(db).into()
     }
 }
 
@@ -821,7 +857,7 @@ impl ImplBlock {
     }
 
     pub fn module(&self, db: &impl DefDatabase) -> Module {
-        self.id.module(db).into()
+        self.id.module(db).containing_module(db).into()
     }
 
     pub fn krate(&self, db: &impl DefDatabase) -> Crate {
@@ -1090,3 +1126,54 @@ impl<T: Into<AttrDef> + Copy> Docs for T {
         db.documentation(def.into())
     }
 }
+
+pub trait HasVisibility {
+    fn visibility(&self, db: &impl HirDatabase) -> Visibility;
+    fn is_visible_from(&self, db: &impl HirDatabase, module: Module) -> bool {
+        let vis = self.visibility(db);
+        vis.is_visible_from(db, module.id)
+    }
+}
+
+impl HasVisibility for StructField {
+    fn visibility(&self, db: &impl HirDatabase) -> Visibility {
+        let variant_data = self.parent.variant_data(db);
+        let visibility = &variant_data.fields()[self.id].visibility;
+        let parent_id: hir_def::VariantId = self.parent.into();
+        visibility.resolve(db, &parent_id.resolver(db))
+    }
+}
+
+impl HasVisibility for Function {
+    fn visibility(&self, db: &impl HirDatabase) -> Visibility {
+        db.function_data(self.id).visibility.resolve(db, &self.id.resolver(db))
+    }
+}
+
+impl HasVisibility for Const {
+    fn visibility(&self, db: &impl HirDatabase) -> Visibility {
+        db.const_data(self.id).visibility.resolve(db, &self.id.resolver(db))
+    }
+}
+
+impl HasVisibility for Static {
+    fn visibility(&self, db: &impl HirDatabase) -> Visibility {
+        db.static_data(self.id).visibility.resolve(db, &self.id.resolver(db))
+    }
+}
+
+impl HasVisibility for TypeAlias {
+    fn visibility(&self, db: &impl HirDatabase) -> Visibility {
+        db.type_alias_data(self.id).visibility.resolve(db, &self.id.resolver(db))
+    }
+}
+
+impl HasVisibility for AssocItem {
+    fn visibility(&self, db: &impl HirDatabase) -> Visibility {
+        match self {
+            AssocItem::Function(it) => it.visibility(db),
+            AssocItem::Const(it) => it.visibility(db),
+            AssocItem::TypeAlias(it) => it.visibility(db),
+        }
+    }
+}
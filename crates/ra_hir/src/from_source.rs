@@ -1,7 +1,8 @@
 //! FIXME: write short doc here
+use either::Either;
 use hir_def::{
     child_by_source::ChildBySource, dyn_map::DynMap, keys, nameres::ModuleSource, AstItemDef,
-    EnumVariantId, GenericDefId, LocationCtx, ModuleId, VariantId,
+    BlockLoc, EnumVariantId, GenericDefId, LocationCtx, ModuleId, VariantId,
 };
 use hir_expand::{name::AsName, AstId, MacroDefId, MacroDefKind};
 use ra_syntax::{
@@ -89,15 +90,20 @@ impl FromSource for TypeAlias {
 }
 
 impl FromSource for MacroDef {
-    type Ast = ast::MacroCall;
+    type Ast = Either<ast::MacroCall, ast::MacroDef>;
     fn from_source(db: &(impl DefDatabase + AstDatabase), src: InFile<Self::Ast>) -> Option<Self> {
         let kind = MacroDefKind::Declarative;
 
-        let module_src = ModuleSource::from_child_node(db, src.as_ref().map(|it| it.syntax()));
+        let syntax = src.as_ref().map(|it| it.as_ref().either(|it| it.syntax(), |it| it.syntax()));
+        let module_src = ModuleSource::from_child_node(db, syntax);
         let module = Module::from_definition(db, InFile::new(src.file_id, module_src))?;
         let krate = Some(module.krate().id);
 
-        let ast_id = Some(AstId::new(src.file_id, db.ast_id_map(src.file_id).ast_id(&src.value)));
+        let ast_id_map = db.ast_id_map(src.file_id);
+        let ast_id = Some(match &src.value {
+            Either::Left(it) => Either::Left(AstId::new(src.file_id, ast_id_map.ast_id(it))),
+            Either::Right(it) => Either::Right(AstId::new(src.file_id, ast_id_map.ast_id(it))),
+        });
 
         let id: MacroDefId = MacroDefId { krate, ast_id, kind };
         Some(MacroDef { id })
@@ -201,25 +207,11 @@ impl TypeParam {
 
 impl Module {
     pub fn from_declaration(db: &impl DefDatabase, src: InFile<ast::Module>) -> Option<Self> {
-        let parent_declaration = src.value.syntax().ancestors().skip(1).find_map(ast::Module::cast);
-
-        let parent_module = match parent_declaration {
-            Some(parent_declaration) => {
-                let src_parent = InFile { file_id: src.file_id, value: parent_declaration };
-                Module::from_declaration(db, src_parent)
-            }
-            None => {
-                let source_file = db.parse(src.file_id.original_file(db)).tree();
-                let src_parent =
-                    InFile { file_id: src.file_id, value: ModuleSource::SourceFile(source_file) };
-                Module::from_definition(db, src_parent)
-            }
-        }?;
-
+        let parent = module_id_for_node(db, src.as_ref().map(|it| it.syntax()))?;
         let child_name = src.value.name()?.as_name();
-        let def_map = db.crate_def_map(parent_module.id.krate);
-        let child_id = def_map[parent_module.id.local_id].children.get(&child_name)?;
-        Some(parent_module.with_module_id(*child_id))
+        let def_map = parent.def_map(db);
+        let child_id = def_map[parent.local_id].children.get(&child_name)?;
+        Some(Module { id: ModuleId { local_id: *child_id, ..parent } })
     }
 
     pub fn from_definition(db: &impl DefDatabase, src: InFile<ModuleSource>) -> Option<Self> {
@@ -241,8 +233,32 @@ impl Module {
             let local_id = crate_def_map.modules_for_file(original_file).next()?;
             Some((crate_id, local_id))
         })?;
-        Some(Module { id: ModuleId { krate, local_id } })
+        Some(Module { id: ModuleId { krate, block: None, local_id } })
+    }
+}
+
+/// Finds the module in which items declared at `node` live. Unlike
+/// `Module::from_definition`, this returns the pseudo-module of a block if
+/// `node` is inside a block with items.
+fn module_id_for_node(db: &impl DefDatabase, node: InFile<&SyntaxNode>) -> Option<ModuleId> {
+    for ancestor in node.value.ancestors().skip(1) {
+        if let Some(module) = ast::Module::cast(ancestor.clone()) {
+            if !module.has_semi() {
+                return Module::from_declaration(db, node.with_value(module)).map(|it| it.id);
+            }
+        }
+        if let Some(block) = ast::BlockExpr::cast(ancestor) {
+            if block.has_items() {
+                let module = module_id_for_node(db, node.with_value(block.syntax()))?;
+                let ast_id = AstId::new(node.file_id, db.ast_id_map(node.file_id).ast_id(&block));
+                let block = db.intern_block(BlockLoc { ast_id, module });
+                let local_id = db.block_def_map(block).root;
+                return Some(ModuleId { krate: module.krate, block: Some(block), local_id });
+            }
+        }
     }
+    let source_file = ModuleSource::from_child_node(db, node);
+    Module::from_definition(db, node.with_value(source_file)).map(|it| it.id)
 }
 
 fn from_source<N, DEF>(db: &(impl DefDatabase + AstDatabase), src: InFile<N>) -> Option<DEF>
@@ -250,9 +266,8 @@ where
     N: AstNode,
     DEF: AstItemDef<N>,
 {
-    let module_src = ModuleSource::from_child_node(db, src.as_ref().map(|it| it.syntax()));
-    let module = Module::from_definition(db, InFile::new(src.file_id, module_src))?;
-    let ctx = LocationCtx::new(db, module.id, src.file_id);
+    let module = module_id_for_node(db, src.as_ref().map(|it| it.syntax()))?;
+    let ctx = LocationCtx::new(db, module, src.file_id);
     let items = db.ast_id_map(src.file_id);
     let item_id = items.ast_id(&src.value);
     Some(DEF::from_ast_id(ctx, item_id))
@@ -266,7 +281,6 @@ enum Container {
 
 impl Container {
     fn find(db: &impl DefDatabase, src: InFile<&SyntaxNode>) -> Option<Container> {
-        // FIXME: this doesn't try to handle nested declarations
         for container in src.value.ancestors() {
             let res = match_ast! {
                 match container {
@@ -278,15 +292,16 @@ impl Container {
                         let c = ImplBlock::from_source(db, src.with_value(it))?;
                         Container::ImplBlock(c)
                      },
+                    // Items declared in a block live in the block's module.
+                    ast::BlockExpr(_it) => { break },
                     _ => { continue },
                 }
             };
             return Some(res);
         }
 
-        let module_source = ModuleSource::from_child_node(db, src);
-        let c = Module::from_definition(db, src.with_value(module_source))?;
-        Some(Container::Module(c))
+        let id = module_id_for_node(db, src)?;
+        Some(Container::Module(Module { id }))
     }
 }
 
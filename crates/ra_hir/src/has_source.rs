@@ -25,14 +25,14 @@ pub trait HasSource {
 impl Module {
     /// Returns a node which defines this module. That is, a file or a `mod foo {}` with items.
     pub fn definition_source(self, db: &impl DefDatabase) -> InFile<ModuleSource> {
-        let def_map = db.crate_def_map(self.id.krate);
+        let def_map = self.id.def_map(db);
         def_map[self.id.local_id].definition_source(db)
     }
 
     /// Returns a node which declares this module, either a `mod foo;` or a `mod foo {}`.
     /// `None` for the crate root.
     pub fn declaration_source(self, db: &impl DefDatabase) -> Option<InFile<ast::Module>> {
-        let def_map = db.crate_def_map(self.id.krate);
+        let def_map = self.id.def_map(db);
         def_map[self.id.local_id].declaration_source(db)
     }
 }
@@ -103,11 +103,11 @@ impl HasSource for TypeAlias {
     }
 }
 impl HasSource for MacroDef {
-    type Ast = ast::MacroCall;
-    fn source(self, db: &impl DefDatabase) -> InFile<ast::MacroCall> {
-        InFile {
-            file_id: self.id.ast_id.expect("MacroDef without ast_id").file_id,
-            value: self.id.ast_id.expect("MacroDef without ast_id").to_node(db),
+    type Ast = Either<ast::MacroCall, ast::MacroDef>;
+    fn source(self, db: &impl DefDatabase) -> InFile<Either<ast::MacroCall, ast::MacroDef>> {
+        match self.id.ast_id.expect("MacroDef without ast_id") {
+            Either::Left(it) => InFile::new(it.file_id, Either::Left(it.to_node(db))),
+            Either::Right(it) => InFile::new(it.file_id, Either::Right(it.to_node(db))),
         }
     }
 }
@@ -39,14 +39,17 @@ mod from_source;
 
 pub use crate::{
     code_model::{
-        Adt, AssocItem, AttrDef, Const, Container, Crate, CrateDependency, DefWithBody, Docs, Enum,
-        EnumVariant, FieldSource, Function, GenericDef, HasAttrs, ImplBlock, Import, Local,
-        MacroDef, Module, ModuleDef, ScopeDef, Static, Struct, StructField, Trait, Type, TypeAlias,
-        TypeParam, Union, VariantDef,
+        Adt, AssocItem, AttrDef, ClosureCapture, Const, Container, Crate, CrateDependency,
+        DefWithBody, Docs, Enum, EnumVariant, FieldSource, Function, GenericDef, HasAttrs,
+        HasVisibility, ImplBlock, Import, Local, MacroDef, Module, ModuleDef, ScopeDef, Static,
+        Struct, StructField, Trait, Type, TypeAlias, TypeParam, Union, VariantDef,
     },
     from_source::FromSource,
     has_source::HasSource,
-    source_binder::{PathResolution, ScopeEntryWithSyntax, SourceAnalyzer},
+    source_binder::{
+        ascend_from_macros, descend_into_macros, PathResolution, ScopeEntryWithSyntax,
+        SourceAnalyzer,
+    },
 };
 
 pub use hir_def::{
@@ -56,8 +59,10 @@ pub use hir_def::{
     nameres::ModuleSource,
     path::{Path, PathKind},
     type_ref::Mutability,
+    visibility::Visibility,
 };
 pub use hir_expand::{
-    name::Name, HirFileId, InFile, MacroCallId, MacroCallLoc, MacroDefId, MacroFile,
+    name::Name, BindingTrace, ExpansionTrace, HirFileId, InFile, MacroCallId, MacroCallLoc,
+    MacroDefId, MacroFile, RuleOutcome, RuleTrace,
 };
-pub use hir_ty::{display::HirDisplay, CallableDef};
+pub use hir_ty::{display::HirDisplay, CallableDef, CaptureMode, FnTrait};
@@ -5,7 +5,7 @@
 //!
 //! So, this modules should not be used during hir construction, it exists
 //! purely for "IDE needs".
-use std::sync::Arc;
+use std::{iter::successors, sync::Arc};
 
 use either::Either;
 use hir_def::{
@@ -20,11 +20,13 @@ use hir_def::{
     AssocItemId, DefWithBodyId,
 };
 use hir_expand::{
-    hygiene::Hygiene, name::AsName, AstId, HirFileId, InFile, MacroCallId, MacroCallKind,
+    hygiene::{Hygiene, SyntaxContext},
+    name::AsName,
+    AstId, ExpansionTrace, HirFileId, InFile, MacroCallId, MacroCallKind,
 };
 use hir_ty::{
     method_resolution::{self, implements_trait},
-    Canonical, InEnvironment, InferenceResult, TraitEnvironment, Ty,
+    Canonical, FnTrait, InEnvironment, InferenceResult, TraitEnvironment, Ty,
 };
 use ra_syntax::{
     ast::{self, AstNode},
@@ -34,9 +36,9 @@ use ra_syntax::{
 };
 
 use crate::{
-    db::HirDatabase, Adt, AssocItem, Const, DefWithBody, Enum, EnumVariant, FromSource, Function,
-    ImplBlock, Local, MacroDef, Name, Path, ScopeDef, Static, Struct, Trait, Type, TypeAlias,
-    TypeParam,
+    db::HirDatabase, Adt, AssocItem, ClosureCapture, Const, DefWithBody, Enum, EnumVariant,
+    FromSource, Function, ImplBlock, Local, MacroDef, Name, Path, ScopeDef, Static, Struct, Trait,
+    Type, TypeAlias, TypeParam,
 };
 
 fn try_get_resolver_for_node(db: &impl HirDatabase, node: InFile<&SyntaxNode>) -> Option<Resolver> {
@@ -157,6 +159,53 @@ impl Expansion {
     pub fn file_id(&self) -> HirFileId {
         self.macro_call_id.as_file()
     }
+
+    /// Re-runs the expansion, recording how the macro arguments were matched
+    /// against the rules of a `macro_rules!` definition.
+    pub fn trace(&self, db: &impl HirDatabase) -> Option<ExpansionTrace> {
+        hir_expand::db::trace_macro_expansion(db, self.macro_call_id)
+    }
+}
+
+/// Maps a token inside the arguments of a macro call to the corresponding token
+/// of the expansion. This is repeated for macro calls nested in the expansion,
+/// so the result is the most deeply expanded token. Tokens which are not inside
+/// of a macro call are returned as is.
+pub fn descend_into_macros(
+    db: &impl HirDatabase,
+    token: InFile<SyntaxToken>,
+) -> InFile<SyntaxToken> {
+    successors(Some(token), |token| {
+        let macro_call = token.value.ancestors().find_map(ast::MacroCall::cast)?;
+        let tt = macro_call.token_tree()?;
+        if !token.value.text_range().is_subrange(&tt.syntax().text_range()) {
+            return None;
+        }
+        let analyzer =
+            SourceAnalyzer::new(db, token.with_value(token.value.parent()).as_ref(), None);
+        let exp = analyzer.expand(db, token.with_value(&macro_call))?;
+        exp.map_token_down(db, token.as_ref())
+    })
+    .last()
+    .unwrap()
+}
+
+/// The inverse of `descend_into_macros`: maps a token of a macro expansion
+/// back to the token it originates from, through all the enclosing expansions.
+/// The result is either in the arguments of the outermost macro call, or in
+/// the body of the macro definition.
+///
+/// Returns `None` if the token was produced by a macro and so does not exist
+/// in any real file.
+pub fn ascend_from_macros(
+    db: &impl HirDatabase,
+    mut token: InFile<SyntaxToken>,
+) -> Option<InFile<SyntaxToken>> {
+    while token.file_id.is_macro_file() {
+        let expansion = token.file_id.expansion_info(db)?;
+        token = expansion.map_token_up(token.as_ref())?;
+    }
+    Some(token)
 }
 
 impl SourceAnalyzer {
@@ -185,9 +234,9 @@ impl SourceAnalyzer {
         } else {
             SourceAnalyzer {
                 resolver: node
-                    .value
-                    .ancestors()
-                    .find_map(|it| try_get_resolver_for_node(db, node.with_value(&it)))
+                    .cloned()
+                    .ancestors_with_macros(db)
+                    .find_map(|it| try_get_resolver_for_node(db, it.as_ref()))
                     .unwrap_or_default(),
                 body_owner: None,
                 body_source_map: None,
@@ -222,6 +271,28 @@ impl SourceAnalyzer {
         Some(Type { krate: self.resolver.krate()?, ty: InEnvironment { value: ty, environment } })
     }
 
+    /// Returns the most general `Fn*` trait that the closure implements.
+    pub fn closure_kind(&self, lambda: &ast::LambdaExpr) -> Option<FnTrait> {
+        let expr_id = self.expr_id(&lambda.clone().into())?;
+        Some(self.infer.as_ref()?.closure_captures(expr_id)?.kind)
+    }
+
+    pub fn closure_captures(&self, lambda: &ast::LambdaExpr) -> Option<Vec<ClosureCapture>> {
+        let expr_id = self.expr_id(&lambda.clone().into())?;
+        let parent = self.body_owner?;
+        let captures = self.infer.as_ref()?.closure_captures(expr_id)?;
+        let res = captures
+            .places
+            .iter()
+            .map(|place| ClosureCapture {
+                local: Local { parent, pat_id: place.local },
+                fields: place.fields.clone(),
+                mode: place.mode,
+            })
+            .collect();
+        Some(res)
+    }
+
     pub fn resolve_method_call(&self, call: &ast::MethodCallExpr) -> Option<Function> {
         let expr_id = self.expr_id(&call.clone().into())?;
         self.infer.as_ref()?.method_resolution(expr_id).map(Function::from)
@@ -257,10 +328,23 @@ impl SourceAnalyzer {
         self.resolver.resolve_path_as_macro(db, &path).map(|it| it.into())
     }
 
+    /// Resolves a path written at `token`, which determines the hygiene of
+    /// the path's names.
     pub fn resolve_hir_path(
         &self,
         db: &impl HirDatabase,
         path: &crate::Path,
+        token: &SyntaxToken,
+    ) -> Option<PathResolution> {
+        let context = SyntaxContext::of_token(db, InFile::new(self.file_id, token));
+        self.resolve_hir_path_in_context(db, path, context)
+    }
+
+    fn resolve_hir_path_in_context(
+        &self,
+        db: &impl HirDatabase,
+        path: &crate::Path,
+        context: SyntaxContext,
     ) -> Option<PathResolution> {
         let types = self.resolver.resolve_path_in_type_ns_fully(db, &path).map(|ty| match ty {
             TypeNs::SelfType(it) => PathResolution::SelfType(it.into()),
@@ -273,20 +357,21 @@ impl SourceAnalyzer {
             TypeNs::BuiltinType(it) => PathResolution::Def(it.into()),
             TypeNs::TraitId(it) => PathResolution::Def(Trait::from(it).into()),
         });
-        let values = self.resolver.resolve_path_in_value_ns_fully(db, &path).and_then(|val| {
-            let res = match val {
-                ValueNs::LocalBinding(pat_id) => {
-                    let var = Local { parent: self.body_owner?, pat_id };
-                    PathResolution::Local(var)
-                }
-                ValueNs::FunctionId(it) => PathResolution::Def(Function::from(it).into()),
-                ValueNs::ConstId(it) => PathResolution::Def(Const::from(it).into()),
-                ValueNs::StaticId(it) => PathResolution::Def(Static::from(it).into()),
-                ValueNs::StructId(it) => PathResolution::Def(Struct::from(it).into()),
-                ValueNs::EnumVariantId(it) => PathResolution::Def(EnumVariant::from(it).into()),
-            };
-            Some(res)
-        });
+        let values =
+            self.resolver.resolve_path_in_value_ns_fully(db, &path, context).and_then(|val| {
+                let res = match val {
+                    ValueNs::LocalBinding(pat_id) => {
+                        let var = Local { parent: self.body_owner?, pat_id };
+                        PathResolution::Local(var)
+                    }
+                    ValueNs::FunctionId(it) => PathResolution::Def(Function::from(it).into()),
+                    ValueNs::ConstId(it) => PathResolution::Def(Const::from(it).into()),
+                    ValueNs::StaticId(it) => PathResolution::Def(Static::from(it).into()),
+                    ValueNs::StructId(it) => PathResolution::Def(Struct::from(it).into()),
+                    ValueNs::EnumVariantId(it) => PathResolution::Def(EnumVariant::from(it).into()),
+                };
+                Some(res)
+            });
 
         let items = self
             .resolver
@@ -315,15 +400,27 @@ impl SourceAnalyzer {
         }
         // This must be a normal source file rather than macro file.
         let hir_path = crate::Path::from_ast(path.clone())?;
-        self.resolve_hir_path(db, &hir_path)
+        let context = match path.syntax().first_token() {
+            Some(token) => SyntaxContext::of_token(db, InFile::new(self.file_id, &token)),
+            None => SyntaxContext::ROOT,
+        };
+        self.resolve_hir_path_in_context(db, &hir_path, context)
     }
 
-    fn resolve_local_name(&self, name_ref: &ast::NameRef) -> Option<ScopeEntryWithSyntax> {
+    fn resolve_local_name(
+        &self,
+        db: &impl HirDatabase,
+        name_ref: &ast::NameRef,
+    ) -> Option<ScopeEntryWithSyntax> {
         let name = name_ref.as_name();
         let source_map = self.body_source_map.as_ref()?;
         let scopes = self.scopes.as_ref()?;
         let scope = scope_for(scopes, source_map, InFile::new(self.file_id, name_ref.syntax()))?;
-        let entry = scopes.resolve_name_in_scope(scope, &name)?;
+        let context = match name_ref.syntax().first_token() {
+            Some(token) => SyntaxContext::of_token(db, InFile::new(self.file_id, &token)),
+            None => SyntaxContext::ROOT,
+        };
+        let entry = scopes.resolve_name_in_scope(scope, &name, context)?;
         Some(ScopeEntryWithSyntax {
             name: entry.name().clone(),
             ptr: source_map.pat_syntax(entry.pat())?.value,
@@ -348,14 +445,18 @@ impl SourceAnalyzer {
 
     // FIXME: we only use this in `inline_local_variable` assist, ideally, we
     // should switch to general reference search infra there.
-    pub fn find_all_refs(&self, pat: &ast::BindPat) -> Vec<ReferenceDescriptor> {
+    pub fn find_all_refs(
+        &self,
+        db: &impl HirDatabase,
+        pat: &ast::BindPat,
+    ) -> Vec<ReferenceDescriptor> {
         let fn_def = pat.syntax().ancestors().find_map(ast::FnDef::cast).unwrap();
         let ptr = Either::Left(AstPtr::new(&ast::Pat::from(pat.clone())));
         fn_def
             .syntax()
             .descendants()
             .filter_map(ast::NameRef::cast)
-            .filter(|name_ref| match self.resolve_local_name(&name_ref) {
+            .filter(|name_ref| match self.resolve_local_name(db, &name_ref) {
                 None => false,
                 Some(entry) => entry.ptr() == ptr,
             })
@@ -450,11 +551,12 @@ impl SourceAnalyzer {
         macro_call: InFile<&ast::MacroCall>,
     ) -> Option<Expansion> {
         let def = self.resolve_macro_call(db, macro_call)?.id;
+        let krate = self.resolver.krate()?;
         let ast_id = AstId::new(
             macro_call.file_id,
             db.ast_id_map(macro_call.file_id).ast_id(macro_call.value),
         );
-        Some(Expansion { macro_call_id: def.as_call_id(db, MacroCallKind::FnLike(ast_id)) })
+        Some(Expansion { macro_call_id: def.as_call_id(db, krate, MacroCallKind::FnLike(ast_id)) })
     }
 }
 
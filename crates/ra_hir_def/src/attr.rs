@@ -3,8 +3,9 @@
 use std::{ops, sync::Arc};
 
 use either::Either;
-use hir_expand::{hygiene::Hygiene, AstId, InFile};
+use hir_expand::{hygiene::Hygiene, name::AsName, AstId, InFile};
 use mbe::ast_to_token_tree;
+use ra_cfg::{parse_cfg, CfgOptions};
 use ra_syntax::{
     ast::{self, AstNode, AttrsOwner},
     SmolStr,
@@ -12,8 +13,11 @@ use ra_syntax::{
 use tt::Subtree;
 
 use crate::{
-    db::DefDatabase, path::Path, src::HasChildSource, src::HasSource, AdtId, AstItemDef, AttrDefId,
-    Lookup,
+    db::DefDatabase,
+    path::{Path, PathKind},
+    src::HasChildSource,
+    src::HasSource,
+    AdtId, AstItemDef, AttrDefId, Lookup,
 };
 
 #[derive(Default, Debug, Clone, PartialEq, Eq)]
@@ -34,9 +38,17 @@ impl ops::Deref for Attrs {
 
 impl Attrs {
     pub(crate) fn attrs_query(db: &impl DefDatabase, def: AttrDefId) -> Attrs {
+        let raw_attrs = Attrs::raw_attrs(db, def);
+        match def.krate(db) {
+            Some(krate) => raw_attrs.expand_cfg_attr(db.crate_graph().cfg_options(krate)),
+            None => raw_attrs,
+        }
+    }
+
+    fn raw_attrs(db: &impl DefDatabase, def: AttrDefId) -> Attrs {
         match def {
             AttrDefId::ModuleId(module) => {
-                let def_map = db.crate_def_map(module.krate);
+                let def_map = module.def_map(db);
                 let src = match def_map[module.local_id].declaration_source(db) {
                     Some(it) => it,
                     None => return Attrs::default(),
@@ -61,9 +73,11 @@ impl Attrs {
                 AdtId::UnionId(it) => attrs_from_ast(it.lookup_intern(db).ast_id, db),
             },
             AttrDefId::TraitId(it) => attrs_from_ast(it.lookup_intern(db).ast_id, db),
-            AttrDefId::MacroDefId(it) => {
-                it.ast_id.map_or_else(Default::default, |ast_id| attrs_from_ast(ast_id, db))
-            }
+            AttrDefId::MacroDefId(it) => match it.ast_id {
+                Some(Either::Left(ast_id)) => attrs_from_ast(ast_id, db),
+                Some(Either::Right(ast_id)) => attrs_from_ast(ast_id, db),
+                None => Attrs::default(),
+            },
             AttrDefId::ImplId(it) => attrs_from_ast(it.lookup_intern(db).ast_id, db),
             AttrDefId::ConstId(it) => attrs_from_loc(it.lookup(db), db),
             AttrDefId::StaticId(it) => attrs_from_loc(it.lookup(db), db),
@@ -88,6 +102,19 @@ impl Attrs {
         Attrs { entries }
     }
 
+    /// Replaces `#[cfg_attr(pred, attr1, attr2)]` with `attr1` and `attr2` if
+    /// `pred` holds for `cfg_options`, and removes it otherwise.
+    pub(crate) fn expand_cfg_attr(&self, cfg_options: &CfgOptions) -> Attrs {
+        if !self.iter().any(Attr::is_cfg_attr) {
+            return self.clone();
+        }
+        let mut entries = Vec::new();
+        for attr in self.iter() {
+            attr.expand_cfg_attr(cfg_options, &mut entries);
+        }
+        Attrs { entries: if entries.is_empty() { None } else { Some(entries.into()) } }
+    }
+
     pub fn by_key(&self, key: &'static str) -> AttrQuery<'_> {
         AttrQuery { attrs: self, key }
     }
@@ -122,6 +149,81 @@ impl Attr {
 
         Some(Attr { path, input })
     }
+
+    /// Parses an attribute like `derive(Debug)` or `path = "foo.rs"` from the
+    /// arguments of a `cfg_attr`.
+    fn from_tt(tokens: &[tt::TokenTree]) -> Option<Attr> {
+        let path_len = tokens
+            .iter()
+            .position(|tt| match tt {
+                tt::TokenTree::Leaf(tt::Leaf::Punct(punct)) => punct.char == '=',
+                tt::TokenTree::Subtree(_) => true,
+                _ => false,
+            })
+            .unwrap_or(tokens.len());
+        let (path, input) = tokens.split_at(path_len);
+
+        let kind = match path.first()? {
+            tt::TokenTree::Leaf(tt::Leaf::Punct(punct)) if punct.char == ':' => PathKind::Abs,
+            _ => PathKind::Plain,
+        };
+        let mut segments = Vec::new();
+        for tt in path {
+            match tt {
+                tt::TokenTree::Leaf(tt::Leaf::Ident(ident)) => segments.push(ident.as_name()),
+                tt::TokenTree::Leaf(tt::Leaf::Punct(punct)) if punct.char == ':' => (),
+                _ => return None,
+            }
+        }
+        if segments.is_empty() {
+            return None;
+        }
+        let path = Path::from_simple_segments(kind, segments);
+
+        let input = match input {
+            [] => None,
+            [tt::TokenTree::Leaf(tt::Leaf::Punct(eq)), tt::TokenTree::Leaf(tt::Leaf::Literal(lit))]
+                if eq.char == '=' =>
+            {
+                // FIXME: escape? raw string?
+                Some(AttrInput::Literal(lit.text.trim_matches('"').into()))
+            }
+            [tt::TokenTree::Subtree(subtree)] => Some(AttrInput::TokenTree(subtree.clone())),
+            _ => return None,
+        };
+
+        Some(Attr { path, input })
+    }
+
+    fn is_cfg_attr(&self) -> bool {
+        self.path.as_ident().map_or(false, |name| name.to_string() == "cfg_attr")
+    }
+
+    fn expand_cfg_attr(&self, cfg_options: &CfgOptions, acc: &mut Vec<Attr>) {
+        if !self.is_cfg_attr() {
+            acc.push(self.clone());
+            return;
+        }
+        let args = match &self.input {
+            Some(AttrInput::TokenTree(args)) => args,
+            _ => return,
+        };
+        let mut parts = args.token_trees.split(|tt| match tt {
+            tt::TokenTree::Leaf(tt::Leaf::Punct(punct)) => punct.char == ',',
+            _ => false,
+        });
+        let pred = match parts.next() {
+            Some(it) => Subtree { delimiter: tt::Delimiter::None, token_trees: it.to_vec() },
+            None => return,
+        };
+        if cfg_options.check(&parse_cfg(&pred)) == Some(false) {
+            return;
+        }
+        for attr in parts.filter(|it| !it.is_empty()).filter_map(Attr::from_tt) {
+            // `cfg_attr`s can be nested
+            attr.expand_cfg_attr(cfg_options, acc);
+        }
+    }
 }
 
 pub struct AttrQuery<'a> {
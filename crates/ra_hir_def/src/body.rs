@@ -6,9 +6,12 @@ pub mod scope;
 use std::{ops::Index, sync::Arc};
 
 use either::Either;
-use hir_expand::{hygiene::Hygiene, AstId, HirFileId, InFile, MacroCallKind, MacroDefId};
+use hir_expand::{
+    hygiene::{Hygiene, SyntaxContext},
+    AstId, HirFileId, InFile, MacroCallKind, MacroDefId,
+};
 use ra_arena::{map::ArenaMap, Arena};
-use ra_syntax::{ast, AstNode, AstPtr};
+use ra_syntax::{ast, AstNode, AstPtr, SyntaxToken};
 use rustc_hash::FxHashMap;
 
 use crate::{
@@ -17,7 +20,7 @@ use crate::{
     nameres::{BuiltinShadowMode, CrateDefMap},
     path::Path,
     src::HasSource,
-    DefWithBodyId, HasModule, Lookup, ModuleId,
+    BlockId, BlockLoc, DefWithBodyId, HasModule, Intern, Lookup, ModuleId,
 };
 
 struct Expander {
@@ -29,7 +32,7 @@ struct Expander {
 
 impl Expander {
     fn new(db: &impl DefDatabase, current_file_id: HirFileId, module: ModuleId) -> Expander {
-        let crate_def_map = db.crate_def_map(module.krate);
+        let crate_def_map = module.def_map(db);
         let hygiene = Hygiene::new(db, current_file_id);
         Expander { crate_def_map, current_file_id, hygiene, module }
     }
@@ -46,7 +49,7 @@ impl Expander {
 
         if let Some(path) = macro_call.path().and_then(|path| self.parse_path(path)) {
             if let Some(def) = self.resolve_path_as_macro(db, &path) {
-                let call_id = def.as_call_id(db, MacroCallKind::FnLike(ast_id));
+                let call_id = def.as_call_id(db, self.module.krate, MacroCallKind::FnLike(ast_id));
                 let file_id = call_id.as_file();
                 if let Some(node) = db.parse_or_expand(file_id) {
                     if let Some(expr) = ast::Expr::cast(node) {
@@ -81,6 +84,35 @@ impl Expander {
         Path::from_src(path, &self.hygiene)
     }
 
+    fn syntax_context(&self, db: &impl DefDatabase, token: &SyntaxToken) -> SyntaxContext {
+        SyntaxContext::of_token(db, self.to_source(token))
+    }
+
+    /// The id of `block`, if it declares items and so has its own `DefMap`.
+    fn block_id(&self, db: &impl DefDatabase, block: &ast::BlockExpr) -> Option<BlockId> {
+        if !block.has_items() {
+            return None;
+        }
+        let ast_id =
+            AstId::new(self.current_file_id, db.ast_id_map(self.current_file_id).ast_id(block));
+        Some(BlockLoc { ast_id, module: self.module }.intern(db))
+    }
+
+    /// Resolves macros in the `DefMap` of `block`, until `exit_block`.
+    fn enter_block(&mut self, db: &impl DefDatabase, block: BlockId) -> BlockMark {
+        let def_map = db.block_def_map(block);
+        let module = def_map.module_id(def_map.root);
+        BlockMark {
+            crate_def_map: std::mem::replace(&mut self.crate_def_map, def_map),
+            module: std::mem::replace(&mut self.module, module),
+        }
+    }
+
+    fn exit_block(&mut self, mark: BlockMark) {
+        self.crate_def_map = mark.crate_def_map;
+        self.module = mark.module;
+    }
+
     fn resolve_path_as_macro(&self, db: &impl DefDatabase, path: &Path) -> Option<MacroDefId> {
         self.crate_def_map
             .resolve_path(db, self.module.local_id, path, BuiltinShadowMode::Other)
@@ -93,6 +125,11 @@ struct Mark {
     file_id: HirFileId,
 }
 
+struct BlockMark {
+    crate_def_map: Arc<CrateDefMap>,
+    module: ModuleId,
+}
+
 impl Drop for Mark {
     fn drop(&mut self) {
         if !std::thread::panicking() {
@@ -115,6 +152,12 @@ pub struct Body {
     pub params: Vec<PatId>,
     /// The `ExprId` of the actual body expression.
     pub body_expr: ExprId,
+    /// Block expressions which declare items, with the ids of their `DefMap`s.
+    pub block_scopes: FxHashMap<ExprId, BlockId>,
+    /// Syntax contexts of the bindings and path expressions which come from
+    /// `macro_rules` definitions. All others have the root context.
+    pub pat_contexts: FxHashMap<PatId, SyntaxContext>,
+    pub expr_contexts: FxHashMap<ExprId, SyntaxContext>,
 }
 
 pub type ExprPtr = Either<AstPtr<ast::Expr>, AstPtr<ast::RecordField>>;
@@ -187,6 +230,16 @@ impl Body {
     }
 }
 
+impl Body {
+    pub fn pat_context(&self, pat: PatId) -> SyntaxContext {
+        self.pat_contexts.get(&pat).copied().unwrap_or(SyntaxContext::ROOT)
+    }
+
+    pub fn expr_context(&self, expr: ExprId) -> SyntaxContext {
+        self.expr_contexts.get(&expr).copied().unwrap_or(SyntaxContext::ROOT)
+    }
+}
+
 impl Index<ExprId> for Body {
     type Output = Expr;
 
@@ -9,8 +9,9 @@ use ra_syntax::{
         self, ArgListOwner, ArrayExprKind, LiteralKind, LoopBodyOwner, NameOwner,
         TypeAscriptionOwner,
     },
-    AstNode, AstPtr,
+    AstNode, AstPtr, SyntaxToken,
 };
+use rustc_hash::FxHashMap;
 use test_utils::tested_by;
 
 use crate::{
@@ -19,7 +20,7 @@ use crate::{
     db::DefDatabase,
     expr::{
         ArithOp, Array, BinaryOp, BindingAnnotation, CmpOp, Expr, ExprId, Literal, LogicOp,
-        MatchArm, Ordering, Pat, PatId, RecordFieldPat, RecordLitField, Statement,
+        MatchArm, Ordering, Pat, PatId, RecordFieldPat, RecordLitField, Statement, UnaryOp,
     },
     path::GenericArgs,
     path::Path,
@@ -41,6 +42,9 @@ pub(super) fn lower(
             pats: Arena::default(),
             params: Vec::new(),
             body_expr: ExprId::dummy(),
+            block_scopes: FxHashMap::default(),
+            pat_contexts: FxHashMap::default(),
+            expr_contexts: FxHashMap::default(),
         },
     }
     .collect(params, body)
@@ -112,6 +116,24 @@ where
         self.source_map.expr_map_back.insert(id, src);
         id
     }
+    /// Records the syntax context of the identifier `token`, which introduces
+    /// or refers to a local variable.
+    fn record_expr_context(&mut self, expr: ExprId, token: Option<SyntaxToken>) {
+        if let Some(token) = token {
+            let context = self.expander.syntax_context(self.db, &token);
+            if !context.is_root() {
+                self.body.expr_contexts.insert(expr, context);
+            }
+        }
+    }
+    fn record_pat_context(&mut self, pat: PatId, token: Option<SyntaxToken>) {
+        if let Some(token) = token {
+            let context = self.expander.syntax_context(self.db, &token);
+            if !context.is_root() {
+                self.body.pat_contexts.insert(pat, context);
+            }
+        }
+    }
     fn alloc_pat(&mut self, pat: Pat, ptr: PatPtr) -> PatId {
         let id = self.body.pats.alloc(pat);
         let src = self.expander.to_source(ptr);
@@ -176,12 +198,20 @@ where
                 let body = self.collect_block_opt(e.body());
                 self.alloc_expr(Expr::TryBlock { body }, syntax_ptr)
             }
-            ast::Expr::BlockExpr(e) => self.collect_block(e),
+            ast::Expr::BlockExpr(e) => {
+                if e.is_async() {
+                    let body = self.collect_block(e);
+                    return self.alloc_expr(Expr::Async { body }, syntax_ptr);
+                }
+                self.collect_block(e)
+            }
             ast::Expr::LoopExpr(e) => {
+                let label = collect_label(e.label());
                 let body = self.collect_block_opt(e.loop_body());
-                self.alloc_expr(Expr::Loop { body }, syntax_ptr)
+                self.alloc_expr(Expr::Loop { body, label }, syntax_ptr)
             }
             ast::Expr::WhileExpr(e) => {
+                let label = collect_label(e.label());
                 let body = self.collect_block_opt(e.loop_body());
 
                 let condition = match e.condition() {
@@ -194,25 +224,28 @@ where
                             let pat = self.collect_pat(pat);
                             let match_expr = self.collect_expr_opt(condition.expr());
                             let placeholder_pat = self.missing_pat();
-                            let break_ = self.alloc_expr_desugared(Expr::Break { expr: None });
+                            let break_ =
+                                self.alloc_expr_desugared(Expr::Break { expr: None, label: None });
                             let arms = vec![
                                 MatchArm { pats: vec![pat], expr: body, guard: None },
                                 MatchArm { pats: vec![placeholder_pat], expr: break_, guard: None },
                             ];
                             let match_expr =
                                 self.alloc_expr_desugared(Expr::Match { expr: match_expr, arms });
-                            return self.alloc_expr(Expr::Loop { body: match_expr }, syntax_ptr);
+                            return self
+                                .alloc_expr(Expr::Loop { body: match_expr, label }, syntax_ptr);
                         }
                     },
                 };
 
-                self.alloc_expr(Expr::While { condition, body }, syntax_ptr)
+                self.alloc_expr(Expr::While { condition, body, label }, syntax_ptr)
             }
             ast::Expr::ForExpr(e) => {
+                let label = collect_label(e.label());
                 let iterable = self.collect_expr_opt(e.iterable());
                 let pat = self.collect_pat_opt(e.pat());
                 let body = self.collect_block_opt(e.loop_body());
-                self.alloc_expr(Expr::For { iterable, pat, body }, syntax_ptr)
+                self.alloc_expr(Expr::For { iterable, pat, body, label }, syntax_ptr)
             }
             ast::Expr::CallExpr(e) => {
                 let callee = self.collect_expr_opt(e.expr());
@@ -262,15 +295,18 @@ where
                     .and_then(|path| self.expander.parse_path(path))
                     .map(Expr::Path)
                     .unwrap_or(Expr::Missing);
-                self.alloc_expr(path, syntax_ptr)
+                let id = self.alloc_expr(path, syntax_ptr);
+                self.record_expr_context(id, e.syntax().first_token());
+                id
             }
-            ast::Expr::ContinueExpr(_e) => {
-                // FIXME: labels
-                self.alloc_expr(Expr::Continue, syntax_ptr)
+            ast::Expr::ContinueExpr(e) => {
+                let label = e.lifetime_token().map(|lt| Name::new_lifetime(&lt));
+                self.alloc_expr(Expr::Continue { label }, syntax_ptr)
             }
             ast::Expr::BreakExpr(e) => {
+                let label = e.lifetime_token().map(|lt| Name::new_lifetime(&lt));
                 let expr = e.expr().map(|e| self.collect_expr(e));
-                self.alloc_expr(Expr::Break { expr }, syntax_ptr)
+                self.alloc_expr(Expr::Break { expr, label }, syntax_ptr)
             }
             ast::Expr::ParenExpr(e) => {
                 let inner = self.collect_expr_opt(e.expr());
@@ -299,10 +335,12 @@ where
                                 self.collect_expr(e)
                             } else if let Some(nr) = field.name_ref() {
                                 // field shorthand
-                                self.alloc_expr_field_shorthand(
+                                let id = self.alloc_expr_field_shorthand(
                                     Expr::Path(Path::from_name_ref(&nr)),
                                     AstPtr::new(&field),
-                                )
+                                );
+                                self.record_expr_context(id, nr.syntax().first_token());
+                                id
                             } else {
                                 self.missing_expr()
                             },
@@ -365,8 +403,13 @@ where
                         arg_types.push(type_ref);
                     }
                 }
-                let body = self.collect_expr_opt(e.body());
-                self.alloc_expr(Expr::Lambda { args, arg_types, body }, syntax_ptr)
+                let mut body = self.collect_expr_opt(e.body());
+                if e.is_async() {
+                    // `async |x| body` returns `async { body }` when called.
+                    body = self.alloc_expr_desugared(Expr::Async { body });
+                }
+                let is_move = e.is_move();
+                self.alloc_expr(Expr::Lambda { args, arg_types, body, is_move }, syntax_ptr)
             }
             ast::Expr::BinExpr(e) => {
                 let lhs = self.collect_expr_opt(e.lhs());
@@ -402,26 +445,7 @@ where
                 }
             }
 
-            ast::Expr::Literal(e) => {
-                let lit = match e.kind() {
-                    LiteralKind::IntNumber { suffix } => {
-                        let known_name = suffix.and_then(|it| BuiltinInt::from_suffix(&it));
-
-                        Literal::Int(Default::default(), known_name)
-                    }
-                    LiteralKind::FloatNumber { suffix } => {
-                        let known_name = suffix.and_then(|it| BuiltinFloat::from_suffix(&it));
-
-                        Literal::Float(Default::default(), known_name)
-                    }
-                    LiteralKind::ByteString => Literal::ByteString(Default::default()),
-                    LiteralKind::String => Literal::String(Default::default()),
-                    LiteralKind::Byte => Literal::Int(Default::default(), Some(BuiltinInt::U8)),
-                    LiteralKind::Bool => Literal::Bool(Default::default()),
-                    LiteralKind::Char => Literal::Char(Default::default()),
-                };
-                self.alloc_expr(Expr::Literal(lit), syntax_ptr)
-            }
+            ast::Expr::Literal(e) => self.alloc_expr(Expr::Literal(lower_literal(&e)), syntax_ptr),
             ast::Expr::IndexExpr(e) => {
                 let base = self.collect_expr_opt(e.base());
                 let index = self.collect_expr_opt(e.index());
@@ -448,6 +472,8 @@ where
             },
 
             // FIXME implement HIR for these:
+            // Labels are lowered together with the loop they are attached to,
+            // so a free-standing one is always an error.
             ast::Expr::Label(_e) => self.alloc_expr(Expr::Missing, syntax_ptr),
         }
     }
@@ -466,6 +492,9 @@ where
             Some(block) => block,
             None => return self.alloc_expr(Expr::Missing, syntax_node_ptr),
         };
+        // Macros in the block are resolved in the block's own item scope.
+        let block_id = self.expander.block_id(self.db, &expr);
+        let block_mark = block_id.map(|block| self.expander.enter_block(self.db, block));
         let statements = block
             .statements()
             .map(|s| match s {
@@ -479,7 +508,14 @@ where
             })
             .collect();
         let tail = block.expr().map(|e| self.collect_expr(e));
-        self.alloc_expr(Expr::Block { statements, tail }, syntax_node_ptr)
+        if let Some(mark) = block_mark {
+            self.expander.exit_block(mark);
+        }
+        let id = self.alloc_expr(Expr::Block { statements, tail }, syntax_node_ptr);
+        if let Some(block) = block_id {
+            self.body.block_scopes.insert(id, block);
+        }
+        id
     }
 
     fn collect_block_opt(&mut self, expr: Option<ast::BlockExpr>) -> ExprId {
@@ -500,8 +536,8 @@ where
             }
             ast::Pat::TupleStructPat(p) => {
                 let path = p.path().and_then(|path| self.expander.parse_path(path));
-                let args = p.args().map(|p| self.collect_pat(p)).collect();
-                Pat::TupleStruct { path, args }
+                let (args, ellipsis) = self.collect_tuple_pat(p.args());
+                Pat::TupleStruct { path, args, ellipsis }
             }
             ast::Pat::RefPat(p) => {
                 let pat = self.collect_pat_opt(p.pat());
@@ -513,8 +549,8 @@ where
                 path.map(Pat::Path).unwrap_or(Pat::Missing)
             }
             ast::Pat::TuplePat(p) => {
-                let args = p.args().map(|p| self.collect_pat(p)).collect();
-                Pat::Tuple(args)
+                let (args, ellipsis) = self.collect_tuple_pat(p.args());
+                Pat::Tuple { args, ellipsis }
             }
             ast::Pat::PlaceholderPat(_) => Pat::Wild,
             ast::Pat::RecordPat(p) => {
@@ -542,14 +578,82 @@ where
                 Pat::Record { path, args: fields }
             }
 
-            // FIXME: implement
-            ast::Pat::DotDotPat(_) => Pat::Missing,
-            ast::Pat::BoxPat(_) => Pat::Missing,
-            ast::Pat::LiteralPat(_) => Pat::Missing,
-            ast::Pat::SlicePat(_) | ast::Pat::RangePat(_) => Pat::Missing,
+            ast::Pat::SlicePat(p) => {
+                let mut prefix = Vec::new();
+                let mut rest = None;
+                let mut suffix = Vec::new();
+                for arg in p.args() {
+                    if rest.is_none() && is_rest_pat(&arg) {
+                        rest = Some(self.collect_pat(arg));
+                    } else if rest.is_none() {
+                        prefix.push(self.collect_pat(arg));
+                    } else {
+                        suffix.push(self.collect_pat(arg));
+                    }
+                }
+                Pat::Slice { prefix, rest, suffix }
+            }
+            ast::Pat::BoxPat(p) => {
+                let inner = self.collect_pat_opt(p.pat());
+                Pat::Box { inner }
+            }
+            ast::Pat::LiteralPat(p) => Pat::Lit(self.collect_literal_pat(p)),
+            ast::Pat::RangePat(p) => {
+                let start = self.collect_range_pat_end(p.start());
+                let end = self.collect_range_pat_end(p.end());
+                Pat::Range { start, end }
+            }
+            // `..` in tuple and slice patterns is handled by the parent
+            // pattern; as the rest of a slice, it matches anything.
+            ast::Pat::DotDotPat(_) => Pat::Wild,
         };
         let ptr = AstPtr::new(&pat);
-        self.alloc_pat(pattern, Either::Left(ptr))
+        let id = self.alloc_pat(pattern, Either::Left(ptr));
+        if let ast::Pat::BindPat(bp) = &pat {
+            self.record_pat_context(id, bp.name().and_then(|it| it.syntax().first_token()));
+        }
+        id
+    }
+
+    fn collect_tuple_pat(
+        &mut self,
+        args: ast::AstChildren<ast::Pat>,
+    ) -> (Vec<PatId>, Option<usize>) {
+        let mut ellipsis = None;
+        let mut pats = Vec::new();
+        for arg in args {
+            match arg {
+                ast::Pat::DotDotPat(_) if ellipsis.is_none() => ellipsis = Some(pats.len()),
+                _ => pats.push(self.collect_pat(arg)),
+            }
+        }
+        (pats, ellipsis)
+    }
+
+    fn collect_literal_pat(&mut self, pat: &ast::LiteralPat) -> ExprId {
+        // The literal is part of the pattern, so it doesn't get a source of its own.
+        let lit = match pat.literal() {
+            Some(it) => self.alloc_expr_desugared(Expr::Literal(lower_literal(&it))),
+            None => return self.missing_expr(),
+        };
+        if pat.is_negative() {
+            self.alloc_expr_desugared(Expr::UnaryOp { expr: lit, op: UnaryOp::Neg })
+        } else {
+            lit
+        }
+    }
+
+    fn collect_range_pat_end(&mut self, pat: Option<ast::Pat>) -> ExprId {
+        match pat {
+            Some(ast::Pat::LiteralPat(p)) => self.collect_literal_pat(&p),
+            Some(ast::Pat::PathPat(p)) => {
+                match p.path().and_then(|path| self.expander.parse_path(path)) {
+                    Some(path) => self.alloc_expr_desugared(Expr::Path(path)),
+                    None => self.missing_expr(),
+                }
+            }
+            _ => self.missing_expr(),
+        }
     }
 
     fn collect_pat_opt(&mut self, pat: Option<ast::Pat>) -> PatId {
@@ -561,6 +665,43 @@ where
     }
 }
 
+/// The name of the label (e.g. `'outer`) attached to a loop, if any.
+fn collect_label(label: Option<ast::Label>) -> Option<Name> {
+    label.and_then(|it| it.lifetime_token()).map(|lt| Name::new_lifetime(&lt))
+}
+
+fn lower_literal(lit: &ast::Literal) -> Literal {
+    match lit.kind() {
+        LiteralKind::IntNumber { suffix } => {
+            let known_name = suffix.and_then(|it| BuiltinInt::from_suffix(&it));
+
+            Literal::Int(Default::default(), known_name)
+        }
+        LiteralKind::FloatNumber { suffix } => {
+            let known_name = suffix.and_then(|it| BuiltinFloat::from_suffix(&it));
+
+            Literal::Float(Default::default(), known_name)
+        }
+        LiteralKind::ByteString => Literal::ByteString(Default::default()),
+        LiteralKind::String => Literal::String(Default::default()),
+        LiteralKind::Byte => Literal::Int(Default::default(), Some(BuiltinInt::U8)),
+        LiteralKind::Bool => Literal::Bool(Default::default()),
+        LiteralKind::Char => Literal::Char(Default::default()),
+    }
+}
+
+/// Whether `pat` matches the rest of a slice: `..` or `name @ ..`.
+fn is_rest_pat(pat: &ast::Pat) -> bool {
+    match pat {
+        ast::Pat::DotDotPat(_) => true,
+        ast::Pat::BindPat(it) => match it.pat() {
+            Some(ast::Pat::DotDotPat(_)) => true,
+            _ => false,
+        },
+        _ => false,
+    }
+}
+
 impl From<ast::BinOp> for BinaryOp {
     fn from(ast_op: ast::BinOp) -> Self {
         match ast_op {
@@ -1,7 +1,7 @@
 //! Name resolution for expressions.
 use std::sync::Arc;
 
-use hir_expand::name::Name;
+use hir_expand::{hygiene::SyntaxContext, name::Name};
 use ra_arena::{impl_arena_id, Arena, RawId};
 use rustc_hash::FxHashMap;
 
@@ -9,7 +9,7 @@ use crate::{
     body::Body,
     db::DefDatabase,
     expr::{Expr, ExprId, Pat, PatId, Statement},
-    DefWithBodyId,
+    BlockId, DefWithBodyId,
 };
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
@@ -25,6 +25,7 @@ pub struct ExprScopes {
 #[derive(Debug, PartialEq, Eq)]
 pub struct ScopeEntry {
     name: Name,
+    context: SyntaxContext,
     pat: PatId,
 }
 
@@ -33,14 +34,26 @@ impl ScopeEntry {
         &self.name
     }
 
+    /// Only identifiers with the same syntax context can refer to the binding.
+    pub fn context(&self) -> SyntaxContext {
+        self.context
+    }
+
     pub fn pat(&self) -> PatId {
         self.pat
     }
+
+    pub fn is_visible_from(&self, name: &Name, context: SyntaxContext) -> bool {
+        self.name == *name && self.context == context
+    }
 }
 
 #[derive(Debug, PartialEq, Eq)]
 pub struct ScopeData {
     parent: Option<ScopeId>,
+    /// The block whose `DefMap` holds the items declared in this scope, if it
+    /// is the scope of a block with items.
+    block: Option<BlockId>,
     entries: Vec<ScopeEntry>,
 }
 
@@ -63,13 +76,23 @@ impl ExprScopes {
         &self.scopes[scope].entries
     }
 
+    pub fn block(&self, scope: ScopeId) -> Option<BlockId> {
+        self.scopes[scope].block
+    }
+
     pub fn scope_chain(&self, scope: Option<ScopeId>) -> impl Iterator<Item = ScopeId> + '_ {
         std::iter::successors(scope, move |&scope| self.scopes[scope].parent)
     }
 
-    pub fn resolve_name_in_scope(&self, scope: ScopeId, name: &Name) -> Option<&ScopeEntry> {
-        self.scope_chain(Some(scope))
-            .find_map(|scope| self.entries(scope).iter().find(|it| it.name == *name))
+    pub fn resolve_name_in_scope(
+        &self,
+        scope: ScopeId,
+        name: &Name,
+        context: SyntaxContext,
+    ) -> Option<&ScopeEntry> {
+        self.scope_chain(Some(scope)).find_map(|scope| {
+            self.entries(scope).iter().find(|it| it.is_visible_from(name, context))
+        })
     }
 
     pub fn scope_for(&self, expr: ExprId) -> Option<ScopeId> {
@@ -81,11 +104,15 @@ impl ExprScopes {
     }
 
     fn root_scope(&mut self) -> ScopeId {
-        self.scopes.alloc(ScopeData { parent: None, entries: vec![] })
+        self.scopes.alloc(ScopeData { parent: None, block: None, entries: vec![] })
     }
 
     fn new_scope(&mut self, parent: ScopeId) -> ScopeId {
-        self.scopes.alloc(ScopeData { parent: Some(parent), entries: vec![] })
+        self.scopes.alloc(ScopeData { parent: Some(parent), block: None, entries: vec![] })
+    }
+
+    fn new_block_scope(&mut self, parent: ScopeId, block: BlockId) -> ScopeId {
+        self.scopes.alloc(ScopeData { parent: Some(parent), block: Some(block), entries: vec![] })
     }
 
     fn add_bindings(&mut self, body: &Body, scope: ScopeId, pat: PatId) {
@@ -93,7 +120,7 @@ impl ExprScopes {
             Pat::Bind { name, .. } => {
                 // bind can have a sub pattern, but it's actually not allowed
                 // to bind to things in there
-                let entry = ScopeEntry { name: name.clone(), pat };
+                let entry = ScopeEntry { name: name.clone(), context: body.pat_context(pat), pat };
                 self.scopes[scope].entries.push(entry)
             }
             p => p.walk_child_pats(|pat| self.add_bindings(body, scope, pat)),
@@ -141,9 +168,13 @@ fn compute_expr_scopes(expr: ExprId, body: &Body, scopes: &mut ExprScopes, scope
     scopes.set_scope(expr, scope);
     match &body[expr] {
         Expr::Block { statements, tail } => {
+            let scope = match body.block_scopes.get(&expr) {
+                Some(&block) => scopes.new_block_scope(scope, block),
+                None => scope,
+            };
             compute_block_scopes(&statements, *tail, body, scopes, scope);
         }
-        Expr::For { iterable, pat, body: body_expr } => {
+        Expr::For { iterable, pat, body: body_expr, .. } => {
             compute_expr_scopes(*iterable, body, scopes, scope);
             let scope = scopes.new_scope(scope);
             scopes.add_bindings(body, scope, *pat);
@@ -171,7 +202,7 @@ fn compute_expr_scopes(expr: ExprId, body: &Body, scopes: &mut ExprScopes, scope
 
 #[cfg(test)]
 mod tests {
-    use hir_expand::{name::AsName, InFile};
+    use hir_expand::{hygiene::SyntaxContext, name::AsName, InFile};
     use ra_db::{fixture::WithFixture, FileId, SourceDatabase};
     use ra_syntax::{algo::find_node_at_offset, ast, AstNode};
     use test_utils::{assert_eq_text, covers, extract_offset};
@@ -289,6 +320,21 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_match_slice_and_box_patterns() {
+        do_check(
+            r"
+            fn quux() {
+                match () {
+                    [first, rest @ .., box last] => {
+                        <|>
+                    }
+                };
+            }",
+            &["first", "rest", "last"],
+        );
+    }
+
     #[test]
     fn test_shadow_variable() {
         do_check(
@@ -322,7 +368,9 @@ mod tests {
             scopes.scope_for(expr_id).unwrap()
         };
 
-        let resolved = scopes.resolve_name_in_scope(expr_scope, &name_ref.as_name()).unwrap();
+        let resolved = scopes
+            .resolve_name_in_scope(expr_scope, &name_ref.as_name(), SyntaxContext::ROOT)
+            .unwrap();
         let pat_src = source_map.pat_syntax(resolved.pat()).unwrap();
 
         let local_name = pat_src.value.either(|it| it.syntax_node_ptr(), |it| it.syntax_node_ptr());
@@ -75,8 +75,8 @@ impl ChildBySource for ModuleId {
     fn child_by_source(&self, db: &impl DefDatabase) -> DynMap {
         let mut res = DynMap::default();
 
-        let crate_def_map = db.crate_def_map(self.krate);
-        for item in crate_def_map[self.local_id].scope.declarations() {
+        let def_map = self.def_map(db);
+        for item in def_map[self.local_id].scope.declarations() {
             match item {
                 ModuleDefId::FunctionId(func) => {
                     let src = func.lookup(db).source(db);
@@ -4,14 +4,15 @@ use std::sync::Arc;
 
 use hir_expand::{
     name::{self, AsName, Name},
-    AstId,
+    AstId, InFile,
 };
-use ra_syntax::ast::{self, NameOwner, TypeAscriptionOwner};
+use ra_syntax::ast::{self, NameOwner, TypeAscriptionOwner, VisibilityOwner};
 
 use crate::{
     db::DefDatabase,
     src::HasSource,
     type_ref::{Mutability, TypeRef},
+    visibility::RawVisibility,
     AssocItemId, AstItemDef, ConstId, ConstLoc, ContainerId, FunctionId, FunctionLoc, ImplId,
     Intern, Lookup, StaticId, TraitId, TypeAliasId, TypeAliasLoc,
 };
@@ -24,11 +25,13 @@ pub struct FunctionData {
     /// True if the first param is `self`. This is relevant to decide whether this
     /// can be called as a method.
     pub has_self_param: bool,
+    pub visibility: RawVisibility,
 }
 
 impl FunctionData {
     pub(crate) fn fn_data_query(db: &impl DefDatabase, func: FunctionId) -> Arc<FunctionData> {
-        let src = func.lookup(db).source(db);
+        let loc = func.lookup(db);
+        let src = loc.source(db);
         let name = src.value.name().map(|n| n.as_name()).unwrap_or_else(Name::missing);
         let mut params = Vec::new();
         let mut has_self_param = false;
@@ -62,7 +65,11 @@ impl FunctionData {
             TypeRef::unit()
         };
 
-        let sig = FunctionData { name, params, ret_type, has_self_param };
+        let vis_default = RawVisibility::default_for_container(db, loc.container);
+        let visibility =
+            RawVisibility::from_ast_with_default(db, vis_default, src.map(|s| s.visibility()));
+
+        let sig = FunctionData { name, params, ret_type, has_self_param, visibility };
         Arc::new(sig)
     }
 }
@@ -71,6 +78,7 @@ impl FunctionData {
 pub struct TypeAliasData {
     pub name: Name,
     pub type_ref: Option<TypeRef>,
+    pub visibility: RawVisibility,
 }
 
 impl TypeAliasData {
@@ -78,10 +86,14 @@ impl TypeAliasData {
         db: &impl DefDatabase,
         typ: TypeAliasId,
     ) -> Arc<TypeAliasData> {
-        let node = typ.lookup(db).source(db).value;
-        let name = node.name().map_or_else(Name::missing, |n| n.as_name());
-        let type_ref = node.type_ref().map(TypeRef::from_ast);
-        Arc::new(TypeAliasData { name, type_ref })
+        let loc = typ.lookup(db);
+        let node = loc.source(db);
+        let name = node.value.name().map_or_else(Name::missing, |n| n.as_name());
+        let type_ref = node.value.type_ref().map(TypeRef::from_ast);
+        let vis_default = RawVisibility::default_for_container(db, loc.container);
+        let visibility =
+            RawVisibility::from_ast_with_default(db, vis_default, node.map(|n| n.visibility()));
+        Arc::new(TypeAliasData { name, type_ref, visibility })
     }
 }
 
@@ -218,22 +230,31 @@ pub struct ConstData {
     /// const _: () = ();
     pub name: Option<Name>,
     pub type_ref: TypeRef,
+    pub visibility: RawVisibility,
 }
 
 impl ConstData {
     pub(crate) fn const_data_query(db: &impl DefDatabase, konst: ConstId) -> Arc<ConstData> {
-        let node = konst.lookup(db).source(db).value;
-        Arc::new(ConstData::new(&node))
+        let loc = konst.lookup(db);
+        let node = loc.source(db);
+        let vis_default = RawVisibility::default_for_container(db, loc.container);
+        Arc::new(ConstData::new(db, vis_default, node))
     }
 
     pub(crate) fn static_data_query(db: &impl DefDatabase, konst: StaticId) -> Arc<ConstData> {
-        let node = konst.lookup(db).source(db).value;
-        Arc::new(ConstData::new(&node))
+        let node = konst.lookup(db).source(db);
+        Arc::new(ConstData::new(db, RawVisibility::private(), node))
     }
 
-    fn new<N: NameOwner + TypeAscriptionOwner>(node: &N) -> ConstData {
-        let name = node.name().map(|n| n.as_name());
-        let type_ref = TypeRef::from_ast_opt(node.ascribed_type());
-        ConstData { name, type_ref }
+    fn new<N: NameOwner + TypeAscriptionOwner + VisibilityOwner>(
+        db: &impl DefDatabase,
+        vis_default: RawVisibility,
+        node: InFile<N>,
+    ) -> ConstData {
+        let name = node.value.name().map(|n| n.as_name());
+        let type_ref = TypeRef::from_ast_opt(node.value.ascribed_type());
+        let visibility =
+            RawVisibility::from_ast_with_default(db, vis_default, node.map(|n| n.visibility()));
+        ConstData { name, type_ref, visibility }
     }
 }
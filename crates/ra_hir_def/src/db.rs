@@ -1,7 +1,7 @@
 //! Defines database & queries for name resolution.
 use std::sync::Arc;
 
-use hir_expand::{db::AstDatabase, HirFileId};
+use hir_expand::{db::AstDatabase, AstId, HirFileId};
 use ra_db::{salsa, CrateId, SourceDatabase};
 use ra_syntax::{ast, SmolStr};
 
@@ -17,9 +17,9 @@ use crate::{
         raw::{ImportSourceMap, RawItems},
         CrateDefMap,
     },
-    AttrDefId, ConstId, ConstLoc, DefWithBodyId, EnumId, FunctionId, FunctionLoc, GenericDefId,
-    ImplId, ItemLoc, ModuleId, StaticId, StaticLoc, StructId, TraitId, TypeAliasId, TypeAliasLoc,
-    UnionId,
+    AttrDefId, BlockId, BlockLoc, ConstId, ConstLoc, DefWithBodyId, EnumId, FunctionId,
+    FunctionLoc, GenericDefId, ImplId, ItemLoc, ModuleId, StaticId, StaticLoc, StructId, TraitId,
+    TypeAliasId, TypeAliasLoc, UnionId,
 };
 
 #[salsa::query_group(InternDatabaseStorage)]
@@ -42,6 +42,8 @@ pub trait InternDatabase: SourceDatabase {
     fn intern_type_alias(&self, loc: TypeAliasLoc) -> TypeAliasId;
     #[salsa::interned]
     fn intern_impl(&self, loc: ItemLoc<ast::ImplBlock>) -> ImplId;
+    #[salsa::interned]
+    fn intern_block(&self, loc: BlockLoc) -> BlockId;
 }
 
 #[salsa::query_group(DefDatabaseStorage)]
@@ -55,9 +57,18 @@ pub trait DefDatabase: InternDatabase + AstDatabase {
     #[salsa::invoke(RawItems::raw_items_query)]
     fn raw_items(&self, file_id: HirFileId) -> Arc<RawItems>;
 
+    #[salsa::invoke(RawItems::block_raw_items_query)]
+    fn block_raw_items(&self, block: AstId<ast::BlockExpr>) -> Arc<RawItems>;
+
     #[salsa::invoke(CrateDefMap::crate_def_map_query)]
     fn crate_def_map(&self, krate: CrateId) -> Arc<CrateDefMap>;
 
+    /// The `DefMap` of the items declared in a block expression. It is computed
+    /// separately from the `crate_def_map`, so that editing items in a function
+    /// body doesn't invalidate the name resolution of the whole crate.
+    #[salsa::invoke(CrateDefMap::block_def_map_query)]
+    fn block_def_map(&self, block: BlockId) -> Arc<CrateDefMap>;
+
     #[salsa::invoke(StructData::struct_data_query)]
     fn struct_data(&self, id: StructId) -> Arc<StructData>;
     #[salsa::invoke(StructData::union_data_query)]
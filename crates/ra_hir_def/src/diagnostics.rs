@@ -16,6 +16,9 @@ pub struct UnresolvedModule {
 }
 
 impl Diagnostic for UnresolvedModule {
+    fn code(&self) -> &'static str {
+        "unresolved-module"
+    }
     fn message(&self) -> String {
         "unresolved module".to_string()
     }
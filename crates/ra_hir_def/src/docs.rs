@@ -39,7 +39,7 @@ impl Documentation {
     ) -> Option<Documentation> {
         match def {
             AttrDefId::ModuleId(module) => {
-                let def_map = db.crate_def_map(module.krate);
+                let def_map = module.def_map(db);
                 let src = def_map[module.local_id].declaration_source(db)?;
                 docs_from_ast(&src.value)
             }
@@ -60,7 +60,10 @@ impl Documentation {
                 docs_from_ast(&src.value[it.local_id])
             }
             AttrDefId::TraitId(it) => docs_from_ast(&it.source(db).value),
-            AttrDefId::MacroDefId(it) => docs_from_ast(&it.ast_id?.to_node(db)),
+            AttrDefId::MacroDefId(it) => match it.ast_id? {
+                Either::Left(it) => docs_from_ast(&it.to_node(db)),
+                Either::Right(it) => docs_from_ast(&it.to_node(db)),
+            },
             AttrDefId::ConstId(it) => docs_from_ast(&it.lookup(db).source(db).value),
             AttrDefId::StaticId(it) => docs_from_ast(&it.lookup(db).source(db).value),
             AttrDefId::FunctionId(it) => docs_from_ast(&it.lookup(db).source(db).value),
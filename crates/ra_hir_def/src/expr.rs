@@ -62,15 +62,18 @@ pub enum Expr {
     },
     Loop {
         body: ExprId,
+        label: Option<Name>,
     },
     While {
         condition: ExprId,
         body: ExprId,
+        label: Option<Name>,
     },
     For {
         iterable: ExprId,
         pat: PatId,
         body: ExprId,
+        label: Option<Name>,
     },
     Call {
         callee: ExprId,
@@ -86,9 +89,12 @@ pub enum Expr {
         expr: ExprId,
         arms: Vec<MatchArm>,
     },
-    Continue,
+    Continue {
+        label: Option<Name>,
+    },
     Break {
         expr: Option<ExprId>,
+        label: Option<Name>,
     },
     Return {
         expr: Option<ExprId>,
@@ -111,6 +117,10 @@ pub enum Expr {
     TryBlock {
         body: ExprId,
     },
+    /// An `async` block, or the body of an `async` closure.
+    Async {
+        body: ExprId,
+    },
     Cast {
         expr: ExprId,
         type_ref: TypeRef,
@@ -144,6 +154,7 @@ pub enum Expr {
         args: Vec<PatId>,
         arg_types: Vec<Option<TypeRef>>,
         body: ExprId,
+        is_move: bool,
     },
     Tuple {
         exprs: Vec<ExprId>,
@@ -245,9 +256,9 @@ impl Expr {
                     f(*expr);
                 }
             }
-            Expr::TryBlock { body } => f(*body),
-            Expr::Loop { body } => f(*body),
-            Expr::While { condition, body } => {
+            Expr::TryBlock { body } | Expr::Async { body } => f(*body),
+            Expr::Loop { body, .. } => f(*body),
+            Expr::While { condition, body, .. } => {
                 f(*condition);
                 f(*body);
             }
@@ -273,8 +284,8 @@ impl Expr {
                     f(arm.expr);
                 }
             }
-            Expr::Continue => {}
-            Expr::Break { expr } | Expr::Return { expr } => {
+            Expr::Continue { .. } => {}
+            Expr::Break { expr, .. } | Expr::Return { expr } => {
                 if let Some(expr) = expr {
                     f(*expr);
                 }
@@ -380,7 +391,11 @@ pub struct RecordFieldPat {
 pub enum Pat {
     Missing,
     Wild,
-    Tuple(Vec<PatId>),
+    Tuple {
+        args: Vec<PatId>,
+        /// The position of the `..` among `args`, if any.
+        ellipsis: Option<usize>,
+    },
     Record {
         path: Option<Path>,
         args: Vec<RecordFieldPat>,
@@ -392,6 +407,7 @@ pub enum Pat {
     },
     Slice {
         prefix: Vec<PatId>,
+        /// The `..` or `name @ ..` pattern matching the rest of the slice.
         rest: Option<PatId>,
         suffix: Vec<PatId>,
     },
@@ -405,11 +421,16 @@ pub enum Pat {
     TupleStruct {
         path: Option<Path>,
         args: Vec<PatId>,
+        /// The position of the `..` among `args`, if any.
+        ellipsis: Option<usize>,
     },
     Ref {
         pat: PatId,
         mutability: Mutability,
     },
+    Box {
+        inner: PatId,
+    },
 }
 
 impl Pat {
@@ -419,10 +440,11 @@ impl Pat {
             Pat::Bind { subpat, .. } => {
                 subpat.iter().copied().for_each(f);
             }
-            Pat::Tuple(args) | Pat::TupleStruct { args, .. } => {
+            Pat::Tuple { args, .. } | Pat::TupleStruct { args, .. } => {
                 args.iter().copied().for_each(f);
             }
             Pat::Ref { pat, .. } => f(*pat),
+            Pat::Box { inner } => f(*inner),
             Pat::Slice { prefix, rest, suffix } => {
                 let total_iter = prefix.iter().chain(rest.iter()).chain(suffix.iter());
                 total_iter.copied().for_each(f);
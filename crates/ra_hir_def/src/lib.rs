@@ -15,6 +15,7 @@ pub mod type_ref;
 pub mod builtin_type;
 pub mod diagnostics;
 pub mod per_ns;
+pub mod visibility;
 
 pub mod dyn_map;
 pub mod keys;
@@ -56,6 +57,9 @@ impl_arena_id!(LocalImportId);
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub struct ModuleId {
     pub krate: CrateId,
+    /// The block expression whose `DefMap` holds this module, for the items
+    /// declared in blocks (and the modules nested in those).
+    pub block: Option<BlockId>,
     pub local_id: LocalModuleId,
 }
 
@@ -65,6 +69,64 @@ pub struct ModuleId {
 pub struct LocalModuleId(RawId);
 impl_arena_id!(LocalModuleId);
 
+impl ModuleId {
+    /// The `DefMap` this module belongs to: the crate's, or the one of the
+    /// block it is declared in.
+    pub fn def_map(&self, db: &impl db::DefDatabase) -> std::sync::Arc<nameres::CrateDefMap> {
+        match self.block {
+            Some(block) => db.block_def_map(block),
+            None => db.crate_def_map(self.krate),
+        }
+    }
+
+    /// The closest enclosing module which is a module in the language, that
+    /// is, which is not a block.
+    pub fn containing_module(self, db: &impl db::DefDatabase) -> ModuleId {
+        let mut module = self;
+        while let Some(block) = module.block {
+            let def_map = db.block_def_map(block);
+            if module.local_id != def_map.root {
+                break;
+            }
+            module = block.lookup(db).module;
+        }
+        module
+    }
+
+    /// The parent of this module, skipping over blocks.
+    pub fn parent(self, db: &impl db::DefDatabase) -> Option<ModuleId> {
+        let def_map = self.def_map(db);
+        let parent = def_map.parent_module(self.local_id)?;
+        Some(parent.containing_module(db))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct BlockId(salsa::InternId);
+impl_intern_key!(BlockId);
+
+/// A block expression which declares items, and so has its own `DefMap`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct BlockLoc {
+    pub ast_id: AstId<ast::BlockExpr>,
+    /// The module (or block) containing the block.
+    pub module: ModuleId,
+}
+
+impl Intern for BlockLoc {
+    type ID = BlockId;
+    fn intern(self, db: &impl db::DefDatabase) -> BlockId {
+        db.intern_block(self)
+    }
+}
+
+impl Lookup for BlockId {
+    type Data = BlockLoc;
+    fn lookup(&self, db: &impl db::DefDatabase) -> BlockLoc {
+        db.lookup_intern_block(*self)
+    }
+}
+
 #[derive(Debug)]
 pub struct ItemLoc<N: AstNode> {
     pub(crate) module: ModuleId,
@@ -544,3 +606,34 @@ impl HasModule for StaticLoc {
         self.container
     }
 }
+
+impl HasModule for VariantId {
+    fn module(&self, db: &impl db::DefDatabase) -> ModuleId {
+        match self {
+            VariantId::EnumVariantId(it) => it.parent.module(db),
+            VariantId::StructId(it) => it.module(db),
+            VariantId::UnionId(it) => it.module(db),
+        }
+    }
+}
+
+impl AttrDefId {
+    /// The crate the item with the attributes is defined in. Built-in
+    /// derives don't belong to any crate.
+    pub fn krate(&self, db: &impl db::DefDatabase) -> Option<CrateId> {
+        let module = match self {
+            AttrDefId::ModuleId(it) => *it,
+            AttrDefId::StructFieldId(it) => it.parent.module(db),
+            AttrDefId::AdtId(it) => it.module(db),
+            AttrDefId::FunctionId(it) => it.lookup(db).module(db),
+            AttrDefId::EnumVariantId(it) => it.parent.module(db),
+            AttrDefId::StaticId(it) => it.lookup(db).module(db),
+            AttrDefId::ConstId(it) => it.lookup(db).module(db),
+            AttrDefId::TraitId(it) => it.module(db),
+            AttrDefId::TypeAliasId(it) => it.lookup(db).module(db),
+            AttrDefId::MacroDefId(it) => return it.krate,
+            AttrDefId::ImplId(it) => it.module(db),
+        };
+        Some(module.krate)
+    }
+}
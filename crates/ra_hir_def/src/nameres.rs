@@ -46,6 +46,14 @@
 //! unexpanded macros. On every iteration, we try to resolve each macro call
 //! path and, upon success, we run macro expansion and "collect module" phase on
 //! the result
+//!
+//! ## Blocks
+//!
+//! Items declared in a block expression (say, in a function body) are not part
+//! of the `CrateDefMap` of the crate. Instead, each block with items gets its
+//! own `CrateDefMap`, computed lazily by the `block_def_map` query. Names which
+//! are not found in such a map are looked up in the map of the containing
+//! block or module.
 
 pub(crate) mod raw;
 mod collector;
@@ -76,7 +84,9 @@ use crate::{
     nameres::{diagnostics::DefDiagnostic, path_resolution::ResolveMode},
     path::Path,
     per_ns::PerNs,
-    AstId, FunctionId, ImplId, LocalImportId, LocalModuleId, ModuleDefId, ModuleId, TraitId,
+    visibility::Visibility,
+    AstId, BlockId, FunctionId, ImplId, LocalImportId, LocalModuleId, Lookup, ModuleDefId,
+    ModuleId, TraitId,
 };
 
 /// Contains all top-level defs from a macro-expanded crate
@@ -90,11 +100,20 @@ pub struct CrateDefMap {
     /// a dependency (`std` or `core`).
     pub(crate) prelude: Option<ModuleId>,
     pub(crate) extern_prelude: FxHashMap<Name, ModuleDefId>,
+    /// For the `DefMap` of a block expression, the block and the module (or
+    /// block) containing it.
+    pub(crate) block: Option<BlockInfo>,
 
     edition: Edition,
     diagnostics: Vec<DefDiagnostic>,
 }
 
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub(crate) struct BlockInfo {
+    pub(crate) block: BlockId,
+    pub(crate) parent: ModuleId,
+}
+
 impl std::ops::Index<LocalModuleId> for CrateDefMap {
     type Output = ModuleData;
     fn index(&self, id: LocalModuleId) -> &ModuleData {
@@ -115,6 +134,11 @@ pub enum ModuleOrigin {
     Inline {
         definition: AstId<ast::Module>,
     },
+    /// The root of the `DefMap` of a block expression. It isn't a module in the
+    /// language, it just holds the items declared in the block.
+    Block {
+        block: BlockId,
+    },
 }
 
 impl Default for ModuleOrigin {
@@ -135,7 +159,7 @@ impl ModuleOrigin {
         match self {
             ModuleOrigin::File { declaration: module, .. }
             | ModuleOrigin::Inline { definition: module, .. } => Some(*module),
-            ModuleOrigin::CrateRoot { .. } => None,
+            ModuleOrigin::CrateRoot { .. } | ModuleOrigin::Block { .. } => None,
         }
     }
 
@@ -149,7 +173,8 @@ impl ModuleOrigin {
     }
 
     /// Returns a node which defines this module.
-    /// That is, a file or a `mod foo {}` with items.
+    /// That is, a file or a `mod foo {}` with items. For a block, this is the
+    /// source of the module containing it.
     fn definition_source(&self, db: &impl DefDatabase) -> InFile<ModuleSource> {
         match self {
             ModuleOrigin::File { definition, .. } | ModuleOrigin::CrateRoot { definition } => {
@@ -160,6 +185,10 @@ impl ModuleOrigin {
             ModuleOrigin::Inline { definition } => {
                 InFile::new(definition.file_id, ModuleSource::Module(definition.to_node(db)))
             }
+            ModuleOrigin::Block { block } => {
+                let module = block.lookup(db).module.containing_module(db);
+                module.def_map(db)[module.local_id].definition_source(db)
+            }
         }
     }
 }
@@ -174,6 +203,12 @@ pub struct ModuleData {
     pub origin: ModuleOrigin,
 
     pub impls: Vec<ImplId>,
+    /// Blocks with items in the bodies of the items of this module. Each of
+    /// them has its own `DefMap`.
+    pub blocks: Vec<BlockId>,
+    /// The legacy macros visible in each of `blocks`, that is, the ones defined
+    /// textually before the block.
+    pub(crate) block_legacy_macros: FxHashMap<BlockId, FxHashMap<Name, MacroDefId>>,
 }
 
 #[derive(Default, Debug, PartialEq, Eq)]
@@ -203,7 +238,13 @@ static BUILTIN_SCOPE: Lazy<FxHashMap<Name, Resolution>> = Lazy::new(|| {
     BuiltinType::ALL
         .iter()
         .map(|(name, ty)| {
-            (name.clone(), Resolution { def: PerNs::types(ty.clone().into()), import: None })
+            (
+                name.clone(),
+                Resolution {
+                    def: PerNs::types(ty.clone().into(), Visibility::Public),
+                    import: None,
+                },
+            )
         })
         .collect()
 });
@@ -262,6 +303,18 @@ impl ModuleScope {
         }
     }
 
+    /// Iterate over the names declared or imported in this scope, without the builtin types
+    pub(crate) fn local_entries<'a>(
+        &'a self,
+    ) -> impl Iterator<Item = (&'a Name, &'a Resolution)> + 'a {
+        self.items.iter()
+    }
+
+    /// Whether `name` is declared or imported in this scope, or is a legacy macro
+    pub(crate) fn contains_name(&self, name: &Name) -> bool {
+        self.items.contains_key(name) || self.legacy_macros.contains_key(name)
+    }
+
     pub fn traits<'a>(&'a self) -> impl Iterator<Item = TraitId> + 'a {
         self.items.values().filter_map(|r| match r.def.take_types() {
             Some(ModuleDefId::TraitId(t)) => Some(t),
@@ -302,6 +355,7 @@ impl CrateDefMap {
                 prelude: None,
                 root,
                 modules,
+                block: None,
                 diagnostics: Vec::new(),
             }
         };
@@ -309,6 +363,33 @@ impl CrateDefMap {
         Arc::new(def_map)
     }
 
+    pub(crate) fn block_def_map_query(db: &impl DefDatabase, block: BlockId) -> Arc<CrateDefMap> {
+        let _p = profile("block_def_map_query");
+        let parent = block.lookup(db).module;
+        let parent_map = parent.def_map(db);
+        let mut modules: Arena<LocalModuleId, ModuleData> = Arena::default();
+        let root = modules.alloc(ModuleData::default());
+        modules[root].origin = ModuleOrigin::Block { block };
+        // Macros defined before the block are visible in it.
+        modules[root].scope.legacy_macros = parent_map[parent.local_id]
+            .block_legacy_macros
+            .get(&block)
+            .cloned()
+            .unwrap_or_default();
+        let def_map = CrateDefMap {
+            krate: parent.krate,
+            edition: parent_map.edition,
+            extern_prelude: parent_map.extern_prelude.clone(),
+            prelude: parent_map.prelude,
+            root,
+            modules,
+            block: Some(BlockInfo { block, parent }),
+            diagnostics: Vec::new(),
+        };
+        let def_map = collector::collect_defs(db, def_map);
+        Arc::new(def_map)
+    }
+
     pub fn add_diagnostics(
         &self,
         db: &impl DefDatabase,
@@ -325,6 +406,10 @@ impl CrateDefMap {
             .map(|(id, _data)| id)
     }
 
+    pub fn module_id(&self, local_id: LocalModuleId) -> ModuleId {
+        ModuleId { krate: self.krate, block: self.block.map(|it| it.block), local_id }
+    }
+
     pub(crate) fn resolve_path(
         &self,
         db: &impl DefDatabase,
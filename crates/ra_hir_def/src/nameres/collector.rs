@@ -3,15 +3,17 @@
 //! `DefCollector::collect` contains the fixed-point iteration loop which
 //! resolves imports and expands macros.
 
+use either::Either;
 use hir_expand::{
     builtin_derive::find_builtin_derive,
     builtin_macro::find_builtin_macro,
     name::{self, AsName, Name},
+    proc_macro::ProcMacroExpander,
     HirFileId, MacroCallId, MacroCallKind, MacroDefId, MacroDefKind,
 };
 use ra_cfg::CfgOptions;
-use ra_db::{CrateId, FileId};
-use ra_syntax::ast;
+use ra_db::{CrateId, FileId, ProcMacroId, ProcMacroKind};
+use ra_syntax::{ast, SmolStr};
 use rustc_hash::FxHashMap;
 use test_utils::tested_by;
 
@@ -24,30 +26,33 @@ use crate::{
     },
     path::{Path, PathKind},
     per_ns::PerNs,
-    AdtId, AstId, AstItemDef, ConstLoc, ContainerId, EnumId, EnumVariantId, FunctionLoc, ImplId,
-    Intern, LocalImportId, LocalModuleId, LocationCtx, ModuleDefId, ModuleId, StaticLoc, StructId,
-    TraitId, TypeAliasLoc, UnionId,
+    visibility::{RawVisibility, Visibility},
+    AdtId, AstId, AstItemDef, BlockLoc, ConstLoc, ContainerId, EnumId, EnumVariantId, FunctionLoc,
+    ImplId, Intern, LocalImportId, LocalModuleId, LocationCtx, Lookup, ModuleDefId, ModuleId,
+    StaticLoc, StructId, TraitId, TypeAliasLoc, UnionId,
 };
 
 pub(super) fn collect_defs(db: &impl DefDatabase, mut def_map: CrateDefMap) -> CrateDefMap {
     let crate_graph = db.crate_graph();
 
-    // populate external prelude
-    for dep in crate_graph.dependencies(def_map.krate) {
-        let dep_def_map = db.crate_def_map(dep.crate_id);
-        log::debug!("crate dep {:?} -> {:?}", dep.name, dep.crate_id);
-        def_map.extern_prelude.insert(
-            dep.as_name(),
-            ModuleId { krate: dep.crate_id, local_id: dep_def_map.root }.into(),
-        );
+    // populate external prelude (the `DefMap` of a block inherits it)
+    if def_map.block.is_none() {
+        for dep in crate_graph.dependencies(def_map.krate) {
+            let dep_def_map = db.crate_def_map(dep.crate_id);
+            log::debug!("crate dep {:?} -> {:?}", dep.name, dep.crate_id);
+            def_map.extern_prelude.insert(
+                dep.as_name(),
+                ModuleId { krate: dep.crate_id, block: None, local_id: dep_def_map.root }.into(),
+            );
 
-        // look for the prelude
-        // If the dependency defines a prelude, we overwrite an already defined
-        // prelude. This is necessary to import the "std" prelude if a crate
-        // depends on both "core" and "std".
-        let dep_def_map = db.crate_def_map(dep.crate_id);
-        if dep_def_map.prelude.is_some() {
-            def_map.prelude = dep_def_map.prelude;
+            // look for the prelude
+            // If the dependency defines a prelude, we overwrite an already defined
+            // prelude. This is necessary to import the "std" prelude if a crate
+            // depends on both "core" and "std".
+            let dep_def_map = db.crate_def_map(dep.crate_id);
+            if dep_def_map.prelude.is_some() {
+                def_map.prelude = dep_def_map.prelude;
+            }
         }
     }
 
@@ -61,7 +66,8 @@ pub(super) fn collect_defs(db: &impl DefDatabase, mut def_map: CrateDefMap) -> C
         resolved_imports: Vec::new(),
 
         unexpanded_macros: Vec::new(),
-        unexpanded_attribute_macros: Vec::new(),
+        unexpanded_derives: Vec::new(),
+        unexpanded_attr_macros: Vec::new(),
         mod_dirs: FxHashMap::default(),
         cfg_options,
     };
@@ -105,15 +111,36 @@ struct MacroDirective {
     legacy: Option<MacroCallId>,
 }
 
+/// An item with attributes which might be attribute macros. If none of
+/// `paths` resolves to an attribute macro, the item is defined as is.
+#[derive(Clone, Debug, Eq, PartialEq)]
+struct AttrMacroDirective {
+    module_id: LocalModuleId,
+    ast_id: AstId<ast::ModuleItem>,
+    paths: Vec<Path>,
+    def: raw::Def,
+    attrs: Attrs,
+}
+
+enum AttrMacroResolution {
+    /// The attribute macro and the name of the attribute which invoked it.
+    Macro(MacroDefId, Name),
+    /// None of the attributes is an attribute macro.
+    Inert,
+    /// Some attribute doesn't resolve yet.
+    Unresolved,
+}
+
 /// Walks the tree of module recursively
 struct DefCollector<'a, DB> {
     db: &'a DB,
     def_map: CrateDefMap,
-    glob_imports: FxHashMap<LocalModuleId, Vec<(LocalModuleId, LocalImportId)>>,
+    glob_imports: FxHashMap<LocalModuleId, Vec<(LocalModuleId, LocalImportId, Visibility)>>,
     unresolved_imports: Vec<ImportDirective>,
     resolved_imports: Vec<ImportDirective>,
     unexpanded_macros: Vec<MacroDirective>,
-    unexpanded_attribute_macros: Vec<(LocalModuleId, AstId<ast::ModuleItem>, Path)>,
+    unexpanded_derives: Vec<(LocalModuleId, AstId<ast::ModuleItem>, Path)>,
+    unexpanded_attr_macros: Vec<AttrMacroDirective>,
     mod_dirs: FxHashMap<LocalModuleId, ModDir>,
     cfg_options: &'a CfgOptions,
 }
@@ -123,19 +150,37 @@ where
     DB: DefDatabase,
 {
     fn collect(&mut self) {
-        let crate_graph = self.db.crate_graph();
-        let file_id = crate_graph.crate_root(self.def_map.krate);
-        let raw_items = self.db.raw_items(file_id.into());
         let module_id = self.def_map.root;
-        self.def_map.modules[module_id].origin = ModuleOrigin::CrateRoot { definition: file_id };
-        ModCollector {
-            def_collector: &mut *self,
-            module_id,
-            file_id: file_id.into(),
-            raw_items: &raw_items,
-            mod_dir: ModDir::root(),
+        match self.def_map.block {
+            Some(block) => {
+                let ast_id = block.block.lookup(self.db).ast_id;
+                let raw_items = self.db.block_raw_items(ast_id);
+                ModCollector {
+                    def_collector: &mut *self,
+                    module_id,
+                    file_id: ast_id.file_id,
+                    raw_items: &raw_items,
+                    mod_dir: ModDir::root(),
+                }
+                .collect(raw_items.items());
+            }
+            None => {
+                let crate_graph = self.db.crate_graph();
+                let file_id = crate_graph.crate_root(self.def_map.krate);
+                let raw_items = self.db.raw_items(file_id.into());
+                self.def_map.modules[module_id].origin =
+                    ModuleOrigin::CrateRoot { definition: file_id };
+                self.define_proc_macros();
+                ModCollector {
+                    def_collector: &mut *self,
+                    module_id,
+                    file_id: file_id.into(),
+                    raw_items: &raw_items,
+                    mod_dir: ModDir::root(),
+                }
+                .collect(raw_items.items());
+            }
         }
-        .collect(raw_items.items());
 
         // main name resolution fixed-point loop.
         let mut i = 0;
@@ -144,7 +189,16 @@ where
             self.resolve_imports();
 
             match self.resolve_macros() {
-                ReachedFixedPoint::Yes => break,
+                ReachedFixedPoint::Yes => {
+                    if self.unexpanded_attr_macros.is_empty() {
+                        break;
+                    }
+                    // The remaining attributes don't resolve to anything.
+                    // Defining the items might make more imports resolve.
+                    let directives =
+                        std::mem::replace(&mut self.unexpanded_attr_macros, Vec::new());
+                    self.define_attr_macro_items(directives);
+                }
                 ReachedFixedPoint::No => i += 1,
             }
             if i == 1000 {
@@ -218,7 +272,38 @@ where
             self.update(
                 self.def_map.root,
                 None,
-                &[(name, Resolution { def: PerNs::macros(macro_), import: None })],
+                &[(
+                    name,
+                    Resolution { def: PerNs::macros(macro_, Visibility::Public), import: None },
+                )],
+                Visibility::Public,
+            );
+        }
+    }
+
+    /// Define the procedural macros of a `proc-macro` crate.
+    ///
+    /// They are defined in the crate root, and, unlike `macro_rules`, are not
+    /// textually scoped: they can only be used from other crates.
+    fn define_proc_macros(&mut self) {
+        let krate = self.def_map.krate;
+        let crate_graph = self.db.crate_graph();
+        let root = self.def_map.root;
+        for (idx, proc_macro) in crate_graph.proc_macros(krate).iter().enumerate() {
+            let expander = ProcMacroExpander::new(krate, ProcMacroId(idx as u32));
+            let macro_ = MacroDefId {
+                ast_id: None,
+                krate: Some(krate),
+                kind: MacroDefKind::ProcMacro(expander),
+            };
+            self.update(
+                root,
+                None,
+                &[(
+                    proc_macro.as_name(),
+                    Resolution { def: PerNs::macros(macro_, Visibility::Public), import: None },
+                )],
+                Visibility::Public,
             );
         }
     }
@@ -356,6 +441,10 @@ where
         let import_id = directive.import_id;
         let import = &directive.import;
         let def = directive.status.namespaces();
+        let vis = self
+            .def_map
+            .resolve_visibility(self.db, module_id, &directive.import.visibility)
+            .unwrap_or(Visibility::Public);
 
         if import.is_glob {
             log::debug!("glob import: {:?}", import);
@@ -367,17 +456,46 @@ where
                     } else if m.krate != self.def_map.krate {
                         tested_by!(glob_across_crates);
                         // glob import from other crate => we can just import everything once
-                        let item_map = self.db.crate_def_map(m.krate);
+                        let item_map = m.def_map(self.db);
                         let scope = &item_map[m.local_id].scope;
 
                         // Module scoped macros is included
                         let items = scope
                             .items
                             .iter()
-                            .map(|(name, res)| (name.clone(), res.clone()))
+                            // only keep visible names...
+                            .map(|(name, res)| {
+                                let def =
+                                    res.def.filter_visibility(|v| v.is_visible_from_other_crate());
+                                (name.clone(), Resolution { def, import: res.import })
+                            })
+                            .filter(|(_, res)| !res.def.is_none())
                             .collect::<Vec<_>>();
 
-                        self.update(module_id, Some(import_id), &items);
+                        self.update(module_id, Some(import_id), &items, vis);
+                    } else if m.block != self.def_map.block_id() {
+                        // glob import from the `DefMap` of the containing
+                        // module or block, which is complete => we can just
+                        // import everything once
+                        let item_map = m.def_map(self.db);
+                        let scope = &item_map[m.local_id].scope;
+                        let from_module = self.def_map.module_id(module_id);
+
+                        // Module scoped macros is included
+                        let items = scope
+                            .items
+                            .iter()
+                            // only keep visible names...
+                            .map(|(name, res)| {
+                                let def = res
+                                    .def
+                                    .filter_visibility(|v| v.is_visible_from(self.db, from_module));
+                                (name.clone(), Resolution { def, import: res.import })
+                            })
+                            .filter(|(_, res)| !res.def.is_none())
+                            .collect::<Vec<_>>();
+
+                        self.update(module_id, Some(import_id), &items, vis);
                     } else {
                         // glob import from same crate => we do an initial
                         // import, and then need to propagate any further
@@ -388,14 +506,21 @@ where
                         let items = scope
                             .items
                             .iter()
-                            .map(|(name, res)| (name.clone(), res.clone()))
+                            // only keep visible names...
+                            .map(|(name, res)| {
+                                let def = res.def.filter_visibility(|v| {
+                                    v.is_visible_from_def_map(self.db, &self.def_map, module_id)
+                                });
+                                (name.clone(), Resolution { def, import: res.import })
+                            })
+                            .filter(|(_, res)| !res.def.is_none())
                             .collect::<Vec<_>>();
 
-                        self.update(module_id, Some(import_id), &items);
+                        self.update(module_id, Some(import_id), &items, vis);
                         // record the glob import in case we add further items
                         let glob = self.glob_imports.entry(m.local_id).or_default();
-                        if !glob.iter().any(|it| *it == (module_id, import_id)) {
-                            glob.push((module_id, import_id));
+                        if !glob.iter().any(|(m, i, _)| (*m, *i) == (module_id, import_id)) {
+                            glob.push((module_id, import_id, vis));
                         }
                     }
                 }
@@ -410,13 +535,13 @@ where
                             let name = variant_data.name.clone();
                             let variant = EnumVariantId { parent: e, local_id };
                             let res = Resolution {
-                                def: PerNs::both(variant.into(), variant.into()),
+                                def: PerNs::both(variant.into(), variant.into(), vis),
                                 import: Some(import_id),
                             };
                             Some((name, res))
                         })
                         .collect::<Vec<_>>();
-                    self.update(module_id, Some(import_id), &resolutions);
+                    self.update(module_id, Some(import_id), &resolutions, vis);
                 }
                 Some(d) => {
                     log::debug!("glob import {:?} from non-module/enum {:?}", import, d);
@@ -439,20 +564,22 @@ where
                     }
 
                     let resolution = Resolution { def, import: Some(import_id) };
-                    self.update(module_id, Some(import_id), &[(name, resolution)]);
+                    self.update(module_id, Some(import_id), &[(name, resolution)], vis);
                 }
                 None => tested_by!(bogus_paths),
             }
         }
     }
 
+    /// Adds `resolutions` to the scope of `module_id`, as visible as `vis`.
     fn update(
         &mut self,
         module_id: LocalModuleId,
         import: Option<LocalImportId>,
         resolutions: &[(Name, Resolution)],
+        vis: Visibility,
     ) {
-        self.update_recursive(module_id, import, resolutions, 0)
+        self.update_recursive(module_id, import, resolutions, vis, 0)
     }
 
     fn update_recursive(
@@ -460,6 +587,7 @@ where
         module_id: LocalModuleId,
         import: Option<LocalImportId>,
         resolutions: &[(Name, Resolution)],
+        vis: Visibility,
         depth: usize,
     ) {
         if depth > 100 {
@@ -470,25 +598,26 @@ where
         let mut changed = false;
         for (name, res) in resolutions {
             let existing = module_items.items.entry(name.clone()).or_default();
+            let def = res.def.with_visibility(vis);
 
-            if existing.def.types.is_none() && res.def.types.is_some() {
-                existing.def.types = res.def.types;
+            if existing.def.types.is_none() && def.types.is_some() {
+                existing.def.types = def.types;
                 existing.import = import.or(res.import);
                 changed = true;
             }
-            if existing.def.values.is_none() && res.def.values.is_some() {
-                existing.def.values = res.def.values;
+            if existing.def.values.is_none() && def.values.is_some() {
+                existing.def.values = def.values;
                 existing.import = import.or(res.import);
                 changed = true;
             }
-            if existing.def.macros.is_none() && res.def.macros.is_some() {
-                existing.def.macros = res.def.macros;
+            if existing.def.macros.is_none() && def.macros.is_some() {
+                existing.def.macros = def.macros;
                 existing.import = import.or(res.import);
                 changed = true;
             }
 
             if existing.def.is_none()
-                && res.def.is_none()
+                && def.is_none()
                 && existing.import.is_none()
                 && res.import.is_some()
             {
@@ -506,16 +635,27 @@ where
             .flat_map(|v| v.iter())
             .cloned()
             .collect::<Vec<_>>();
-        for (glob_importing_module, glob_import) in glob_imports {
+        for (glob_importing_module, glob_import, glob_import_vis) in glob_imports {
+            // we know all resolutions have the same visibility (`vis`), so we
+            // just need to check that once
+            if !vis.is_visible_from_def_map(self.db, &self.def_map, glob_importing_module) {
+                continue;
+            }
             // We pass the glob import so that the tracked import in those modules is that glob import
-            self.update_recursive(glob_importing_module, Some(glob_import), resolutions, depth + 1);
+            self.update_recursive(
+                glob_importing_module,
+                Some(glob_import),
+                resolutions,
+                glob_import_vis,
+                depth + 1,
+            );
         }
     }
 
     fn resolve_macros(&mut self) -> ReachedFixedPoint {
         let mut macros = std::mem::replace(&mut self.unexpanded_macros, Vec::new());
-        let mut attribute_macros =
-            std::mem::replace(&mut self.unexpanded_attribute_macros, Vec::new());
+        let mut derives = std::mem::replace(&mut self.unexpanded_derives, Vec::new());
+        let mut attr_macros = std::mem::replace(&mut self.unexpanded_attr_macros, Vec::new());
         let mut resolved = Vec::new();
         let mut res = ReachedFixedPoint::Yes;
         macros.retain(|directive| {
@@ -534,7 +674,11 @@ where
             );
 
             if let Some(def) = resolved_res.resolved_def.take_macros() {
-                let call_id = def.as_call_id(self.db, MacroCallKind::FnLike(directive.ast_id));
+                let call_id = def.as_call_id(
+                    self.db,
+                    self.def_map.krate,
+                    MacroCallKind::FnLike(directive.ast_id),
+                );
                 resolved.push((directive.module_id, call_id));
                 res = ReachedFixedPoint::No;
                 return false;
@@ -542,11 +686,12 @@ where
 
             true
         });
-        attribute_macros.retain(|(module_id, ast_id, path)| {
-            let resolved_res = self.resolve_attribute_macro(path);
+        derives.retain(|(module_id, ast_id, path)| {
+            let resolved_res = self.resolve_derive_macro(*module_id, path);
 
             if let Some(def) = resolved_res {
-                let call_id = def.as_call_id(self.db, MacroCallKind::Attr(*ast_id));
+                let call_id =
+                    def.as_call_id(self.db, self.def_map.krate, MacroCallKind::Derive(*ast_id));
                 resolved.push((*module_id, call_id));
                 res = ReachedFixedPoint::No;
                 return false;
@@ -554,18 +699,38 @@ where
 
             true
         });
+        let mut inert_items = Vec::new();
+        attr_macros.retain(|directive| {
+            match self.resolve_attr_macro(directive) {
+                AttrMacroResolution::Macro(def, name) => {
+                    // The expansion replaces the item.
+                    let call_id = def.as_call_id(
+                        self.db,
+                        self.def_map.krate,
+                        MacroCallKind::Attr(directive.ast_id, name.to_string()),
+                    );
+                    resolved.push((directive.module_id, call_id));
+                }
+                AttrMacroResolution::Inert => inert_items.push(directive.clone()),
+                AttrMacroResolution::Unresolved => return true,
+            }
+            res = ReachedFixedPoint::No;
+            false
+        });
 
         self.unexpanded_macros = macros;
-        self.unexpanded_attribute_macros = attribute_macros;
+        self.unexpanded_derives = derives;
+        self.unexpanded_attr_macros = attr_macros;
 
         for (module_id, macro_call_id) in resolved {
             self.collect_macro_expansion(module_id, macro_call_id);
         }
+        self.define_attr_macro_items(inert_items);
 
         res
     }
 
-    fn resolve_attribute_macro(&self, path: &Path) -> Option<MacroDefId> {
+    fn resolve_derive_macro(&self, module_id: LocalModuleId, path: &Path) -> Option<MacroDefId> {
         // FIXME this is currently super hacky, just enough to support the
         // built-in derives
         if let Some(name) = path.as_ident() {
@@ -576,7 +741,82 @@ where
                 return Some(def_id);
             }
         }
-        None
+
+        // Procedural macros are imported like any other item, e.g. via
+        // `use serde::Serialize;`.
+        let def = self.resolve_macro(module_id, path).take_macros()?;
+        match self.proc_macro_kind(&def) {
+            Some(ProcMacroKind::CustomDerive { .. }) => Some(def),
+            _ => None,
+        }
+    }
+
+    /// Finds the first of the item's attributes which is an attribute macro.
+    fn resolve_attr_macro(&self, directive: &AttrMacroDirective) -> AttrMacroResolution {
+        let helpers = match self.derive_helpers(directive.module_id, &directive.attrs) {
+            Some(it) => it,
+            None => return AttrMacroResolution::Unresolved,
+        };
+        for path in &directive.paths {
+            let name = match path.segments.last() {
+                Some(segment) => &segment.name,
+                None => continue,
+            };
+            if let Some(ident) = path.as_ident() {
+                let ident = ident.to_string();
+                if helpers.iter().any(|helper| helper.as_str() == ident) {
+                    continue;
+                }
+            }
+            let resolved_def = self.resolve_macro(directive.module_id, path);
+            let def = match resolved_def.take_macros() {
+                Some(def) => def,
+                // This is an error, like `#[some_module]`, which we ignore.
+                None if !resolved_def.is_none() => continue,
+                // It might become resolvable once more imports or macros are.
+                None => return AttrMacroResolution::Unresolved,
+            };
+            // Other macros, like derives, can't be used as attributes.
+            if let Some(ProcMacroKind::Attr) = self.proc_macro_kind(&def) {
+                return AttrMacroResolution::Macro(def, name.clone());
+            }
+        }
+        AttrMacroResolution::Inert
+    }
+
+    /// The inert helper attributes, like `#[serde(...)]`, of the derives
+    /// applied to an item, or `None` if some derive doesn't resolve yet.
+    fn derive_helpers(&self, module_id: LocalModuleId, attrs: &Attrs) -> Option<Vec<SmolStr>> {
+        let mut res = Vec::new();
+        for path in derive_paths(attrs) {
+            if path.as_ident().and_then(find_builtin_derive).is_some() {
+                continue;
+            }
+            let def = self.resolve_macro(module_id, &path).take_macros()?;
+            if let Some(ProcMacroKind::CustomDerive { helpers }) = self.proc_macro_kind(&def) {
+                res.extend(helpers);
+            }
+        }
+        Some(res)
+    }
+
+    fn resolve_macro(&self, module_id: LocalModuleId, path: &Path) -> PerNs {
+        self.def_map
+            .resolve_path_fp_with_macro(
+                self.db,
+                ResolveMode::Other,
+                module_id,
+                path,
+                BuiltinShadowMode::Module,
+            )
+            .resolved_def
+    }
+
+    fn proc_macro_kind(&self, def: &MacroDefId) -> Option<ProcMacroKind> {
+        match &def.kind {
+            MacroDefKind::ProcMacro(expander) => expander.kind(&self.db.crate_graph()).cloned(),
+            _ => None,
+        }
     }
 
     fn collect_macro_expansion(&mut self, module_id: LocalModuleId, macro_call_id: MacroCallId) {
@@ -593,6 +833,22 @@ where
         .collect(raw_items.items());
     }
 
+    fn define_attr_macro_items(&mut self, directives: Vec<AttrMacroDirective>) {
+        for directive in directives {
+            let file_id = directive.ast_id.file_id;
+            let raw_items = self.db.raw_items(file_id);
+            let mod_dir = self.mod_dirs[&directive.module_id].clone();
+            ModCollector {
+                def_collector: &mut *self,
+                module_id: directive.module_id,
+                file_id,
+                raw_items: &raw_items,
+                mod_dir,
+            }
+            .define_def(&raw_items[directive.def], &directive.attrs);
+        }
+    }
+
     fn finish(self) -> CrateDefMap {
         self.def_map
     }
@@ -628,7 +884,8 @@ where
         // `#[macro_use] extern crate` is hoisted to imports macros before collecting
         // any other items.
         for item in items {
-            if self.is_cfg_enabled(&item.attrs) {
+            let attrs = item.attrs.expand_cfg_attr(self.def_collector.cfg_options);
+            if self.is_cfg_enabled(&attrs) {
                 if let raw::RawItemKind::Import(import_id) = item.kind {
                     let import = self.raw_items[import_id].clone();
                     if import.is_extern_crate && import.is_macro_use {
@@ -639,11 +896,10 @@ where
         }
 
         for item in items {
-            if self.is_cfg_enabled(&item.attrs) {
+            let attrs = item.attrs.expand_cfg_attr(self.def_collector.cfg_options);
+            if self.is_cfg_enabled(&attrs) {
                 match item.kind {
-                    raw::RawItemKind::Module(m) => {
-                        self.collect_module(&self.raw_items[m], &item.attrs)
-                    }
+                    raw::RawItemKind::Module(m) => self.collect_module(&self.raw_items[m], &attrs),
                     raw::RawItemKind::Import(import_id) => {
                         self.def_collector.unresolved_imports.push(ImportDirective {
                             module_id: self.module_id,
@@ -653,18 +909,36 @@ where
                         })
                     }
                     raw::RawItemKind::Def(def) => {
-                        self.define_def(&self.raw_items[def], &item.attrs)
+                        let paths = attr_macro_paths(&attrs);
+                        if paths.is_empty() {
+                            self.define_def(&self.raw_items[def], &attrs);
+                        } else {
+                            let ast_id = self.raw_items[def].kind.ast_id();
+                            self.def_collector.unexpanded_attr_macros.push(AttrMacroDirective {
+                                module_id: self.module_id,
+                                ast_id: AstId::new(self.file_id, ast_id),
+                                paths,
+                                def,
+                                attrs,
+                            })
+                        }
                     }
                     raw::RawItemKind::Macro(mac) => self.collect_macro(&self.raw_items[mac]),
                     raw::RawItemKind::Impl(imp) => {
-                        let module = ModuleId {
-                            krate: self.def_collector.def_map.krate,
-                            local_id: self.module_id,
-                        };
+                        let module = self.def_collector.def_map.module_id(self.module_id);
                         let ctx = LocationCtx::new(self.def_collector.db, module, self.file_id);
                         let imp_id = ImplId::from_ast_id(ctx, self.raw_items[imp].ast_id);
                         self.def_collector.def_map.modules[self.module_id].impls.push(imp_id)
                     }
+                    raw::RawItemKind::Block(ast_id) => {
+                        let module = self.def_collector.def_map.module_id(self.module_id);
+                        let block = BlockLoc { ast_id: AstId::new(self.file_id, ast_id), module }
+                            .intern(self.def_collector.db);
+                        let module_data = &mut self.def_collector.def_map.modules[self.module_id];
+                        module_data.blocks.push(block);
+                        let legacy_macros = module_data.scope.legacy_macros.clone();
+                        module_data.block_legacy_macros.insert(block, legacy_macros);
+                    }
                 }
             }
         }
@@ -675,9 +949,13 @@ where
         let is_macro_use = attrs.by_key("macro_use").exists();
         match module {
             // inline module, just recurse
-            raw::ModuleData::Definition { name, items, ast_id } => {
-                let module_id =
-                    self.push_child_module(name.clone(), AstId::new(self.file_id, *ast_id), None);
+            raw::ModuleData::Definition { name, visibility, items, ast_id } => {
+                let module_id = self.push_child_module(
+                    name.clone(),
+                    AstId::new(self.file_id, *ast_id),
+                    None,
+                    visibility,
+                );
 
                 ModCollector {
                     def_collector: &mut *self.def_collector,
@@ -692,7 +970,7 @@ where
                 }
             }
             // out of line module, resolve, parse and recurse
-            raw::ModuleData::Declaration { name, ast_id } => {
+            raw::ModuleData::Declaration { name, visibility, ast_id } => {
                 let ast_id = AstId::new(self.file_id, *ast_id);
                 match self.mod_dir.resolve_declaration(
                     self.def_collector.db,
@@ -701,7 +979,8 @@ where
                     path_attr,
                 ) {
                     Ok((file_id, mod_dir)) => {
-                        let module_id = self.push_child_module(name.clone(), ast_id, Some(file_id));
+                        let module_id =
+                            self.push_child_module(name.clone(), ast_id, Some(file_id), visibility);
                         let raw_items = self.def_collector.db.raw_items(file_id.into());
                         ModCollector {
                             def_collector: &mut *self.def_collector,
@@ -732,7 +1011,9 @@ where
         name: Name,
         declaration: AstId<ast::Module>,
         definition: Option<FileId>,
+        visibility: &RawVisibility,
     ) -> LocalModuleId {
+        let vis = self.resolve_visibility(visibility);
         let modules = &mut self.def_collector.def_map.modules;
         let res = modules.alloc(ModuleData::default());
         modules[res].parent = Some(self.module_id);
@@ -740,26 +1021,21 @@ where
         modules[res].scope.legacy_macros = modules[self.module_id].scope.legacy_macros.clone();
         modules[self.module_id].children.insert(name.clone(), res);
         let resolution = Resolution {
-            def: PerNs::types(
-                ModuleId { krate: self.def_collector.def_map.krate, local_id: res }.into(),
-            ),
+            def: PerNs::types(self.def_collector.def_map.module_id(res).into(), vis),
             import: None,
         };
-        self.def_collector.update(self.module_id, None, &[(name, resolution)]);
+        self.def_collector.update(self.module_id, None, &[(name, resolution)], vis);
         res
     }
 
     fn define_def(&mut self, def: &raw::DefData, attrs: &Attrs) {
-        let module = ModuleId { krate: self.def_collector.def_map.krate, local_id: self.module_id };
+        let module = self.def_collector.def_map.module_id(self.module_id);
         let ctx = LocationCtx::new(self.def_collector.db, module, self.file_id);
 
-        // FIXME: check attrs to see if this is an attribute macro invocation;
-        // in which case we don't add the invocation, just a single attribute
-        // macro invocation
-
         self.collect_derives(attrs, def);
 
         let name = def.name.clone();
+        let vis = self.resolve_visibility(&def.visibility);
         let def: PerNs = match def.kind {
             raw::DefKind::Function(ast_id) => {
                 let def = FunctionLoc {
@@ -768,17 +1044,19 @@ where
                 }
                 .intern(self.def_collector.db);
 
-                PerNs::values(def.into())
+                PerNs::values(def.into(), vis)
             }
             raw::DefKind::Struct(ast_id) => {
                 let id = StructId::from_ast_id(ctx, ast_id).into();
-                PerNs::both(id, id)
+                PerNs::both(id, id, vis)
             }
             raw::DefKind::Union(ast_id) => {
                 let id = UnionId::from_ast_id(ctx, ast_id).into();
-                PerNs::both(id, id)
+                PerNs::both(id, id, vis)
+            }
+            raw::DefKind::Enum(ast_id) => {
+                PerNs::types(EnumId::from_ast_id(ctx, ast_id).into(), vis)
             }
-            raw::DefKind::Enum(ast_id) => PerNs::types(EnumId::from_ast_id(ctx, ast_id).into()),
             raw::DefKind::Const(ast_id) => {
                 let def = ConstLoc {
                     container: ContainerId::ModuleId(module),
@@ -786,15 +1064,17 @@ where
                 }
                 .intern(self.def_collector.db);
 
-                PerNs::values(def.into())
+                PerNs::values(def.into(), vis)
             }
             raw::DefKind::Static(ast_id) => {
                 let def = StaticLoc { container: module, ast_id: AstId::new(self.file_id, ast_id) }
                     .intern(self.def_collector.db);
 
-                PerNs::values(def.into())
+                PerNs::values(def.into(), vis)
+            }
+            raw::DefKind::Trait(ast_id) => {
+                PerNs::types(TraitId::from_ast_id(ctx, ast_id).into(), vis)
             }
-            raw::DefKind::Trait(ast_id) => PerNs::types(TraitId::from_ast_id(ctx, ast_id).into()),
             raw::DefKind::TypeAlias(ast_id) => {
                 let def = TypeAliasLoc {
                     container: ContainerId::ModuleId(module),
@@ -802,27 +1082,41 @@ where
                 }
                 .intern(self.def_collector.db);
 
-                PerNs::types(def.into())
+                PerNs::types(def.into(), vis)
+            }
+            // Unlike `macro_rules!`, macros 2.0 are items, scoped by modules.
+            raw::DefKind::MacroDef(ast_id) => {
+                let krate = self.def_collector.def_map.krate;
+                let ast_id = Either::Right(AstId::new(self.file_id, ast_id));
+                let builtin = if attrs.by_key("rustc_builtin_macro").exists() {
+                    find_builtin_macro(&name, krate, ast_id)
+                } else {
+                    None
+                };
+                let macro_id = builtin.unwrap_or(MacroDefId {
+                    ast_id: Some(ast_id),
+                    krate: Some(krate),
+                    kind: MacroDefKind::Declarative,
+                });
+
+                PerNs::macros(macro_id, vis)
             }
         };
         let resolution = Resolution { def, import: None };
-        self.def_collector.update(self.module_id, None, &[(name, resolution)])
+        self.def_collector.update(self.module_id, None, &[(name, resolution)], vis)
     }
 
-    fn collect_derives(&mut self, attrs: &Attrs, def: &raw::DefData) {
-        for derive_subtree in attrs.by_key("derive").tt_values() {
-            // for #[derive(Copy, Clone)], `derive_subtree` is the `(Copy, Clone)` subtree
-            for tt in &derive_subtree.token_trees {
-                let ident = match &tt {
-                    tt::TokenTree::Leaf(tt::Leaf::Ident(ident)) => ident,
-                    tt::TokenTree::Leaf(tt::Leaf::Punct(_)) => continue, // , is ok
-                    _ => continue, // anything else would be an error (which we currently ignore)
-                };
-                let path = Path::from_tt_ident(ident);
+    fn resolve_visibility(&self, visibility: &RawVisibility) -> Visibility {
+        self.def_collector
+            .def_map
+            .resolve_visibility(self.def_collector.db, self.module_id, visibility)
+            .unwrap_or(Visibility::Public)
+    }
 
-                let ast_id = AstId::new(self.file_id, def.kind.ast_id());
-                self.def_collector.unexpanded_attribute_macros.push((self.module_id, ast_id, path));
-            }
+    fn collect_derives(&mut self, attrs: &Attrs, def: &raw::DefData) {
+        for path in derive_paths(attrs) {
+            let ast_id = AstId::new(self.file_id, def.kind.ast_id());
+            self.def_collector.unexpanded_derives.push((self.module_id, ast_id, path));
         }
     }
 
@@ -833,7 +1127,7 @@ where
         if mac.builtin {
             if let Some(name) = &mac.name {
                 let krate = self.def_collector.def_map.krate;
-                if let Some(macro_id) = find_builtin_macro(name, krate, ast_id) {
+                if let Some(macro_id) = find_builtin_macro(name, krate, Either::Left(ast_id)) {
                     self.def_collector.define_macro(
                         self.module_id,
                         name.clone(),
@@ -849,7 +1143,7 @@ where
         if is_macro_rules(&mac.path) {
             if let Some(name) = &mac.name {
                 let macro_id = MacroDefId {
-                    ast_id: Some(ast_id),
+                    ast_id: Some(Either::Left(ast_id)),
                     krate: Some(self.def_collector.def_map.krate),
                     kind: MacroDefKind::Declarative,
                 };
@@ -862,8 +1156,11 @@ where
         if let Some(macro_def) = mac.path.as_ident().and_then(|name| {
             self.def_collector.def_map[self.module_id].scope.get_legacy_macro(&name)
         }) {
-            let macro_call_id =
-                macro_def.as_call_id(self.def_collector.db, MacroCallKind::FnLike(ast_id));
+            let macro_call_id = macro_def.as_call_id(
+                self.def_collector.db,
+                self.def_collector.def_map.krate,
+                MacroCallKind::FnLike(ast_id),
+            );
 
             self.def_collector.unexpanded_macros.push(MacroDirective {
                 module_id: self.module_id,
@@ -898,7 +1195,6 @@ where
     }
 
     fn is_cfg_enabled(&self, attrs: &Attrs) -> bool {
-        // FIXME: handle cfg_attr :-)
         attrs
             .by_key("cfg")
             .tt_values()
@@ -910,6 +1206,116 @@ fn is_macro_rules(path: &Path) -> bool {
     path.as_ident() == Some(&name::MACRO_RULES)
 }
 
+/// The paths of the derives in `#[derive(...)]` attributes.
+fn derive_paths(attrs: &Attrs) -> impl Iterator<Item = Path> + '_ {
+    attrs
+        .by_key("derive")
+        .tt_values()
+        // for #[derive(Copy, Clone)], the subtree is the `(Copy, Clone)` subtree
+        .flat_map(|derive_subtree| derive_subtree.token_trees.iter())
+        .filter_map(|tt| match tt {
+            tt::TokenTree::Leaf(tt::Leaf::Ident(ident)) => Some(Path::from_tt_ident(ident)),
+            // `,` is ok, anything else would be an error (which we currently ignore)
+            _ => None,
+        })
+}
+
+/// Attributes which are never attribute macros: the built-in attributes of
+/// the compiler, including the unstable ones used by the standard library.
+const BUILTIN_ATTRS: &[&str] = &[
+    "allow",
+    "automatically_derived",
+    "bench",
+    "cfg",
+    "cfg_attr",
+    "cold",
+    "crate_name",
+    "crate_type",
+    "deny",
+    "deprecated",
+    "derive",
+    "doc",
+    "export_name",
+    "feature",
+    "forbid",
+    "fundamental",
+    "global_allocator",
+    "ignore",
+    "inline",
+    "lang",
+    "link",
+    "link_args",
+    "link_name",
+    "link_ordinal",
+    "link_section",
+    "linkage",
+    "macro_export",
+    "macro_use",
+    "marker",
+    "may_dangle",
+    "must_use",
+    "naked",
+    "no_builtins",
+    "no_core",
+    "no_implicit_prelude",
+    "no_link",
+    "no_main",
+    "no_mangle",
+    "no_std",
+    "non_exhaustive",
+    "optimize",
+    "panic_handler",
+    "path",
+    "plugin",
+    "plugin_registrar",
+    "prelude_import",
+    "proc_macro",
+    "proc_macro_attribute",
+    "proc_macro_derive",
+    "recursion_limit",
+    "register_attr",
+    "register_tool",
+    "repr",
+    "rustc_const_stable",
+    "rustc_const_unstable",
+    "should_panic",
+    "stable",
+    "start",
+    "target_feature",
+    "test",
+    "test_runner",
+    "thread_local",
+    "track_caller",
+    "type_length_limit",
+    "unstable",
+    "unwind",
+    "used",
+    "warn",
+    "windows_subsystem",
+];
+
+/// Tools whose attributes, like `#[rustfmt::skip]`, are inert.
+const TOOL_ATTRS: &[&str] = &["rustfmt", "clippy"];
+
+/// Returns the paths of the attributes which might be attribute macros.
+fn attr_macro_paths(attrs: &Attrs) -> Vec<Path> {
+    attrs
+        .iter()
+        .filter(|attr| {
+            let is_inert = match attr.path.segments.as_slice() {
+                [] => true,
+                [name] => {
+                    let name = name.name.to_string();
+                    BUILTIN_ATTRS.contains(&name.as_str()) || name.starts_with("rustc_")
+                }
+                [tool, ..] => TOOL_ATTRS.contains(&tool.name.to_string().as_str()),
+            };
+            !is_inert
+        })
+        .map(|attr| attr.path.clone())
+        .collect()
+}
+
 #[cfg(test)]
 mod tests {
     use crate::{db::DefDatabase, test_db::TestDB};
@@ -926,7 +1332,8 @@ mod tests {
             unresolved_imports: Vec::new(),
             resolved_imports: Vec::new(),
             unexpanded_macros: Vec::new(),
-            unexpanded_attribute_macros: Vec::new(),
+            unexpanded_derives: Vec::new(),
+            unexpanded_attr_macros: Vec::new(),
             mod_dirs: FxHashMap::default(),
             cfg_options: &CfgOptions::default(),
         };
@@ -949,6 +1356,7 @@ mod tests {
                 prelude: None,
                 root,
                 modules,
+                block: None,
                 diagnostics: Vec::new(),
             }
         };
@@ -19,7 +19,8 @@ use crate::{
     nameres::{BuiltinShadowMode, CrateDefMap},
     path::{Path, PathKind},
     per_ns::PerNs,
-    AdtId, CrateId, EnumVariantId, LocalModuleId, ModuleDefId, ModuleId,
+    visibility::{RawVisibility, Visibility},
+    AdtId, BlockId, CrateId, EnumVariantId, LocalModuleId, ModuleDefId, ModuleId,
 };
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -59,7 +60,9 @@ impl ResolvePathResult {
 
 impl CrateDefMap {
     pub(super) fn resolve_name_in_extern_prelude(&self, name: &Name) -> PerNs {
-        self.extern_prelude.get(name).map_or(PerNs::none(), |&it| PerNs::types(it))
+        self.extern_prelude
+            .get(name)
+            .map_or(PerNs::none(), |&it| PerNs::types(it, Visibility::Public))
     }
 
     // Returns Yes if we are sure that additions to `ItemMap` wouldn't change
@@ -86,19 +89,18 @@ impl CrateDefMap {
             PathKind::DollarCrate(krate) => {
                 if krate == self.krate {
                     tested_by!(macro_dollar_crate_self);
-                    PerNs::types(ModuleId { krate: self.krate, local_id: self.root }.into())
+                    PerNs::types(self.crate_root(db).into(), Visibility::Public)
                 } else {
                     let def_map = db.crate_def_map(krate);
-                    let module = ModuleId { krate, local_id: def_map.root };
+                    let module = ModuleId { krate, block: None, local_id: def_map.root };
                     tested_by!(macro_dollar_crate_other);
-                    PerNs::types(module.into())
+                    PerNs::types(module.into(), Visibility::Public)
                 }
             }
-            PathKind::Crate => {
-                PerNs::types(ModuleId { krate: self.krate, local_id: self.root }.into())
-            }
+            PathKind::Crate => PerNs::types(self.crate_root(db).into(), Visibility::Public),
             PathKind::Self_ => {
-                PerNs::types(ModuleId { krate: self.krate, local_id: original_module }.into())
+                let module = self.containing_module(db, original_module);
+                PerNs::types(module.into(), Visibility::Public)
             }
             // plain import or absolute path in 2015: crate-relative with
             // fallback to extern prelude (with the simplification in
@@ -113,7 +115,11 @@ impl CrateDefMap {
                     None => return ResolvePathResult::empty(ReachedFixedPoint::Yes),
                 };
                 log::debug!("resolving {:?} in crate root (+ extern prelude)", segment);
-                self.resolve_name_in_crate_root_or_extern_prelude(&segment.name, prefer_module(idx))
+                self.resolve_name_in_crate_root_or_extern_prelude(
+                    db,
+                    &segment.name,
+                    prefer_module(idx),
+                )
             }
             PathKind::Plain => {
                 let (idx, segment) = match segments.next() {
@@ -124,8 +130,9 @@ impl CrateDefMap {
                 self.resolve_name_in_module(db, original_module, &segment.name, prefer_module(idx))
             }
             PathKind::Super => {
-                if let Some(p) = self.modules[original_module].parent {
-                    PerNs::types(ModuleId { krate: self.krate, local_id: p }.into())
+                let module = self.containing_module(db, original_module);
+                if let Some(p) = module.def_map(db).parent_module(module.local_id) {
+                    PerNs::types(p.containing_module(db).into(), Visibility::Public)
                 } else {
                     log::debug!("super path in root module");
                     return ResolvePathResult::empty(ReachedFixedPoint::Yes);
@@ -139,7 +146,7 @@ impl CrateDefMap {
                 };
                 if let Some(def) = self.extern_prelude.get(&segment.name) {
                     log::debug!("absolute path {:?} resolved to crate {:?}", path, def);
-                    PerNs::types(*def)
+                    PerNs::types(*def, Visibility::Public)
                 } else {
                     return ResolvePathResult::empty(ReachedFixedPoint::No); // extern crate declarations can add to the extern prelude
                 }
@@ -167,11 +174,11 @@ impl CrateDefMap {
 
             curr_per_ns = match curr {
                 ModuleDefId::ModuleId(module) => {
-                    if module.krate != self.krate {
+                    if module.krate != self.krate || module.block != self.block_id() {
                         let path =
                             Path { segments: path.segments[i..].to_vec(), kind: PathKind::Self_ };
                         log::debug!("resolving {:?} in other crate", path);
-                        let defp_map = db.crate_def_map(module.krate);
+                        let defp_map = module.def_map(db);
                         let (def, s) = defp_map.resolve_path(db, module.local_id, &path, shadow);
                         return ResolvePathResult::with(
                             def,
@@ -197,11 +204,11 @@ impl CrateDefMap {
                     match enum_data.variant(&segment.name) {
                         Some(local_id) => {
                             let variant = EnumVariantId { parent: e, local_id };
-                            PerNs::both(variant.into(), variant.into())
+                            PerNs::both(variant.into(), variant.into(), Visibility::Public)
                         }
                         None => {
                             return ResolvePathResult::with(
-                                PerNs::types(e.into()),
+                                PerNs::types(e.into(), Visibility::Public),
                                 ReachedFixedPoint::Yes,
                                 Some(i),
                                 Some(self.krate),
@@ -219,7 +226,7 @@ impl CrateDefMap {
                     );
 
                     return ResolvePathResult::with(
-                        PerNs::types(s),
+                        PerNs::types(s, Visibility::Public),
                         ReachedFixedPoint::Yes,
                         Some(i),
                         Some(self.krate),
@@ -231,6 +238,64 @@ impl CrateDefMap {
         ResolvePathResult::with(curr_per_ns, ReachedFixedPoint::Yes, None, Some(self.krate))
     }
 
+    pub(crate) fn resolve_visibility(
+        &self,
+        db: &impl DefDatabase,
+        original_module: LocalModuleId,
+        visibility: &RawVisibility,
+    ) -> Option<Visibility> {
+        match visibility {
+            RawVisibility::Module(path) => {
+                let (result, remaining) =
+                    self.resolve_path(db, original_module, path, BuiltinShadowMode::Module);
+                if remaining.is_some() {
+                    return None;
+                }
+                match result.take_types()? {
+                    ModuleDefId::ModuleId(id) => Some(Visibility::Module(id)),
+                    _ => {
+                        // error: visibility needs to refer to module
+                        None
+                    }
+                }
+            }
+            RawVisibility::Public => Some(Visibility::Public),
+        }
+    }
+
+    pub(crate) fn block_id(&self) -> Option<BlockId> {
+        self.block.map(|it| it.block)
+    }
+
+    fn crate_root(&self, db: &impl DefDatabase) -> ModuleId {
+        match self.block {
+            Some(_) => ModuleId {
+                krate: self.krate,
+                block: None,
+                local_id: db.crate_def_map(self.krate).root,
+            },
+            None => self.module_id(self.root),
+        }
+    }
+
+    /// The parent of `module`, which is in the `DefMap` of the containing
+    /// block or module for the root of a block's `DefMap`.
+    pub(crate) fn parent_module(&self, module: LocalModuleId) -> Option<ModuleId> {
+        match (self[module].parent, self.block) {
+            (Some(parent), _) => Some(self.module_id(parent)),
+            (None, Some(block)) => Some(block.parent),
+            (None, None) => None,
+        }
+    }
+
+    /// The closest module containing `module` which is not a block.
+    fn containing_module(&self, db: &impl DefDatabase, module: LocalModuleId) -> ModuleId {
+        match self.block {
+            Some(block) if module == self.root => block.parent.containing_module(db),
+            _ => self.module_id(module),
+        }
+    }
+
     fn resolve_name_in_module(
         &self,
         db: &impl DefDatabase,
@@ -243,22 +308,59 @@ impl CrateDefMap {
         //  - current module / scope
         //  - extern prelude
         //  - std prelude
-        let from_legacy_macro =
-            self[module].scope.get_legacy_macro(name).map_or_else(PerNs::none, PerNs::macros);
+        let from_legacy_macro = self[module]
+            .scope
+            .get_legacy_macro(name)
+            .map_or_else(PerNs::none, |m| PerNs::macros(m, Visibility::Public));
+        from_legacy_macro.or(self.resolve_name_in_module_scope(db, module, name, shadow))
+    }
+
+    /// Like `resolve_name_in_module`, but ignores the legacy macros. Those of
+    /// the containing blocks and module are only visible in a block if they are
+    /// defined textually before it, which is already accounted for in the
+    /// legacy scope of the block.
+    fn resolve_name_in_module_scope(
+        &self,
+        db: &impl DefDatabase,
+        module: LocalModuleId,
+        name: &Name,
+        shadow: BuiltinShadowMode,
+    ) -> PerNs {
+        if let Some(block) = self.block.filter(|_| module == self.root) {
+            // Items declared in a block shadow the ones of the enclosing
+            // blocks and module, which are visible in the block.
+            let from_block =
+                self[module].scope.items.get(name).map_or_else(PerNs::none, |res| res.def);
+            let from_parent = block.parent.def_map(db).resolve_name_in_module_scope(
+                db,
+                block.parent.local_id,
+                name,
+                shadow,
+            );
+            return from_block.or(from_parent);
+        }
         let from_scope =
             self[module].scope.get(name, shadow).map_or_else(PerNs::none, |res| res.def);
-        let from_extern_prelude =
-            self.extern_prelude.get(name).map_or(PerNs::none(), |&it| PerNs::types(it));
+        let from_extern_prelude = self
+            .extern_prelude
+            .get(name)
+            .map_or(PerNs::none(), |&it| PerNs::types(it, Visibility::Public));
         let from_prelude = self.resolve_in_prelude(db, name, shadow);
 
-        from_legacy_macro.or(from_scope).or(from_extern_prelude).or(from_prelude)
+        from_scope.or(from_extern_prelude).or(from_prelude)
     }
 
     fn resolve_name_in_crate_root_or_extern_prelude(
         &self,
+        db: &impl DefDatabase,
         name: &Name,
         shadow: BuiltinShadowMode,
     ) -> PerNs {
+        if self.block.is_some() {
+            return db
+                .crate_def_map(self.krate)
+                .resolve_name_in_crate_root_or_extern_prelude(db, name, shadow);
+        }
         let from_crate_root =
             self[self.root].scope.get(name, shadow).map_or_else(PerNs::none, |res| res.def);
         let from_extern_prelude = self.resolve_name_in_extern_prelude(name);
@@ -274,11 +376,11 @@ impl CrateDefMap {
     ) -> PerNs {
         if let Some(prelude) = self.prelude {
             let keep;
-            let def_map = if prelude.krate == self.krate {
+            let def_map = if prelude.krate == self.krate && prelude.block == self.block_id() {
                 self
             } else {
                 // Extend lifetime
-                keep = db.crate_def_map(prelude.krate);
+                keep = prelude.def_map(db);
                 &keep
             };
             def_map[prelude.local_id]
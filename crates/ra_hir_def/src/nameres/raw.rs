@@ -16,17 +16,18 @@ use hir_expand::{
 };
 use ra_arena::{impl_arena_id, map::ArenaMap, Arena, RawId};
 use ra_syntax::{
-    ast::{self, AttrsOwner, NameOwner},
-    AstNode, AstPtr,
+    ast::{self, AttrsOwner, NameOwner, VisibilityOwner},
+    AstNode, AstPtr, SyntaxNode,
 };
 use test_utils::tested_by;
 
 use crate::{
-    attr::Attrs, db::DefDatabase, path::Path, trace::Trace, FileAstId, HirFileId, InFile,
-    LocalImportId,
+    attr::Attrs, db::DefDatabase, path::Path, trace::Trace, visibility::RawVisibility, AstId,
+    FileAstId, HirFileId, InFile, LocalImportId,
 };
 
-/// `RawItems` is a set of top-level items in a file (except for impls).
+/// `RawItems` is a set of top-level items in a file (except for impls), or of
+/// the items declared in a block expression.
 ///
 /// It is the input to name resolution algorithm. `RawItems` are not invalidated
 /// on most edits.
@@ -87,6 +88,26 @@ impl RawItems {
         (Arc::new(raw_items), Arc::new(source_map))
     }
 
+    /// The items declared directly in `block`. The blocks with items nested in
+    /// it are recorded, but not descended into.
+    pub(crate) fn block_raw_items_query(
+        db: &(impl DefDatabase + AstDatabase),
+        block: AstId<ast::BlockExpr>,
+    ) -> Arc<RawItems> {
+        let file_id = block.file_id;
+        let mut collector = RawItemsCollector {
+            raw_items: RawItems::default(),
+            source_ast_id_map: db.ast_id_map(file_id),
+            imports: Trace::new(),
+            file_id,
+            hygiene: Hygiene::new(db, file_id),
+        };
+        collector.process_block(block.to_node(db));
+        let mut raw_items = collector.raw_items;
+        raw_items.imports = collector.imports.into_arena_and_map().0;
+        Arc::new(raw_items)
+    }
+
     pub(super) fn items(&self) -> &[RawItem] {
         &self.items
     }
@@ -140,6 +161,9 @@ pub(super) enum RawItemKind {
     Def(Def),
     Macro(Macro),
     Impl(Impl),
+    /// A block with items, in the body of an item. Its items are collected
+    /// separately, by `block_raw_items`.
+    Block(FileAstId<ast::BlockExpr>),
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
@@ -148,8 +172,17 @@ impl_arena_id!(Module);
 
 #[derive(Debug, PartialEq, Eq)]
 pub(super) enum ModuleData {
-    Declaration { name: Name, ast_id: FileAstId<ast::Module> },
-    Definition { name: Name, ast_id: FileAstId<ast::Module>, items: Vec<RawItem> },
+    Declaration {
+        name: Name,
+        visibility: RawVisibility,
+        ast_id: FileAstId<ast::Module>,
+    },
+    Definition {
+        name: Name,
+        visibility: RawVisibility,
+        ast_id: FileAstId<ast::Module>,
+        items: Vec<RawItem>,
+    },
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -160,6 +193,7 @@ pub struct ImportData {
     pub(super) is_prelude: bool,
     pub(super) is_extern_crate: bool,
     pub(super) is_macro_use: bool,
+    pub(super) visibility: RawVisibility,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
@@ -170,6 +204,7 @@ impl_arena_id!(Def);
 pub(super) struct DefData {
     pub(super) name: Name,
     pub(super) kind: DefKind,
+    pub(super) visibility: RawVisibility,
 }
 
 #[derive(Debug, PartialEq, Eq, Clone, Copy)]
@@ -182,6 +217,7 @@ pub(super) enum DefKind {
     Static(FileAstId<ast::StaticDef>),
     Trait(FileAstId<ast::TraitDef>),
     TypeAlias(FileAstId<ast::TypeAliasDef>),
+    MacroDef(FileAstId<ast::MacroDef>),
 }
 
 impl DefKind {
@@ -195,6 +231,7 @@ impl DefKind {
             DefKind::Static(it) => it.upcast(),
             DefKind::Trait(it) => it.upcast(),
             DefKind::TypeAlias(it) => it.upcast(),
+            DefKind::MacroDef(it) => it.upcast(),
         }
     }
 }
@@ -239,8 +276,48 @@ impl RawItemsCollector {
         }
     }
 
+    fn process_block(&mut self, block_expr: ast::BlockExpr) {
+        let block = match block_expr.block() {
+            Some(it) => it,
+            None => return,
+        };
+        for child in block.syntax().children() {
+            if let Some(item) = ast::ModuleItem::cast(child.clone()) {
+                match item {
+                    // FIXME: out of line modules declared in blocks
+                    ast::ModuleItem::Module(ref it) if it.has_semi() => (),
+                    _ => self.add_item(None, item),
+                }
+                continue;
+            }
+            // `macro_rules!` in a block is parsed as an expression statement
+            let macro_call = match ast::ExprStmt::cast(child.clone()) {
+                Some(stmt) => match stmt.expr() {
+                    Some(ast::Expr::MacroCall(it)) => Some(it),
+                    _ => None,
+                },
+                None => ast::MacroCall::cast(child.clone()),
+            };
+            match macro_call {
+                Some(m) if m.name().is_some() => self.add_macro(None, m),
+                _ => self.add_blocks(None, &Attrs::default(), &child),
+            }
+        }
+    }
+
     fn add_item(&mut self, current_module: Option<Module>, item: ast::ModuleItem) {
         let attrs = self.parse_attrs(&item);
+        match &item {
+            ast::ModuleItem::FnDef(_)
+            | ast::ModuleItem::ConstDef(_)
+            | ast::ModuleItem::StaticDef(_)
+            | ast::ModuleItem::ImplBlock(_)
+            | ast::ModuleItem::TraitDef(_) => {
+                self.add_blocks(current_module, &attrs, item.syntax())
+            }
+            _ => (),
+        }
+        let visibility = RawVisibility::from_ast_with_hygiene(item.visibility(), &self.hygiene);
         let (kind, name) = match item {
             ast::ModuleItem::Module(module) => {
                 self.add_module(current_module, module);
@@ -286,24 +363,44 @@ impl RawItemsCollector {
             ast::ModuleItem::StaticDef(it) => {
                 (DefKind::Static(self.source_ast_id_map.ast_id(&it)), it.name())
             }
+            ast::ModuleItem::MacroDef(it) => {
+                (DefKind::MacroDef(self.source_ast_id_map.ast_id(&it)), it.name())
+            }
         };
         if let Some(name) = name {
             let name = name.as_name();
-            let def = self.raw_items.defs.alloc(DefData { name, kind });
+            let def = self.raw_items.defs.alloc(DefData { name, kind, visibility });
             self.push_item(current_module, attrs, RawItemKind::Def(def));
         }
     }
 
+    /// Records the outermost blocks with items in the bodies of `owner`, which
+    /// may be a function, a const, or an impl or trait with methods. These are
+    /// `cfg`-ed out with their owner.
+    fn add_blocks(&mut self, current_module: Option<Module>, attrs: &Attrs, owner: &SyntaxNode) {
+        for child in owner.children() {
+            match ast::BlockExpr::cast(child.clone()) {
+                Some(block) if block.has_items() => {
+                    let ast_id = self.source_ast_id_map.ast_id(&block);
+                    self.push_item(current_module, attrs.clone(), RawItemKind::Block(ast_id));
+                }
+                _ => self.add_blocks(current_module, attrs, &child),
+            }
+        }
+    }
+
     fn add_module(&mut self, current_module: Option<Module>, module: ast::Module) {
         let name = match module.name() {
             Some(it) => it.as_name(),
             None => return,
         };
         let attrs = self.parse_attrs(&module);
+        let visibility = RawVisibility::from_ast_with_hygiene(module.visibility(), &self.hygiene);
 
         let ast_id = self.source_ast_id_map.ast_id(&module);
         if module.has_semi() {
-            let item = self.raw_items.modules.alloc(ModuleData::Declaration { name, ast_id });
+            let item =
+                self.raw_items.modules.alloc(ModuleData::Declaration { name, visibility, ast_id });
             self.push_item(current_module, attrs, RawItemKind::Module(item));
             return;
         }
@@ -311,6 +408,7 @@ impl RawItemsCollector {
         if let Some(item_list) = module.item_list() {
             let item = self.raw_items.modules.alloc(ModuleData::Definition {
                 name,
+                visibility,
                 ast_id,
                 items: Vec::new(),
             });
@@ -325,6 +423,7 @@ impl RawItemsCollector {
         // FIXME: cfg_attr
         let is_prelude = use_item.has_atom_attr("prelude_import");
         let attrs = self.parse_attrs(&use_item);
+        let visibility = RawVisibility::from_ast_with_hygiene(use_item.visibility(), &self.hygiene);
 
         let mut buf = Vec::new();
         Path::expand_use_item(
@@ -338,6 +437,7 @@ impl RawItemsCollector {
                     is_prelude,
                     is_extern_crate: false,
                     is_macro_use: false,
+                    visibility: visibility.clone(),
                 };
                 buf.push((import_data, Either::Left(AstPtr::new(use_tree))));
             },
@@ -356,6 +456,8 @@ impl RawItemsCollector {
             let path = Path::from_name_ref(&name_ref);
             let alias = extern_crate.alias().and_then(|a| a.name()).map(|it| it.as_name());
             let attrs = self.parse_attrs(&extern_crate);
+            let visibility =
+                RawVisibility::from_ast_with_hygiene(extern_crate.visibility(), &self.hygiene);
             // FIXME: cfg_attr
             let is_macro_use = extern_crate.has_atom_attr("macro_use");
             let import_data = ImportData {
@@ -365,6 +467,7 @@ impl RawItemsCollector {
                 is_prelude: false,
                 is_extern_crate: true,
                 is_macro_use,
+                visibility,
             };
             self.push_import(
                 current_module,
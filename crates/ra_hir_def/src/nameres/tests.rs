@@ -559,6 +559,29 @@ fn cfg_test() {
     "###);
 }
 
+#[test]
+fn cfg_attr() {
+    let map = def_map(
+        r#"
+        //- /main.rs crate:main cfg:test,feature=foo
+        #[cfg_attr(test, cfg(feature = "foo"))]
+        pub struct Foo;
+        #[cfg_attr(test, cfg(feature = "bar"))]
+        pub struct Bar;
+        #[cfg_attr(not(test), cfg(feature = "bar"))]
+        pub struct Baz;
+        #[cfg_attr(test, allow(dead_code), cfg_attr(feature = "foo", cfg(feature = "bar")))]
+        pub struct Nested;
+        "#,
+    );
+
+    assert_snapshot!(map, @r###"
+        ⋮crate
+        ⋮Baz: t v
+        ⋮Foo: t v
+    "###);
+}
+
 #[test]
 fn infer_multiple_namespace() {
     let map = def_map(
@@ -590,3 +613,23 @@ mod b {
     ⋮T: t v    
 "###);
 }
+
+#[test]
+fn items_in_fn_body_are_not_module_items() {
+    let map = def_map(
+        r#"
+//- /main.rs
+fn f() {
+    struct S;
+    use self::g as h;
+}
+fn g() {}
+"#,
+    );
+
+    assert_snapshot!(map, @r###"
+    ⋮crate
+    ⋮f: v
+    ⋮g: v
+"###);
+}
@@ -624,3 +624,72 @@ fn expand_multiple_derive() {
     );
     assert_eq!(map.modules[map.root].impls.len(), 2);
 }
+
+#[test]
+fn macro2_items_are_module_scoped() {
+    let map = def_map(
+        "
+        //- /lib.rs
+        mod m {
+            pub macro structs($($i:ident),*) {
+                $(struct $i;)*
+            }
+
+            pub(crate) macro items {
+                (fn $i:ident) => { fn $i() {} },
+                (struct $i:ident) => { struct $i; }
+            }
+
+            structs!(Foo);
+        }
+
+        m::structs!(Bar);
+        use m::items;
+        items!(fn baz);
+        items!(struct Baz);
+        structs!(NotInTextualScope);
+        ",
+    );
+    assert_snapshot!(map, @r###"
+   ⋮crate
+   ⋮Bar: t v
+   ⋮Baz: t v
+   ⋮baz: v
+   ⋮items: m
+   ⋮m: t
+   ⋮
+   ⋮crate::m
+   ⋮Foo: t v
+   ⋮items: m
+   ⋮structs: m
+    "###);
+}
+
+#[test]
+fn macro2_items_from_other_crates() {
+    let map = def_map(
+        "
+        //- /main.rs crate:main deps:foo
+        foo::make_struct!(Bar);
+        use foo::m::make_fn;
+        make_fn!(bar);
+
+        //- /lib.rs crate:foo
+        pub macro make_struct($i:ident) {
+            pub struct $i;
+        }
+
+        pub mod m {
+            pub macro make_fn($i:ident) {
+                fn $i() {}
+            }
+        }
+        ",
+    );
+    assert_snapshot!(map, @r###"
+   ⋮crate
+   ⋮Bar: t v
+   ⋮bar: v
+   ⋮make_fn: m
+    "###);
+}
@@ -122,6 +122,34 @@ fn module_resolution_decl_path() {
     "###);
 }
 
+#[test]
+fn module_resolution_decl_path_in_cfg_attr() {
+    let map = def_map(
+        r###"
+        //- /lib.rs crate:lib cfg:feature=baz
+        #[cfg_attr(feature = "baz", path = "bar/baz/foo.rs")]
+        #[cfg_attr(not(feature = "baz"), path = "bar/foo.rs")]
+        mod foo;
+        use self::foo::Bar;
+
+        //- /bar/baz/foo.rs
+        pub struct Bar;
+
+        //- /bar/foo.rs
+        pub struct Baz;
+        "###,
+    );
+
+    assert_snapshot!(map, @r###"
+        ⋮crate
+        ⋮Bar: t v
+        ⋮foo: t
+        ⋮
+        ⋮crate::foo
+        ⋮Bar: t v
+    "###);
+}
+
 #[test]
 fn module_resolution_module_with_path_in_mod_rs() {
     let map = def_map(
@@ -2,6 +2,7 @@
 use std::sync::Arc;
 
 use hir_expand::{
+    hygiene::SyntaxContext,
     name::{self, Name},
     MacroDefId,
 };
@@ -17,6 +18,7 @@ use crate::{
     nameres::{BuiltinShadowMode, CrateDefMap},
     path::{Path, PathKind},
     per_ns::PerNs,
+    visibility::{RawVisibility, Visibility},
     AdtId, AstItemDef, ConstId, ContainerId, DefWithBodyId, EnumId, EnumVariantId, FunctionId,
     GenericDefId, HasModule, ImplId, LocalModuleId, Lookup, ModuleDefId, ModuleId, StaticId,
     StructId, TraitId, TypeAliasId, TypeParamId, VariantId,
@@ -35,6 +37,81 @@ struct ModuleItemMap {
     module_id: LocalModuleId,
 }
 
+impl ModuleItemMap {
+    /// Whether `name` is declared in this module itself, rather than in one of
+    /// the modules (or blocks) containing it.
+    fn declares(&self, name: &Name) -> bool {
+        self.crate_def_map[self.module_id].scope.contains_name(name)
+    }
+
+    fn resolve_path_in_type_ns(
+        &self,
+        db: &impl DefDatabase,
+        path: &Path,
+    ) -> Option<(TypeNs, Option<usize>)> {
+        let (module_def, idx) =
+            self.crate_def_map.resolve_path(db, self.module_id, path, BuiltinShadowMode::Other);
+        let res = match module_def.take_types()? {
+            ModuleDefId::AdtId(it) => TypeNs::AdtId(it),
+            ModuleDefId::EnumVariantId(it) => TypeNs::EnumVariantId(it),
+
+            ModuleDefId::TypeAliasId(it) => TypeNs::TypeAliasId(it),
+            ModuleDefId::BuiltinType(it) => TypeNs::BuiltinType(it),
+
+            ModuleDefId::TraitId(it) => TypeNs::TraitId(it),
+
+            ModuleDefId::FunctionId(_)
+            | ModuleDefId::ConstId(_)
+            | ModuleDefId::StaticId(_)
+            | ModuleDefId::ModuleId(_) => return None,
+        };
+        Some((res, idx))
+    }
+
+    fn resolve_path_in_value_ns(
+        &self,
+        db: &impl DefDatabase,
+        path: &Path,
+    ) -> Option<ResolveValueResult> {
+        let (module_def, idx) =
+            self.crate_def_map.resolve_path(db, self.module_id, path, BuiltinShadowMode::Other);
+        match idx {
+            None => {
+                let value = match module_def.take_values()? {
+                    ModuleDefId::FunctionId(it) => ValueNs::FunctionId(it),
+                    ModuleDefId::AdtId(AdtId::StructId(it)) => ValueNs::StructId(it),
+                    ModuleDefId::EnumVariantId(it) => ValueNs::EnumVariantId(it),
+                    ModuleDefId::ConstId(it) => ValueNs::ConstId(it),
+                    ModuleDefId::StaticId(it) => ValueNs::StaticId(it),
+
+                    ModuleDefId::AdtId(AdtId::EnumId(_))
+                    | ModuleDefId::AdtId(AdtId::UnionId(_))
+                    | ModuleDefId::TraitId(_)
+                    | ModuleDefId::TypeAliasId(_)
+                    | ModuleDefId::BuiltinType(_)
+                    | ModuleDefId::ModuleId(_) => return None,
+                };
+                Some(ResolveValueResult::ValueNs(value))
+            }
+            Some(idx) => {
+                let ty = match module_def.take_types()? {
+                    ModuleDefId::AdtId(it) => TypeNs::AdtId(it),
+                    ModuleDefId::TraitId(it) => TypeNs::TraitId(it),
+                    ModuleDefId::TypeAliasId(it) => TypeNs::TypeAliasId(it),
+                    ModuleDefId::BuiltinType(it) => TypeNs::BuiltinType(it),
+
+                    ModuleDefId::ModuleId(_)
+                    | ModuleDefId::FunctionId(_)
+                    | ModuleDefId::EnumVariantId(_)
+                    | ModuleDefId::ConstId(_)
+                    | ModuleDefId::StaticId(_) => return None,
+                };
+                Some(ResolveValueResult::Partial(ty, idx))
+            }
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 struct ExprScope {
     owner: DefWithBodyId,
@@ -46,6 +123,8 @@ struct ExprScope {
 enum Scope {
     /// All the items and imported names of a module
     ModuleScope(ModuleItemMap),
+    /// Items declared in a block expression
+    BlockScope(ModuleItemMap),
     /// Brings the generic parameters of an item into scope
     GenericParams { def: GenericDefId, params: Arc<GenericParams> },
     /// Brings `Self` in `impl` block into scope
@@ -138,6 +217,20 @@ impl Resolver {
         self.resolve_module_path(db, path, BuiltinShadowMode::Module)
     }
 
+    pub fn resolve_visibility(
+        &self,
+        db: &impl DefDatabase,
+        visibility: &RawVisibility,
+    ) -> Option<Visibility> {
+        match visibility {
+            RawVisibility::Module(_) => {
+                let (item_map, module) = self.module()?;
+                item_map.resolve_visibility(db, module, visibility)
+            }
+            RawVisibility::Public => Some(Visibility::Public),
+        }
+    }
+
     pub fn resolve_path_in_type_ns(
         &self,
         db: &impl DefDatabase,
@@ -151,7 +244,11 @@ impl Resolver {
         for scope in self.scopes.iter().rev() {
             match scope {
                 Scope::ExprScope(_) => continue,
-                Scope::GenericParams { .. } | Scope::ImplBlockScope(_) if skip_to_mod => continue,
+                Scope::GenericParams { .. } | Scope::ImplBlockScope(_) | Scope::BlockScope(_)
+                    if skip_to_mod =>
+                {
+                    continue
+                }
 
                 Scope::GenericParams { params, def } => {
                     if let Some(local_id) = params.find_by_name(first_name) {
@@ -174,29 +271,12 @@ impl Resolver {
                         return Some((TypeNs::AdtSelfType(*adt), idx));
                     }
                 }
-                Scope::ModuleScope(m) => {
-                    let (module_def, idx) = m.crate_def_map.resolve_path(
-                        db,
-                        m.module_id,
-                        path,
-                        BuiltinShadowMode::Other,
-                    );
-                    let res = match module_def.take_types()? {
-                        ModuleDefId::AdtId(it) => TypeNs::AdtId(it),
-                        ModuleDefId::EnumVariantId(it) => TypeNs::EnumVariantId(it),
-
-                        ModuleDefId::TypeAliasId(it) => TypeNs::TypeAliasId(it),
-                        ModuleDefId::BuiltinType(it) => TypeNs::BuiltinType(it),
-
-                        ModuleDefId::TraitId(it) => TypeNs::TraitId(it),
-
-                        ModuleDefId::FunctionId(_)
-                        | ModuleDefId::ConstId(_)
-                        | ModuleDefId::StaticId(_)
-                        | ModuleDefId::ModuleId(_) => return None,
-                    };
-                    return Some((res, idx));
+                Scope::BlockScope(m) => {
+                    if m.declares(first_name) {
+                        return m.resolve_path_in_type_ns(db, path);
+                    }
                 }
+                Scope::ModuleScope(m) => return m.resolve_path_in_type_ns(db, path),
             }
         }
         None
@@ -214,10 +294,13 @@ impl Resolver {
         Some(res)
     }
 
+    /// Resolves `path` in the value namespace. Local variables are only
+    /// visible if they have the same syntax `context` as the path.
     pub fn resolve_path_in_value_ns<'p>(
         &self,
         db: &impl DefDatabase,
         path: &'p Path,
+        context: SyntaxContext,
     ) -> Option<ResolveValueResult> {
         if path.is_type_relative() {
             return None;
@@ -232,6 +315,7 @@ impl Resolver {
                 | Scope::ExprScope(_)
                 | Scope::GenericParams { .. }
                 | Scope::ImplBlockScope(_)
+                | Scope::BlockScope(_)
                     if skip_to_mod =>
                 {
                     continue
@@ -242,7 +326,7 @@ impl Resolver {
                         .expr_scopes
                         .entries(scope.scope_id)
                         .iter()
-                        .find(|entry| entry.name() == first_name);
+                        .find(|entry| entry.is_visible_from(first_name, context));
 
                     if let Some(e) = entry {
                         return Some(ResolveValueResult::ValueNs(ValueNs::LocalBinding(e.pat())));
@@ -272,48 +356,12 @@ impl Resolver {
                 }
                 Scope::ImplBlockScope(_) | Scope::AdtScope(_) => continue,
 
-                Scope::ModuleScope(m) => {
-                    let (module_def, idx) = m.crate_def_map.resolve_path(
-                        db,
-                        m.module_id,
-                        path,
-                        BuiltinShadowMode::Other,
-                    );
-                    return match idx {
-                        None => {
-                            let value = match module_def.take_values()? {
-                                ModuleDefId::FunctionId(it) => ValueNs::FunctionId(it),
-                                ModuleDefId::AdtId(AdtId::StructId(it)) => ValueNs::StructId(it),
-                                ModuleDefId::EnumVariantId(it) => ValueNs::EnumVariantId(it),
-                                ModuleDefId::ConstId(it) => ValueNs::ConstId(it),
-                                ModuleDefId::StaticId(it) => ValueNs::StaticId(it),
-
-                                ModuleDefId::AdtId(AdtId::EnumId(_))
-                                | ModuleDefId::AdtId(AdtId::UnionId(_))
-                                | ModuleDefId::TraitId(_)
-                                | ModuleDefId::TypeAliasId(_)
-                                | ModuleDefId::BuiltinType(_)
-                                | ModuleDefId::ModuleId(_) => return None,
-                            };
-                            Some(ResolveValueResult::ValueNs(value))
-                        }
-                        Some(idx) => {
-                            let ty = match module_def.take_types()? {
-                                ModuleDefId::AdtId(it) => TypeNs::AdtId(it),
-                                ModuleDefId::TraitId(it) => TypeNs::TraitId(it),
-                                ModuleDefId::TypeAliasId(it) => TypeNs::TypeAliasId(it),
-                                ModuleDefId::BuiltinType(it) => TypeNs::BuiltinType(it),
-
-                                ModuleDefId::ModuleId(_)
-                                | ModuleDefId::FunctionId(_)
-                                | ModuleDefId::EnumVariantId(_)
-                                | ModuleDefId::ConstId(_)
-                                | ModuleDefId::StaticId(_) => return None,
-                            };
-                            Some(ResolveValueResult::Partial(ty, idx))
-                        }
-                    };
+                Scope::BlockScope(m) => {
+                    if m.declares(first_name) {
+                        return m.resolve_path_in_value_ns(db, path);
+                    }
                 }
+                Scope::ModuleScope(m) => return m.resolve_path_in_value_ns(db, path),
             }
         }
         None
@@ -323,8 +371,9 @@ impl Resolver {
         &self,
         db: &impl DefDatabase,
         path: &Path,
+        context: SyntaxContext,
     ) -> Option<ValueNs> {
-        match self.resolve_path_in_value_ns(db, path)? {
+        match self.resolve_path_in_value_ns(db, path, context)? {
             ResolveValueResult::ValueNs(it) => Some(it),
             ResolveValueResult::Partial(..) => None,
         }
@@ -344,12 +393,16 @@ impl Resolver {
     pub fn traits_in_scope(&self, db: &impl DefDatabase) -> FxHashSet<TraitId> {
         let mut traits = FxHashSet::default();
         for scope in &self.scopes {
-            if let Scope::ModuleScope(m) = scope {
-                if let Some(prelude) = m.crate_def_map.prelude {
-                    let prelude_def_map = db.crate_def_map(prelude.krate);
-                    traits.extend(prelude_def_map[prelude.local_id].scope.traits());
+            match scope {
+                Scope::ModuleScope(m) => {
+                    if let Some(prelude) = m.crate_def_map.prelude {
+                        let prelude_def_map = prelude.def_map(db);
+                        traits.extend(prelude_def_map[prelude.local_id].scope.traits());
+                    }
+                    traits.extend(m.crate_def_map[m.module_id].scope.traits());
                 }
-                traits.extend(m.crate_def_map[m.module_id].scope.traits());
+                Scope::BlockScope(m) => traits.extend(m.crate_def_map[m.module_id].scope.traits()),
+                _ => (),
             }
         }
         traits
@@ -357,7 +410,7 @@ impl Resolver {
 
     fn module(&self) -> Option<(&CrateDefMap, LocalModuleId)> {
         self.scopes.iter().rev().find_map(|scope| match scope {
-            Scope::ModuleScope(m) => Some((&*m.crate_def_map, m.module_id)),
+            Scope::ModuleScope(m) | Scope::BlockScope(m) => Some((&*m.crate_def_map, m.module_id)),
 
             _ => None,
         })
@@ -418,18 +471,23 @@ impl Scope {
                     f(name.clone(), ScopeDef::PerNs(res.def));
                 });
                 m.crate_def_map[m.module_id].scope.legacy_macros().for_each(|(name, macro_)| {
-                    f(name.clone(), ScopeDef::PerNs(PerNs::macros(macro_)));
+                    f(name.clone(), ScopeDef::PerNs(PerNs::macros(macro_, Visibility::Public)));
                 });
                 m.crate_def_map.extern_prelude.iter().for_each(|(name, &def)| {
-                    f(name.clone(), ScopeDef::PerNs(PerNs::types(def.into())));
+                    f(name.clone(), ScopeDef::PerNs(PerNs::types(def.into(), Visibility::Public)));
                 });
                 if let Some(prelude) = m.crate_def_map.prelude {
-                    let prelude_def_map = db.crate_def_map(prelude.krate);
+                    let prelude_def_map = prelude.def_map(db);
                     prelude_def_map[prelude.local_id].scope.entries().for_each(|(name, res)| {
                         f(name.clone(), ScopeDef::PerNs(res.def));
                     });
                 }
             }
+            Scope::BlockScope(m) => {
+                m.crate_def_map[m.module_id].scope.local_entries().for_each(|(name, res)| {
+                    f(name.clone(), ScopeDef::PerNs(res.def));
+                });
+            }
             Scope::GenericParams { params, def } => {
                 for (local_id, param) in params.types.iter() {
                     f(
@@ -468,6 +526,11 @@ pub fn resolver_for_scope(
     let scopes = db.expr_scopes(owner);
     let scope_chain = scopes.scope_chain(scope_id).collect::<Vec<_>>();
     for scope in scope_chain.into_iter().rev() {
+        if let Some(block) = scopes.block(scope) {
+            let def_map = db.block_def_map(block);
+            let root = def_map.root;
+            r = r.push_block_scope(def_map, root);
+        }
         r = r.push_expr_scope(owner, Arc::clone(&scopes), scope);
     }
     r
@@ -500,6 +563,26 @@ impl Resolver {
         self.push_scope(Scope::ModuleScope(ModuleItemMap { crate_def_map, module_id }))
     }
 
+    fn push_block_scope(
+        self,
+        crate_def_map: Arc<CrateDefMap>,
+        module_id: LocalModuleId,
+    ) -> Resolver {
+        self.push_scope(Scope::BlockScope(ModuleItemMap { crate_def_map, module_id }))
+    }
+
+    /// Items declared in a block also see the items of the blocks and module
+    /// containing it.
+    fn push_module_scopes(self, db: &impl DefDatabase, module: ModuleId) -> Resolver {
+        let def_map = module.def_map(db);
+        match def_map.block {
+            Some(block) if module.local_id == def_map.root => {
+                self.push_module_scopes(db, block.parent).push_block_scope(def_map, module.local_id)
+            }
+            _ => self.push_module_scope(def_map, module.local_id),
+        }
+    }
+
     fn push_expr_scope(
         self,
         owner: DefWithBodyId,
@@ -517,8 +600,7 @@ pub trait HasResolver: Copy {
 
 impl HasResolver for ModuleId {
     fn resolver(self, db: &impl DefDatabase) -> Resolver {
-        let def_map = db.crate_def_map(self.krate);
-        Resolver::default().push_module_scope(def_map, self.local_id)
+        Resolver::default().push_module_scopes(db, self)
     }
 }
 
@@ -0,0 +1,147 @@
+//! Defines hir-level representation of visibility (e.g. `pub` and `pub(crate)`).
+
+use std::iter;
+
+use hir_expand::{hygiene::Hygiene, InFile};
+use ra_syntax::ast;
+
+use crate::{
+    db::DefDatabase,
+    nameres::CrateDefMap,
+    path::{Path, PathKind},
+    resolver::Resolver,
+    ContainerId, LocalModuleId, ModuleId,
+};
+
+/// Visibility of an item, not yet resolved.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum RawVisibility {
+    /// `pub(in module)`, `pub(crate)` or `pub(super)`. Also private, which is
+    /// equivalent to `pub(self)`.
+    Module(Path),
+    /// `pub`.
+    Public,
+}
+
+impl RawVisibility {
+    pub(crate) fn private() -> RawVisibility {
+        RawVisibility::Module(Path::from_simple_segments(PathKind::Self_, iter::empty()))
+    }
+
+    /// The visibility of an item without a visibility modifier in `container`.
+    /// Items of traits and trait impls are as visible as the trait itself.
+    pub(crate) fn default_for_container(
+        db: &impl DefDatabase,
+        container: ContainerId,
+    ) -> RawVisibility {
+        match container {
+            ContainerId::TraitId(_) => RawVisibility::Public,
+            ContainerId::ImplId(it) if db.impl_data(it).target_trait.is_some() => {
+                RawVisibility::Public
+            }
+            ContainerId::ModuleId(_) | ContainerId::ImplId(_) => RawVisibility::private(),
+        }
+    }
+
+    pub(crate) fn from_ast_with_default(
+        db: &impl DefDatabase,
+        default: RawVisibility,
+        node: InFile<Option<ast::Visibility>>,
+    ) -> RawVisibility {
+        Self::from_ast_with_hygiene_and_default(
+            node.value,
+            default,
+            &Hygiene::new(db, node.file_id),
+        )
+    }
+
+    pub(crate) fn from_ast_with_hygiene(
+        node: Option<ast::Visibility>,
+        hygiene: &Hygiene,
+    ) -> RawVisibility {
+        Self::from_ast_with_hygiene_and_default(node, RawVisibility::private(), hygiene)
+    }
+
+    fn from_ast_with_hygiene_and_default(
+        node: Option<ast::Visibility>,
+        default: RawVisibility,
+        hygiene: &Hygiene,
+    ) -> RawVisibility {
+        let node = match node {
+            None => return default,
+            Some(node) => node,
+        };
+        match node.kind() {
+            ast::VisibilityKind::In(path) => match Path::from_src(path, hygiene) {
+                Some(path) => RawVisibility::Module(path),
+                None => RawVisibility::private(),
+            },
+            ast::VisibilityKind::PubCrate => {
+                RawVisibility::Module(Path::from_simple_segments(PathKind::Crate, iter::empty()))
+            }
+            ast::VisibilityKind::PubSuper => {
+                RawVisibility::Module(Path::from_simple_segments(PathKind::Super, iter::empty()))
+            }
+            ast::VisibilityKind::PubSelf => RawVisibility::private(),
+            ast::VisibilityKind::Pub => RawVisibility::Public,
+        }
+    }
+
+    pub fn resolve(&self, db: &impl DefDatabase, resolver: &Resolver) -> Visibility {
+        // we fall back to public visibility (i.e. fail open) if the path can't be resolved
+        resolver.resolve_visibility(db, self).unwrap_or(Visibility::Public)
+    }
+}
+
+/// Visibility of an item, with the path resolved.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Visibility {
+    /// Visibility is restricted to a certain module.
+    Module(ModuleId),
+    /// Visibility is unrestricted.
+    Public,
+}
+
+impl Visibility {
+    pub fn is_visible_from(self, db: &impl DefDatabase, from_module: ModuleId) -> bool {
+        let to_module = match self {
+            Visibility::Module(m) => m,
+            Visibility::Public => return true,
+        };
+        // if they're not in the same crate, it can't be visible
+        if from_module.krate != to_module.krate {
+            return false;
+        }
+        let def_map = from_module.def_map(db);
+        self.is_visible_from_def_map(db, &def_map, from_module.local_id)
+    }
+
+    pub(crate) fn is_visible_from_other_crate(self) -> bool {
+        match self {
+            Visibility::Module(_) => false,
+            Visibility::Public => true,
+        }
+    }
+
+    pub(crate) fn is_visible_from_def_map(
+        self,
+        db: &impl DefDatabase,
+        def_map: &CrateDefMap,
+        from_module: LocalModuleId,
+    ) -> bool {
+        let to_module = match self {
+            Visibility::Module(m) => m,
+            Visibility::Public => return true,
+        };
+        // from_module needs to be a descendant of to_module
+        let mut ancestors = iter::successors(Some(from_module), |m| def_map[*m].parent);
+        if ancestors.any(|m| def_map.module_id(m) == to_module) {
+            return true;
+        }
+        // the root of a block's `DefMap` is nested in the containing module
+        match def_map.block {
+            Some(block) => self.is_visible_from(db, block.parent),
+            None => false,
+        }
+    }
+}
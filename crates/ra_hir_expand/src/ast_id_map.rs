@@ -70,8 +70,13 @@ impl AstIdMap {
         bfs(node, |it| {
             if let Some(module_item) = ast::ModuleItem::cast(it.clone()) {
                 res.alloc(module_item.syntax());
-            } else if let Some(macro_call) = ast::MacroCall::cast(it) {
+            } else if let Some(macro_call) = ast::MacroCall::cast(it.clone()) {
                 res.alloc(macro_call.syntax());
+            } else if let Some(block) = ast::BlockExpr::cast(it) {
+                // Blocks with items have their own item scope, which needs an id.
+                if block.has_items() {
+                    res.alloc(block.syntax());
+                }
             }
         });
         res
@@ -209,7 +209,7 @@ fn partial_ord_expand(
 mod tests {
     use super::*;
     use crate::{test_db::TestDB, AstId, MacroCallKind, MacroCallLoc};
-    use ra_db::{fixture::WithFixture, SourceDatabase};
+    use ra_db::{fixture::WithFixture, CrateId, SourceDatabase};
 
     fn expand_builtin_derive(s: &str, expander: BuiltinDeriveExpander) -> String {
         let (db, file_id) = TestDB::with_single_file(&s);
@@ -225,7 +225,8 @@ mod tests {
 
         let loc = MacroCallLoc {
             def,
-            kind: MacroCallKind::Attr(AstId::new(file_id.into(), ast_id_map.ast_id(&items[0]))),
+            krate: CrateId(0),
+            kind: MacroCallKind::Derive(AstId::new(file_id.into(), ast_id_map.ast_id(&items[0]))),
         };
 
         let id = db.intern_macro(loc);
@@ -1,4 +1,7 @@
 //! Builtin macro
+use either::Either;
+use ra_db::{FileId, RelativePath};
+
 use crate::db::AstDatabase;
 use crate::{
     ast::{self, AstNode},
@@ -31,7 +34,7 @@ macro_rules! register_builtin {
         pub fn find_builtin_macro(
             ident: &name::Name,
             krate: CrateId,
-            ast_id: AstId<ast::MacroCall>,
+            ast_id: Either<AstId<ast::MacroCall>, AstId<ast::MacroDef>>,
         ) -> Option<MacroDefId> {
             let kind = match ident {
                  $( id if id == &name::$name => BuiltinFnLikeExpander::$kind, )*
@@ -52,7 +55,30 @@ register_builtin! {
     (FORMAT_ARGS_MACRO, FormatArgs) => format_args_expand,
     // format_args_nl only differs in that it adds a newline in the end,
     // so we use the same stub expansion for now
-    (FORMAT_ARGS_NL_MACRO, FormatArgsNl) => format_args_expand
+    (FORMAT_ARGS_NL_MACRO, FormatArgsNl) => format_args_expand,
+    (INCLUDE_MACRO, Include) => include_expand,
+    (INCLUDE_STR_MACRO, IncludeStr) => include_str_expand,
+    (INCLUDE_BYTES_MACRO, IncludeBytes) => include_bytes_expand,
+    (CONCAT_MACRO, Concat) => concat_expand,
+    (ENV_MACRO, Env) => env_expand,
+    (OPTION_ENV_MACRO, OptionEnv) => option_env_expand
+}
+
+impl BuiltinFnLikeExpander {
+    /// Finds a builtin macro which rustc expands eagerly when it is used inside
+    /// the arguments of another eager builtin macro, like
+    /// `include!(concat!(env!("OUT_DIR"), "/foo.rs"))`.
+    fn find_eager(name: &str) -> Option<BuiltinFnLikeExpander> {
+        let kind = match name {
+            "concat" => BuiltinFnLikeExpander::Concat,
+            "env" => BuiltinFnLikeExpander::Env,
+            "option_env" => BuiltinFnLikeExpander::OptionEnv,
+            "include_str" => BuiltinFnLikeExpander::IncludeStr,
+            "include_bytes" => BuiltinFnLikeExpander::IncludeBytes,
+            _ => return None,
+        };
+        Some(kind)
+    }
 }
 
 fn to_line_number(db: &dyn AstDatabase, file: HirFileId, pos: TextUnit) -> usize {
@@ -245,6 +271,224 @@ fn format_args_expand(
     Ok(expanded)
 }
 
+/// Expands calls to eager builtin macros (`concat!`, `env!`, ...) inside the
+/// arguments of an eager builtin macro.
+///
+/// FIXME: rustc expands arbitrary macros in these positions, but we can't
+/// resolve names here, so only builtins are supported.
+fn expand_eager_args(
+    db: &dyn AstDatabase,
+    id: MacroCallId,
+    tt: &tt::Subtree,
+) -> Result<tt::Subtree, mbe::ExpandError> {
+    let tts = &tt.token_trees;
+    let mut token_trees = Vec::with_capacity(tts.len());
+    let mut i = 0;
+    while i < tts.len() {
+        if let (
+            tt::TokenTree::Leaf(tt::Leaf::Ident(ident)),
+            Some(tt::TokenTree::Leaf(tt::Leaf::Punct(punct))),
+            Some(tt::TokenTree::Subtree(args)),
+        ) = (&tts[i], tts.get(i + 1), tts.get(i + 2))
+        {
+            if punct.char == '!' {
+                if let Some(expander) = BuiltinFnLikeExpander::find_eager(&ident.text) {
+                    let expanded = expander.expand(db, id, args)?;
+                    token_trees.extend(expanded.token_trees);
+                    i += 3;
+                    continue;
+                }
+            }
+        }
+        token_trees.push(tts[i].clone());
+        i += 1;
+    }
+    Ok(tt::Subtree { delimiter: tt.delimiter, token_trees })
+}
+
+/// Returns the contents of a string literal, without quotes. Escapes are kept
+/// as they are written.
+fn unquote_str(lit: &tt::Literal) -> Option<&str> {
+    let text = lit.text.as_str();
+    let text = if text.starts_with('r') { text[1..].trim_matches('#') } else { text };
+    if text.len() >= 2 && text.starts_with('"') && text.ends_with('"') {
+        Some(&text[1..text.len() - 1])
+    } else {
+        None
+    }
+}
+
+/// Parses the single string literal argument of macros like `env!` or
+/// `include!`. Anything after the first argument is ignored.
+fn parse_string(tt: &tt::Subtree) -> Result<String, mbe::ExpandError> {
+    match tt.token_trees.get(0) {
+        Some(tt::TokenTree::Leaf(tt::Leaf::Literal(it))) => {
+            unquote_str(it).map(str::to_string).ok_or(mbe::ExpandError::ConversionError)
+        }
+        _ => Err(mbe::ExpandError::ConversionError),
+    }
+}
+
+fn string_literal(text: &str) -> tt::Literal {
+    tt::Literal { text: format!("{:?}", text).into() }
+}
+
+fn concat_expand(
+    db: &dyn AstDatabase,
+    id: MacroCallId,
+    tt: &tt::Subtree,
+) -> Result<tt::Subtree, mbe::ExpandError> {
+    let tt = expand_eager_args(db, id, tt)?;
+    let mut text = String::new();
+    for (i, t) in tt.token_trees.iter().enumerate() {
+        match t {
+            tt::TokenTree::Leaf(tt::Leaf::Literal(it)) if i % 2 == 0 => {
+                let lit = it.text.as_str();
+                if let Some(s) = unquote_str(it) {
+                    text += s;
+                } else if lit.len() >= 2 && lit.starts_with('\'') && lit.ends_with('\'') {
+                    match &lit[1..lit.len() - 1] {
+                        "\"" => text += "\\\"",
+                        "\\'" => text += "'",
+                        c => text += c,
+                    }
+                } else if lit.starts_with('b') {
+                    // byte literals are rejected by rustc
+                    return Err(mbe::ExpandError::UnexpectedToken);
+                } else {
+                    // numbers and `true`/`false`
+                    text += lit;
+                }
+            }
+            tt::TokenTree::Leaf(tt::Leaf::Punct(punct)) if i % 2 == 1 && punct.char == ',' => (),
+            _ => return Err(mbe::ExpandError::UnexpectedToken),
+        }
+    }
+    // `text` already contains escapes, so we can't use `string_literal` here
+    let literal = tt::Literal { text: format!("\"{}\"", text).into() };
+    Ok(quote!(#literal))
+}
+
+fn env_expand(
+    db: &dyn AstDatabase,
+    id: MacroCallId,
+    tt: &tt::Subtree,
+) -> Result<tt::Subtree, mbe::ExpandError> {
+    let tt = expand_eager_args(db, id, tt)?;
+    let key = parse_string(&tt)?;
+    // FIXME: rustc reports an error for unset variables; we expand to an
+    // empty string instead, so that at least the type is right.
+    let value = get_env_inner(db, id, &key).unwrap_or_default();
+    let literal = string_literal(&value);
+    Ok(quote!(#literal))
+}
+
+fn option_env_expand(
+    db: &dyn AstDatabase,
+    id: MacroCallId,
+    tt: &tt::Subtree,
+) -> Result<tt::Subtree, mbe::ExpandError> {
+    let tt = expand_eager_args(db, id, tt)?;
+    let key = parse_string(&tt)?;
+    let expanded = match get_env_inner(db, id, &key) {
+        Some(value) => {
+            let literal = string_literal(&value);
+            quote! { std::option::Option::Some(#literal) }
+        }
+        None => quote! { std::option::Option::None::<&str> },
+    };
+    Ok(expanded)
+}
+
+fn get_env_inner(db: &dyn AstDatabase, id: MacroCallId, key: &str) -> Option<String> {
+    let krate = db.lookup_intern_macro(id).krate;
+    db.crate_graph().env(krate).get(key)
+}
+
+/// Resolves `path` relative to the file containing the macro call.
+fn relative_file(
+    db: &dyn AstDatabase,
+    id: MacroCallId,
+    path: &str,
+) -> Result<FileId, mbe::ExpandError> {
+    let loc = db.lookup_intern_macro(id);
+    // Absolute paths, e.g. those starting with `env!("OUT_DIR")`, are resolved
+    // against the extern sources (like the build script output dir) of the crate.
+    if let Some((extern_id, rel_path)) = db.crate_graph().extern_source(loc.krate).extern_path(path)
+    {
+        return db
+            .resolve_extern_path(extern_id, &rel_path)
+            .ok_or(mbe::ExpandError::ConversionError);
+    }
+    if path.starts_with('/') {
+        return Err(mbe::ExpandError::ConversionError);
+    }
+    let call_site = loc.kind.file_id().original_file(db);
+    db.resolve_relative_path(call_site, RelativePath::new(path))
+        .ok_or(mbe::ExpandError::ConversionError)
+}
+
+fn include_expand(
+    db: &dyn AstDatabase,
+    id: MacroCallId,
+    tt: &tt::Subtree,
+) -> Result<tt::Subtree, mbe::ExpandError> {
+    let tt = expand_eager_args(db, id, tt)?;
+    let path = parse_string(&tt)?;
+    let file_id = relative_file(db, id, &path)?;
+
+    // The included file is parsed as a whole, and then reparsed as items or an
+    // expression, depending on the position of the macro call.
+    let parse = db.parse(file_id);
+    let (subtree, _token_map) = mbe::syntax_node_to_token_tree(parse.tree().syntax())
+        .ok_or(mbe::ExpandError::ConversionError)?;
+    // Token ids refer to the included file, which is neither the call nor the
+    // definition of the macro, so we can't map them back.
+    Ok(clear_token_ids(subtree))
+}
+
+fn clear_token_ids(subtree: tt::Subtree) -> tt::Subtree {
+    let token_trees = subtree
+        .token_trees
+        .into_iter()
+        .map(|tt| match tt {
+            tt::TokenTree::Leaf(tt::Leaf::Ident(ident)) => {
+                tt::Leaf::Ident(tt::Ident { id: tt::TokenId::unspecified(), ..ident }).into()
+            }
+            tt::TokenTree::Subtree(subtree) => clear_token_ids(subtree).into(),
+            tt => tt,
+        })
+        .collect();
+    tt::Subtree { delimiter: subtree.delimiter, token_trees }
+}
+
+fn include_str_expand(
+    db: &dyn AstDatabase,
+    id: MacroCallId,
+    tt: &tt::Subtree,
+) -> Result<tt::Subtree, mbe::ExpandError> {
+    let tt = expand_eager_args(db, id, tt)?;
+    let path = parse_string(&tt)?;
+    let file_id = relative_file(db, id, &path)?;
+    let text = db.file_text(file_id);
+    let literal = string_literal(&text);
+    Ok(quote!(#literal))
+}
+
+fn include_bytes_expand(
+    db: &dyn AstDatabase,
+    id: MacroCallId,
+    tt: &tt::Subtree,
+) -> Result<tt::Subtree, mbe::ExpandError> {
+    let tt = expand_eager_args(db, id, tt)?;
+    let path = parse_string(&tt)?;
+    let file_id = relative_file(db, id, &path)?;
+    let text = db.file_text(file_id);
+    let bytes: String = text.bytes().flat_map(std::ascii::escape_default).map(char::from).collect();
+    let literal = tt::Literal { text: format!("b\"{}\"", bytes).into() };
+    Ok(quote!(#literal))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -262,12 +506,16 @@ mod tests {
         // the first one should be a macro_rules
         let def = MacroDefId {
             krate: Some(CrateId(0)),
-            ast_id: Some(AstId::new(file_id.into(), ast_id_map.ast_id(&macro_calls[0]))),
+            ast_id: Some(Either::Left(AstId::new(
+                file_id.into(),
+                ast_id_map.ast_id(&macro_calls[0]),
+            ))),
             kind: MacroDefKind::BuiltIn(expander),
         };
 
         let loc = MacroCallLoc {
             def,
+            krate: CrateId(0),
             kind: MacroCallKind::FnLike(AstId::new(
                 file_id.into(),
                 ast_id_map.ast_id(&macro_calls[1]),
@@ -367,6 +615,37 @@ mod tests {
             BuiltinFnLikeExpander::FormatArgs,
         );
 
-        assert_eq!(expanded, r#"std::fmt::Arguments::new_v1(&[] ,&[std::fmt::ArgumentV1::new(&(arg1(a,b,c)),std::fmt::Display::fmt),std::fmt::ArgumentV1::new(&(arg2),std::fmt::Display::fmt),])"#);
+        assert_eq!(
+            expanded,
+            r#"std::fmt::Arguments::new_v1(&[] ,&[std::fmt::ArgumentV1::new(&(arg1(a,b,c)),std::fmt::Display::fmt),std::fmt::ArgumentV1::new(&(arg2),std::fmt::Display::fmt),])"#
+        );
+    }
+
+    #[test]
+    fn test_concat_expand() {
+        let expanded = expand_builtin_macro(
+            r#"
+        #[rustc_builtin_macro]
+        macro_rules! concat {}
+        concat!("foo", 0, 'r', true, "bar");
+"#,
+            BuiltinFnLikeExpander::Concat,
+        );
+
+        assert_eq!(expanded, r#""foo0rtruebar""#);
+    }
+
+    #[test]
+    fn test_env_expand_unset() {
+        let expanded = expand_builtin_macro(
+            r#"
+        #[rustc_builtin_macro]
+        macro_rules! env {}
+        env!("NOT_SET");
+"#,
+            BuiltinFnLikeExpander::Env,
+        );
+
+        assert_eq!(expanded, r#""""#);
     }
 }
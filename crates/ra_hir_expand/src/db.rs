@@ -2,6 +2,7 @@
 
 use std::sync::Arc;
 
+use either::Either;
 use mbe::MacroRules;
 use ra_db::{salsa, SourceDatabase};
 use ra_parser::FragmentKind;
@@ -10,7 +11,7 @@ use ra_syntax::{AstNode, Parse, SyntaxKind::*, SyntaxNode};
 
 use crate::{
     ast_id_map::AstIdMap, BuiltinDeriveExpander, BuiltinFnLikeExpander, HirFileId, HirFileIdRepr,
-    MacroCallId, MacroCallLoc, MacroDefId, MacroDefKind, MacroFile,
+    MacroCallId, MacroCallLoc, MacroDefId, MacroDefKind, MacroFile, ProcMacroExpander,
 };
 
 #[derive(Debug, Clone, Eq, PartialEq)]
@@ -18,6 +19,7 @@ pub enum TokenExpander {
     MacroRules(mbe::MacroRules),
     Builtin(BuiltinFnLikeExpander),
     BuiltinDerive(BuiltinDeriveExpander),
+    ProcMacro(ProcMacroExpander),
 }
 
 impl TokenExpander {
@@ -31,6 +33,7 @@ impl TokenExpander {
             TokenExpander::MacroRules(it) => it.expand(tt),
             TokenExpander::Builtin(it) => it.expand(db, id, tt),
             TokenExpander::BuiltinDerive(it) => it.expand(db, id, tt),
+            TokenExpander::ProcMacro(it) => it.expand(db, id, tt),
         }
     }
 
@@ -39,6 +42,7 @@ impl TokenExpander {
             TokenExpander::MacroRules(it) => it.map_id_down(id),
             TokenExpander::Builtin(..) => id,
             TokenExpander::BuiltinDerive(..) => id,
+            TokenExpander::ProcMacro(..) => id,
         }
     }
 
@@ -47,6 +51,7 @@ impl TokenExpander {
             TokenExpander::MacroRules(it) => it.map_id_up(id),
             TokenExpander::Builtin(..) => (id, mbe::Origin::Call),
             TokenExpander::BuiltinDerive(..) => (id, mbe::Origin::Call),
+            TokenExpander::ProcMacro(..) => (id, mbe::Origin::Call),
         }
     }
 }
@@ -80,13 +85,16 @@ pub(crate) fn macro_def(
 ) -> Option<Arc<(TokenExpander, mbe::TokenMap)>> {
     match id.kind {
         MacroDefKind::Declarative => {
-            let macro_call = id.ast_id?.to_node(db);
-            let arg = macro_call.token_tree()?;
+            let arg = id.def_token_tree(db)?.value;
             let (tt, tmap) = mbe::ast_to_token_tree(&arg).or_else(|| {
                 log::warn!("fail on macro_def to token tree: {:#?}", arg);
                 None
             })?;
-            let rules = MacroRules::parse(&tt).ok().or_else(|| {
+            let rules = match id.ast_id? {
+                Either::Left(_) => MacroRules::parse(&tt),
+                Either::Right(_) => MacroRules::parse_macro2(&tt),
+            };
+            let rules = rules.ok().or_else(|| {
                 log::warn!("fail on macro_def parse: {:#?}", tt);
                 None
             })?;
@@ -99,6 +107,9 @@ pub(crate) fn macro_def(
             TokenExpander::BuiltinDerive(expander.clone()),
             mbe::TokenMap::default(),
         ))),
+        MacroDefKind::ProcMacro(expander) => {
+            Some(Arc::new((TokenExpander::ProcMacro(expander.clone()), mbe::TokenMap::default())))
+        }
     }
 }
 
@@ -129,6 +140,21 @@ pub(crate) fn macro_expand(
     Ok(Arc::new(tt))
 }
 
+/// Expands `id` like `macro_expand` does, but records how the arguments were
+/// matched against each rule of the macro. Only `macro_rules!` and `macro`
+/// definitions can be traced; `None` is returned for other macros.
+pub fn trace_macro_expansion(db: &dyn AstDatabase, id: MacroCallId) -> Option<mbe::ExpansionTrace> {
+    let loc = db.lookup_intern_macro(id);
+    let macro_arg = db.macro_arg(id)?;
+    let macro_def = db.macro_def(loc.def)?;
+    match &macro_def.0 {
+        TokenExpander::MacroRules(rules) => Some(rules.expand_traced(&macro_arg.0)),
+        TokenExpander::Builtin(_)
+        | TokenExpander::BuiltinDerive(_)
+        | TokenExpander::ProcMacro(_) => None,
+    }
+}
+
 pub(crate) fn parse_or_expand(db: &dyn AstDatabase, file_id: HirFileId) -> Option<SyntaxNode> {
     match file_id.0 {
         HirFileIdRepr::FileId(file_id) => Some(db.parse(file_id).tree().syntax().clone()),
@@ -21,6 +21,9 @@ use ra_syntax::{SyntaxNode, SyntaxNodePtr, TextRange};
 use crate::{db::AstDatabase, InFile};
 
 pub trait Diagnostic: Any + Send + Sync + fmt::Debug + 'static {
+    /// A stable, kebab-case identifier of the kind of this diagnostic, like
+    /// `unresolved-module`.
+    fn code(&self) -> &'static str;
     fn message(&self) -> String;
     fn source(&self) -> InFile<SyntaxNodePtr>;
     fn highlight_range(&self) -> TextRange {
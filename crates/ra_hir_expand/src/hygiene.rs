@@ -1,15 +1,16 @@
 //! This modules handles hygiene information.
 //!
-//! Specifically, `ast` + `Hygiene` allows you to create a `Name`. Note that, at
-//! this moment, this is horribly incomplete and handles only `$crate`.
+//! Specifically, `ast` + `Hygiene` allows you to create a `Name`, resolving
+//! `$crate`. Besides that, `SyntaxContext` tells which `macro_rules`
+//! expansion introduced a token, which is what makes local variables hygienic.
 use either::Either;
 use ra_db::CrateId;
-use ra_syntax::ast;
+use ra_syntax::{ast, SyntaxToken};
 
 use crate::{
     db::AstDatabase,
     name::{AsName, Name},
-    HirFileId, HirFileIdRepr, MacroDefKind,
+    HirFileId, HirFileIdRepr, InFile, MacroCallId, MacroDefKind,
 };
 
 #[derive(Debug)]
@@ -28,6 +29,7 @@ impl Hygiene {
                     MacroDefKind::Declarative => loc.def.krate,
                     MacroDefKind::BuiltIn(_) => None,
                     MacroDefKind::BuiltInDerive(_) => None,
+                    MacroDefKind::ProcMacro(_) => None,
                 }
             }
         };
@@ -48,3 +50,52 @@ impl Hygiene {
         Either::Left(name_ref.as_name())
     }
 }
+
+/// The syntax context of a token: the `macro_rules` expansion whose definition
+/// the token comes from, if any.
+///
+/// Tokens passed to a macro as arguments keep the context they have at the
+/// call site, so local variables introduced by a macro are only visible to
+/// identifiers from the macro definition, and vice versa.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SyntaxContext(Option<MacroCallId>);
+
+impl SyntaxContext {
+    /// The context of the code written by the user.
+    pub const ROOT: SyntaxContext = SyntaxContext(None);
+
+    pub fn is_root(self) -> bool {
+        self.0.is_none()
+    }
+
+    pub fn of_token(db: &impl AstDatabase, token: InFile<&SyntaxToken>) -> SyntaxContext {
+        let mut token = token.cloned();
+        loop {
+            let macro_file = match token.file_id.0 {
+                HirFileIdRepr::FileId(_) => return SyntaxContext::ROOT,
+                HirFileIdRepr::MacroFile(it) => it,
+            };
+            let loc = db.lookup_intern_macro(macro_file.macro_call_id);
+            let origin = token.file_id.expansion_info(db).and_then(|info| {
+                let token_id = info.exp_map.token_by_range(token.value.text_range())?;
+                let (_, origin) = info.macro_def.0.map_id_up(token_id);
+                Some((origin, info.map_token_up(token.as_ref())))
+            });
+            token = match (origin, loc.def.kind) {
+                (Some((mbe::Origin::Def, _)), MacroDefKind::Declarative) => {
+                    return SyntaxContext(Some(macro_file.macro_call_id))
+                }
+                (Some((mbe::Origin::Call, Some(call_site_token))), _) => call_site_token,
+                // Tokens made up by built-in and procedural macros behave as if
+                // they were written at the call site.
+                _ => {
+                    let call = loc.kind.node(db);
+                    match call.value.first_token() {
+                        Some(it) => call.with_value(it),
+                        None => return SyntaxContext::ROOT,
+                    }
+                }
+            };
+        }
+    }
+}
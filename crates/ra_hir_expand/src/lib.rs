@@ -11,11 +11,13 @@ pub mod hygiene;
 pub mod diagnostics;
 pub mod builtin_derive;
 pub mod builtin_macro;
+pub mod proc_macro;
 pub mod quote;
 
 use std::hash::Hash;
 use std::sync::Arc;
 
+use either::Either;
 use ra_db::{salsa, CrateId, FileId};
 use ra_syntax::{
     algo,
@@ -26,6 +28,9 @@ use ra_syntax::{
 use crate::ast_id_map::FileAstId;
 use crate::builtin_derive::BuiltinDeriveExpander;
 use crate::builtin_macro::BuiltinFnLikeExpander;
+use crate::proc_macro::ProcMacroExpander;
+
+pub use mbe::{BindingTrace, ExpansionTrace, RuleOutcome, RuleTrace};
 
 #[cfg(test)]
 mod test_db;
@@ -76,6 +81,14 @@ impl HirFileId {
         }
     }
 
+    /// Returns `true` if this file is the expansion of a macro call.
+    pub fn is_macro_file(self) -> bool {
+        match self.0 {
+            HirFileIdRepr::FileId(_) => false,
+            HirFileIdRepr::MacroFile(_) => true,
+        }
+    }
+
     /// If this is a macro call, returns the syntax node of the call.
     pub fn call_node(self, db: &dyn db::AstDatabase) -> Option<InFile<SyntaxNode>> {
         match self.0 {
@@ -95,7 +108,7 @@ impl HirFileId {
                 let loc: MacroCallLoc = db.lookup_intern_macro(macro_file.macro_call_id);
 
                 let arg_tt = loc.kind.arg(db)?;
-                let def_tt = loc.def.ast_id?.to_node(db).token_tree()?;
+                let def_tt = loc.def.def_token_tree(db)?;
 
                 let macro_def = db.macro_def(loc.def)?;
                 let (parse, exp_map) = db.parse_macro(macro_file)?;
@@ -104,7 +117,7 @@ impl HirFileId {
                 Some(ExpansionInfo {
                     expanded: InFile::new(self, parse.syntax_node()),
                     arg: InFile::new(loc.kind.file_id(), arg_tt),
-                    def: InFile::new(loc.def.ast_id?.file_id, def_tt),
+                    def: def_tt,
                     macro_arg,
                     macro_def,
                     exp_map,
@@ -136,18 +149,32 @@ impl salsa::InternKey for MacroCallId {
 pub struct MacroDefId {
     // FIXME: krate and ast_id are currently optional because we don't have a
     // definition location for built-in derives. There is one, though: the
-    // standard library defines them. The problem is that it uses the new
-    // `macro` syntax for this, which we don't support yet. As soon as we do
-    // (which will probably require touching this code), we can instead use
-    // that (and also remove the hacks for resolving built-in derives).
+    // standard library defines them with the new `macro` syntax. We could
+    // instead use that (and also remove the hacks for resolving built-in
+    // derives).
     pub krate: Option<CrateId>,
-    pub ast_id: Option<AstId<ast::MacroCall>>,
+    /// The `macro_rules!` call or the macros 2.0 `macro` item which defines
+    /// the macro.
+    pub ast_id: Option<Either<AstId<ast::MacroCall>, AstId<ast::MacroDef>>>,
     pub kind: MacroDefKind,
 }
 
 impl MacroDefId {
-    pub fn as_call_id(self, db: &dyn db::AstDatabase, kind: MacroCallKind) -> MacroCallId {
-        db.intern_macro(MacroCallLoc { def: self, kind })
+    pub fn as_call_id(
+        self,
+        db: &dyn db::AstDatabase,
+        krate: CrateId,
+        kind: MacroCallKind,
+    ) -> MacroCallId {
+        db.intern_macro(MacroCallLoc { def: self, krate, kind })
+    }
+
+    /// The token tree which holds the rules of the macro.
+    pub fn def_token_tree(self, db: &dyn db::AstDatabase) -> Option<InFile<ast::TokenTree>> {
+        match self.ast_id? {
+            Either::Left(it) => Some(it.with_value(it.to_node(db).token_tree()?)),
+            Either::Right(it) => Some(it.with_value(it.to_node(db).token_tree()?)),
+        }
     }
 }
 
@@ -157,32 +184,42 @@ pub enum MacroDefKind {
     BuiltIn(BuiltinFnLikeExpander),
     // FIXME: maybe just Builtin and rename BuiltinFnLikeExpander to BuiltinExpander
     BuiltInDerive(BuiltinDeriveExpander),
+    ProcMacro(ProcMacroExpander),
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct MacroCallLoc {
     pub(crate) def: MacroDefId,
+    /// The crate in which the macro is called. Builtin macros like `env!`
+    /// need this, as `def.krate` points to the crate defining the macro.
+    pub(crate) krate: CrateId,
     pub(crate) kind: MacroCallKind,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub enum MacroCallKind {
     FnLike(AstId<ast::MacroCall>),
-    Attr(AstId<ast::ModuleItem>),
+    /// A derive applied to an item.
+    Derive(AstId<ast::ModuleItem>),
+    /// An attribute macro applied to an item. The `String` is the name of the
+    /// attribute.
+    Attr(AstId<ast::ModuleItem>, String),
 }
 
 impl MacroCallKind {
     pub fn file_id(&self) -> HirFileId {
         match self {
             MacroCallKind::FnLike(ast_id) => ast_id.file_id,
-            MacroCallKind::Attr(ast_id) => ast_id.file_id,
+            MacroCallKind::Derive(ast_id) | MacroCallKind::Attr(ast_id, _) => ast_id.file_id,
         }
     }
 
     pub fn node(&self, db: &dyn db::AstDatabase) -> InFile<SyntaxNode> {
         match self {
             MacroCallKind::FnLike(ast_id) => ast_id.with_value(ast_id.to_node(db).syntax().clone()),
-            MacroCallKind::Attr(ast_id) => ast_id.with_value(ast_id.to_node(db).syntax().clone()),
+            MacroCallKind::Derive(ast_id) | MacroCallKind::Attr(ast_id, _) => {
+                ast_id.with_value(ast_id.to_node(db).syntax().clone())
+            }
         }
     }
 
@@ -191,7 +228,9 @@ impl MacroCallKind {
             MacroCallKind::FnLike(ast_id) => {
                 Some(ast_id.to_node(db).token_tree()?.syntax().clone())
             }
-            MacroCallKind::Attr(ast_id) => Some(ast_id.to_node(db).syntax().clone()),
+            MacroCallKind::Derive(ast_id) | MacroCallKind::Attr(ast_id, _) => {
+                Some(ast_id.to_node(db).syntax().clone())
+            }
         }
     }
 }
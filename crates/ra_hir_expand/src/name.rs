@@ -37,6 +37,11 @@ impl Name {
         Name(Repr::TupleField(idx))
     }
 
+    /// Creates a name for a label from its lifetime token, e.g. `'outer`.
+    pub fn new_lifetime(lt: &ra_syntax::SyntaxToken) -> Name {
+        Name::new_text(lt.text().clone())
+    }
+
     /// Shortcut to create inline plain text name
     const fn new_inline_ascii(text: &[u8]) -> Name {
         Name::new_text(SmolStr::new_inline_from_ascii(text.len(), text))
@@ -104,6 +109,12 @@ impl AsName for ra_db::Dependency {
     }
 }
 
+impl AsName for ra_db::ProcMacro {
+    fn as_name(&self) -> Name {
+        Name::new_text(self.name.clone())
+    }
+}
+
 // Primitives
 pub const ISIZE: Name = Name::new_inline_ascii(b"isize");
 pub const I8: Name = Name::new_inline_ascii(b"i8");
@@ -161,6 +172,12 @@ pub const LINE_MACRO: Name = Name::new_inline_ascii(b"line");
 pub const STRINGIFY_MACRO: Name = Name::new_inline_ascii(b"stringify");
 pub const FORMAT_ARGS_MACRO: Name = Name::new_inline_ascii(b"format_args");
 pub const FORMAT_ARGS_NL_MACRO: Name = Name::new_inline_ascii(b"format_args_nl");
+pub const INCLUDE_MACRO: Name = Name::new_inline_ascii(b"include");
+pub const INCLUDE_STR_MACRO: Name = Name::new_inline_ascii(b"include_str");
+pub const INCLUDE_BYTES_MACRO: Name = Name::new_inline_ascii(b"include_bytes");
+pub const CONCAT_MACRO: Name = Name::new_inline_ascii(b"concat");
+pub const ENV_MACRO: Name = Name::new_inline_ascii(b"env");
+pub const OPTION_ENV_MACRO: Name = Name::new_inline_ascii(b"option_env");
 
 // Builtin derives
 pub const COPY_TRAIT: Name = Name::new_inline_ascii(b"Copy");
@@ -0,0 +1,116 @@
+//! Expansion of procedural macros, which is delegated to the expanders
+//! registered for the crate in the `CrateGraph`.
+
+use ra_db::{CrateGraph, CrateId, ProcMacroId, ProcMacroKind};
+
+use crate::{db::AstDatabase, MacroCallId, MacroCallKind};
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+pub struct ProcMacroExpander {
+    krate: CrateId,
+    proc_macro_id: ProcMacroId,
+}
+
+impl ProcMacroExpander {
+    pub fn new(krate: CrateId, proc_macro_id: ProcMacroId) -> ProcMacroExpander {
+        ProcMacroExpander { krate, proc_macro_id }
+    }
+
+    /// How the macro is invoked, as declared by its crate.
+    pub fn kind<'a>(&self, crate_graph: &'a CrateGraph) -> Option<&'a ProcMacroKind> {
+        crate_graph.proc_macros(self.krate).get(self.proc_macro_id.0 as usize).map(|it| &it.kind)
+    }
+
+    pub fn expand(
+        &self,
+        db: &dyn AstDatabase,
+        id: MacroCallId,
+        tt: &tt::Subtree,
+    ) -> Result<tt::Subtree, mbe::ExpandError> {
+        let krate_graph = db.crate_graph();
+        let proc_macro = krate_graph
+            .proc_macros(self.krate)
+            .get(self.proc_macro_id.0 as usize)
+            .ok_or_else(|| mbe::ExpandError::ConversionError)?;
+
+        match db.lookup_intern_macro(id).kind {
+            MacroCallKind::FnLike(_) => proc_macro.expander.expand(tt, None),
+            // Like rustc, remove the attributes which invoked the macro from
+            // the item, so that they are not expanded again.
+            MacroCallKind::Derive(_) => {
+                let (item, _) = strip_attrs(tt, "derive", true);
+                proc_macro.expander.expand(&item, None)
+            }
+            MacroCallKind::Attr(_, attr_name) => {
+                let (item, args) = strip_attrs(tt, &attr_name, false);
+                let args = args.unwrap_or_else(|| tt::Subtree {
+                    delimiter: tt::Delimiter::None,
+                    token_trees: Vec::new(),
+                });
+                proc_macro.expander.expand(&item, Some(&args))
+            }
+        }
+        .map_err(mbe::ExpandError::from)
+    }
+}
+
+/// Removes the first (or, with `all`, every) `#[attr_name ...]` attribute
+/// from `item`, returning the arguments of the first removed attribute.
+fn strip_attrs(
+    item: &tt::Subtree,
+    attr_name: &str,
+    all: bool,
+) -> (tt::Subtree, Option<tt::Subtree>) {
+    let mut args = None;
+    let mut removed = false;
+    let mut token_trees = Vec::with_capacity(item.token_trees.len());
+    let mut iter = item.token_trees.iter().peekable();
+    while let Some(tt) = iter.next() {
+        if let tt::TokenTree::Leaf(tt::Leaf::Punct(punct)) = tt {
+            if punct.char == '#' && (all || !removed) {
+                if let Some(tt::TokenTree::Subtree(attr)) = iter.peek() {
+                    if let Some(attr_args) = attr_args(attr, attr_name) {
+                        if !removed {
+                            args = attr_args;
+                            removed = true;
+                        }
+                        iter.next();
+                        continue;
+                    }
+                }
+            }
+        }
+        token_trees.push(tt.clone());
+    }
+    (tt::Subtree { delimiter: item.delimiter, token_trees }, args)
+}
+
+/// If `attr` is the `[...]` part of an `#[attr_name ...]` attribute, returns
+/// its arguments, without delimiters.
+fn attr_args(attr: &tt::Subtree, attr_name: &str) -> Option<Option<tt::Subtree>> {
+    if attr.delimiter != tt::Delimiter::Bracket {
+        return None;
+    }
+    let path_len = attr
+        .token_trees
+        .iter()
+        .position(|tt| match tt {
+            tt::TokenTree::Leaf(tt::Leaf::Ident(_)) => false,
+            tt::TokenTree::Leaf(tt::Leaf::Punct(punct)) => punct.char != ':',
+            _ => true,
+        })
+        .unwrap_or(attr.token_trees.len());
+    // The attribute might be written as a path, like `#[my_macros::route]`.
+    match attr.token_trees[..path_len].last() {
+        Some(tt::TokenTree::Leaf(tt::Leaf::Ident(ident))) if ident.text == attr_name => (),
+        _ => return None,
+    }
+    let args = match &attr.token_trees[path_len..] {
+        [tt::TokenTree::Subtree(args)] => Some(tt::Subtree {
+            delimiter: tt::Delimiter::None,
+            token_trees: args.token_trees.clone(),
+        }),
+        _ => None,
+    };
+    Some(args)
+}
@@ -96,6 +96,8 @@ macro_rules! __quote {
     ( : ) => {$crate::__quote!(@PUNCT ':')};
     ( :: ) => {$crate::__quote!(@PUNCT ':', ':')};
     ( . ) => {$crate::__quote!(@PUNCT '.')};
+    ( < ) => {$crate::__quote!(@PUNCT '<')};
+    ( > ) => {$crate::__quote!(@PUNCT '>')};
 
     ( $first:tt $($tail:tt)+ ) => {
         {
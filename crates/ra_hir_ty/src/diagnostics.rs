@@ -15,6 +15,9 @@ pub struct NoSuchField {
 }
 
 impl Diagnostic for NoSuchField {
+    fn code(&self) -> &'static str {
+        "no-such-field"
+    }
     fn message(&self) -> String {
         "no such field".to_string()
     }
@@ -36,6 +39,9 @@ pub struct MissingFields {
 }
 
 impl Diagnostic for MissingFields {
+    fn code(&self) -> &'static str {
+        "missing-structure-fields"
+    }
     fn message(&self) -> String {
         use std::fmt::Write;
         let mut message = String::from("Missing structure fields:\n");
@@ -69,6 +75,9 @@ pub struct MissingOkInTailExpr {
 }
 
 impl Diagnostic for MissingOkInTailExpr {
+    fn code(&self) -> &'static str {
+        "missing-ok-in-tail-expr"
+    }
     fn message(&self) -> String {
         "wrap return expression in Ok".to_string()
     }
@@ -27,9 +27,12 @@ use hir_def::{
     path::{known, Path},
     resolver::{HasResolver, Resolver, TypeNs},
     type_ref::{Mutability, TypeRef},
-    AdtId, AssocItemId, DefWithBodyId, FunctionId, StructFieldId, TypeAliasId, VariantId,
+    AdtId, AssocItemId, DefWithBodyId, FunctionId, StructFieldId, TraitId, TypeAliasId, VariantId,
+};
+use hir_expand::{
+    diagnostics::DiagnosticSink,
+    name::{self, Name},
 };
-use hir_expand::{diagnostics::DiagnosticSink, name};
 use ra_arena::map::ArenaMap;
 use ra_prof::profile;
 
@@ -41,6 +44,7 @@ use super::{
 };
 use crate::{db::HirDatabase, infer::diagnostics::InferenceDiagnostic};
 
+pub use closure::{CaptureMode, CapturedPlace, ClosureCaptures};
 pub(crate) use unify::unify;
 
 macro_rules! ty_app {
@@ -57,6 +61,7 @@ mod path;
 mod expr;
 mod pat;
 mod coerce;
+mod closure;
 
 /// The entry point of type inference.
 pub fn infer_query(db: &impl HirDatabase, def: DefWithBodyId) -> Arc<InferenceResult> {
@@ -127,6 +132,8 @@ pub struct InferenceResult {
     variant_resolutions: FxHashMap<ExprOrPatId, VariantId>,
     /// For each associated item record what it resolves to
     assoc_resolutions: FxHashMap<ExprOrPatId, AssocItemId>,
+    /// For each closure expr, records what it captures and its kind.
+    closure_captures: FxHashMap<ExprId, ClosureCaptures>,
     diagnostics: Vec<InferenceDiagnostic>,
     pub type_of_expr: ArenaMap<ExprId, Ty>,
     pub type_of_pat: ArenaMap<PatId, Ty>,
@@ -155,6 +162,9 @@ impl InferenceResult {
     pub fn assoc_resolutions_for_pat(&self, id: PatId) -> Option<AssocItemId> {
         self.assoc_resolutions.get(&id.into()).copied()
     }
+    pub fn closure_captures(&self, closure: ExprId) -> Option<&ClosureCaptures> {
+        self.closure_captures.get(&closure)
+    }
     pub fn type_mismatch_for_expr(&self, expr: ExprId) -> Option<&TypeMismatch> {
         self.type_mismatches.get(expr)
     }
@@ -197,6 +207,9 @@ struct InferenceContext<'a, D: HirDatabase> {
     result: InferenceResult,
     /// The return type of the function being inferred.
     return_ty: Ty,
+    /// The loops we're currently inside of, innermost last; `break`s look up
+    /// their target here.
+    breakables: Vec<BreakableContext>,
 
     /// Impls of `CoerceUnsized` used in coercion.
     /// (from_ty_ctor, to_ty_ctor) => coerce_generic_index
@@ -212,6 +225,7 @@ impl<'a, D: HirDatabase> InferenceContext<'a, D> {
             table: unify::InferenceTable::new(),
             obligations: Vec::default(),
             return_ty: Ty::Unknown, // set in collect_fn_signature
+            breakables: Vec::new(),
             trait_env: TraitEnvironment::lower(db, &resolver),
             coerce_unsized_map: Self::init_coerce_unsized_map(db, &resolver),
             db,
@@ -415,9 +429,13 @@ impl<'a, D: HirDatabase> InferenceContext<'a, D> {
         self.db.trait_data(trait_).associated_type_by_name(&name::OK_TYPE)
     }
 
-    fn resolve_future_future_output(&self) -> Option<TypeAliasId> {
+    fn resolve_future_future(&self) -> Option<TraitId> {
         let path = known::std_future_future();
-        let trait_ = self.resolver.resolve_known_trait(self.db, &path)?;
+        self.resolver.resolve_known_trait(self.db, &path)
+    }
+
+    fn resolve_future_future_output(&self) -> Option<TypeAliasId> {
+        let trait_ = self.resolve_future_future()?;
         self.db.trait_data(trait_).associated_type_by_name(&name::OUTPUT_TYPE)
     }
 
@@ -496,6 +514,27 @@ impl InferTy {
     }
 }
 
+/// A loop that `break` and `continue` expressions inside it can target.
+#[derive(Clone, Debug)]
+struct BreakableContext {
+    /// Whether any `break` targets this loop.
+    may_break: bool,
+    /// The type of the values the loop is broken with; always `()` for `while`
+    /// and `for` loops.
+    break_ty: Ty,
+    label: Option<Name>,
+}
+
+fn find_breakable<'c>(
+    ctxs: &'c mut [BreakableContext],
+    label: Option<&Name>,
+) -> Option<&'c mut BreakableContext> {
+    match label {
+        Some(_) => ctxs.iter_mut().rev().find(|ctx| ctx.label.as_ref() == label),
+        None => ctxs.last_mut(),
+    }
+}
+
 /// When inferring an expression, we propagate downward whatever type hint we
 /// are able in the form of an `Expectation`.
 #[derive(Clone, PartialEq, Eq, Debug)]
@@ -0,0 +1,385 @@
+//! Capture analysis for closures.
+//!
+//! For each closure, we determine which places of its environment it uses,
+//! i.e. local bindings declared outside of the closure, possibly followed by
+//! field accesses, and how it uses them: through a shared reference, through a
+//! mutable reference, or by moving out of them. This determines how each place
+//! is captured, and which of the `Fn*` traits the closure implements.
+
+use std::sync::Arc;
+
+use hir_def::{
+    body::scope::{ExprScopes, ScopeId},
+    expr::{BinaryOp, BindingAnnotation, Expr, ExprId, MatchArm, Pat, PatId, Statement, UnaryOp},
+    lang_item::LangItemTarget,
+    type_ref::{Mutability, TypeRef},
+};
+use hir_expand::name::{self, Name};
+
+use super::InferenceContext;
+use crate::{
+    db::HirDatabase,
+    traits::{FnTrait, InEnvironment, Obligation, Solution},
+    Substs, TraitRef, Ty, TypeCtor,
+};
+
+/// How a closure captures a place from its environment. The variants are
+/// ordered from the least to the most restrictive one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum CaptureMode {
+    ByRef,
+    ByMutRef,
+    ByMove,
+}
+
+/// A place captured by a closure: a local binding of the enclosing body,
+/// followed by zero or more field accesses, like `self.config.name`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CapturedPlace {
+    pub local: PatId,
+    pub fields: Vec<Name>,
+    pub mode: CaptureMode,
+}
+
+/// The result of capture analysis for a single closure.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ClosureCaptures {
+    /// The most specific `Fn*` trait the closure implements.
+    pub kind: FnTrait,
+    pub places: Vec<CapturedPlace>,
+}
+
+struct CaptureCtx {
+    scopes: Arc<ExprScopes>,
+    /// The scope of the closure expression itself. Bindings visible from there
+    /// are declared outside of the closure.
+    outer_scope: Option<ScopeId>,
+}
+
+impl CaptureCtx {
+    fn is_outer_binding(&self, pat: PatId) -> bool {
+        self.scopes
+            .scope_chain(self.outer_scope)
+            .any(|scope| self.scopes.entries(scope).iter().any(|entry| entry.pat() == pat))
+    }
+}
+
+impl<'a, D: HirDatabase> InferenceContext<'a, D> {
+    /// Computes the captures of the given closure. This needs to be called
+    /// after the closure body has been inferred, since whether a use of a
+    /// place moves out of it depends on its type.
+    pub(super) fn analyze_closure_captures(&mut self, closure: ExprId) -> ClosureCaptures {
+        let body = Arc::clone(&self.body); // avoid borrow checker problem
+        let (closure_body, is_move) = match &body[closure] {
+            Expr::Lambda { body, is_move, .. } => (*body, *is_move),
+            _ => panic!("capture analysis for a non-closure expression"),
+        };
+        let scopes = self.db.expr_scopes(self.owner);
+        let ctx = CaptureCtx { outer_scope: scopes.scope_for(closure), scopes };
+
+        // The value of the body is returned from the closure, so it is moved.
+        let mut uses = Vec::new();
+        self.collect_place_uses(&ctx, closure_body, CaptureMode::ByMove, &mut uses);
+
+        // Moving out of a captured place makes the closure callable only once,
+        // mutating one requires a mutable borrow of the closure. Note that this
+        // is independent of whether the closure is `move`.
+        let kind = match uses.iter().map(|it| it.mode).max() {
+            Some(CaptureMode::ByMove) => FnTrait::FnOnce,
+            Some(CaptureMode::ByMutRef) => FnTrait::FnMut,
+            Some(CaptureMode::ByRef) | None => FnTrait::Fn,
+        };
+
+        let mut places: Vec<CapturedPlace> = Vec::new();
+        for place_use in uses {
+            let mut mode = if is_move { CaptureMode::ByMove } else { place_use.mode };
+            // A captured place covers all places inside of it.
+            if let Some(parent) = places
+                .iter_mut()
+                .find(|it| it.local == place_use.local && place_use.fields.starts_with(&it.fields))
+            {
+                parent.mode = parent.mode.max(mode);
+                continue;
+            }
+            places.retain(|it| {
+                let is_child =
+                    it.local == place_use.local && it.fields.starts_with(&place_use.fields);
+                if is_child {
+                    mode = mode.max(it.mode);
+                }
+                !is_child
+            });
+            places.push(CapturedPlace { local: place_use.local, fields: place_use.fields, mode });
+        }
+
+        ClosureCaptures { kind, places }
+    }
+
+    /// Collects the uses of places from the closure's environment in `expr`,
+    /// whose value is used according to `mode`.
+    fn collect_place_uses(
+        &mut self,
+        ctx: &CaptureCtx,
+        expr: ExprId,
+        mode: CaptureMode,
+        uses: &mut Vec<CapturedPlace>,
+    ) {
+        if let Some((local, fields)) = self.captured_place(ctx, expr) {
+            // Using a `Copy` value copies it, which just needs a shared borrow.
+            let is_copied = mode == CaptureMode::ByMove && self.is_copy(self.result[expr].clone());
+            let mode = if is_copied { CaptureMode::ByRef } else { mode };
+            uses.push(CapturedPlace { local, fields, mode });
+            return;
+        }
+
+        let body = Arc::clone(&self.body); // avoid borrow checker problem
+        match &body[expr] {
+            Expr::Ref { expr, mutability } => {
+                let mode = match mutability {
+                    Mutability::Shared => CaptureMode::ByRef,
+                    Mutability::Mut => CaptureMode::ByMutRef,
+                };
+                self.collect_place_uses(ctx, *expr, mode, uses);
+            }
+            Expr::BinaryOp { lhs, rhs, op: Some(BinaryOp::Assignment { .. }) } => {
+                self.collect_place_uses(ctx, *lhs, CaptureMode::ByMutRef, uses);
+                self.collect_place_uses(ctx, *rhs, CaptureMode::ByMove, uses);
+            }
+            Expr::BinaryOp { lhs, rhs, op: Some(BinaryOp::CmpOp(_)) } => {
+                // comparison operators take their operands by reference
+                self.collect_place_uses(ctx, *lhs, CaptureMode::ByRef, uses);
+                self.collect_place_uses(ctx, *rhs, CaptureMode::ByRef, uses);
+            }
+            Expr::MethodCall { receiver, args, .. } => {
+                let receiver_mode = self.method_receiver_mode(expr);
+                self.collect_place_uses(ctx, *receiver, receiver_mode, uses);
+                for arg in args {
+                    self.collect_place_uses(ctx, *arg, CaptureMode::ByMove, uses);
+                }
+            }
+            Expr::Call { callee, args } => {
+                // FIXME: calling a captured `FnMut` closure requires a mutable
+                // borrow, and calling a captured `FnOnce` closure moves it
+                self.collect_place_uses(ctx, *callee, CaptureMode::ByRef, uses);
+                for arg in args {
+                    self.collect_place_uses(ctx, *arg, CaptureMode::ByMove, uses);
+                }
+            }
+            Expr::Field { expr, .. } => self.collect_place_uses(ctx, *expr, mode, uses),
+            Expr::Index { base, index } => {
+                let base_mode = place_mode(mode);
+                self.collect_place_uses(ctx, *base, base_mode, uses);
+                self.collect_place_uses(ctx, *index, CaptureMode::ByMove, uses);
+            }
+            Expr::UnaryOp { expr, op: UnaryOp::Deref } => {
+                let inner_mode = place_mode(mode);
+                self.collect_place_uses(ctx, *expr, inner_mode, uses);
+            }
+            Expr::If { condition, then_branch, else_branch } => {
+                self.collect_place_uses(ctx, *condition, CaptureMode::ByMove, uses);
+                self.collect_place_uses(ctx, *then_branch, mode, uses);
+                if let Some(else_branch) = else_branch {
+                    self.collect_place_uses(ctx, *else_branch, mode, uses);
+                }
+            }
+            Expr::Block { statements, tail } => {
+                for stmt in statements {
+                    match stmt {
+                        Statement::Let { initializer, .. } => {
+                            if let Some(expr) = initializer {
+                                self.collect_place_uses(ctx, *expr, CaptureMode::ByMove, uses);
+                            }
+                        }
+                        Statement::Expr(expr) => {
+                            self.collect_place_uses(ctx, *expr, CaptureMode::ByMove, uses)
+                        }
+                    }
+                }
+                if let Some(tail) = tail {
+                    self.collect_place_uses(ctx, *tail, mode, uses);
+                }
+            }
+            Expr::Match { expr, arms } => {
+                let scrutinee_mode = self.match_scrutinee_mode(*expr, arms);
+                self.collect_place_uses(ctx, *expr, scrutinee_mode, uses);
+                for arm in arms {
+                    if let Some(guard) = arm.guard {
+                        self.collect_place_uses(ctx, guard, CaptureMode::ByMove, uses);
+                    }
+                    self.collect_place_uses(ctx, arm.expr, mode, uses);
+                }
+            }
+            Expr::Lambda { .. } => {
+                // Nested closures have been analyzed already, since their
+                // bodies are inferred as part of the body of this one.
+                let nested = match self.result.closure_captures(expr) {
+                    Some(it) => it.places.clone(),
+                    None => return,
+                };
+                for place in nested {
+                    if !ctx.is_outer_binding(place.local) {
+                        continue;
+                    }
+                    let mode = if place.mode == CaptureMode::ByMove && place.fields.is_empty() {
+                        let ty = self.result[place.local].clone();
+                        if self.is_copy(ty) {
+                            CaptureMode::ByRef
+                        } else {
+                            CaptureMode::ByMove
+                        }
+                    } else {
+                        place.mode
+                    };
+                    uses.push(CapturedPlace { mode, ..place });
+                }
+            }
+            e => {
+                let mut children = Vec::new();
+                e.walk_child_exprs(|child| children.push(child));
+                for child in children {
+                    self.collect_place_uses(ctx, child, CaptureMode::ByMove, uses);
+                }
+            }
+        }
+    }
+
+    /// If `expr` is a place from the environment of the closure, returns the
+    /// binding and the fields that are accessed on it.
+    fn captured_place(&mut self, ctx: &CaptureCtx, expr: ExprId) -> Option<(PatId, Vec<Name>)> {
+        let body = Arc::clone(&self.body); // avoid borrow checker problem
+        match &body[expr] {
+            Expr::Path(path) => {
+                let name = if path.is_self() { name::SELF_PARAM } else { path.as_ident()?.clone() };
+                let scope = ctx.scopes.scope_for(expr)?;
+                let context = body.expr_context(expr);
+                let local = ctx.scopes.resolve_name_in_scope(scope, &name, context)?.pat();
+                if ctx.is_outer_binding(local) {
+                    Some((local, Vec::new()))
+                } else {
+                    None
+                }
+            }
+            Expr::Field { expr: base, name } => {
+                let (local, mut fields) = self.captured_place(ctx, *base)?;
+                // We don't look through references: using a place behind a
+                // reference is a use of the reference.
+                let base_ty = self.table.resolve_ty_as_possible(self.result[*base].clone());
+                match base_ty {
+                    ty_app!(TypeCtor::Ref(_)) | ty_app!(TypeCtor::RawPtr(_)) => {}
+                    _ => fields.push(name.clone()),
+                }
+                Some((local, fields))
+            }
+            _ => None,
+        }
+    }
+
+    /// The mode in which the scrutinee of a match is used: by-value bindings
+    /// of non-`Copy` values move out of it, `ref mut` bindings borrow it
+    /// mutably, anything else just reads it.
+    fn match_scrutinee_mode(&mut self, scrutinee: ExprId, arms: &[MatchArm]) -> CaptureMode {
+        let body = Arc::clone(&self.body); // avoid borrow checker problem
+        let mut mode = CaptureMode::ByRef;
+        let mut pats = arms.iter().flat_map(|arm| arm.pats.iter().copied()).collect::<Vec<_>>();
+        while let Some(pat) = pats.pop() {
+            let pat_data = &body[pat];
+            if let Pat::Bind { mode: annotation, .. } = pat_data {
+                let binding_mode = match annotation {
+                    BindingAnnotation::RefMut => CaptureMode::ByMutRef,
+                    BindingAnnotation::Ref => CaptureMode::ByRef,
+                    // With default binding modes, the type of the binding is
+                    // a reference when matching through one, which is `Copy`
+                    // for shared references.
+                    BindingAnnotation::Unannotated | BindingAnnotation::Mutable => {
+                        if self.is_copy(self.result[pat].clone()) {
+                            CaptureMode::ByRef
+                        } else {
+                            CaptureMode::ByMove
+                        }
+                    }
+                };
+                mode = mode.max(binding_mode);
+            }
+            pat_data.walk_child_pats(|it| pats.push(it));
+        }
+        // Matching on a reference can't move out of the referenced place.
+        let scrutinee_ty = self.table.resolve_ty_as_possible(self.result[scrutinee].clone());
+        match scrutinee_ty {
+            ty_app!(TypeCtor::Ref(Mutability::Shared)) => CaptureMode::ByRef,
+            ty_app!(TypeCtor::Ref(Mutability::Mut)) => mode.min(CaptureMode::ByMutRef),
+            _ => mode,
+        }
+    }
+
+    fn method_receiver_mode(&self, method_call: ExprId) -> CaptureMode {
+        let func = match self.result.method_resolution(method_call) {
+            Some(func) => func,
+            None => return CaptureMode::ByRef,
+        };
+        let data = self.db.function_data(func);
+        if !data.has_self_param {
+            return CaptureMode::ByRef;
+        }
+        match data.params.first() {
+            Some(TypeRef::Reference(_, Mutability::Shared)) => CaptureMode::ByRef,
+            Some(TypeRef::Reference(_, Mutability::Mut)) => CaptureMode::ByMutRef,
+            _ => CaptureMode::ByMove,
+        }
+    }
+
+    /// Whether values of the given type are copied instead of moved. If we
+    /// don't know, we assume they are, so that we don't restrict the kind of
+    /// closures without reason.
+    fn is_copy(&mut self, ty: Ty) -> bool {
+        let ty = self.table.resolve_ty_as_possible(ty);
+        match &ty {
+            Ty::Apply(a_ty) => match a_ty.ctor {
+                TypeCtor::Bool
+                | TypeCtor::Char
+                | TypeCtor::Int(_)
+                | TypeCtor::Float(_)
+                | TypeCtor::Never
+                | TypeCtor::RawPtr(_)
+                | TypeCtor::Ref(Mutability::Shared)
+                | TypeCtor::FnDef(_)
+                | TypeCtor::FnPtr { .. } => return true,
+                TypeCtor::Ref(Mutability::Mut) | TypeCtor::Str | TypeCtor::Slice => return false,
+                TypeCtor::Tuple { .. } | TypeCtor::Array => {
+                    return a_ty.parameters.iter().all(|ty| self.is_copy(ty.clone()))
+                }
+                TypeCtor::Closure { .. }
+                | TypeCtor::ClosureKind(_)
+                | TypeCtor::AssociatedType(_) => return true,
+                TypeCtor::Adt(_) => {}
+            },
+            Ty::Unknown | Ty::Infer(_) | Ty::Bound(_) => return true,
+            Ty::Param { .. } | Ty::Projection(_) | Ty::Dyn(_) | Ty::Opaque(_) => {}
+        }
+
+        let krate = match self.resolver.krate() {
+            Some(krate) => krate,
+            None => return true,
+        };
+        let copy_trait = match self.db.lang_item(krate, "copy".into()) {
+            Some(LangItemTarget::TraitId(it)) => it,
+            _ => return true,
+        };
+        let trait_ref = TraitRef { trait_: copy_trait, substs: Substs::single(ty) };
+        let obligation = InEnvironment::new(self.trait_env.clone(), Obligation::Trait(trait_ref));
+        let canonicalized = self.canonicalizer().canonicalize_obligation(obligation);
+        match self.db.trait_solve(krate, canonicalized.value) {
+            Some(Solution::Unique(_)) | Some(Solution::Ambig(_)) => true,
+            None => false,
+        }
+    }
+}
+
+/// The mode in which the base of an indexing or deref expression is used, if
+/// the resulting place is used in `mode`. Moving out of such places isn't
+/// possible, so a by-value use copies and just needs a shared borrow.
+fn place_mode(mode: CaptureMode) -> CaptureMode {
+    match mode {
+        CaptureMode::ByMutRef => CaptureMode::ByMutRef,
+        CaptureMode::ByRef | CaptureMode::ByMove => CaptureMode::ByRef,
+    }
+}
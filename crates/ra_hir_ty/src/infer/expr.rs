@@ -1,6 +1,7 @@
 //! Type inference for expressions.
 
 use std::iter::{repeat, repeat_with};
+use std::mem;
 use std::sync::Arc;
 
 use hir_def::{
@@ -19,11 +20,14 @@ use crate::{
     method_resolution, op,
     traits::InEnvironment,
     utils::{generics, variant_data, Generics},
-    CallableDef, InferTy, IntTy, Mutability, Obligation, ProjectionPredicate, ProjectionTy, Substs,
-    TraitRef, Ty, TypeCtor, TypeWalk, Uncertain,
+    CallableDef, GenericPredicate, InferTy, IntTy, Mutability, Obligation, ProjectionPredicate,
+    ProjectionTy, Substs, TraitRef, Ty, TypeCtor, TypeWalk, Uncertain,
 };
 
-use super::{BindingMode, Expectation, InferenceContext, InferenceDiagnostic, TypeMismatch};
+use super::{
+    find_breakable, BindingMode, BreakableContext, Expectation, InferenceContext,
+    InferenceDiagnostic, TypeMismatch,
+};
 
 impl<'a, D: HirDatabase> InferenceContext<'a, D> {
     pub(super) fn infer_expr(&mut self, tgt_expr: ExprId, expected: &Expectation) -> Ty {
@@ -81,18 +85,44 @@ impl<'a, D: HirDatabase> InferenceContext<'a, D> {
                 // FIXME should be std::result::Result<{inner}, _>
                 Ty::Unknown
             }
-            Expr::Loop { body } => {
+            Expr::Async { body } => {
+                // `break` and `return` can't leave an async block; `return`
+                // gives the block its output instead.
+                let output_ty = self.table.new_type_var();
+                let prev_return_ty = mem::replace(&mut self.return_ty, output_ty.clone());
+                let prev_breakables = mem::replace(&mut self.breakables, Vec::new());
+                self.infer_expr_coerce(*body, &Expectation::has_type(output_ty.clone()));
+                self.breakables = prev_breakables;
+                self.return_ty = prev_return_ty;
+                self.impl_future_ty(output_ty)
+            }
+            Expr::Loop { body, label } => {
+                self.breakables.push(BreakableContext {
+                    may_break: false,
+                    break_ty: self.table.new_type_var(),
+                    label: label.clone(),
+                });
                 self.infer_expr(*body, &Expectation::has_type(Ty::unit()));
-                // FIXME handle break with value
-                Ty::simple(TypeCtor::Never)
+                let ctxt = self.breakables.pop().expect("breakable stack broken");
+                if ctxt.may_break {
+                    ctxt.break_ty
+                } else {
+                    Ty::simple(TypeCtor::Never)
+                }
             }
-            Expr::While { condition, body } => {
+            Expr::While { condition, body, label } => {
+                self.breakables.push(BreakableContext {
+                    may_break: false,
+                    break_ty: Ty::unit(),
+                    label: label.clone(),
+                });
                 // while let is desugared to a match loop, so this is always simple while
                 self.infer_expr(*condition, &Expectation::has_type(Ty::simple(TypeCtor::Bool)));
                 self.infer_expr(*body, &Expectation::has_type(Ty::unit()));
+                self.breakables.pop().expect("breakable stack broken");
                 Ty::unit()
             }
-            Expr::For { iterable, body, pat } => {
+            Expr::For { iterable, body, pat, label } => {
                 let iterable_ty = self.infer_expr(*iterable, &Expectation::none());
 
                 let pat_ty = match self.resolve_into_iter_item() {
@@ -112,10 +142,16 @@ impl<'a, D: HirDatabase> InferenceContext<'a, D> {
                 };
 
                 self.infer_pat(*pat, &pat_ty, BindingMode::default());
+                self.breakables.push(BreakableContext {
+                    may_break: false,
+                    break_ty: Ty::unit(),
+                    label: label.clone(),
+                });
                 self.infer_expr(*body, &Expectation::has_type(Ty::unit()));
+                self.breakables.pop().expect("breakable stack broken");
                 Ty::unit()
             }
-            Expr::Lambda { body, args, arg_types } => {
+            Expr::Lambda { body, args, arg_types, .. } => {
                 assert_eq!(args.len(), arg_types.len());
 
                 let mut sig_tys = Vec::new();
@@ -137,9 +173,12 @@ impl<'a, D: HirDatabase> InferenceContext<'a, D> {
                     TypeCtor::FnPtr { num_args: sig_tys.len() as u16 - 1 },
                     Substs(sig_tys.into()),
                 );
-                let closure_ty = Ty::apply_one(
+                // The kind of the closure is only known after capture analysis
+                // for its body is done.
+                let kind_ty = self.table.new_type_var();
+                let closure_ty = Ty::apply(
                     TypeCtor::Closure { def: self.owner.into(), expr: tgt_expr },
-                    sig_ty,
+                    Substs(vec![sig_ty, kind_ty.clone()].into()),
                 );
 
                 // Eagerly try to relate the closure type with the expected
@@ -147,7 +186,13 @@ impl<'a, D: HirDatabase> InferenceContext<'a, D> {
                 // infer the body.
                 self.coerce(&closure_ty, &expected.ty);
 
+                let prev_breakables = mem::replace(&mut self.breakables, Vec::new());
                 self.infer_expr(*body, &Expectation::has_type(ret_ty));
+                self.breakables = prev_breakables;
+
+                let captures = self.analyze_closure_captures(tgt_expr);
+                self.unify(&kind_ty, &Ty::simple(TypeCtor::ClosureKind(captures.kind)));
+                self.result.closure_captures.insert(tgt_expr, captures);
                 closure_ty
             }
             Expr::Call { callee, args } => {
@@ -194,11 +239,22 @@ impl<'a, D: HirDatabase> InferenceContext<'a, D> {
                 let resolver = resolver_for_expr(self.db, self.owner.into(), tgt_expr);
                 self.infer_path(&resolver, p, tgt_expr.into()).unwrap_or(Ty::Unknown)
             }
-            Expr::Continue => Ty::simple(TypeCtor::Never),
-            Expr::Break { expr } => {
-                if let Some(expr) = expr {
-                    // FIXME handle break with value
-                    self.infer_expr(*expr, &Expectation::none());
+            Expr::Continue { .. } => Ty::simple(TypeCtor::Never),
+            Expr::Break { expr, label } => {
+                let last_ty = match find_breakable(&mut self.breakables, label.as_ref()) {
+                    Some(ctxt) => ctxt.break_ty.clone(),
+                    None => Ty::Unknown,
+                };
+                let val_ty = match expr {
+                    Some(expr) => {
+                        self.infer_expr_inner(*expr, &Expectation::has_type(last_ty.clone()))
+                    }
+                    None => Ty::unit(),
+                };
+                let merged_ty = self.coerce_merge_branch(&last_ty, &val_ty);
+                if let Some(ctxt) = find_breakable(&mut self.breakables, label.as_ref()) {
+                    ctxt.break_ty = merged_ty;
+                    ctxt.may_break = true;
                 }
                 Ty::simple(TypeCtor::Never)
             }
@@ -284,18 +340,26 @@ impl<'a, D: HirDatabase> InferenceContext<'a, D> {
             }
             Expr::Await { expr } => {
                 let inner_ty = self.infer_expr_inner(*expr, &Expectation::none());
+                let inner_ty = self.resolve_ty_shallow(&inner_ty).into_owned();
                 let ty = match self.resolve_future_future_output() {
                     Some(future_future_output_alias) => {
-                        let ty = self.table.new_type_var();
-                        let projection = ProjectionPredicate {
-                            ty: ty.clone(),
-                            projection_ty: ProjectionTy {
-                                associated_ty: future_future_output_alias,
-                                parameters: Substs::single(inner_ty),
-                            },
-                        };
-                        self.obligations.push(Obligation::Projection(projection));
-                        self.resolve_ty_as_possible(ty)
+                        // Chalk can't normalize projections out of `impl Trait`
+                        // types, so use the `Output` bound of async blocks directly.
+                        match inner_ty.associated_type_binding(future_future_output_alias) {
+                            Some(output_ty) => output_ty.clone(),
+                            None => {
+                                let ty = self.table.new_type_var();
+                                let projection = ProjectionPredicate {
+                                    ty: ty.clone(),
+                                    projection_ty: ProjectionTy {
+                                        associated_ty: future_future_output_alias,
+                                        parameters: Substs::single(inner_ty),
+                                    },
+                                };
+                                self.obligations.push(Obligation::Projection(projection));
+                                self.resolve_ty_as_possible(ty)
+                            }
+                        }
                     }
                     None => Ty::Unknown,
                 };
@@ -531,6 +595,26 @@ impl<'a, D: HirDatabase> InferenceContext<'a, D> {
         ty
     }
 
+    /// Builds the opaque `impl Future<Output = output_ty>` type of an async
+    /// block.
+    fn impl_future_ty(&self, output_ty: Ty) -> Ty {
+        let (future_trait, output_alias) =
+            match (self.resolve_future_future(), self.resolve_future_future_output()) {
+                (Some(future_trait), Some(output_alias)) => (future_trait, output_alias),
+                _ => return Ty::Unknown,
+            };
+        let self_ty = Substs::single(Ty::Bound(0));
+        let implemented = GenericPredicate::Implemented(TraitRef {
+            trait_: future_trait,
+            substs: self_ty.clone(),
+        });
+        let output = GenericPredicate::Projection(ProjectionPredicate {
+            projection_ty: ProjectionTy { associated_ty: output_alias, parameters: self_ty },
+            ty: output_ty,
+        });
+        Ty::Opaque(Arc::new([implemented, output]))
+    }
+
     fn infer_block(
         &mut self,
         statements: &[Statement],
@@ -4,14 +4,14 @@ use std::iter::repeat;
 use std::sync::Arc;
 
 use hir_def::{
-    expr::{BindingAnnotation, Pat, PatId, RecordFieldPat},
+    expr::{BindingAnnotation, Expr, Literal, Pat, PatId, RecordFieldPat},
     path::Path,
     type_ref::Mutability,
 };
 use hir_expand::name::Name;
 use test_utils::tested_by;
 
-use super::{BindingMode, InferenceContext};
+use super::{BindingMode, Expectation, InferenceContext};
 use crate::{db::HirDatabase, utils::variant_data, Substs, Ty, TypeCtor, TypeWalk};
 
 impl<'a, D: HirDatabase> InferenceContext<'a, D> {
@@ -19,6 +19,7 @@ impl<'a, D: HirDatabase> InferenceContext<'a, D> {
         &mut self,
         path: Option<&Path>,
         subpats: &[PatId],
+        ellipsis: Option<usize>,
         expected: &Ty,
         default_bm: BindingMode,
     ) -> Ty {
@@ -29,8 +30,19 @@ impl<'a, D: HirDatabase> InferenceContext<'a, D> {
         let substs = ty.substs().unwrap_or_else(Substs::empty);
 
         let field_tys = def.map(|it| self.db.field_types(it.into())).unwrap_or_default();
+        let n_uncovered = match ellipsis {
+            Some(_) => {
+                var_data.as_ref().map_or(0, |d| d.fields().len()).saturating_sub(subpats.len())
+            }
+            None => 0,
+        };
 
         for (i, &subpat) in subpats.iter().enumerate() {
+            // Sub-patterns after the `..` match the last fields.
+            let i = match ellipsis {
+                Some(idx) if i >= idx => i + n_uncovered,
+                _ => i,
+            };
             let expected_ty = var_data
                 .as_ref()
                 .and_then(|d| d.field(&Name::new_tuple_field(i)))
@@ -82,13 +94,21 @@ impl<'a, D: HirDatabase> InferenceContext<'a, D> {
         let body = Arc::clone(&self.body); // avoid borrow checker problem
 
         let is_non_ref_pat = match &body[pat] {
-            Pat::Tuple(..)
+            Pat::Tuple { .. }
             | Pat::TupleStruct { .. }
             | Pat::Record { .. }
             | Pat::Range { .. }
-            | Pat::Slice { .. } => true,
-            // FIXME: Path/Lit might actually evaluate to ref, but inference is unimplemented.
-            Pat::Path(..) | Pat::Lit(..) => true,
+            | Pat::Slice { .. }
+            | Pat::Box { .. } => true,
+            // String literals match references to strings.
+            Pat::Lit(expr) => match &body[*expr] {
+                Expr::Literal(Literal::String(..)) | Expr::Literal(Literal::ByteString(..)) => {
+                    false
+                }
+                _ => true,
+            },
+            // FIXME: Path might actually evaluate to ref, but inference is unimplemented.
+            Pat::Path(..) => true,
             Pat::Wild | Pat::Bind { .. } | Pat::Ref { .. } | Pat::Missing => false,
         };
         if is_non_ref_pat {
@@ -112,20 +132,37 @@ impl<'a, D: HirDatabase> InferenceContext<'a, D> {
         let expected = expected;
 
         let ty = match &body[pat] {
-            Pat::Tuple(ref args) => {
+            Pat::Tuple { args, ellipsis } => {
                 let expectations = match expected.as_tuple() {
                     Some(parameters) => &*parameters.0,
                     _ => &[],
                 };
-                let expectations_iter = expectations.iter().chain(repeat(&Ty::Unknown));
+                let (pre, post) = match ellipsis {
+                    Some(idx) => args.split_at(*idx),
+                    None => (&args[..], &[][..]),
+                };
+                // The elements matched by `..` keep their expected types.
+                let n_uncovered = match ellipsis {
+                    Some(_) => expectations.len().saturating_sub(args.len()),
+                    None => 0,
+                };
+                let mut expectations_iter = expectations.iter().cloned().chain(repeat(Ty::Unknown));
 
-                let inner_tys = args
-                    .iter()
-                    .zip(expectations_iter)
-                    .map(|(&pat, ty)| self.infer_pat(pat, ty, default_bm))
-                    .collect();
+                let mut inner_tys = Vec::with_capacity(args.len() + n_uncovered);
+                for &pat in pre {
+                    let ty = expectations_iter.next().unwrap();
+                    inner_tys.push(self.infer_pat(pat, &ty, default_bm));
+                }
+                inner_tys.extend(expectations_iter.by_ref().take(n_uncovered));
+                for &pat in post {
+                    let ty = expectations_iter.next().unwrap();
+                    inner_tys.push(self.infer_pat(pat, &ty, default_bm));
+                }
 
-                Ty::apply(TypeCtor::Tuple { cardinality: args.len() as u16 }, Substs(inner_tys))
+                Ty::apply(
+                    TypeCtor::Tuple { cardinality: inner_tys.len() as u16 },
+                    Substs(inner_tys.into()),
+                )
             }
             Pat::Ref { pat, mutability } => {
                 let expectation = match expected.as_reference() {
@@ -140,8 +177,8 @@ impl<'a, D: HirDatabase> InferenceContext<'a, D> {
                 let subty = self.infer_pat(*pat, expectation, default_bm);
                 Ty::apply_one(TypeCtor::Ref(*mutability), subty)
             }
-            Pat::TupleStruct { path: p, args: subpats } => {
-                self.infer_tuple_struct_pat(p.as_ref(), subpats, expected, default_bm)
+            Pat::TupleStruct { path: p, args: subpats, ellipsis } => {
+                self.infer_tuple_struct_pat(p.as_ref(), subpats, *ellipsis, expected, default_bm)
             }
             Pat::Record { path: p, args: fields } => {
                 self.infer_record_pat(p.as_ref(), fields, expected, default_bm, pat)
@@ -174,7 +211,42 @@ impl<'a, D: HirDatabase> InferenceContext<'a, D> {
                 self.write_pat_ty(pat, bound_ty);
                 return inner_ty;
             }
-            _ => Ty::Unknown,
+            Pat::Slice { prefix, rest, suffix } => {
+                let (container_ctor, elem_ty) = match expected {
+                    ty_app!(TypeCtor::Array, st) => (TypeCtor::Array, st.as_single().clone()),
+                    ty_app!(TypeCtor::Slice, st) => (TypeCtor::Slice, st.as_single().clone()),
+                    _ => (TypeCtor::Slice, self.table.new_type_var()),
+                };
+
+                for &pat in prefix.iter().chain(suffix.iter()) {
+                    self.infer_pat(pat, &elem_ty, default_bm);
+                }
+
+                let pat_ty = Ty::apply_one(container_ctor, elem_ty);
+                if let Some(rest) = rest {
+                    self.infer_pat(*rest, &pat_ty, default_bm);
+                }
+                pat_ty
+            }
+            Pat::Box { inner } => match self.resolve_boxed_box() {
+                Some(box_adt) => {
+                    let inner_expected = match expected {
+                        ty_app!(TypeCtor::Adt(adt), st) if *adt == box_adt => {
+                            st.first().cloned().unwrap_or(Ty::Unknown)
+                        }
+                        _ => Ty::Unknown,
+                    };
+                    let inner_ty = self.infer_pat(*inner, &inner_expected, default_bm);
+                    Ty::apply_one(TypeCtor::Adt(box_adt), inner_ty)
+                }
+                None => Ty::Unknown,
+            },
+            Pat::Lit(expr) => self.infer_expr(*expr, &Expectation::has_type(expected.clone())),
+            Pat::Range { start, end } => {
+                let start_ty = self.infer_expr(*start, &Expectation::has_type(expected.clone()));
+                self.infer_expr(*end, &Expectation::has_type(start_ty))
+            }
+            Pat::Wild | Pat::Missing => Ty::Unknown,
         };
         // use a new type variable if we got Ty::Unknown here
         let ty = self.insert_type_vars_shallow(ty);
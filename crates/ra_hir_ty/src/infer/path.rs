@@ -7,7 +7,7 @@ use hir_def::{
     resolver::{ResolveValueResult, Resolver, TypeNs, ValueNs},
     AssocItemId, ContainerId, Lookup,
 };
-use hir_expand::name::Name;
+use hir_expand::{hygiene::SyntaxContext, name::Name};
 
 use crate::{db::HirDatabase, method_resolution, Substs, Ty, TypeWalk, ValueTyDefId};
 
@@ -46,7 +46,12 @@ impl<'a, D: HirDatabase> InferenceContext<'a, D> {
                 id,
             )?
         } else {
-            let value_or_partial = resolver.resolve_path_in_value_ns(self.db, &path)?;
+            // Patterns can't refer to local variables.
+            let context = match id {
+                ExprOrPatId::ExprId(expr) => self.body.expr_context(expr),
+                ExprOrPatId::PatId(_) => SyntaxContext::ROOT,
+            };
+            let value_or_partial = resolver.resolve_path_in_value_ns(self.db, &path, context)?;
 
             match value_or_partial {
                 ResolveValueResult::ValueNs(it) => (it, None),
@@ -58,10 +58,12 @@ use crate::{
 use display::{HirDisplay, HirFormatter};
 
 pub use autoderef::autoderef;
-pub use infer::{infer_query, InferTy, InferenceResult};
+pub use infer::{
+    infer_query, CaptureMode, CapturedPlace, ClosureCaptures, InferTy, InferenceResult,
+};
 pub use lower::CallableDef;
 pub use lower::{callable_item_sig, TyDefId, ValueTyDefId};
-pub use traits::{InEnvironment, Obligation, ProjectionPredicate, TraitEnvironment};
+pub use traits::{FnTrait, InEnvironment, Obligation, ProjectionPredicate, TraitEnvironment};
 
 /// A type constructor or type name: this might be something like the primitive
 /// type `bool`, a struct like `Vec`, or things like function pointers or
@@ -139,8 +141,16 @@ pub enum TypeCtor {
     /// The type of a specific closure.
     ///
     /// The closure signature is stored in a `FnPtr` type in the first type
-    /// parameter.
+    /// parameter. The second type parameter is the closure's kind (see
+    /// `ClosureKind` below), which is an inference variable until capture
+    /// analysis for the closure body is done.
     Closure { def: DefWithBodyId, expr: ExprId },
+
+    /// The kind of a closure, i.e. the most specific of the `Fn*` traits it
+    /// implements. This is never the type of a value; it only appears as a
+    /// parameter of `Closure`, so that trait selection can take it into
+    /// account.
+    ClosureKind(FnTrait),
 }
 
 /// This exists just for Chalk, because Chalk just has a single `StructId` where
@@ -158,13 +168,11 @@ impl TypeCtor {
             | TypeCtor::Int(_)
             | TypeCtor::Float(_)
             | TypeCtor::Str
-            | TypeCtor::Never => 0,
-            TypeCtor::Slice
-            | TypeCtor::Array
-            | TypeCtor::RawPtr(_)
-            | TypeCtor::Ref(_)
-            | TypeCtor::Closure { .. } // 1 param representing the signature of the closure
-            => 1,
+            | TypeCtor::Never
+            | TypeCtor::ClosureKind(_) => 0,
+            TypeCtor::Slice | TypeCtor::Array | TypeCtor::RawPtr(_) | TypeCtor::Ref(_) => 1,
+            // the signature and the kind of the closure
+            TypeCtor::Closure { .. } => 2,
             TypeCtor::Adt(adt) => {
                 let generic_params = generics(db, AdtId::from(adt).into());
                 generic_params.len()
@@ -197,7 +205,7 @@ impl TypeCtor {
             | TypeCtor::FnPtr { .. }
             | TypeCtor::Tuple { .. } => None,
             // Closure's krate is irrelevant for coherence I would think?
-            TypeCtor::Closure { .. } => None,
+            TypeCtor::Closure { .. } | TypeCtor::ClosureKind(_) => None,
             TypeCtor::Adt(adt) => Some(adt.module(db).krate),
             TypeCtor::FnDef(callable) => Some(callable.krate(db)),
             TypeCtor::AssociatedType(type_alias) => Some(type_alias.lookup(db).module(db).krate),
@@ -218,7 +226,8 @@ impl TypeCtor {
             | TypeCtor::Ref(_)
             | TypeCtor::FnPtr { .. }
             | TypeCtor::Tuple { .. }
-            | TypeCtor::Closure { .. } => None,
+            | TypeCtor::Closure { .. }
+            | TypeCtor::ClosureKind(_) => None,
             TypeCtor::Adt(adt) => Some(adt.into()),
             TypeCtor::FnDef(callable) => Some(callable.into()),
             TypeCtor::AssociatedType(type_alias) => Some(type_alias.into()),
@@ -698,6 +707,24 @@ impl Ty {
             _ => None,
         }
     }
+
+    /// If this is a `dyn Trait` or `impl Trait` type that binds the given
+    /// associated type, like `impl Future<Output = T>`, returns the bound type.
+    pub fn associated_type_binding(&self, associated_ty: TypeAliasId) -> Option<&Ty> {
+        match self {
+            Ty::Dyn(predicates) | Ty::Opaque(predicates) => {
+                predicates.iter().find_map(|pred| match pred {
+                    GenericPredicate::Projection(proj)
+                        if proj.projection_ty.associated_ty == associated_ty =>
+                    {
+                        Some(&proj.ty)
+                    }
+                    _ => None,
+                })
+            }
+            _ => None,
+        }
+    }
 }
 
 /// This allows walking structures that contain types to do something with those
@@ -932,6 +959,7 @@ impl HirDisplay for ApplicationTy {
                 f.write_joined(sig.params(), ", ")?;
                 write!(f, "| -> {}", sig.ret().display(f.db))?;
             }
+            TypeCtor::ClosureKind(kind) => write!(f, "{}", kind)?,
         }
         Ok(())
     }
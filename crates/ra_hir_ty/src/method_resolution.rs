@@ -6,8 +6,8 @@ use std::sync::Arc;
 
 use arrayvec::ArrayVec;
 use hir_def::{
-    lang_item::LangItemTarget, resolver::Resolver, type_ref::Mutability, AssocItemId, AstItemDef,
-    FunctionId, HasModule, ImplId, Lookup, TraitId,
+    lang_item::LangItemTarget, nameres::CrateDefMap, resolver::Resolver, type_ref::Mutability,
+    AssocItemId, AstItemDef, FunctionId, HasModule, ImplId, Lookup, TraitId,
 };
 use hir_expand::name::Name;
 use ra_db::CrateId;
@@ -56,24 +56,32 @@ impl CrateImplBlocks {
         let mut res =
             CrateImplBlocks { impls: FxHashMap::default(), impls_by_trait: FxHashMap::default() };
 
-        let crate_def_map = db.crate_def_map(krate);
-        for (_module_id, module_data) in crate_def_map.modules.iter() {
+        res.collect_def_map(db, &db.crate_def_map(krate));
+
+        Arc::new(res)
+    }
+
+    /// Collects the impls of `def_map`, and of the blocks nested in it, which
+    /// have their own `DefMap`s.
+    fn collect_def_map(&mut self, db: &impl HirDatabase, def_map: &CrateDefMap) {
+        for (_module_id, module_data) in def_map.modules.iter() {
             for &impl_id in module_data.impls.iter() {
                 match db.impl_trait(impl_id) {
                     Some(tr) => {
-                        res.impls_by_trait.entry(tr.trait_).or_default().push(impl_id);
+                        self.impls_by_trait.entry(tr.trait_).or_default().push(impl_id);
                     }
                     None => {
                         let self_ty = db.impl_self_ty(impl_id);
                         if let Some(self_ty_fp) = TyFingerprint::for_impl(&self_ty) {
-                            res.impls.entry(self_ty_fp).or_default().push(impl_id);
+                            self.impls.entry(self_ty_fp).or_default().push(impl_id);
                         }
                     }
                 }
             }
+            for &block in module_data.blocks.iter() {
+                self.collect_def_map(db, &db.block_def_map(block));
+            }
         }
-
-        Arc::new(res)
     }
     pub fn lookup_impl_blocks(&self, ty: &Ty) -> impl Iterator<Item = ImplId> + '_ {
         let fingerprint = TyFingerprint::for_impl(ty);
@@ -1,7 +1,12 @@
+use std::{path::PathBuf, sync::Arc};
+
 use super::{infer, type_at, type_at_pos};
 use crate::test_db::TestDB;
 use insta::assert_snapshot;
-use ra_db::fixture::WithFixture;
+use ra_db::{
+    fixture::WithFixture, CrateGraph, FileId, FileLoader, ProcMacro, ProcMacroKind, RelativePath,
+    SourceDatabase, SourceDatabaseExt,
+};
 
 #[test]
 fn cfg_impl_block() {
@@ -296,6 +301,159 @@ fn main() {
     );
 }
 
+#[test]
+fn infer_builtin_macros_include() {
+    let (db, pos) = TestDB::with_position(
+        r#"
+//- /main.rs
+#[rustc_builtin_macro]
+macro_rules! include {() => {}}
+
+include!("foo.rs");
+
+fn main() {
+    bar()<|>;
+}
+
+//- /foo.rs
+fn bar() -> u32 {0}
+"#,
+    );
+    assert_eq!("u32", type_at_pos(&db, pos));
+}
+
+#[test]
+fn infer_builtin_macros_include_expression() {
+    let (db, pos) = TestDB::with_position(
+        r#"
+//- /main.rs
+#[rustc_builtin_macro]
+macro_rules! include {() => {}}
+
+fn main() {
+    let i = include!("consts/i.rs");
+    i<|>;
+}
+
+//- /consts/i.rs
+92u64
+"#,
+    );
+    assert_eq!("u64", type_at_pos(&db, pos));
+}
+
+#[test]
+fn infer_builtin_macros_include_concat_env() {
+    let (db, pos) = TestDB::with_position(
+        r#"
+//- /main.rs crate:main env:GEN_DIR=gen
+#[rustc_builtin_macro]
+macro_rules! include {() => {}}
+#[rustc_builtin_macro]
+macro_rules! concat {() => {}}
+#[rustc_builtin_macro]
+macro_rules! env {() => {}}
+
+include!(concat!(env!("GEN_DIR"), "/bindings.rs"));
+
+fn main() {
+    bar()<|>;
+}
+
+//- /gen/bindings.rs
+fn bar() -> u32 {0}
+"#,
+    );
+    assert_eq!("u32", type_at_pos(&db, pos));
+}
+
+#[test]
+fn infer_builtin_macros_include_str_and_bytes() {
+    assert_eq!(
+        "&str",
+        type_at(
+            r#"
+//- /main.rs
+#[rustc_builtin_macro]
+macro_rules! include_str {() => {}}
+
+fn main() {
+    let s = include_str!("data.txt");
+    s<|>;
+}
+
+//- /data.txt
+hello
+"#
+        )
+    );
+    assert_eq!(
+        "&[u8]",
+        type_at(
+            r#"
+//- /main.rs
+#[rustc_builtin_macro]
+macro_rules! include_bytes {() => {}}
+
+fn main() {
+    let b = include_bytes!("data.txt");
+    b<|>;
+}
+
+//- /data.txt
+hello
+"#
+        )
+    );
+}
+
+#[test]
+fn infer_builtin_macros_option_env() {
+    let (db, pos) = TestDB::with_position(
+        r#"
+//- /main.rs crate:main deps:std env:KEY=value
+#[rustc_builtin_macro]
+macro_rules! option_env {() => {}}
+
+fn main() {
+    let x = option_env!("KEY");
+    x<|>;
+}
+
+//- /lib.rs crate:std
+pub mod option {
+    pub enum Option<T> { None, Some(T) }
+}
+"#,
+    );
+    assert_eq!("Option<&str>", type_at_pos(&db, pos));
+}
+
+#[test]
+fn include_expansion_is_invalidated_by_edits() {
+    let (mut db, pos) = TestDB::with_position(
+        r#"
+//- /main.rs
+#[rustc_builtin_macro]
+macro_rules! include {() => {}}
+
+include!("foo.rs");
+
+fn main() {
+    bar()<|>;
+}
+
+//- /foo.rs
+fn bar() -> u32 {0}
+"#,
+    );
+    assert_eq!("u32", type_at_pos(&db, pos));
+
+    let foo = db.resolve_relative_path(pos.file_id, RelativePath::new("foo.rs")).unwrap();
+    db.set_file_text(foo, Arc::new("fn bar() -> i64 {0}".to_string()));
+    assert_eq!("i64", type_at_pos(&db, pos));
+}
+
 #[test]
 fn infer_derive_clone_simple() {
     let (db, pos) = TestDB::with_position(
@@ -346,3 +504,360 @@ mod clone {
     );
     assert_eq!("(Wrapper<S>, {unknown})", type_at_pos(&db, pos));
 }
+
+#[test]
+fn infer_macro_rules_in_fn_body() {
+    let t = type_at(
+        r#"
+//- /main.rs
+fn main() {
+    macro_rules! local {
+        () => { 1u16 }
+    }
+    fn nested() -> u16 { local!() }
+    local!()<|>;
+}
+"#,
+    );
+    assert_eq!(t, "u16");
+}
+
+#[test]
+fn infer_macro_locals_are_hygienic() {
+    let (db, pos) = TestDB::with_position(
+        r#"
+//- /main.rs
+macro_rules! with_local {
+    ($e:expr) => { { let x = 1u8; $e } };
+}
+
+macro_rules! own_local {
+    () => { { let x = 1u16; x } };
+}
+
+fn test() {
+    let x = 1u64;
+    let a = with_local!(x);
+    let b = own_local!();
+    (a, b)<|>;
+}
+"#,
+    );
+    assert_eq!("(u64, u16)", type_at_pos(&db, pos));
+}
+
+#[test]
+fn infer_macro2_items() {
+    let (db, pos) = TestDB::with_position(
+        r#"
+//- /main.rs
+mod m {
+    pub macro pair($e:expr) {
+        ($e, $e)
+    }
+
+    pub macro first {
+        ($a:expr, $b:expr) => { $a },
+        ($a:expr) => { $a }
+    }
+}
+
+fn test() {
+    let a = m::pair!(1u8);
+    let b = m::first!(1u16, 1u32);
+    (a, b)<|>;
+}
+"#,
+    );
+    assert_eq!("((u8, u8), u16)", type_at_pos(&db, pos));
+}
+
+/// Registers `proc_macros` for the crate whose root is `crate_root`.
+fn set_proc_macros(db: &mut TestDB, crate_root: FileId, proc_macros: Vec<ProcMacro>) {
+    let old_graph = db.crate_graph();
+    let mut crate_ids = old_graph.iter().collect::<Vec<_>>();
+    crate_ids.sort();
+    let mut proc_macros = Some(proc_macros);
+    let mut crate_graph = CrateGraph::default();
+    for &krate in crate_ids.iter() {
+        let file_id = old_graph.crate_root(krate);
+        let crate_proc_macros =
+            if file_id == crate_root { proc_macros.take().unwrap() } else { Vec::new() };
+        let new_krate = crate_graph.add_crate_root(
+            file_id,
+            old_graph.edition(krate),
+            old_graph.cfg_options(krate).clone(),
+            old_graph.env(krate).clone(),
+            old_graph.extern_source(krate).clone(),
+            crate_proc_macros,
+        );
+        assert_eq!(new_krate, krate);
+    }
+    for &krate in crate_ids.iter() {
+        for dep in old_graph.dependencies(krate) {
+            crate_graph.add_dep(krate, dep.name.clone(), dep.crate_id()).unwrap();
+        }
+    }
+    db.set_crate_graph(Arc::new(crate_graph));
+}
+
+fn proc_macro(
+    name: &str,
+    kind: ProcMacroKind,
+    expander: impl tt::TokenExpander + 'static,
+) -> ProcMacro {
+    let dylib_path = PathBuf::from("/libproc.so");
+    ProcMacro {
+        name: name.into(),
+        kind,
+        dylib_path,
+        dylib_modified: None,
+        expander: Arc::new(expander),
+    }
+}
+
+fn ident(text: &str) -> tt::TokenTree {
+    tt::Leaf::from(tt::Ident { text: text.into(), id: tt::TokenId::unspecified() }).into()
+}
+
+fn punct(char: char, spacing: tt::Spacing) -> tt::TokenTree {
+    tt::Leaf::from(tt::Punct { char, spacing }).into()
+}
+
+fn subtree(delimiter: tt::Delimiter, token_trees: Vec<tt::TokenTree>) -> tt::TokenTree {
+    tt::Subtree { delimiter, token_trees }.into()
+}
+
+/// A derive which generates `impl Name { fn getter(&self) -> u32 { 0 } }`.
+#[derive(Debug)]
+struct GetterDerive;
+
+impl tt::TokenExpander for GetterDerive {
+    fn expand(
+        &self,
+        subtree: &tt::Subtree,
+        _attrs: Option<&tt::Subtree>,
+    ) -> Result<tt::Subtree, tt::ExpansionError> {
+        let name = subtree
+            .token_trees
+            .iter()
+            .skip_while(|tt| tt.to_string() != "struct")
+            .nth(1)
+            .ok_or_else(|| tt::ExpansionError::ExpansionError("expected a struct".into()))?;
+        let body = vec![
+            ident("fn"),
+            ident("getter"),
+            subtree(
+                tt::Delimiter::Parenthesis,
+                vec![punct('&', tt::Spacing::Alone), ident("self")],
+            ),
+            punct('-', tt::Spacing::Joint),
+            punct('>', tt::Spacing::Alone),
+            ident("u32"),
+            subtree(
+                tt::Delimiter::Brace,
+                vec![tt::Leaf::from(tt::Literal { text: "0".into() }).into()],
+            ),
+        ];
+        Ok(tt::Subtree {
+            delimiter: tt::Delimiter::None,
+            token_trees: vec![ident("impl"), name.clone(), subtree(tt::Delimiter::Brace, body)],
+        })
+    }
+}
+
+#[derive(Debug)]
+struct IdentityMacro;
+
+impl tt::TokenExpander for IdentityMacro {
+    fn expand(
+        &self,
+        subtree: &tt::Subtree,
+        _attrs: Option<&tt::Subtree>,
+    ) -> Result<tt::Subtree, tt::ExpansionError> {
+        Ok(tt::Subtree { delimiter: tt::Delimiter::None, token_trees: subtree.token_trees.clone() })
+    }
+}
+
+/// An attribute macro which replaces the return type of a function with the
+/// attribute's argument, as in `#[returns(u8)] fn foo() -> u32 { 0 }`.
+#[derive(Debug)]
+struct ReturnsMacro;
+
+impl tt::TokenExpander for ReturnsMacro {
+    fn expand(
+        &self,
+        subtree: &tt::Subtree,
+        attrs: Option<&tt::Subtree>,
+    ) -> Result<tt::Subtree, tt::ExpansionError> {
+        let ret_ty = attrs
+            .and_then(|it| it.token_trees.first())
+            .ok_or_else(|| tt::ExpansionError::ExpansionError("expected a type".into()))?;
+        let mut token_trees = subtree.token_trees.clone();
+        let arrow = token_trees
+            .iter()
+            .position(|tt| tt.to_string() == ">")
+            .ok_or_else(|| tt::ExpansionError::ExpansionError("expected a function".into()))?;
+        token_trees[arrow + 1] = ret_ty.clone();
+        Ok(tt::Subtree { delimiter: tt::Delimiter::None, token_trees })
+    }
+}
+
+/// Behaves like a proc macro server which crashed.
+#[derive(Debug)]
+struct BrokenMacro;
+
+impl tt::TokenExpander for BrokenMacro {
+    fn expand(
+        &self,
+        _subtree: &tt::Subtree,
+        _attrs: Option<&tt::Subtree>,
+    ) -> Result<tt::Subtree, tt::ExpansionError> {
+        Err(tt::ExpansionError::IOError("broken pipe".into()))
+    }
+}
+
+#[test]
+fn infer_proc_macro_derive() {
+    let (mut db, pos) = TestDB::with_position(
+        r#"
+//- /main.rs crate:main deps:proc
+use proc::Getter;
+
+#[derive(Getter)]
+struct S;
+
+fn test() {
+    S.getter()<|>;
+}
+
+//- /proc.rs crate:proc
+"#,
+    );
+    let proc_root = db.resolve_relative_path(pos.file_id, RelativePath::new("proc.rs")).unwrap();
+    set_proc_macros(
+        &mut db,
+        proc_root,
+        vec![proc_macro("Getter", ProcMacroKind::CustomDerive { helpers: vec![] }, GetterDerive)],
+    );
+    assert_eq!("u32", type_at_pos(&db, pos));
+}
+
+#[test]
+fn infer_proc_macro_fn_like() {
+    let (mut db, pos) = TestDB::with_position(
+        r#"
+//- /main.rs crate:main deps:proc
+fn test() {
+    let x = proc::identity!(92u64);
+    let y = proc::broken!(92u64);
+    (x, y)<|>;
+}
+
+//- /proc.rs crate:proc
+"#,
+    );
+    let proc_root = db.resolve_relative_path(pos.file_id, RelativePath::new("proc.rs")).unwrap();
+    set_proc_macros(
+        &mut db,
+        proc_root,
+        vec![
+            proc_macro("identity", ProcMacroKind::FuncLike, IdentityMacro),
+            proc_macro("broken", ProcMacroKind::FuncLike, BrokenMacro),
+        ],
+    );
+    assert_eq!("(u64, {unknown})", type_at_pos(&db, pos));
+}
+
+#[test]
+fn infer_proc_macro_attr() {
+    let (mut db, pos) = TestDB::with_position(
+        r#"
+//- /main.rs crate:main deps:proc
+use proc::returns;
+
+#[returns(u8)]
+#[returns(u16)]
+fn foo() -> u32 { 0 }
+
+#[proc::identity]
+#[inline]
+fn bar() -> u64 { 0 }
+
+#[unknown]
+fn baz() -> i8 { 0 }
+
+fn test() {
+    (foo(), bar(), baz())<|>;
+}
+
+//- /proc.rs crate:proc
+"#,
+    );
+    let proc_root = db.resolve_relative_path(pos.file_id, RelativePath::new("proc.rs")).unwrap();
+    set_proc_macros(
+        &mut db,
+        proc_root,
+        vec![
+            proc_macro("returns", ProcMacroKind::Attr, ReturnsMacro),
+            proc_macro("identity", ProcMacroKind::Attr, IdentityMacro),
+        ],
+    );
+    assert_eq!("(u16, u64, i8)", type_at_pos(&db, pos));
+}
+
+#[test]
+fn infer_proc_macro_attr_inert() {
+    let (mut db, pos) = TestDB::with_position(
+        r#"
+//- /main.rs crate:main deps:proc
+use proc::{returns, Getter};
+
+#[derive(Getter)]
+#[returns(u8)]
+struct S;
+
+#[Getter]
+fn foo() -> u16 { 0 }
+
+fn test() {
+    (S.getter(), foo())<|>;
+}
+
+//- /proc.rs crate:proc
+"#,
+    );
+    let proc_root = db.resolve_relative_path(pos.file_id, RelativePath::new("proc.rs")).unwrap();
+    let helpers = vec!["returns".into()];
+    set_proc_macros(
+        &mut db,
+        proc_root,
+        vec![
+            proc_macro("returns", ProcMacroKind::Attr, ReturnsMacro),
+            proc_macro("Getter", ProcMacroKind::CustomDerive { helpers }, GetterDerive),
+        ],
+    );
+    // `#[returns]` is a helper of the derive, and a derive isn't an attribute.
+    assert_eq!("(u32, u16)", type_at_pos(&db, pos));
+}
+
+#[test]
+fn infer_macro_rules_defined_after_fn_body() {
+    let t = type_at(
+        r#"
+//- /main.rs
+macro_rules! before {
+    () => { 1u16 }
+}
+fn main() {
+    struct S;
+    let t = (before!(), after!());
+    t<|>;
+}
+macro_rules! after {
+    () => { 1u32 }
+}
+"#,
+    );
+    assert_eq!(t, "(u16, {unknown})");
+}
@@ -1003,3 +1003,34 @@ fn test() { (S {}).method()<|>; }
     );
     assert_eq!(t, "()");
 }
+
+#[test]
+fn method_resolution_impl_in_fn_body() {
+    let t = type_at(
+        r#"
+//- /main.rs
+struct S;
+fn test() {
+    trait Trait { fn foo(&self) -> u128; }
+    impl Trait for S { fn foo(&self) -> u128 { 0 } }
+    S.foo()<|>;
+}
+"#,
+    );
+    assert_eq!(t, "u128");
+}
+
+#[test]
+fn method_resolution_inherent_impl_in_other_fn_body() {
+    let t = type_at(
+        r#"
+//- /main.rs
+struct S;
+fn define() {
+    impl S { fn foo(&self) -> i8 { 0 } }
+}
+fn test() { S.foo()<|>; }
+"#,
+    );
+    assert_eq!(t, "i8");
+}
@@ -244,3 +244,57 @@ fn test(a: i32) {
     );
     assert_eq!(t, "f64");
 }
+
+#[test]
+fn loop_with_break_value() {
+    let t = type_at(
+        r#"
+//- /main.rs
+fn test(a: bool) {
+    let i = loop {
+        if a {
+            break 1u32;
+        }
+        break 2;
+    };
+    i<|>;
+}
+"#,
+    );
+    assert_eq!(t, "u32");
+}
+
+#[test]
+fn labeled_break_targets_outer_loop() {
+    let t = type_at(
+        r#"
+//- /main.rs
+fn test() {
+    let i = 'outer: loop {
+        let j = loop {
+            break 'outer 1u8;
+        };
+        j<|>;
+    };
+}
+"#,
+    );
+    assert_eq!(t, "!");
+
+    let t = type_at(
+        r#"
+//- /main.rs
+fn test() {
+    let i = 'outer: loop {
+        for x in 0..10 {
+            loop {
+                break 'outer "done";
+            }
+        }
+    };
+    i<|>;
+}
+"#,
+    );
+    assert_eq!(t, "&str");
+}
@@ -1,5 +1,7 @@
-use super::infer;
+use super::{infer, type_at, type_at_pos};
+use crate::test_db::TestDB;
 use insta::assert_snapshot;
+use ra_db::fixture::WithFixture;
 use test_utils::covers;
 
 #[test]
@@ -53,7 +55,8 @@ fn test(x: &i32) {
     [140; 141) 'g': {unknown}
     [144; 145) 'e': {unknown}
     [158; 205) 'if let...     }': ()
-    [165; 170) '[val]': {unknown}
+    [165; 170) '[val]': [{unknown}]
+    [166; 169) 'val': {unknown}
     [173; 176) 'opt': {unknown}
     [177; 205) '{     ...     }': ()
     [191; 192) 'h': {unknown}
@@ -236,3 +239,94 @@ fn test(a1: A<u32>, o: Option<u64>) {
     "###
     );
 }
+
+#[test]
+fn infer_literal_and_range_patterns() {
+    assert_snapshot!(
+        infer(r#"
+fn test(x: &str, n: i32) {
+    match n {
+        -1 => {}
+        0..=9 => {}
+        _ => {}
+    }
+    if let "foo" = x {}
+}
+"#),
+        @r###"
+    [9; 10) 'x': &str
+    [18; 19) 'n': i32
+    [26; 126) '{     ...x {} }': ()
+    [32; 100) 'match ...     }': ()
+    [38; 39) 'n': i32
+    [50; 52) '-1': i32
+    [56; 58) '{}': ()
+    [67; 72) '0..=9': i32
+    [76; 78) '{}': ()
+    [87; 88) '_': i32
+    [92; 94) '{}': ()
+    [105; 124) 'if let...= x {}': ()
+    [112; 117) '"foo"': &str
+    [120; 121) 'x': &str
+    [122; 124) '{}': ()
+    "###
+    );
+}
+
+#[test]
+fn infer_slice_pattern_bindings() {
+    let t = type_at(
+        r#"
+//- /main.rs
+fn test(slice: &[u32], array: [i8; 4]) {
+    if let [first, rest @ .., last] = slice {
+        let [a, .., b] = array;
+        (first, rest, last, a, b)<|>;
+    }
+}
+"#,
+    );
+    assert_eq!(t, "(&u32, &[u32], &u32, i8, i8)");
+}
+
+#[test]
+fn infer_tuple_patterns_with_rest() {
+    let t = type_at(
+        r#"
+//- /main.rs
+struct S(u8, u16, u32);
+fn test(s: S) {
+    let (a, .., b) = (1u8, 2u16, 3u32, 4u64);
+    let S(.., c) = s;
+    let S(d, ..) = s;
+    (a, b, c, d)<|>;
+}
+"#,
+    );
+    assert_eq!(t, "(u8, u64, u32, u8)");
+}
+
+#[test]
+fn infer_box_pattern() {
+    let (db, pos) = TestDB::with_position(
+        r#"
+//- /main.rs crate:main deps:std
+fn test() {
+    let b = box (1u8, 2i64);
+    let box (x, y) = b;
+    (x, y)<|>;
+}
+
+//- /std.rs crate:std
+#[prelude_import] use prelude::*;
+mod prelude {}
+
+mod boxed {
+    pub struct Box<T: ?Sized> {
+        inner: *mut T,
+    }
+}
+"#,
+    );
+    assert_eq!("(u8, i64)", type_at_pos(&db, pos));
+}
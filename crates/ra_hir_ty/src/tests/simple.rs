@@ -1606,3 +1606,38 @@ fn main() {
     );
     assert_eq!(t, "u32");
 }
+
+#[test]
+fn infer_items_in_fn_body() {
+    let t = type_at(
+        r#"
+//- /main.rs
+struct S;
+
+fn main() {
+    struct S(u32);
+    fn make() -> S { S(0) }
+    {
+        use self::S as Outer;
+        let x: (S, Outer) = (make(), Outer);
+        x<|>;
+    }
+}"#,
+    );
+    assert_eq!(t, "(S, S)");
+}
+
+#[test]
+fn item_in_fn_body_shadows_module_item() {
+    let t = type_at(
+        r#"
+//- /main.rs
+fn foo() -> u32 { 0 }
+
+fn main() {
+    fn foo() -> i64 { 0 }
+    foo()<|>;
+}"#,
+    );
+    assert_eq!(t, "i64");
+}
@@ -34,6 +34,84 @@ mod future {
     assert_eq!("u64", type_at_pos(&db, pos));
 }
 
+#[test]
+fn infer_async_block() {
+    let (db, pos) = TestDB::with_position(
+        r#"
+//- /main.rs crate:main deps:std
+
+fn test() {
+    let f = async { 1u64 };
+    f<|>;
+}
+
+//- /std.rs crate:std
+#[prelude_import] use future::*;
+mod future {
+    trait Future {
+        type Output;
+    }
+}
+
+"#,
+    );
+    assert_eq!("impl Future<Output = u64>", type_at_pos(&db, pos));
+}
+
+#[test]
+fn infer_await_async_block() {
+    let (db, pos) = TestDB::with_position(
+        r#"
+//- /main.rs crate:main deps:std
+
+fn test() {
+    let r = async move {
+        loop {
+            break "foo";
+        }
+    };
+    let v = r.await;
+    v<|>;
+}
+
+//- /std.rs crate:std
+#[prelude_import] use future::*;
+mod future {
+    trait Future {
+        type Output;
+    }
+}
+
+"#,
+    );
+    assert_eq!("&str", type_at_pos(&db, pos));
+}
+
+#[test]
+fn infer_await_async_closure() {
+    let (db, pos) = TestDB::with_position(
+        r#"
+//- /main.rs crate:main deps:std
+
+fn test() {
+    let f = async move |x: u32| x;
+    let v = f(1).await;
+    v<|>;
+}
+
+//- /std.rs crate:std
+#[prelude_import] use future::*;
+mod future {
+    trait Future {
+        type Output;
+    }
+}
+
+"#,
+    );
+    assert_eq!("u32", type_at_pos(&db, pos));
+}
+
 #[test]
 fn infer_try() {
     let (db, pos) = TestDB::with_position(
@@ -1299,6 +1377,145 @@ fn test() {
     );
 }
 
+// `type_at` with the `Fn` traits and `CallTwice`, which is only implemented for
+// closures which can be called through a shared reference.
+fn type_at_with_fn_traits(source: &str) -> String {
+    let defs = r#"
+#[lang = "fn_once"]
+trait FnOnce<Args> {
+    type Output;
+}
+#[lang = "fn_mut"]
+trait FnMut<Args>: FnOnce<Args> {}
+#[lang = "fn"]
+trait Fn<Args>: FnMut<Args> {}
+#[lang = "copy"]
+trait Copy {}
+
+trait CallTwice {
+    fn call_twice(&self) -> u32;
+}
+impl<F: Fn()> CallTwice for F {}
+"#;
+
+    // Append to the end to keep positions unchanged.
+    type_at(&format!("//- /main.rs\n{}{}", source, defs))
+}
+
+#[test]
+fn closure_kind_fn_from_shared_uses() {
+    let t = type_at_with_fn_traits(
+        r#"
+struct S;
+fn test() {
+    let x = 0u32;
+    let s = S;
+    let c = || {
+        x;
+        &s;
+    };
+    c.call_twice()<|>;
+}
+"#,
+    );
+    assert_eq!(t, "u32");
+}
+
+#[test]
+fn closure_kind_fn_for_move_closure() {
+    let t = type_at_with_fn_traits(
+        r#"
+fn test() {
+    let x = 0u32;
+    let c = move || {
+        x;
+    };
+    c.call_twice()<|>;
+}
+"#,
+    );
+    assert_eq!(t, "u32");
+}
+
+#[test]
+fn closure_kind_fn_mut_from_assignment() {
+    let t = type_at_with_fn_traits(
+        r#"
+fn test() {
+    let mut x = 0u32;
+    let c = || {
+        x = 1;
+    };
+    c.call_twice()<|>;
+}
+"#,
+    );
+    assert_eq!(t, "{unknown}");
+}
+
+#[test]
+fn closure_kind_fn_once_from_move() {
+    let t = type_at_with_fn_traits(
+        r#"
+struct S;
+fn consume(s: S) {}
+fn test() {
+    let s = S;
+    let c = || {
+        consume(s);
+    };
+    c.call_twice()<|>;
+}
+"#,
+    );
+    assert_eq!(t, "{unknown}");
+}
+
+#[test]
+fn closure_kind_fn_once_from_move_in_match() {
+    let t = type_at_with_fn_traits(
+        r#"
+struct S;
+fn consume(s: S) {}
+enum E {
+    A(S),
+    B,
+}
+fn test() {
+    let e = E::A(S);
+    let c = || match e {
+        E::A(s) => consume(s),
+        E::B => {}
+    };
+    c.call_twice()<|>;
+}
+"#,
+    );
+    assert_eq!(t, "{unknown}");
+}
+
+#[test]
+fn closure_kind_fn_from_match_by_ref() {
+    let t = type_at_with_fn_traits(
+        r#"
+struct S;
+enum E {
+    A(S),
+    B,
+}
+fn test() {
+    let e = E::A(S);
+    let c = || match &e {
+        E::A(s) => {}
+        E::B => {}
+    };
+    c.call_twice()<|>;
+}
+"#,
+    );
+    assert_eq!(t, "u32");
+}
+
 #[test]
 fn unselected_projection_in_trait_env_1() {
     let t = type_at(
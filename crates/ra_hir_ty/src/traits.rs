@@ -1,5 +1,8 @@
 //! Trait solving using Chalk.
-use std::sync::{Arc, Mutex};
+use std::{
+    fmt,
+    sync::{Arc, Mutex},
+};
 
 use chalk_ir::{cast::Cast, family::ChalkIr};
 use hir_def::{expr::ExprId, DefWithBodyId, ImplId, TraitId, TypeAliasId};
@@ -274,7 +277,10 @@ pub enum Guidance {
     Unknown,
 }
 
-#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+/// The `Fn*` traits, ordered from the least to the most restrictive one: every
+/// closure implementing `Fn` also implements `FnMut`, and every closure
+/// implementing `FnMut` also implements `FnOnce`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub enum FnTrait {
     FnOnce,
     FnMut,
@@ -289,6 +295,22 @@ impl FnTrait {
             FnTrait::Fn => "fn",
         }
     }
+
+    /// Whether a closure of kind `self` implements `fn_trait`.
+    pub fn implements(self, fn_trait: FnTrait) -> bool {
+        fn_trait <= self
+    }
+}
+
+impl fmt::Display for FnTrait {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            FnTrait::FnOnce => "FnOnce",
+            FnTrait::FnMut => "FnMut",
+            FnTrait::Fn => "Fn",
+        };
+        f.write_str(name)
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
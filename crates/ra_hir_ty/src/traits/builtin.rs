@@ -28,9 +28,19 @@ pub(super) fn get_builtin_impls(
     trait_: TraitId,
     mut callback: impl FnMut(Impl),
 ) {
-    if let Ty::Apply(ApplicationTy { ctor: TypeCtor::Closure { def, expr }, .. }) = ty {
+    if let Ty::Apply(ApplicationTy { ctor: TypeCtor::Closure { def, expr }, parameters }) = ty {
+        // If capture analysis has already determined the kind of the closure,
+        // only provide the impls that it actually has; otherwise, we don't
+        // know yet and provide all of them.
+        let closure_kind = match parameters.0.get(1) {
+            Some(Ty::Apply(ApplicationTy { ctor: TypeCtor::ClosureKind(kind), .. })) => Some(*kind),
+            _ => None,
+        };
         for &fn_trait in [super::FnTrait::FnOnce, super::FnTrait::FnMut, super::FnTrait::Fn].iter()
         {
+            if closure_kind.map_or(false, |kind| !kind.implements(fn_trait)) {
+                continue;
+            }
             if let Some(actual_trait) = get_fn_trait(db, krate, fn_trait) {
                 if trait_ == actual_trait {
                     let impl_ = super::ClosureFnTraitImplData { def: *def, expr: *expr, fn_trait };
@@ -71,7 +81,8 @@ fn closure_fn_trait_impl_datum(
     data: super::ClosureFnTraitImplData,
 ) -> Option<BuiltinImplData> {
     // for some closure |X, Y| -> Z:
-    // impl<T, U, V> Fn<(T, U)> for closure<fn(T, U) -> V> { Output = V }
+    // impl<T, U, V, K> Fn<(T, U)> for closure<fn(T, U) -> V, K> { Output = V }
+    // (the kind K is filtered in `get_builtin_impls`)
 
     let trait_ = get_fn_trait(db, krate, data.fn_trait)?; // get corresponding fn trait
 
@@ -98,7 +109,12 @@ fn closure_fn_trait_impl_datum(
         Substs::builder(num_args as usize + 1).fill_with_bound_vars(0).build(),
     );
 
-    let self_ty = Ty::apply_one(TypeCtor::Closure { def: data.def, expr: data.expr }, sig_ty);
+    let kind_ty = Ty::Bound(num_args as u32 + 1);
+
+    let self_ty = Ty::apply(
+        TypeCtor::Closure { def: data.def, expr: data.expr },
+        Substs(vec![sig_ty, kind_ty].into()),
+    );
 
     let trait_ref = TraitRef {
         trait_: trait_.into(),
@@ -108,7 +124,7 @@ fn closure_fn_trait_impl_datum(
     let output_ty_id = AssocTyValue::ClosureFnTraitImplOutput(data.clone());
 
     Some(BuiltinImplData {
-        num_vars: num_args as usize + 1,
+        num_vars: num_args as usize + 2,
         trait_ref,
         where_clauses: Vec::new(),
         assoc_ty_values: vec![output_ty_id],
@@ -143,7 +159,7 @@ fn closure_fn_trait_output_assoc_ty_value(
     BuiltinImplAssocTyValueData {
         impl_,
         assoc_ty_id: output_ty_id,
-        num_vars: num_args as usize + 1,
+        num_vars: num_args as usize + 2,
         value: output_ty,
     }
 }
@@ -1,5 +1,6 @@
 //! FIXME: write short doc here
 
+use hir::{descend_into_macros, InFile};
 use ra_db::SourceDatabase;
 use ra_syntax::{
     algo::ancestors_at_offset,
@@ -15,10 +16,24 @@ pub(crate) fn call_info(db: &RootDatabase, position: FilePosition) -> Option<Cal
     let parse = db.parse(position.file_id);
     let syntax = parse.tree().syntax().clone();
 
+    // If the position is inside of a macro call, look at the expansion instead,
+    // with the offset adjusted accordingly
+    let token = syntax.token_at_offset(position.offset).find(|it| !it.kind().is_trivia());
+    let (file_id, syntax, offset) = match token {
+        Some(token) => {
+            let delta = position.offset - token.text_range().start();
+            let token = descend_into_macros(db, InFile::new(position.file_id.into(), token));
+            let offset = token.value.text_range().start() + delta;
+            let root = token.value.ancestors().last()?;
+            (token.file_id, root, offset)
+        }
+        None => (position.file_id.into(), syntax, position.offset),
+    };
+
     // Find the calling expression and it's NameRef
-    let calling_node = FnCallNode::with_node(&syntax, position.offset)?;
+    let calling_node = FnCallNode::with_node(&syntax, offset)?;
     let name_ref = calling_node.name_ref()?;
-    let name_ref = hir::InFile::new(position.file_id.into(), name_ref.syntax());
+    let name_ref = InFile::new(file_id, name_ref.syntax());
 
     let analyzer = hir::SourceAnalyzer::new(db, name_ref, None);
     let (mut call_info, has_self) = match &calling_node {
@@ -60,17 +75,14 @@ pub(crate) fn call_info(db: &RootDatabase, position: FilePosition) -> Option<Cal
             let num_args_at_callsite = arg_list.args().count();
 
             let arg_list_range = arg_list.syntax().text_range();
-            if !arg_list_range.contains_inclusive(position.offset) {
+            if !arg_list_range.contains_inclusive(offset) {
                 tested_by!(call_info_bad_offset);
                 return None;
             }
 
             let mut param = std::cmp::min(
                 num_args_at_callsite,
-                arg_list
-                    .args()
-                    .take_while(|arg| arg.syntax().text_range().end() < position.offset)
-                    .count(),
+                arg_list.args().take_while(|arg| arg.syntax().text_range().end() < offset).count(),
             );
 
             // If we are in a method account for `self`
@@ -589,4 +601,25 @@ fn f() {
         assert_eq!(info.label(), "foo!()");
         assert_eq!(info.doc().map(|it| it.into()), Some("empty macro".to_string()));
     }
+
+    #[test]
+    fn fn_signature_inside_macro_call() {
+        let info = call_info(
+            r#"
+macro_rules! id {
+    ($($tt:tt)*) => { $($tt)* }
+}
+
+fn foo(x: u32, y: u32) -> u32 {x + y}
+
+fn bar() {
+    let y = 92;
+    id!(foo(1, y<|>));
+}
+        "#,
+        );
+
+        assert_eq!(info.parameters(), ["x: u32", "y: u32"]);
+        assert_eq!(info.active_parameter, Some(1));
+    }
 }
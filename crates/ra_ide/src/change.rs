@@ -21,6 +21,7 @@ use crate::{
 #[derive(Default)]
 pub struct AnalysisChange {
     new_roots: Vec<(SourceRootId, bool)>,
+    roots_removed: Vec<SourceRootId>,
     roots_changed: FxHashMap<SourceRootId, RootChange>,
     files_changed: Vec<(FileId, Arc<String>)>,
     libraries_added: Vec<LibraryData>,
@@ -34,6 +35,9 @@ impl fmt::Debug for AnalysisChange {
         if !self.new_roots.is_empty() {
             d.field("new_roots", &self.new_roots);
         }
+        if !self.roots_removed.is_empty() {
+            d.field("roots_removed", &self.roots_removed);
+        }
         if !self.roots_changed.is_empty() {
             d.field("roots_changed", &self.roots_changed);
         }
@@ -59,6 +63,11 @@ impl AnalysisChange {
         self.new_roots.push((root_id, is_local));
     }
 
+    /// Removes the root and all of its files from the analysis.
+    pub fn remove_root(&mut self, root_id: SourceRootId) {
+        self.roots_removed.push(root_id);
+    }
+
     pub fn add_file(
         &mut self,
         root_id: SourceRootId,
@@ -185,6 +194,27 @@ impl RootDatabase {
             }
             self.set_local_roots_with_durability(Arc::new(local_roots), Durability::HIGH);
         }
+        if !change.roots_removed.is_empty() {
+            let mut local_roots = Vec::clone(&self.local_roots());
+            let mut library_roots = Vec::clone(&self.library_roots());
+            for &root_id in change.roots_removed.iter() {
+                let source_root = self.source_root(root_id);
+                let durability = durability(&source_root);
+                for file_id in source_root.walk() {
+                    self.set_file_text_with_durability(file_id, Default::default(), durability);
+                }
+                let root = if source_root.is_library {
+                    SourceRoot::new_library()
+                } else {
+                    SourceRoot::new()
+                };
+                self.set_source_root_with_durability(root_id, Arc::new(root), durability);
+            }
+            local_roots.retain(|it| !change.roots_removed.contains(it));
+            library_roots.retain(|it| !change.roots_removed.contains(it));
+            self.set_local_roots_with_durability(Arc::new(local_roots), Durability::HIGH);
+            self.set_library_roots_with_durability(Arc::new(library_roots), Durability::HIGH);
+        }
 
         for (root_id, root_change) in change.roots_changed {
             self.apply_root_change(root_id, root_change);
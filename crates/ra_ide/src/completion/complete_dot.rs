@@ -1,6 +1,6 @@
 //! FIXME: write short doc here
 
-use hir::Type;
+use hir::{HasVisibility, Type};
 
 use crate::completion::completion_item::CompletionKind;
 use crate::{
@@ -38,6 +38,11 @@ pub(super) fn complete_dot(acc: &mut Completions, ctx: &CompletionContext) {
 fn complete_fields(acc: &mut Completions, ctx: &CompletionContext, receiver: &Type) {
     for receiver in receiver.autoderef(ctx.db) {
         for (field, ty) in receiver.fields(ctx.db) {
+            if ctx.module.map_or(false, |m| !field.is_visible_from(ctx.db, m)) {
+                // Skip private field. FIXME: If the definition location of the
+                // field is editable, we should show the completion
+                continue;
+            }
             acc.add_field(ctx, field, &ty);
         }
         for (i, ty) in receiver.tuple_fields(ctx.db).into_iter().enumerate() {
@@ -49,7 +54,10 @@ fn complete_fields(acc: &mut Completions, ctx: &CompletionContext, receiver: &Ty
 fn complete_methods(acc: &mut Completions, ctx: &CompletionContext, receiver: &Type) {
     let mut seen_methods = FxHashSet::default();
     ctx.analyzer.iterate_method_candidates(ctx.db, receiver, None, |_ty, func| {
-        if func.has_self_param(ctx.db) && seen_methods.insert(func.name(ctx.db)) {
+        if func.has_self_param(ctx.db)
+            && ctx.module.map_or(true, |m| func.is_visible_from(ctx.db, m))
+            && seen_methods.insert(func.name(ctx.db))
+        {
             acc.add_function(ctx, func);
         }
         None::<()>
@@ -186,6 +194,88 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_struct_field_visibility_private() {
+        assert_debug_snapshot!(
+        do_ref_completion(
+            r"
+            mod inner {
+                pub struct A {
+                    private_field: u32,
+                    pub pub_field: u32,
+                    pub(crate) crate_field: u32,
+                    pub(super) super_field: u32,
+                }
+            }
+            fn foo(a: inner::A) {
+               a.<|>
+            }
+            ",
+        ),
+        @r###"
+        [
+            CompletionItem {
+                label: "crate_field",
+                source_range: [317; 317),
+                delete: [317; 317),
+                insert: "crate_field",
+                kind: Field,
+                detail: "u32",
+            },
+            CompletionItem {
+                label: "pub_field",
+                source_range: [317; 317),
+                delete: [317; 317),
+                insert: "pub_field",
+                kind: Field,
+                detail: "u32",
+            },
+            CompletionItem {
+                label: "super_field",
+                source_range: [317; 317),
+                delete: [317; 317),
+                insert: "super_field",
+                kind: Field,
+                detail: "u32",
+            },
+        ]
+        "###
+        );
+    }
+
+    #[test]
+    fn test_method_completion_private() {
+        assert_debug_snapshot!(
+        do_ref_completion(
+            r"
+            struct A {}
+            mod m {
+                impl super::A {
+                    fn private_method(&self) {}
+                    pub(super) fn the_method(&self) {}
+                }
+            }
+            fn foo(a: A) {
+               a.<|>
+            }
+            ",
+        ),
+        @r###"
+        [
+            CompletionItem {
+                label: "the_method()",
+                source_range: [256; 256),
+                delete: [256; 256),
+                insert: "the_method()$0",
+                kind: Method,
+                lookup: "the_method",
+                detail: "pub(super) fn the_method(&self)",
+            },
+        ]
+        "###
+        );
+    }
+
     #[test]
     fn test_method_completion() {
         assert_debug_snapshot!(
@@ -1,7 +1,7 @@
 //! FIXME: write short doc here
 
 use either::Either;
-use hir::{Adt, HasSource, PathResolution};
+use hir::{Adt, HasSource, HasVisibility, PathResolution};
 use ra_syntax::AstNode;
 use test_utils::tested_by;
 
@@ -12,13 +12,13 @@ pub(super) fn complete_path(acc: &mut Completions, ctx: &CompletionContext) {
         Some(path) => path.clone(),
         _ => return,
     };
-    let def = match ctx.analyzer.resolve_hir_path(ctx.db, &path) {
+    let def = match ctx.analyzer.resolve_hir_path(ctx.db, &path, &ctx.token) {
         Some(PathResolution::Def(def)) => def,
         _ => return,
     };
     match def {
         hir::ModuleDef::Module(module) => {
-            let module_scope = module.scope(ctx.db);
+            let module_scope = module.scope(ctx.db, ctx.module);
             for (name, def, import) in module_scope {
                 if let hir::ScopeDef::ModuleDef(hir::ModuleDef::BuiltinType(..)) = def {
                     if ctx.use_item_syntax.is_some() {
@@ -52,6 +52,9 @@ pub(super) fn complete_path(acc: &mut Completions, ctx: &CompletionContext) {
                 _ => unreachable!(),
             };
             ctx.analyzer.iterate_path_candidates(ctx.db, &ty, None, |_ty, item| {
+                if ctx.module.map_or(false, |m| !item.is_visible_from(ctx.db, m)) {
+                    return None;
+                }
                 match item {
                     hir::AssocItem::Function(func) => {
                         if !func.has_self_param(ctx.db) {
@@ -68,6 +71,9 @@ pub(super) fn complete_path(acc: &mut Completions, ctx: &CompletionContext) {
             let krate = ctx.module.map(|m| m.krate());
             if let Some(krate) = krate {
                 ty.iterate_impl_items(ctx.db, krate, |item| {
+                    if ctx.module.map_or(false, |m| !item.is_visible_from(ctx.db, m)) {
+                        return None;
+                    }
                     match item {
                         hir::AssocItem::Function(_) | hir::AssocItem::Const(_) => {}
                         hir::AssocItem::TypeAlias(ty) => acc.add_type_alias(ctx, ty),
@@ -176,7 +182,7 @@ mod tests {
                 use self::m::<|>;
 
                 mod m {
-                    struct Bar;
+                    pub struct Bar;
                 }
                 "
             ),
@@ -194,6 +200,51 @@ mod tests {
         );
     }
 
+    #[test]
+    fn does_not_complete_private_items() {
+        assert_debug_snapshot!(
+            do_reference_completion(
+                r"
+                use self::m::<|>;
+
+                mod m {
+                    pub struct PubStruct;
+                    pub(crate) struct CrateStruct;
+                    struct PrivateStruct;
+                    pub(super) fn super_fn() {}
+                    fn private_fn() {}
+                }
+                "
+            ),
+            @r###"
+        [
+            CompletionItem {
+                label: "CrateStruct",
+                source_range: [30; 30),
+                delete: [30; 30),
+                insert: "CrateStruct",
+                kind: Struct,
+            },
+            CompletionItem {
+                label: "PubStruct",
+                source_range: [30; 30),
+                delete: [30; 30),
+                insert: "PubStruct",
+                kind: Struct,
+            },
+            CompletionItem {
+                label: "super_fn",
+                source_range: [30; 30),
+                delete: [30; 30),
+                insert: "super_fn",
+                kind: Function,
+                detail: "pub(super) fn super_fn()",
+            },
+        ]
+        "###
+        );
+    }
+
     #[test]
     fn completes_use_item_starting_with_crate() {
         assert_debug_snapshot!(
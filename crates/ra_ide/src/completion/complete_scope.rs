@@ -22,6 +22,10 @@ pub(super) fn complete_scope(acc: &mut Completions, ctx: &CompletionContext) {
         let import_resolver = ImportResolver::new();
         let import_names = import_resolver.all_names(ctx.token.text());
         import_names.into_iter().for_each(|(name, path)| {
+            match ctx.module {
+                Some(module) if is_importable_from(ctx, module, &path) => (),
+                _ => return,
+            }
             let edit = {
                 let mut builder = TextEditBuilder::default();
                 builder.replace(ctx.source_range(), name.to_string());
@@ -54,6 +58,41 @@ pub(super) fn complete_scope(acc: &mut Completions, ctx: &CompletionContext) {
     }
 }
 
+/// Whether the item at `path`, which starts with a crate name, can be named
+/// from `module`.
+fn is_importable_from(ctx: &CompletionContext, module: hir::Module, path: &[SmolStr]) -> bool {
+    let (krate, rest) = match path.split_first() {
+        Some(it) => it,
+        None => return false,
+    };
+    let dep = module
+        .krate()
+        .dependencies(ctx.db)
+        .into_iter()
+        .find(|dep| dep.name.to_string() == krate.as_str());
+    let mut current = match dep.and_then(|dep| dep.krate.root_module(ctx.db)) {
+        Some(it) => hir::ScopeDef::ModuleDef(hir::ModuleDef::Module(it)),
+        None => return false,
+    };
+    for segment in rest {
+        let parent = match current {
+            hir::ScopeDef::ModuleDef(hir::ModuleDef::Module(it)) => it,
+            _ => return false,
+        };
+        // `scope` only returns the items visible from `module`.
+        let def = parent
+            .scope(ctx.db, Some(module))
+            .into_iter()
+            .find(|(name, _, _)| name.to_string() == segment.as_str())
+            .map(|(_, def, _)| def);
+        current = match def {
+            Some(it) => it,
+            None => return false,
+        };
+    }
+    true
+}
+
 fn build_import_label(name: &str, path: &[SmolStr]) -> String {
     let mut buf = String::with_capacity(64);
     buf.push_str(name);
@@ -873,4 +912,36 @@ mod tests {
         "###
         );
     }
+
+    #[test]
+    fn completes_auto_imports_of_visible_items() {
+        let completions = do_reference_completion(
+            r"
+            //- /main.rs
+            fn main() { Deb<|> }
+
+            //- /std/lib.rs
+            pub mod fmt {
+                pub trait Debug {}
+            }
+            ",
+        );
+        assert!(completions.iter().any(|it| it.label() == "Debug (std::fmt::Debug)"));
+    }
+
+    #[test]
+    fn does_not_complete_auto_imports_of_private_items() {
+        let completions = do_reference_completion(
+            r"
+            //- /main.rs
+            fn main() { Deb<|> }
+
+            //- /std/lib.rs
+            mod fmt {
+                pub trait Debug {}
+            }
+            ",
+        );
+        assert!(completions.iter().all(|it| it.label() != "Debug (std::fmt::Debug)"));
+    }
 }
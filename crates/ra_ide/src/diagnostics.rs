@@ -27,6 +27,7 @@ pub(crate) fn diagnostics(db: &RootDatabase, file_id: FileId) -> Vec<Diagnostic>
     let mut res = Vec::new();
 
     res.extend(parse.errors().iter().map(|err| Diagnostic {
+        code: "syntax-error",
         range: location_to_range(err.location()),
         message: format!("Syntax Error: {}", err),
         severity: Severity::Error,
@@ -40,6 +41,7 @@ pub(crate) fn diagnostics(db: &RootDatabase, file_id: FileId) -> Vec<Diagnostic>
     let res = RefCell::new(res);
     let mut sink = DiagnosticSink::new(|d| {
         res.borrow_mut().push(Diagnostic {
+            code: d.code(),
             message: d.message(),
             range: d.highlight_range(),
             severity: Severity::Error,
@@ -57,6 +59,7 @@ pub(crate) fn diagnostics(db: &RootDatabase, file_id: FileId) -> Vec<Diagnostic>
         let create_file = FileSystemEdit::CreateFile { source_root, path };
         let fix = SourceChange::file_system_edit("create module", create_file);
         res.borrow_mut().push(Diagnostic {
+            code: d.code(),
             range: d.highlight_range(),
             message: d.message(),
             severity: Severity::Error,
@@ -76,6 +79,7 @@ pub(crate) fn diagnostics(db: &RootDatabase, file_id: FileId) -> Vec<Diagnostic>
         let fix =
             SourceChange::source_file_edit_from("fill struct fields", file_id, builder.finish());
         res.borrow_mut().push(Diagnostic {
+            code: d.code(),
             range: d.highlight_range(),
             message: d.message(),
             severity: Severity::Error,
@@ -88,6 +92,7 @@ pub(crate) fn diagnostics(db: &RootDatabase, file_id: FileId) -> Vec<Diagnostic>
         let edit = TextEdit::replace(node.syntax().text_range(), replacement);
         let fix = SourceChange::source_file_edit_from("wrap with ok", file_id, edit);
         res.borrow_mut().push(Diagnostic {
+            code: d.code(),
             range: d.highlight_range(),
             message: d.message(),
             severity: Severity::Error,
@@ -129,6 +134,7 @@ fn check_unnecessary_braces_in_use_statement(
                 });
 
         acc.push(Diagnostic {
+            code: "unnecessary-braces",
             range,
             message: "Unnecessary braces in use statement".to_string(),
             severity: Severity::WeakWarning,
@@ -173,6 +179,7 @@ fn check_struct_shorthand_initialization(
                 let edit = edit_builder.finish();
 
                 acc.push(Diagnostic {
+                    code: "struct-shorthand-initialization",
                     range: record_field.syntax().text_range(),
                     message: "Shorthand struct initialization".to_string(),
                     severity: Severity::WeakWarning,
@@ -528,6 +535,7 @@ mod tests {
         assert_debug_snapshot!(diagnostics, @r###"
         [
             Diagnostic {
+                code: "unresolved-module",
                 message: "unresolved module",
                 range: [0; 8),
                 fix: Some(
@@ -6,8 +6,9 @@ mod navigation_target;
 mod structure;
 mod short_label;
 
+use either::Either;
 use ra_syntax::{
-    ast::{self, AstNode, AttrsOwner, NameOwner, TypeParamsOwner},
+    ast::{self, AstNode, AttrsOwner, NameOwner, TypeParamsOwner, VisibilityOwner},
     SyntaxKind::{ATTR, COMMENT},
 };
 
@@ -61,10 +62,19 @@ pub(crate) fn where_predicates<N: TypeParamsOwner>(node: &N) -> Vec<String> {
     res
 }
 
-pub(crate) fn macro_label(node: &ast::MacroCall) -> String {
-    let name = node.name().map(|name| name.syntax().text().to_string()).unwrap_or_default();
-    let vis = if node.has_atom_attr("macro_export") { "#[macro_export]\n" } else { "" };
-    format!("{}macro_rules! {}", vis, name)
+pub(crate) fn macro_label(node: &Either<ast::MacroCall, ast::MacroDef>) -> String {
+    match node {
+        Either::Left(node) => {
+            let name = node.name().map(|name| name.syntax().text().to_string()).unwrap_or_default();
+            let vis = if node.has_atom_attr("macro_export") { "#[macro_export]\n" } else { "" };
+            format!("{}macro_rules! {}", vis, name)
+        }
+        Either::Right(node) => {
+            let name = node.name().map(|name| name.syntax().text().to_string()).unwrap_or_default();
+            let vis = node.visibility().map(|vis| format!("{} ", vis.syntax())).unwrap_or_default();
+            format!("{}macro {}", vis, name)
+        }
+    }
 }
 
 pub(crate) fn rust_code_markup<CODE: AsRef<str>>(val: CODE) -> String {
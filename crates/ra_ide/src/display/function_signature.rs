@@ -2,6 +2,7 @@
 
 use std::fmt::{self, Display};
 
+use either::Either;
 use hir::{Docs, Documentation, HasSource, HirDisplay};
 use join_to_string::join;
 use ra_syntax::ast::{self, AstNode, NameOwner, VisibilityOwner};
@@ -123,7 +124,10 @@ impl FunctionSignature {
     }
 
     pub(crate) fn from_macro(db: &db::RootDatabase, macro_def: hir::MacroDef) -> Option<Self> {
-        let node: ast::MacroCall = macro_def.source(db).value;
+        let name = match macro_def.source(db).value {
+            Either::Left(node) => node.name(),
+            Either::Right(node) => node.name(),
+        };
 
         let params = vec![];
 
@@ -131,7 +135,7 @@ impl FunctionSignature {
             FunctionSignature {
                 kind: CallableKind::Macro,
                 visibility: None,
-                name: node.name().map(|n| n.text().to_string()),
+                name: name.map(|n| n.text().to_string()),
                 ret_type: None,
                 parameters: params,
                 generic_parameters: vec![],
@@ -295,13 +295,12 @@ impl ToNav for hir::StructField {
 impl ToNav for hir::MacroDef {
     fn to_nav(&self, db: &RootDatabase) -> NavigationTarget {
         let src = self.source(db);
-        log::debug!("nav target {:#?}", src.value.syntax());
-        NavigationTarget::from_named(
-            db,
-            src.as_ref().map(|it| it as &dyn ast::NameOwner),
-            src.value.doc_comment_text(),
-            None,
-        )
+        log::debug!("nav target {:#?}", src.value);
+        let (node, docs): (&dyn ast::NameOwner, _) = match &src.value {
+            Either::Left(it) => (it, it.doc_comment_text()),
+            Either::Right(it) => (it, it.doc_comment_text()),
+        };
+        NavigationTarget::from_named(db, src.with_value(node), docs, None)
     }
 }
 
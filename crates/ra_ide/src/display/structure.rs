@@ -157,6 +157,7 @@ fn structure_node(node: &SyntaxNode) -> Option<StructureNode> {
                 }
                 decl(it)
             },
+            ast::MacroDef(it) => { decl(it) },
             _ => None,
         }
     }
@@ -2,6 +2,7 @@
 
 use crate::{db::RootDatabase, FilePosition};
 use hir::db::AstDatabase;
+use itertools::Itertools;
 use ra_db::SourceDatabase;
 use rustc_hash::FxHashMap;
 
@@ -32,6 +33,107 @@ pub(crate) fn expand_macro(db: &RootDatabase, position: FilePosition) -> Option<
     Some(ExpandedMacro { name: name_ref.text().to_string(), expansion })
 }
 
+/// Describes how the macro call at a position was matched against the rules
+/// of its `macro_rules!` definition. All token trees are rendered as text.
+#[derive(Debug)]
+pub struct MacroExpansionTrace {
+    pub name: String,
+    pub rules: Vec<MacroRuleTrace>,
+    /// The expanded token tree, or the reason why expansion failed
+    pub result: Result<String, String>,
+}
+
+#[derive(Debug)]
+pub struct MacroRuleTrace {
+    /// Index of the rule in the macro definition
+    pub index: usize,
+    /// The part of the rule to the left of `=>`
+    pub pattern: String,
+    pub matched: bool,
+    /// Why the rule didn't match or failed to transcribe
+    pub error: Option<String>,
+    /// For rules which didn't match, the input starting from the failure
+    pub unmatched: Option<String>,
+    pub bindings: Vec<MacroBindingTrace>,
+    /// The expansion produced by this rule, if it matched
+    pub expansion: Option<String>,
+}
+
+#[derive(Debug)]
+pub struct MacroBindingTrace {
+    pub name: String,
+    /// The matched fragment. Bindings inside of repetitions are rendered as a
+    /// (possibly nested) list of fragments, like `[a, b]`.
+    pub value: String,
+}
+
+pub(crate) fn debug_macro_expansion(
+    db: &RootDatabase,
+    position: FilePosition,
+) -> Option<MacroExpansionTrace> {
+    let parse = db.parse(position.file_id);
+    let file = parse.tree();
+    let name_ref = find_node_at_offset::<ast::NameRef>(file.syntax(), position.offset)?;
+    let mac = name_ref.syntax().ancestors().find_map(ast::MacroCall::cast)?;
+
+    let source = hir::InFile::new(position.file_id.into(), mac.syntax());
+    let analyzer = hir::SourceAnalyzer::new(db, source, None);
+    let trace = analyzer.expand(db, source.with_value(&mac))?.trace(db)?;
+
+    let rules = trace
+        .rules
+        .into_iter()
+        .map(|rule| {
+            let mut res = MacroRuleTrace {
+                index: rule.rule,
+                pattern: rule.lhs.to_string(),
+                matched: false,
+                error: None,
+                unmatched: None,
+                bindings: Vec::new(),
+                expansion: None,
+            };
+            match rule.outcome {
+                hir::RuleOutcome::NoMatch { error, unmatched } => {
+                    res.error = Some(error.to_string());
+                    res.unmatched = Some(unmatched.iter().join(" "));
+                }
+                hir::RuleOutcome::Matched { bindings, expansion } => {
+                    res.matched = true;
+                    res.bindings = bindings
+                        .into_iter()
+                        .map(|(name, value)| MacroBindingTrace {
+                            name: name.to_string(),
+                            value: binding_to_string(&value),
+                        })
+                        .collect();
+                    match expansion {
+                        Ok(it) => res.expansion = Some(it.to_string()),
+                        Err(err) => res.error = Some(err.to_string()),
+                    }
+                }
+            }
+            res
+        })
+        .collect();
+
+    let result = match trace.result {
+        Ok(it) => Ok(it.to_string()),
+        Err(err) => Err(err.to_string()),
+    };
+    Some(MacroExpansionTrace { name: name_ref.text().to_string(), rules, result })
+}
+
+fn binding_to_string(binding: &hir::BindingTrace) -> String {
+    match binding {
+        hir::BindingTrace::Fragment(it) => it.to_string(),
+        hir::BindingTrace::Nested(it) => {
+            format!("[{}]", it.iter().map(binding_to_string).join(", "))
+        }
+        hir::BindingTrace::Empty => "[]".to_string(),
+    }
+}
+
 fn expand_macro_recur(
     db: &RootDatabase,
     source: hir::InFile<&SyntaxNode>,
@@ -292,4 +394,36 @@ fn some_thing() -> u32 {
         assert_eq!(res.name, "foo");
         assert_snapshot!(res.expansion, @r###"0"###);
     }
+
+    #[test]
+    fn debug_macro_expansion_reports_each_rule() {
+        let (analysis, pos) = analysis_and_position(
+            r#"
+        //- /lib.rs
+        macro_rules! foo {
+            ($i:ident) => { fn $i() {} };
+            ($($i:ident),*) => { $(struct $i;)* };
+        }
+        fo<|>o!(a, b);
+        "#,
+        );
+        let trace = analysis.debug_macro_expansion(pos).unwrap().unwrap();
+
+        assert_eq!(trace.name, "foo");
+        assert_eq!(trace.rules.len(), 2);
+
+        let first = &trace.rules[0];
+        assert!(!first.matched);
+        assert_eq!(first.error.as_ref().unwrap(), "leftover tokens");
+        assert_eq!(first.unmatched.as_ref().unwrap(), ", b");
+
+        let second = &trace.rules[1];
+        assert!(second.matched);
+        assert_eq!(second.bindings.len(), 1);
+        assert_eq!(second.bindings[0].name, "i");
+        assert_eq!(second.bindings[0].value, "[a, b]");
+        assert_eq!(second.expansion.as_ref().unwrap(), "struct a ; struct b ;");
+
+        assert_eq!(trace.result, Ok("struct a ; struct b ;".to_string()));
+    }
 }
@@ -14,7 +14,7 @@ use rustc_hash::FxHashMap;
 ///
 /// Also note that, at the moment, `FeatureFlags` also store features for
 /// `ra_lsp_server`. This should be benign layering violation.
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct FeatureFlags {
     flags: FxHashMap<String, bool>,
 }
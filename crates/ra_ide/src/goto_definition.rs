@@ -1,6 +1,6 @@
 //! FIXME: write short doc here
 
-use hir::{db::AstDatabase, InFile};
+use hir::{db::AstDatabase, descend_into_macros, InFile};
 use ra_syntax::{
     ast::{self, DocCommentsOwner},
     match_ast, AstNode, SyntaxNode,
@@ -9,7 +9,6 @@ use ra_syntax::{
 use crate::{
     db::RootDatabase,
     display::{ShortLabel, ToNav},
-    expand::descend_into_macros,
     references::{classify_name_ref, NameKind::*},
     FilePosition, NavigationTarget, RangeInfo,
 };
@@ -21,7 +20,8 @@ pub(crate) fn goto_definition(
     let file = db.parse_or_expand(position.file_id.into())?;
     let original_token =
         file.token_at_offset(position.offset).filter(|it| !it.kind().is_trivia()).next()?;
-    let token = descend_into_macros(db, position.file_id, original_token.clone());
+    let token =
+        descend_into_macros(db, InFile::new(position.file_id.into(), original_token.clone()));
 
     let nav_targets = match_ast! {
         match (token.value.parent()) {
@@ -691,6 +691,27 @@ mod tests {
         );
     }
 
+    #[test]
+    fn goto_from_nested_macro() {
+        check_goto(
+            "
+            //- /lib.rs
+            macro_rules! id {
+                ($($tt:tt)*) => { $($tt)* }
+            }
+            fn foo() {}
+            id! {
+                id! {
+                    fn bar() {
+                        fo<|>o();
+                    }
+                }
+            }
+            ",
+            "foo FN_DEF FileId(1) [52; 63) [55; 58)",
+        );
+    }
+
     #[test]
     fn goto_through_format() {
         check_goto(
@@ -1,12 +1,9 @@
 //! FIXME: write short doc here
 
-use hir::db::AstDatabase;
+use hir::{db::AstDatabase, descend_into_macros, InFile};
 use ra_syntax::{ast, AstNode};
 
-use crate::{
-    db::RootDatabase, display::ToNav, expand::descend_into_macros, FilePosition, NavigationTarget,
-    RangeInfo,
-};
+use crate::{db::RootDatabase, display::ToNav, FilePosition, NavigationTarget, RangeInfo};
 
 pub(crate) fn goto_type_definition(
     db: &RootDatabase,
@@ -14,7 +11,7 @@ pub(crate) fn goto_type_definition(
 ) -> Option<RangeInfo<Vec<NavigationTarget>>> {
     let file = db.parse_or_expand(position.file_id.into())?;
     let token = file.token_at_offset(position.offset).filter(|it| !it.kind().is_trivia()).next()?;
-    let token = descend_into_macros(db, position.file_id, token);
+    let token = descend_into_macros(db, InFile::new(position.file_id.into(), token));
 
     let node = token.value.ancestors().find_map(|token| {
         token
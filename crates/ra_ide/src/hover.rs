@@ -1,11 +1,11 @@
 //! FIXME: write short doc here
 
-use hir::{db::AstDatabase, Adt, HasSource, HirDisplay};
+use hir::{db::AstDatabase, descend_into_macros, Adt, CaptureMode, HasSource, HirDisplay, InFile};
 use ra_db::SourceDatabase;
 use ra_syntax::{
     algo::find_covering_element,
     ast::{self, DocCommentsOwner},
-    match_ast, AstNode,
+    match_ast, AstNode, SyntaxNode, T,
 };
 
 use crate::{
@@ -14,7 +14,6 @@ use crate::{
         description_from_symbol, docs_from_symbol, macro_label, rust_code_markup,
         rust_code_markup_with_doc, ShortLabel,
     },
-    expand::descend_into_macros,
     references::{classify_name, classify_name_ref, NameKind, NameKind::*},
     FilePosition, FileRange, RangeInfo,
 };
@@ -101,7 +100,9 @@ fn hover_text_from_name_kind(
     return match name_kind {
         Macro(it) => {
             let src = it.source(db);
-            hover_text(src.value.doc_comment_text(), Some(macro_label(&src.value)))
+            let docs =
+                src.value.as_ref().either(|it| it.doc_comment_text(), |it| it.doc_comment_text());
+            hover_text(docs, Some(macro_label(&src.value)))
         }
         Field(it) => {
             let src = it.source(db);
@@ -156,8 +157,10 @@ fn hover_text_from_name_kind(
 
 pub(crate) fn hover(db: &RootDatabase, position: FilePosition) -> Option<RangeInfo<HoverResult>> {
     let file = db.parse_or_expand(position.file_id.into())?;
-    let token = file.token_at_offset(position.offset).filter(|it| !it.kind().is_trivia()).next()?;
-    let token = descend_into_macros(db, position.file_id, token);
+    let original_token =
+        file.token_at_offset(position.offset).filter(|it| !it.kind().is_trivia()).next()?;
+    let token =
+        descend_into_macros(db, InFile::new(position.file_id.into(), original_token.clone()));
 
     let mut res = HoverResult::new();
 
@@ -198,6 +201,16 @@ pub(crate) fn hover(db: &RootDatabase, position: FilePosition) -> Option<RangeIn
                     None
                 }
             },
+            ast::ParamList(param_list) => {
+                let lambda = param_list.syntax().parent().and_then(ast::LambdaExpr::cast);
+                match lambda {
+                    Some(lambda) if token.value.kind() == T![|] => {
+                        res.extend(hover_text_for_closure(db, token.with_value(&lambda)));
+                        Some(param_list.syntax().text_range())
+                    }
+                    _ => None,
+                }
+            },
             _ => None,
         }
     };
@@ -206,18 +219,48 @@ pub(crate) fn hover(db: &RootDatabase, position: FilePosition) -> Option<RangeIn
         let node = token.value.ancestors().find(|n| {
             ast::Expr::cast(n.clone()).is_some() || ast::Pat::cast(n.clone()).is_some()
         })?;
-        let frange = FileRange { file_id: position.file_id, range: node.text_range() };
-        res.extend(type_of(db, frange).map(rust_code_markup));
+        res.extend(type_of_node(db, token.with_value(&node)).map(rust_code_markup));
         range = Some(node.text_range());
     };
 
-    let range = range?;
+    // Ranges inside of a macro expansion are meaningless for the client,
+    // so highlight the token in the macro call instead.
+    let range = if token.file_id.is_macro_file() { original_token.text_range() } else { range? };
     if res.is_empty() {
         return None;
     }
     Some(RangeInfo::new(range, res))
 }
 
+fn hover_text_for_closure(db: &RootDatabase, lambda: InFile<&ast::LambdaExpr>) -> Option<String> {
+    let analyzer = hir::SourceAnalyzer::new(db, lambda.map(|it| it.syntax()), None);
+    let ty = analyzer.type_of(db, &lambda.value.clone().into())?;
+    let kind = analyzer.closure_kind(lambda.value)?;
+    let captures = analyzer.closure_captures(lambda.value)?;
+
+    let mut text = rust_code_markup(ty.display(db).to_string());
+    text.push_str(&format!("\n\nImplements `{}`", kind));
+    if !captures.is_empty() {
+        text.push_str("\n\nCaptures:");
+        for capture in captures {
+            let mut place = match capture.local().name(db) {
+                Some(name) => name.to_string(),
+                None => continue,
+            };
+            for field in capture.fields() {
+                place.push_str(&format!(".{}", field));
+            }
+            let mode = match capture.mode() {
+                CaptureMode::ByRef => "by reference",
+                CaptureMode::ByMutRef => "by mutable reference",
+                CaptureMode::ByMove => "by move",
+            };
+            text.push_str(&format!("\n* `{}` {}", place, mode));
+        }
+    }
+    Some(text)
+}
+
 pub(crate) fn type_of(db: &RootDatabase, frange: FileRange) -> Option<String> {
     let parse = db.parse(frange.file_id);
     let leaf_node = find_covering_element(parse.tree().syntax(), frange.range);
@@ -226,12 +269,18 @@ pub(crate) fn type_of(db: &RootDatabase, frange: FileRange) -> Option<String> {
         .ancestors()
         .take_while(|it| it.text_range() == leaf_node.text_range())
         .find(|it| ast::Expr::cast(it.clone()).is_some() || ast::Pat::cast(it.clone()).is_some())?;
-    let analyzer =
-        hir::SourceAnalyzer::new(db, hir::InFile::new(frange.file_id.into(), &node), None);
-    let ty = if let Some(ty) = ast::Expr::cast(node.clone()).and_then(|e| analyzer.type_of(db, &e))
+    type_of_node(db, InFile::new(frange.file_id.into(), &node))
+}
+
+fn type_of_node(db: &RootDatabase, node: InFile<&SyntaxNode>) -> Option<String> {
+    let analyzer = hir::SourceAnalyzer::new(db, node, None);
+    let ty = if let Some(ty) =
+        ast::Expr::cast(node.value.clone()).and_then(|e| analyzer.type_of(db, &e))
     {
         ty
-    } else if let Some(ty) = ast::Pat::cast(node).and_then(|p| analyzer.type_of_pat(db, &p)) {
+    } else if let Some(ty) =
+        ast::Pat::cast(node.value.clone()).and_then(|p| analyzer.type_of_pat(db, &p))
+    {
         ty
     } else {
         return None;
@@ -697,6 +746,24 @@ fn func(foo: i32) { if true { <|>foo; }; }
         assert_eq!(hover.info.is_exact(), true);
     }
 
+    #[test]
+    fn test_hover_macro2_invocation() {
+        let (analysis, position) = single_file_with_position(
+            "
+            mod m {
+                pub macro foo($e:expr) { $e }
+            }
+
+            fn f() {
+                m::fo<|>o!(92);
+            }
+            ",
+        );
+        let hover = analysis.hover(position).unwrap().unwrap();
+        assert_eq!(trim_markup_opt(hover.info.first()), Some("pub macro foo"));
+        assert_eq!(hover.info.is_exact(), true);
+    }
+
     #[test]
     fn test_hover_tuple_field() {
         let (analysis, position) = single_file_with_position(
@@ -727,4 +794,49 @@ fn func(foo: i32) { if true { <|>foo; }; }
             &["fn foo()"],
         );
     }
+
+    #[test]
+    fn test_hover_through_macro_has_range_in_macro_call() {
+        let (analysis, position) = single_file_with_position(
+            "
+            macro_rules! id {
+                ($($tt:tt)*) => { $($tt)* }
+            }
+            fn foo() {}
+            id! {
+                fn bar() {
+                    fo<|>o();
+                }
+            }
+            ",
+        );
+        let hover = analysis.hover(position).unwrap().unwrap();
+        let text = analysis.file_text(position.file_id).unwrap();
+        assert_eq!(&text[hover.range], "foo");
+        assert_eq!(trim_markup_opt(hover.info.first()), Some("fn foo()"));
+    }
+
+    #[test]
+    fn test_hover_closure_captures() {
+        let (analysis, position) = single_file_with_position(
+            "
+            #[lang = \"copy\"]
+            trait Copy {}
+            struct S { a: u32 }
+            fn consume(s: S) {}
+            fn main() {
+                let mut x = 0u32;
+                let s = S { a: 1 };
+                let c = <|>|| { x += s.a; consume(s); };
+            }
+            ",
+        );
+        let hover = analysis.hover(position).unwrap().unwrap();
+        assert_eq!(
+            hover.info.first(),
+            Some(
+                "```rust\n|| -> ()\n```\n\nImplements `FnOnce`\n\nCaptures:\n* `x` by mutable reference\n* `s` by move"
+            )
+        );
+    }
 }
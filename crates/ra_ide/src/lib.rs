@@ -66,7 +66,7 @@ pub use crate::{
     completion::{CompletionItem, CompletionItemKind, InsertTextFormat},
     diagnostics::Severity,
     display::{file_structure, FunctionSignature, NavigationTarget, StructureNode},
-    expand_macro::ExpandedMacro,
+    expand_macro::{ExpandedMacro, MacroBindingTrace, MacroExpansionTrace, MacroRuleTrace},
     feature_flags::FeatureFlags,
     folding_ranges::{Fold, FoldKind},
     hover::HoverResult,
@@ -76,18 +76,21 @@ pub use crate::{
     references::{ReferenceSearchResult, SearchScope},
     runnables::{Runnable, RunnableKind},
     source_change::{FileSystemEdit, SourceChange, SourceFileEdit},
-    syntax_highlighting::HighlightedRange,
+    syntax_highlighting::{HighlightedRange, HtmlAnnotation},
 };
 
 pub use hir::Documentation;
 pub use ra_db::{
-    Canceled, CrateGraph, CrateId, Edition, FileId, FilePosition, FileRange, SourceRootId,
+    Canceled, CrateGraph, CrateId, Edition, ExternSourceId, FileId, FilePosition, FileRange,
+    SourceRootId,
 };
 
 pub type Cancelable<T> = Result<T, Canceled>;
 
 #[derive(Debug)]
 pub struct Diagnostic {
+    /// See `hir::diagnostics::Diagnostic::code`.
+    pub code: &'static str,
     pub message: String,
     pub range: TextRange,
     pub fix: Option<SourceChange>,
@@ -240,7 +243,14 @@ impl Analysis {
         // Default to enable test for single file.
         let mut cfg_options = CfgOptions::default();
         cfg_options.insert_atom("test".into());
-        crate_graph.add_crate_root(file_id, Edition::Edition2018, cfg_options, Env::default());
+        crate_graph.add_crate_root(
+            file_id,
+            Edition::Edition2018,
+            cfg_options,
+            Env::default(),
+            Default::default(),
+            Vec::new(),
+        );
         change.add_file(source_root, file_id, "main.rs".into(), Arc::new(text));
         change.set_crate_graph(crate_graph);
         host.apply_change(change);
@@ -302,6 +312,15 @@ impl Analysis {
         self.with_db(|db| expand_macro::expand_macro(db, position))
     }
 
+    /// Explains how the macro call at `position` was matched against the
+    /// rules of its definition.
+    pub fn debug_macro_expansion(
+        &self,
+        position: FilePosition,
+    ) -> Cancelable<Option<MacroExpansionTrace>> {
+        self.with_db(|db| expand_macro::debug_macro_expansion(db, position))
+    }
+
     /// Returns an edit to remove all newlines in the range, cleaning up minor
     /// stuff like trailing commas.
     pub fn join_lines(&self, frange: FileRange) -> Cancelable<SourceChange> {
@@ -444,7 +463,18 @@ impl Analysis {
 
     /// Computes syntax highlighting for the given file.
     pub fn highlight_as_html(&self, file_id: FileId, rainbow: bool) -> Cancelable<String> {
-        self.with_db(|db| syntax_highlighting::highlight_as_html(db, file_id, rainbow))
+        self.with_db(|db| syntax_highlighting::highlight_as_html(db, file_id, rainbow, &[]))
+    }
+
+    /// Computes syntax highlighting for the given file, with extra attributes,
+    /// like links, on the annotated tokens.
+    pub fn highlight_as_html_with_annotations(
+        &self,
+        file_id: FileId,
+        rainbow: bool,
+        annotations: &[HtmlAnnotation],
+    ) -> Cancelable<String> {
+        self.with_db(|db| syntax_highlighting::highlight_as_html(db, file_id, rainbow, annotations))
     }
 
     /// Computes completions at the given position.
@@ -101,10 +101,18 @@ impl MockAnalysis {
                     Edition2018,
                     cfg_options,
                     Env::default(),
+                    Default::default(),
+                    Vec::new(),
                 ));
             } else if path.ends_with("/lib.rs") {
-                let other_crate =
-                    crate_graph.add_crate_root(file_id, Edition2018, cfg_options, Env::default());
+                let other_crate = crate_graph.add_crate_root(
+                    file_id,
+                    Edition2018,
+                    cfg_options,
+                    Env::default(),
+                    Default::default(),
+                    Vec::new(),
+                );
                 let crate_name = path.parent().unwrap().file_name().unwrap();
                 if let Some(root_crate) = root_crate {
                     crate_graph.add_dep(root_crate, crate_name.into(), other_crate).unwrap();
@@ -95,6 +95,8 @@ mod tests {
             Edition2018,
             CfgOptions::default(),
             Env::default(),
+            Default::default(),
+            Vec::new(),
         );
         let mut change = AnalysisChange::new();
         change.set_crate_graph(crate_graph);
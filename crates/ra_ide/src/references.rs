@@ -8,20 +8,24 @@
 //! for text occurrences of the identifier. If there's an `ast::NameRef`
 //! at the index that the match starts at and its tree parent is
 //! resolved to the search element definition, we get a reference.
+//! Identifiers inside of macro calls are first mapped into the expansion.
 
 mod classify;
 mod name_definition;
 mod rename;
 mod search_scope;
 
-use hir::InFile;
+use hir::{descend_into_macros, InFile};
 use once_cell::unsync::Lazy;
 use ra_db::{SourceDatabase, SourceDatabaseExt};
 use ra_prof::profile;
-use ra_syntax::{algo::find_node_at_offset, ast, AstNode, SourceFile, SyntaxNode, TextUnit};
+use ra_syntax::{
+    algo::find_node_at_offset, ast, match_ast, AstNode, SourceFile, SyntaxKind::*, SyntaxNode,
+    SyntaxToken, TextRange, TextUnit,
+};
 
 use crate::{
-    db::RootDatabase, display::ToNav, FilePosition, FileRange, NavigationTarget, RangeInfo,
+    db::RootDatabase, display::ToNav, FileId, FilePosition, FileRange, NavigationTarget, RangeInfo,
 };
 
 pub(crate) use self::{
@@ -111,10 +115,46 @@ fn find_name<'a>(
         let range = name.syntax().text_range();
         return Some(RangeInfo::new(range, (name.text().to_string(), def)));
     }
-    let name_ref = find_node_at_offset::<ast::NameRef>(&syntax, position.offset)?;
-    let def = classify_name_ref(db, InFile::new(position.file_id.into(), &name_ref))?;
-    let range = name_ref.syntax().text_range();
-    Some(RangeInfo::new(range, (name_ref.text().to_string(), def)))
+    if let Some(name_ref) = find_node_at_offset::<ast::NameRef>(&syntax, position.offset) {
+        let def = classify_name_ref(db, InFile::new(position.file_id.into(), &name_ref))?;
+        let range = name_ref.syntax().text_range();
+        return Some(RangeInfo::new(range, (name_ref.text().to_string(), def)));
+    }
+    let token = macro_ident_at_offset(syntax, position.offset)?;
+    let range = token.text_range();
+    let token = descend_into_macros(db, InFile::new(position.file_id.into(), token));
+    let def = match_ast! {
+        match (token.value.parent()) {
+            ast::Name(name) => { classify_name(db, token.with_value(&name))? },
+            ast::NameRef(name_ref) => { classify_name_ref(db, token.with_value(&name_ref))? },
+            _ => return None,
+        }
+    };
+    Some(RangeInfo::new(range, (token.value.text().to_string(), def)))
+}
+
+/// Returns the identifier at `offset` if it is a part of the arguments of a
+/// macro call, and so has no `Name` or `NameRef` node around it.
+fn macro_ident_at_offset(syntax: &SyntaxNode, offset: TextUnit) -> Option<SyntaxToken> {
+    syntax.token_at_offset(offset).find(|it| it.kind() == IDENT && it.parent().kind() == TOKEN_TREE)
+}
+
+/// Finds the `NameRef` at `offset`, looking into the expansion if the offset
+/// is inside of a macro call. The returned range is always in `file_id`.
+fn find_name_ref(
+    db: &RootDatabase,
+    file_id: FileId,
+    syntax: &SyntaxNode,
+    offset: TextUnit,
+) -> Option<(TextRange, InFile<ast::NameRef>)> {
+    if let Some(name_ref) = find_node_at_offset::<ast::NameRef>(syntax, offset) {
+        return Some((name_ref.syntax().text_range(), InFile::new(file_id.into(), name_ref)));
+    }
+    let token = macro_ident_at_offset(syntax, offset)?;
+    let range = token.text_range();
+    let token = descend_into_macros(db, InFile::new(file_id.into(), token));
+    let name_ref = ast::NameRef::cast(token.value.parent())?;
+    Some((range, token.with_value(name_ref)))
 }
 
 fn process_definition(
@@ -135,16 +175,15 @@ fn process_definition(
         for (idx, _) in text.match_indices(pat) {
             let offset = TextUnit::from_usize(idx);
 
-            if let Some(name_ref) =
-                find_node_at_offset::<ast::NameRef>(parse.tree().syntax(), offset)
+            if let Some((range, name_ref)) =
+                find_name_ref(db, file_id, parse.tree().syntax(), offset)
             {
-                let range = name_ref.syntax().text_range();
                 if let Some(search_range) = search_range {
                     if !range.is_subrange(&search_range) {
                         continue;
                     }
                 }
-                if let Some(d) = classify_name_ref(db, InFile::new(file_id.into(), &name_ref)) {
+                if let Some(d) = classify_name_ref(db, name_ref.as_ref()) {
                     if d == def {
                         refs.push(FileRange { file_id, range });
                     }
@@ -382,6 +421,43 @@ mod tests {
         assert_eq!(refs.len(), 3);
     }
 
+    #[test]
+    fn test_find_all_refs_inside_macro_calls() {
+        let code = r#"
+        macro_rules! id {
+            ($($tt:tt)*) => { $($tt)* }
+        }
+
+        fn foo<|>() {}
+
+        fn main() {
+            foo();
+            id!(foo());
+            id!(id!(foo()));
+        }"#;
+
+        let refs = get_all_refs(code);
+        assert_eq!(refs.len(), 4);
+    }
+
+    #[test]
+    fn test_find_all_refs_from_macro_call() {
+        let code = r#"
+        macro_rules! id {
+            ($($tt:tt)*) => { $($tt)* }
+        }
+
+        fn foo() {}
+
+        fn main() {
+            foo();
+            id!(fo<|>o());
+        }"#;
+
+        let refs = get_all_refs(code);
+        assert_eq!(refs.len(), 3);
+    }
+
     fn get_all_refs(text: &str) -> ReferenceSearchResult {
         let (analysis, position) = single_file_with_position(text);
         analysis.find_all_refs(position, None).unwrap().unwrap()
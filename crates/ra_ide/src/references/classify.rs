@@ -1,8 +1,12 @@
 //! Functions that are used to classify an element from its definition or reference.
 
+use either::Either;
 use hir::{FromSource, InFile, Module, ModuleSource, PathResolution, SourceAnalyzer};
 use ra_prof::profile;
-use ra_syntax::{ast, match_ast, AstNode};
+use ra_syntax::{
+    ast::{self, VisibilityOwner},
+    match_ast, AstNode,
+};
 use test_utils::tested_by;
 
 use super::{
@@ -98,11 +102,9 @@ pub(crate) fn classify_name(db: &RootDatabase, name: InFile<&ast::Name>) -> Opti
                 }
             },
             ast::MacroCall(it) => {
-                let src = name.with_value(it);
-                let def = hir::MacroDef::from_source(db, src.clone())?;
-
-                let module_src = ModuleSource::from_child_node(db, src.as_ref().map(|it| it.syntax()));
-                let module = Module::from_definition(db, src.with_value(module_src))?;
+                let module_src = ModuleSource::from_child_node(db, name.with_value(it.syntax()));
+                let module = Module::from_definition(db, name.with_value(module_src))?;
+                let def = hir::MacroDef::from_source(db, name.with_value(Either::Left(it)))?;
 
                 Some(NameDefinition {
                     visibility: None,
@@ -110,6 +112,14 @@ pub(crate) fn classify_name(db: &RootDatabase, name: InFile<&ast::Name>) -> Opti
                     kind: NameKind::Macro(def),
                 })
             },
+            ast::MacroDef(it) => {
+                let module_src = ModuleSource::from_child_node(db, name.with_value(it.syntax()));
+                let module = Module::from_definition(db, name.with_value(module_src))?;
+                let visibility = it.visibility();
+                let def = hir::MacroDef::from_source(db, name.with_value(Either::Right(it)))?;
+
+                Some(NameDefinition { visibility, container: module, kind: NameKind::Macro(def) })
+            },
             ast::TypeParam(it) => {
                 let src = name.with_value(it);
                 let def = hir::TypeParam::from_source(db, src)?;
@@ -315,6 +315,7 @@ fn to_symbol(node: &SyntaxNode) -> Option<(SmolStr, SyntaxNodePtr, TextRange)> {
             ast::TypeAliasDef(it) => { decl(it) },
             ast::ConstDef(it) => { decl(it) },
             ast::StaticDef(it) => { decl(it) },
+            ast::MacroDef(it) => { decl(it) },
             _ => None,
         }
     }
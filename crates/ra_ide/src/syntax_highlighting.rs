@@ -2,10 +2,12 @@
 
 use rustc_hash::{FxHashMap, FxHashSet};
 
-use hir::{InFile, Name};
+use hir::{descend_into_macros, InFile, Name};
 use ra_db::SourceDatabase;
 use ra_prof::profile;
-use ra_syntax::{ast, AstNode, Direction, SyntaxElement, SyntaxKind, SyntaxKind::*, TextRange, T};
+use ra_syntax::{
+    ast, match_ast, AstNode, Direction, SyntaxElement, SyntaxKind, SyntaxKind::*, TextRange, T,
+};
 
 use crate::{
     db::RootDatabase,
@@ -16,6 +18,20 @@ use crate::{
     FileId,
 };
 
+/// Extra HTML attributes of a token, used to turn the HTML rendering of a file
+/// into a browsable page.
+#[derive(Debug, Clone)]
+pub struct HtmlAnnotation {
+    /// Range of the annotated token.
+    pub range: TextRange,
+    /// Anchor, which other pages can link to.
+    pub id: Option<String>,
+    /// Link target, usually the definition of the token.
+    pub href: Option<String>,
+    /// Text shown on hover, like the type of the token.
+    pub title: Option<String>,
+}
+
 #[derive(Debug)]
 pub struct HighlightedRange {
     pub range: TextRange,
@@ -114,6 +130,38 @@ pub(crate) fn highlight(db: &RootDatabase, file_id: FileId) -> Vec<HighlightedRa
                     }),
                 }
             }
+            IDENT if node.parent().map_or(false, |it| it.kind() == TOKEN_TREE) => {
+                // Identifiers in macro calls are highlighted according to
+                // what they become in the expansion
+                let token = node.as_token().cloned().unwrap();
+                let token = descend_into_macros(db, InFile::new(file_id.into(), token));
+                if !token.file_id.is_macro_file() {
+                    continue;
+                }
+                let name_kind = match_ast! {
+                    match (token.value.parent()) {
+                        ast::NameRef(name_ref) => {
+                            classify_name_ref(db, token.with_value(&name_ref)).map(|d| d.kind)
+                        },
+                        ast::Name(name) => {
+                            classify_name(db, token.with_value(&name)).map(|d| d.kind)
+                        },
+                        _ => None,
+                    }
+                };
+
+                if let Some(Local(local)) = &name_kind {
+                    if let Some(name) = local.name(db) {
+                        let shadow_count = bindings_shadow_count.entry(name.clone()).or_default();
+                        binding_hash = Some(calc_binding_hash(file_id, &name, *shadow_count))
+                    }
+                };
+
+                match name_kind {
+                    Some(name_kind) => highlight_name(db, name_kind),
+                    None => continue,
+                }
+            }
             INT_NUMBER | FLOAT_NUMBER | CHAR | BYTE => "literal",
             LIFETIME => "parameter",
             T![unsafe] => "keyword.unsafe",
@@ -150,8 +198,15 @@ pub(crate) fn highlight(db: &RootDatabase, file_id: FileId) -> Vec<HighlightedRa
     res
 }
 
-pub(crate) fn highlight_as_html(db: &RootDatabase, file_id: FileId, rainbow: bool) -> String {
+pub(crate) fn highlight_as_html(
+    db: &RootDatabase,
+    file_id: FileId,
+    rainbow: bool,
+    annotations: &[HtmlAnnotation],
+) -> String {
     let parse = db.parse(file_id);
+    let annotations: FxHashMap<TextRange, &HtmlAnnotation> =
+        annotations.iter().map(|it| (it.range, it)).collect();
 
     fn rainbowify(seed: u64) -> String {
         use rand::prelude::*;
@@ -189,6 +244,10 @@ pub(crate) fn highlight_as_html(db: &RootDatabase, file_id: FileId, rainbow: boo
             .iter()
             .filter(|it| token.text_range().is_subrange(&it.range))
             .collect::<Vec<_>>();
+        let annotation = annotations.get(&token.text_range());
+        if let Some(annotation) = annotation {
+            push_annotation_start(&mut buf, annotation);
+        }
         if ranges.is_empty() {
             buf.push_str(&text);
         } else {
@@ -204,11 +263,25 @@ pub(crate) fn highlight_as_html(db: &RootDatabase, file_id: FileId, rainbow: boo
             };
             buf.push_str(&format!("<span class=\"{}\"{}>{}</span>", classes, color, text));
         }
+        if let Some(annotation) = annotation {
+            buf.push_str(if annotation.href.is_some() { "</a>" } else { "</span>" });
+        }
     }
     buf.push_str("</code></pre>");
     buf
 }
 
+fn push_annotation_start(buf: &mut String, annotation: &HtmlAnnotation) {
+    buf.push_str(if annotation.href.is_some() { "<a" } else { "<span" });
+    let attrs = [("id", &annotation.id), ("href", &annotation.href), ("title", &annotation.title)];
+    for (name, value) in attrs.iter() {
+        if let Some(value) = value {
+            buf.push_str(&format!(" {}=\"{}\"", name, html_escape_attr(value)));
+        }
+    }
+    buf.push('>');
+}
+
 fn highlight_name(db: &RootDatabase, name_kind: NameKind) -> &'static str {
     match name_kind {
         Macro(_) => "macro",
@@ -243,6 +316,10 @@ fn html_escape(text: &str) -> String {
     text.replace("<", "&lt;").replace(">", "&gt;")
 }
 
+fn html_escape_attr(text: &str) -> String {
+    html_escape(&text.replace("&", "&amp;")).replace("\"", "&quot;")
+}
+
 const STYLE: &str = "
 <style>
 body                { margin: 0; }
@@ -269,6 +346,9 @@ pre                 { color: #DCDCCC; background: #3F3F3F; font-size: 22px; padd
 
 #[cfg(test)]
 mod tests {
+    use ra_syntax::TextRange;
+
+    use super::HtmlAnnotation;
     use crate::mock_analysis::single_file;
     use test_utils::{assert_eq_text, project_dir, read_text};
 
@@ -346,4 +426,29 @@ fn bar() {
         std::fs::write(dst_file, &actual_html).unwrap();
         assert_eq_text!(expected_html, actual_html);
     }
+
+    #[test]
+    fn test_highlighting_with_annotations() {
+        let (analysis, file_id) = single_file("fn foo() {}\nfn main() { foo(); }");
+        let annotations = vec![
+            HtmlAnnotation {
+                range: TextRange::from_to(3.into(), 6.into()),
+                id: Some("3".to_string()),
+                href: None,
+                title: None,
+            },
+            HtmlAnnotation {
+                range: TextRange::from_to(24.into(), 27.into()),
+                id: None,
+                href: Some("#3".to_string()),
+                title: Some("fn foo() -> \"<()>\"".to_string()),
+            },
+        ];
+        let html =
+            analysis.highlight_as_html_with_annotations(file_id, false, &annotations).unwrap();
+        assert!(html.contains(r#"<span id="3"><span class="function">foo</span></span>"#));
+        assert!(html.contains(
+            r##"<a href="#3" title="fn foo() -&gt; &quot;&lt;()&gt;&quot;"><span class="function">foo</span></a>"##
+        ));
+    }
 }
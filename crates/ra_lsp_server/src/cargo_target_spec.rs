@@ -73,7 +73,7 @@ impl CargoTargetSpec {
                     target_kind: tgt.kind(&cargo),
                 })
             }
-            ProjectWorkspace::Json { .. } => None,
+            ProjectWorkspace::Json { .. } | ProjectWorkspace::DetachedFiles { .. } => None,
         });
         Ok(res)
     }
@@ -7,6 +7,7 @@
 //! configure the server itself, feature flags are passed into analysis, and
 //! tweak things like automatic insertion of `()` in completions.
 
+use ra_project_model::CargoConfig;
 use rustc_hash::FxHashMap;
 
 use serde::{Deserialize, Deserializer};
@@ -27,6 +28,13 @@ pub struct ServerConfig {
     #[serde(deserialize_with = "nullable_bool_false")]
     pub use_client_watching: bool,
 
+    /// Whether files ignored by `.gitignore`, `.ignore` and `.git/info/exclude`
+    /// should be excluded from analysis.
+    ///
+    /// Defaults to `true`
+    #[serde(deserialize_with = "nullable_bool_true")]
+    pub use_ignore_files: bool,
+
     pub lru_capacity: Option<usize>,
 
     pub max_inlay_hint_length: Option<usize>,
@@ -37,6 +45,19 @@ pub struct ServerConfig {
 
     /// Fine grained feature flags to disable specific features.
     pub feature_flags: FxHashMap<String, bool>,
+
+    /// Cargo configuration, e.g. whether to load build script outputs.
+    pub cargo: CargoConfig,
+
+    /// Command which prints `rust-project.json` for the paths given as its
+    /// trailing arguments. If set, it is used instead of looking for
+    /// `Cargo.toml`, and it is re-run for each batch of opened files which
+    /// don't belong to any known crate.
+    pub discover_project_command: Option<Vec<String>>,
+
+    /// Path to the proc macro server executable. Proc macros are not expanded
+    /// if this is not set.
+    pub proc_macro_srv: Option<String>,
 }
 
 impl Default for ServerConfig {
@@ -45,10 +66,14 @@ impl Default for ServerConfig {
             publish_decorations: false,
             exclude_globs: Vec::new(),
             use_client_watching: false,
+            use_ignore_files: true,
             lru_capacity: None,
             max_inlay_hint_length: None,
             with_sysroot: true,
             feature_flags: FxHashMap::default(),
+            cargo: CargoConfig::default(),
+            discover_project_command: None,
+            proc_macro_srv: None,
         }
     }
 }
@@ -85,4 +110,26 @@ mod test {
             serde_json::from_str(r#"{"publishDecorations":null, "lruCapacity":null}"#).unwrap()
         );
     }
+
+    #[test]
+    fn deserialize_cargo_options() {
+        let config: ServerConfig = serde_json::from_str(
+            r#"{"cargo": {"noDefaultFeatures": true, "allFeatures": false, "features": ["foo", "bar"], "target": "wasm32-unknown-unknown"}}"#,
+        )
+        .unwrap();
+        assert!(config.cargo.no_default_features);
+        assert!(!config.cargo.all_features);
+        assert_eq!(config.cargo.features, vec!["foo".to_string(), "bar".to_string()]);
+        assert_eq!(
+            config.cargo.target.as_ref().map(String::as_str),
+            Some("wasm32-unknown-unknown")
+        );
+    }
+
+    #[test]
+    fn deserialize_cargo_options_defaults() {
+        let config: ServerConfig = serde_json::from_str(r#"{"cargo": {}}"#).unwrap();
+        assert!(config.cargo.all_features);
+        assert_eq!(config.cargo, CargoConfig::default());
+    }
 }
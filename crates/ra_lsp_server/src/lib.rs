@@ -28,6 +28,7 @@ mod main_loop;
 mod markdown;
 pub mod req;
 mod config;
+mod vfs;
 mod world;
 
 pub type Result<T> = std::result::Result<T, Box<dyn std::error::Error + Send + Sync>>;
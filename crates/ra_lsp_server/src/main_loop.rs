@@ -4,14 +4,22 @@ mod handlers;
 mod subscriptions;
 pub(crate) mod pending_requests;
 
-use std::{error::Error, fmt, panic, path::PathBuf, sync::Arc, time::Instant};
+use std::{
+    error::Error,
+    fmt, panic,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Instant,
+};
 
 use crossbeam_channel::{select, unbounded, RecvError, Sender};
 use lsp_server::{Connection, ErrorCode, Message, Notification, Request, RequestId, Response};
 use lsp_types::{ClientCapabilities, NumberOrString};
 use ra_ide::{Canceled, FeatureFlags, FileId, LibraryData, SourceRootId};
+use ra_proc_macro::ProcMacroClient;
 use ra_prof::profile;
-use ra_vfs::{VfsTask, Watch};
+use ra_project_model::{BuildOutputs, CargoConfig, JsonProject, ProjectWorkspace};
+use ra_vfs::Watch;
 use relative_path::RelativePathBuf;
 use rustc_hash::FxHashSet;
 use serde::{de::DeserializeOwned, Serialize};
@@ -23,6 +31,7 @@ use crate::{
         subscriptions::Subscriptions,
     },
     req,
+    vfs::VfsTask,
     world::{Options, WorldSnapshot, WorldState},
     Result, ServerConfig,
 };
@@ -59,15 +68,15 @@ pub fn main_loop(
     log::info!("server_config: {:#?}", config);
 
     let mut loop_state = LoopState::default();
+    loop_state.project_config = ProjectConfig {
+        with_sysroot: config.with_sysroot,
+        cargo: config.cargo.clone(),
+        discover_command: config.discover_project_command.clone(),
+    };
     let mut world_state = {
-        // FIXME: support dynamic workspace loading.
         let workspaces = {
             let mut loaded_workspaces = Vec::new();
-            for ws_root in &ws_roots {
-                let workspace = ra_project_model::ProjectWorkspace::discover_with_sysroot(
-                    ws_root.as_path(),
-                    config.with_sysroot,
-                );
+            for workspace in discover_workspaces(&ws_roots, &[], &loop_state.project_config) {
                 match workspace {
                     Ok(workspace) => loaded_workspaces.push(workspace),
                     Err(e) => {
@@ -91,24 +100,8 @@ pub fn main_loop(
             .collect::<std::result::Result<Vec<_>, _>>()?;
 
         if config.use_client_watching {
-            let registration_options = req::DidChangeWatchedFilesRegistrationOptions {
-                watchers: workspaces
-                    .iter()
-                    .flat_map(|ws| ws.to_roots())
-                    .filter(|root| root.is_member())
-                    .map(|root| format!("{}/**/*.rs", root.path().display()))
-                    .map(|glob_pattern| req::FileSystemWatcher { glob_pattern, kind: None })
-                    .collect(),
-            };
-            let registration = req::Registration {
-                id: "file-watcher".to_string(),
-                method: "workspace/didChangeWatchedFiles".to_string(),
-                register_options: Some(serde_json::to_value(registration_options).unwrap()),
-            };
-            let params = req::RegistrationParams { registrations: vec![registration] };
-            let request =
-                request_new::<req::RegisterCapability>(loop_state.next_request_id(), params);
-            connection.sender.send(request.into()).unwrap();
+            let globs = file_watcher_globs(&ws_roots, &workspaces);
+            register_file_watcher(&connection.sender, &mut loop_state, globs);
         }
 
         let options = {
@@ -143,14 +136,30 @@ pub fn main_loop(
         };
         log::info!("feature_flags: {:#?}", feature_flags);
 
+        let proc_macro_client = match &config.proc_macro_srv {
+            None => ProcMacroClient::dummy(),
+            Some(srv) => match ProcMacroClient::extern_process(Path::new(srv)) {
+                Ok(it) => it,
+                Err(err) => {
+                    let msg = format!("failed to start proc macro server {:?}: {}", srv, err);
+                    log::error!("{}", msg);
+                    show_message(req::MessageType::Error, msg, &connection.sender);
+                    ProcMacroClient::dummy()
+                }
+            },
+        };
+
         WorldState::new(
             ws_roots,
             workspaces,
+            config.cargo.target.clone(),
             config.lru_capacity,
             &globs,
+            config.use_ignore_files,
             Watch(!config.use_client_watching),
             options,
             feature_flags,
+            proc_macro_client,
         )
     };
 
@@ -158,6 +167,13 @@ pub fn main_loop(
     let (task_sender, task_receiver) = unbounded::<Task>();
     let (libdata_sender, libdata_receiver) = unbounded::<LibraryData>();
 
+    load_build_outputs_on_threadpool(
+        &pool,
+        &task_sender,
+        &world_state.workspaces,
+        &loop_state.project_config,
+    );
+
     log::info!("server initialized, serving requests");
     {
         let task_sender = task_sender;
@@ -213,6 +229,10 @@ pub fn main_loop(
 enum Task {
     Respond(Response),
     Notify(Notification),
+    Workspaces(std::result::Result<Vec<ProjectWorkspace>, String>),
+    DiscoveredProject(Vec<PathBuf>, std::result::Result<JsonProject, String>),
+    DetachedFile(PathBuf, Option<PathBuf>, std::result::Result<ProjectWorkspace, String>),
+    BuildOutputs(Vec<std::result::Result<BuildOutputs, String>>),
 }
 
 enum Event {
@@ -272,6 +292,29 @@ struct LoopState {
     in_flight_libraries: usize,
     pending_libraries: Vec<(SourceRootId, Vec<(FileId, RelativePathBuf, Arc<String>)>)>,
     workspace_loaded: bool,
+    // Workspaces are reloaded in the background after a manifest changes, and
+    // applied once no libraries are being indexed.
+    workspace_reload_in_progress: bool,
+    pending_workspaces: Option<Vec<ProjectWorkspace>>,
+    project_config: ProjectConfig,
+    // The files the client watches for us, if it does.
+    file_watcher_globs: Option<Vec<String>>,
+    // Files opened since the last turn, which might not belong to any known
+    // crate yet, and the files which the discovery command was run for.
+    opened_files: Vec<PathBuf>,
+    checked_files: FxHashSet<PathBuf>,
+    discovered_files: Vec<PathBuf>,
+    detached_files: Vec<PathBuf>,
+    // Directories added to the VFS for detached files outside of all roots.
+    detached_roots: Vec<PathBuf>,
+}
+
+/// Settings controlling how `ProjectWorkspace`s are loaded.
+#[derive(Debug, Clone, Default)]
+struct ProjectConfig {
+    with_sysroot: bool,
+    cargo: CargoConfig,
+    discover_command: Option<Vec<String>>,
 }
 
 impl LoopState {
@@ -284,6 +327,61 @@ impl LoopState {
     }
 }
 
+const FILE_WATCHER_ID: &str = "file-watcher";
+
+/// The globs of the files the client should watch for us: the sources of the
+/// member packages and the files which affect the project layout.
+fn file_watcher_globs(ws_roots: &[PathBuf], workspaces: &[ProjectWorkspace]) -> Vec<String> {
+    let member_roots = workspaces
+        .iter()
+        .flat_map(|ws| ws.to_roots())
+        .filter(|root| root.is_member())
+        .map(|root| root.path().clone())
+        .collect::<Vec<_>>();
+    let source_globs = member_roots.iter().map(|root| format!("{}/**/*.rs", root.display()));
+    // `.git/info/exclude` is not watched: `.git` is excluded from the
+    // VFS anyway, and it is rarely edited.
+    let manifest_globs = ws_roots.iter().chain(member_roots.iter()).map(|root| {
+        format!(
+            "{}/**/{{Cargo.toml,Cargo.lock,rust-project.json,.gitignore,.ignore}}",
+            root.display()
+        )
+    });
+    source_globs.chain(manifest_globs).collect()
+}
+
+fn register_file_watcher(sender: &Sender<Message>, loop_state: &mut LoopState, globs: Vec<String>) {
+    let registration_options = req::DidChangeWatchedFilesRegistrationOptions {
+        watchers: globs
+            .iter()
+            .map(|glob_pattern| req::FileSystemWatcher {
+                glob_pattern: glob_pattern.clone(),
+                kind: None,
+            })
+            .collect(),
+    };
+    let registration = req::Registration {
+        id: FILE_WATCHER_ID.to_string(),
+        method: "workspace/didChangeWatchedFiles".to_string(),
+        register_options: Some(serde_json::to_value(registration_options).unwrap()),
+    };
+    let params = req::RegistrationParams { registrations: vec![registration] };
+    let request = request_new::<req::RegisterCapability>(loop_state.next_request_id(), params);
+    sender.send(request.into()).unwrap();
+    loop_state.file_watcher_globs = Some(globs);
+}
+
+fn unregister_file_watcher(sender: &Sender<Message>, loop_state: &mut LoopState) {
+    let unregistration = req::Unregistration {
+        id: FILE_WATCHER_ID.to_string(),
+        method: "workspace/didChangeWatchedFiles".to_string(),
+    };
+    let params = req::UnregistrationParams { unregisterations: vec![unregistration] };
+    let request = request_new::<req::UnregisterCapability>(loop_state.next_request_id(), params);
+    sender.send(request.into()).unwrap();
+    loop_state.file_watcher_globs = None;
+}
+
 fn loop_turn(
     pool: &ThreadPool,
     task_sender: &Sender<Task>,
@@ -305,6 +403,113 @@ fn loop_turn(
 
     let mut state_changed = false;
     match event {
+        Event::Task(Task::Workspaces(workspaces)) => {
+            loop_state.workspace_reload_in_progress = false;
+            match workspaces {
+                Ok(mut workspaces) => {
+                    load_build_outputs_on_threadpool(
+                        pool,
+                        task_sender,
+                        &workspaces,
+                        &loop_state.project_config,
+                    );
+                    // Projects discovered and files detached while the reload
+                    // was in flight are not part of its result.
+                    if let Some(pending) = loop_state.pending_workspaces.take() {
+                        merge_pending_workspaces(&mut workspaces, pending);
+                    }
+                    loop_state.pending_workspaces = Some(workspaces);
+                }
+                Err(e) => {
+                    log::error!("reloading workspace failed: {}", e);
+                    show_message(
+                        req::MessageType::Error,
+                        format!("rust-analyzer failed to reload workspace: {}", e),
+                        &connection.sender,
+                    );
+                }
+            }
+        }
+        Event::Task(Task::DiscoveredProject(paths, project)) => match project {
+            Ok(project) => {
+                log::info!("discovered project for {} files", paths.len());
+                loop_state.discovered_files.extend(paths);
+                let mut workspaces = match loop_state.pending_workspaces.take() {
+                    Some(it) => it,
+                    None => world_state.workspaces.as_ref().clone(),
+                };
+                if !workspaces.iter_mut().any(|ws| ws.merge_json_project(&project)) {
+                    let with_sysroot = loop_state.project_config.with_sysroot;
+                    match ProjectWorkspace::from_json_project(project, with_sysroot) {
+                        Ok(ws) => workspaces.push(ws),
+                        Err(e) => log::error!("loading discovered project failed: {}", e),
+                    }
+                }
+                loop_state.pending_workspaces = Some(workspaces);
+            }
+            Err(e) => {
+                log::error!("project discovery failed: {}", e);
+                show_message(
+                    req::MessageType::Error,
+                    format!("rust-analyzer failed to discover project: {}", e),
+                    &connection.sender,
+                );
+            }
+        },
+        Event::Task(Task::DetachedFile(path, root, workspace)) => match workspace {
+            Ok(workspace) => {
+                log::info!("analyzing {} as a detached file", path.display());
+                let mut workspaces = match loop_state.pending_workspaces.take() {
+                    Some(it) => it,
+                    None => world_state.workspaces.as_ref().clone(),
+                };
+                let root_path = root.as_ref().map(PathBuf::as_path);
+                if !workspaces.iter_mut().any(|ws| ws.add_detached_file(&path, root_path)) {
+                    workspaces.push(workspace);
+                }
+                loop_state.detached_files.push(path);
+                loop_state.detached_roots.extend(root);
+                loop_state.pending_workspaces = Some(workspaces);
+            }
+            Err(e) => log::error!("loading detached file {} failed: {}", path.display(), e),
+        },
+        Event::Task(Task::BuildOutputs(outputs)) => {
+            let mut workspaces = match loop_state.pending_workspaces.take() {
+                Some(it) => it,
+                None => world_state.workspaces.as_ref().clone(),
+            };
+            for outputs in outputs {
+                match outputs {
+                    Ok(outputs) => {
+                        if let Some(e) = outputs.error() {
+                            log::error!("running build scripts failed: {}", e);
+                            show_message(
+                                req::MessageType::Warning,
+                                format!(
+                                    "rust-analyzer failed to run the build scripts of {}, \
+                                     generated code may be missing: {}",
+                                    outputs.workspace_root().display(),
+                                    e
+                                ),
+                                &connection.sender,
+                            );
+                        }
+                        workspaces.iter_mut().for_each(|ws| {
+                            ws.set_build_outputs(&outputs);
+                        });
+                    }
+                    Err(e) => {
+                        log::error!("running build scripts failed: {}", e);
+                        show_message(
+                            req::MessageType::Error,
+                            format!("rust-analyzer failed to run build scripts: {}", e),
+                            &connection.sender,
+                        );
+                    }
+                }
+            }
+            loop_state.pending_workspaces = Some(workspaces);
+        }
         Event::Task(task) => {
             on_task(task, &connection.sender, &mut loop_state.pending_requests, world_state);
             world_state.maybe_collect_garbage();
@@ -334,6 +539,7 @@ fn loop_turn(
                     world_state,
                     &mut loop_state.pending_requests,
                     &mut loop_state.subscriptions,
+                    &mut loop_state.opened_files,
                     not,
                 )?;
                 state_changed = true;
@@ -347,7 +553,118 @@ fn loop_turn(
         },
     };
 
+    if loop_state.in_flight_libraries == 0 {
+        if let Some(workspaces) = loop_state.pending_workspaces.take() {
+            world_state.reload_workspaces(workspaces);
+            let pending_libraries =
+                std::mem::replace(&mut loop_state.pending_libraries, Vec::new());
+            for (root, files) in pending_libraries {
+                if world_state.is_removed_root(root) {
+                    world_state.roots_to_scan -= 1;
+                } else {
+                    loop_state.pending_libraries.push((root, files));
+                }
+            }
+            if let Some(old_globs) = &loop_state.file_watcher_globs {
+                let globs = file_watcher_globs(&world_state.roots, &world_state.workspaces);
+                if globs != *old_globs {
+                    unregister_file_watcher(&connection.sender, loop_state);
+                    register_file_watcher(&connection.sender, loop_state, globs);
+                }
+            }
+            state_changed = true;
+        }
+    }
+
     loop_state.pending_libraries.extend(world_state.process_changes());
+    if world_state.workspaces_changed && !loop_state.workspace_reload_in_progress {
+        world_state.workspaces_changed = false;
+        loop_state.workspace_reload_in_progress = true;
+        let ws_roots = world_state.roots.clone();
+        let discovered_files = loop_state.discovered_files.clone();
+        let detached_files = loop_state.detached_files.clone();
+        let detached_roots = loop_state.detached_roots.clone();
+        let project_config = loop_state.project_config.clone();
+        let sender = task_sender.clone();
+        pool.execute(move || {
+            log::info!("reloading workspaces ...");
+            let _p = profile("reload workspaces");
+            let mut workspaces = discover_workspaces(&ws_roots, &discovered_files, &project_config);
+            if !detached_files.is_empty() {
+                workspaces.push(ProjectWorkspace::detached_files(
+                    detached_files,
+                    detached_roots,
+                    project_config.with_sysroot,
+                ));
+            }
+            let workspaces = workspaces
+                .into_iter()
+                .collect::<ra_project_model::Result<Vec<_>>>()
+                .map_err(|e| e.to_string());
+            sender.send(Task::Workspaces(workspaces)).unwrap();
+        });
+    }
+    // Opened files which don't belong to any crate are either passed to the
+    // discovery command, or analyzed as detached files. Until the workspace is
+    // loaded, we can't tell whether a file belongs to a crate.
+    let opened_files = if loop_state.workspace_loaded && loop_state.pending_workspaces.is_none() {
+        std::mem::replace(&mut loop_state.opened_files, Vec::new())
+    } else {
+        Vec::new()
+    };
+    let opened_files = opened_files
+        .into_iter()
+        .filter(|path| loop_state.checked_files.insert(path.clone()))
+        .collect::<Vec<_>>();
+    if !opened_files.is_empty() {
+        let world = world_state.snapshot();
+        let config = loop_state.project_config.clone();
+        let sender = task_sender.clone();
+        pool.execute(move || {
+            let vfs = world.vfs.read();
+            let mut orphans = Vec::new();
+            for path in opened_files {
+                let file_id = vfs.path2file(&path).map(|it| FileId(it.0));
+                let has_crate = file_id
+                    .and_then(|file_id| world.analysis().crate_for(file_id).ok())
+                    .map_or(false, |crates| !crates.is_empty());
+                if !has_crate {
+                    orphans.push((path, file_id));
+                }
+            }
+            drop(vfs);
+            match &config.discover_command {
+                // The whole batch is discovered with a single run.
+                Some(command) if !orphans.is_empty() => {
+                    let paths = orphans.into_iter().map(|(path, _)| path).collect::<Vec<_>>();
+                    log::info!("discovering project for {} files ...", paths.len());
+                    let project = ra_project_model::run_discover_command(command, &paths)
+                        .map_err(|e| e.to_string());
+                    sender.send(Task::DiscoveredProject(paths, project)).unwrap();
+                }
+                Some(_) => (),
+                None => {
+                    for (path, file_id) in orphans {
+                        // The VFS can't watch individual files, so the
+                        // directory of a file outside of all roots becomes a
+                        // new root.
+                        let root = match file_id {
+                            Some(_) => None,
+                            None => path.parent().map(Path::to_path_buf),
+                        };
+                        let workspace = ProjectWorkspace::detached_files(
+                            vec![path.clone()],
+                            root.iter().cloned().collect(),
+                            config.with_sysroot,
+                        )
+                        .map_err(|e| e.to_string());
+                        sender.send(Task::DetachedFile(path, root, workspace)).unwrap();
+                    }
+                }
+            }
+        });
+    }
+
     while loop_state.in_flight_libraries < MAX_IN_FLIGHT_LIBS
         && !loop_state.pending_libraries.is_empty()
     {
@@ -404,6 +721,92 @@ fn on_task(
         Task::Notify(n) => {
             msg_sender.send(n.into()).unwrap();
         }
+        // Workspace reloads are handled in `loop_turn`, and are not
+        // interesting during shutdown.
+        Task::Workspaces(_)
+        | Task::DiscoveredProject(..)
+        | Task::DetachedFile(..)
+        | Task::BuildOutputs(_) => (),
+    }
+}
+
+/// Runs `cargo check` for the cargo workspaces in the background, if enabled,
+/// to load the outputs of their build scripts.
+fn load_build_outputs_on_threadpool(
+    pool: &ThreadPool,
+    task_sender: &Sender<Task>,
+    workspaces: &[ProjectWorkspace],
+    config: &ProjectConfig,
+) {
+    if !config.cargo.load_out_dirs_from_check {
+        return;
+    }
+    let workspaces = workspaces.to_vec();
+    let cargo_config = config.cargo.clone();
+    let sender = task_sender.clone();
+    pool.execute(move || {
+        log::info!("loading build script outputs ...");
+        let _p = profile("load build outputs");
+        let outputs = workspaces
+            .iter()
+            .filter_map(|ws| ws.load_build_outputs(&cargo_config))
+            .map(|it| it.map_err(|e| e.to_string()))
+            .collect();
+        sender.send(Task::BuildOutputs(outputs)).unwrap();
+    });
+}
+
+/// Adds the discovered projects and detached files of the `pending`
+/// workspaces to the freshly loaded `workspaces`.
+fn merge_pending_workspaces(
+    workspaces: &mut Vec<ProjectWorkspace>,
+    pending: Vec<ProjectWorkspace>,
+) {
+    for ws in pending {
+        match &ws {
+            ProjectWorkspace::Json { project, .. } => {
+                if !workspaces.iter_mut().any(|it| it.merge_json_project(project)) {
+                    workspaces.push(ws);
+                }
+            }
+            ProjectWorkspace::DetachedFiles { files, roots, .. } => {
+                match workspaces.iter_mut().find(|it| match it {
+                    ProjectWorkspace::DetachedFiles { .. } => true,
+                    _ => false,
+                }) {
+                    Some(it) => files.iter().for_each(|file| {
+                        let root = roots.iter().find(|root| file.parent() == Some(root.as_path()));
+                        it.add_detached_file(file, root.map(PathBuf::as_path));
+                    }),
+                    None => workspaces.push(ws),
+                }
+            }
+            // Cargo workspaces are always reloaded from scratch.
+            ProjectWorkspace::Cargo { .. } => (),
+        }
+    }
+}
+
+fn discover_workspaces(
+    ws_roots: &[PathBuf],
+    discovered_files: &[PathBuf],
+    config: &ProjectConfig,
+) -> Vec<ra_project_model::Result<ProjectWorkspace>> {
+    match &config.discover_command {
+        Some(command) => {
+            let paths = ws_roots.iter().chain(discovered_files).cloned().collect::<Vec<_>>();
+            vec![ProjectWorkspace::discover_with_command(command, &paths, config.with_sysroot)]
+        }
+        None => ws_roots
+            .iter()
+            .map(|ws_root| {
+                ProjectWorkspace::discover_with_sysroot(
+                    ws_root.as_path(),
+                    config.with_sysroot,
+                    &config.cargo,
+                )
+            })
+            .collect(),
     }
 }
 
@@ -438,6 +841,7 @@ fn on_request(
         .on::<req::AnalyzerStatus>(handlers::handle_analyzer_status)?
         .on::<req::SyntaxTree>(handlers::handle_syntax_tree)?
         .on::<req::ExpandMacro>(handlers::handle_expand_macro)?
+        .on::<req::DebugMacroExpansion>(handlers::handle_debug_macro_expansion)?
         .on::<req::OnTypeFormatting>(handlers::handle_on_type_formatting)?
         .on::<req::DocumentSymbolRequest>(handlers::handle_document_symbol)?
         .on::<req::WorkspaceSymbol>(handlers::handle_workspace_symbol)?
@@ -469,6 +873,7 @@ fn on_notification(
     state: &mut WorldState,
     pending_requests: &mut PendingRequests,
     subs: &mut Subscriptions,
+    opened_files: &mut Vec<PathBuf>,
     not: Notification,
 ) -> Result<()> {
     let not = match notification_cast::<req::Cancel>(not) {
@@ -493,11 +898,10 @@ fn on_notification(
         Ok(params) => {
             let uri = params.text_document.uri;
             let path = uri.to_file_path().map_err(|()| format!("invalid uri: {}", uri))?;
-            if let Some(file_id) =
-                state.vfs.write().add_file_overlay(&path, params.text_document.text)
-            {
+            if let Some(file_id) = state.add_file_overlay(&path, params.text_document.text) {
                 subs.add_sub(FileId(file_id.0));
             }
+            opened_files.push(path);
             return Ok(());
         }
         Err(not) => not,
@@ -508,7 +912,7 @@ fn on_notification(
             let path = uri.to_file_path().map_err(|()| format!("invalid uri: {}", uri))?;
             let text =
                 params.content_changes.pop().ok_or_else(|| "empty changes".to_string())?.text;
-            state.vfs.write().change_file_overlay(path.as_path(), text);
+            state.change_file_overlay(path.as_path(), text);
             return Ok(());
         }
         Err(not) => not,
@@ -517,7 +921,7 @@ fn on_notification(
         Ok(params) => {
             let uri = params.text_document.uri;
             let path = uri.to_file_path().map_err(|()| format!("invalid uri: {}", uri))?;
-            if let Some(file_id) = state.vfs.write().remove_file_overlay(path.as_path()) {
+            if let Some(file_id) = state.remove_file_overlay(path.as_path()) {
                 subs.remove_sub(FileId(file_id.0));
             }
             let params = req::PublishDiagnosticsParams { uri, diagnostics: Vec::new() };
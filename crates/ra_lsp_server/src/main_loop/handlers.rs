@@ -6,8 +6,9 @@ use lsp_server::ErrorCode;
 use lsp_types::{
     CodeAction, CodeActionResponse, CodeLens, Command, CompletionItem, Diagnostic,
     DocumentFormattingParams, DocumentHighlight, DocumentSymbol, FoldingRange, FoldingRangeParams,
-    Hover, HoverContents, Location, MarkupContent, MarkupKind, Position, PrepareRenameResponse,
-    Range, RenameParams, SymbolInformation, TextDocumentIdentifier, TextEdit, WorkspaceEdit,
+    Hover, HoverContents, Location, MarkupContent, MarkupKind, NumberOrString, Position,
+    PrepareRenameResponse, Range, RenameParams, SymbolInformation, TextDocumentIdentifier,
+    TextEdit, WorkspaceEdit,
 };
 use ra_ide::{
     AssistId, FileId, FilePosition, FileRange, Query, Runnable, RunnableKind, SearchScope,
@@ -65,6 +66,46 @@ pub fn handle_expand_macro(
     }
 }
 
+pub fn handle_debug_macro_expansion(
+    world: WorldSnapshot,
+    params: req::ExpandMacroParams,
+) -> Result<Option<req::MacroExpansionTrace>> {
+    let _p = profile("handle_debug_macro_expansion");
+    let file_id = params.text_document.try_conv_with(&world)?;
+    let line_index = world.analysis().file_line_index(file_id)?;
+    let offset = match params.position {
+        Some(it) => it.conv_with(&line_index),
+        None => return Ok(None),
+    };
+
+    let trace = match world.analysis().debug_macro_expansion(FilePosition { file_id, offset })? {
+        Some(it) => it,
+        None => return Ok(None),
+    };
+    let rules = trace
+        .rules
+        .into_iter()
+        .map(|rule| req::MacroRuleTrace {
+            index: rule.index,
+            pattern: rule.pattern,
+            matched: rule.matched,
+            error: rule.error,
+            unmatched: rule.unmatched,
+            bindings: rule
+                .bindings
+                .into_iter()
+                .map(|it| req::MacroBindingTrace { name: it.name, value: it.value })
+                .collect(),
+            expansion: rule.expansion,
+        })
+        .collect();
+    let (expansion, error) = match trace.result {
+        Ok(it) => (Some(it), None),
+        Err(err) => (None, Some(err)),
+    };
+    Ok(Some(req::MacroExpansionTrace { name: trace.name, rules, expansion, error }))
+}
+
 pub fn handle_selection_range(
     world: WorldSnapshot,
     params: req::SelectionRangeParams,
@@ -824,7 +865,7 @@ pub fn publish_diagnostics(
         .map(|d| Diagnostic {
             range: d.range.conv_with(&line_index),
             severity: Some(d.severity.conv()),
-            code: None,
+            code: Some(NumberOrString::String(d.code.to_string())),
             source: Some("rust-analyzer".to_string()),
             message: d.message,
             related_information: None,
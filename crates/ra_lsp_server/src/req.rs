@@ -11,7 +11,8 @@ pub use lsp_types::{
     DocumentOnTypeFormattingParams, DocumentSymbolParams, DocumentSymbolResponse,
     FileSystemWatcher, Hover, InitializeResult, MessageType, PublishDiagnosticsParams,
     ReferenceParams, Registration, RegistrationParams, ShowMessageParams, SignatureHelp,
-    TextDocumentEdit, TextDocumentPositionParams, TextEdit, WorkspaceEdit, WorkspaceSymbolParams,
+    TextDocumentEdit, TextDocumentPositionParams, TextEdit, Unregistration, UnregistrationParams,
+    WorkspaceEdit, WorkspaceSymbolParams,
 };
 
 pub enum AnalyzerStatus {}
@@ -67,6 +68,42 @@ pub struct ExpandMacroParams {
     pub position: Option<Position>,
 }
 
+pub enum DebugMacroExpansion {}
+
+impl Request for DebugMacroExpansion {
+    type Params = ExpandMacroParams;
+    type Result = Option<MacroExpansionTrace>;
+    const METHOD: &'static str = "rust-analyzer/debugMacroExpansion";
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct MacroExpansionTrace {
+    pub name: String,
+    pub rules: Vec<MacroRuleTrace>,
+    pub expansion: Option<String>,
+    pub error: Option<String>,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct MacroRuleTrace {
+    pub index: usize,
+    pub pattern: String,
+    pub matched: bool,
+    pub error: Option<String>,
+    pub unmatched: Option<String>,
+    pub bindings: Vec<MacroBindingTrace>,
+    pub expansion: Option<String>,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct MacroBindingTrace {
+    pub name: String,
+    pub value: String,
+}
+
 pub enum SelectionRangeRequest {}
 
 impl Request for SelectionRangeRequest {
@@ -0,0 +1,169 @@
+//! The VFS of the language server.
+//!
+//! `ra_vfs::Vfs` can't add roots once it is created, so the roots which appear
+//! when the workspaces are reloaded get an `ra_vfs::Vfs` of their own. The ids
+//! of the files and roots of each part are tagged with the index of the part,
+//! so the ids of the existing files, and with them the caches of the analysis,
+//! stay valid.
+
+use std::path::{Path, PathBuf};
+
+use crossbeam_channel::Sender;
+use ra_vfs::{LineEndings, RootEntry, VfsChange, VfsFile, VfsRoot, Watch};
+
+/// The ids of a single `ra_vfs::Vfs` are below `1 << PART_SHIFT`, the index
+/// of the part is stored in the bits above.
+const PART_SHIFT: u32 = 24;
+
+/// A task of the part with the given index.
+#[derive(Debug)]
+pub struct VfsTask(usize, ra_vfs::VfsTask);
+
+#[derive(Debug)]
+pub struct Vfs {
+    parts: Vec<ra_vfs::Vfs>,
+    task_sender: Sender<VfsTask>,
+    watch: bool,
+}
+
+impl Vfs {
+    pub fn new(
+        roots: Vec<RootEntry>,
+        task_sender: Sender<VfsTask>,
+        watch: Watch,
+    ) -> (Vfs, Vec<VfsRoot>) {
+        let mut vfs = Vfs { parts: Vec::new(), task_sender, watch: watch.0 };
+        let roots = vfs.add_roots(roots);
+        (vfs, roots)
+    }
+
+    /// Starts loading `roots` in a new part. They should not overlap with the
+    /// existing roots, as the files would end up in both.
+    pub fn add_roots(&mut self, roots: Vec<RootEntry>) -> Vec<VfsRoot> {
+        let part = self.parts.len();
+        let task_sender = self.task_sender.clone();
+        let task_sender = Box::new(move |task| {
+            // The receiver is gone if the world state was dropped while a
+            // snapshot kept the `Vfs` alive, it's fine to drop the task then.
+            let _ = task_sender.send(VfsTask(part, task));
+        });
+        let (vfs, roots) = ra_vfs::Vfs::new(roots, task_sender, Watch(self.watch));
+        self.parts.push(vfs);
+        roots.into_iter().map(|root| VfsRoot(tag(part, root.0))).collect()
+    }
+
+    /// All the roots, in the order they were added.
+    pub fn roots(&self) -> Vec<VfsRoot> {
+        self.parts
+            .iter()
+            .enumerate()
+            .flat_map(|(part, vfs)| {
+                (0..vfs.n_roots()).map(move |root| VfsRoot(tag(part, root as u32)))
+            })
+            .collect()
+    }
+
+    pub fn root2path(&self, root: VfsRoot) -> PathBuf {
+        let (part, root) = untag(root.0);
+        self.parts[part].root2path(VfsRoot(root))
+    }
+
+    pub fn file2path(&self, file: VfsFile) -> PathBuf {
+        let (part, file) = untag(file.0);
+        self.parts[part].file2path(VfsFile(file))
+    }
+
+    pub fn file_line_endings(&self, file: VfsFile) -> LineEndings {
+        let (part, file) = untag(file.0);
+        self.parts[part].file_line_endings(VfsFile(file))
+    }
+
+    /// A root which was removed from the workspaces and added back again is
+    /// in two parts, so the newer parts take precedence for the lookups by
+    /// path.
+    pub fn path2file(&self, path: &Path) -> Option<VfsFile> {
+        self.parts
+            .iter()
+            .enumerate()
+            .rev()
+            .find_map(|(part, vfs)| vfs.path2file(path).map(|file| VfsFile(tag(part, file.0))))
+    }
+
+    pub fn load(&mut self, path: &Path) -> Option<VfsFile> {
+        self.find_part_mut(|vfs| vfs.load(path))
+    }
+
+    pub fn add_file_overlay(&mut self, path: &Path, text: String) -> Option<VfsFile> {
+        self.find_part_mut(|vfs| vfs.add_file_overlay(path, text.clone()))
+    }
+
+    pub fn change_file_overlay(&mut self, path: &Path, new_text: String) {
+        if let Some(file) = self.path2file(path) {
+            let (part, _) = untag(file.0);
+            self.parts[part].change_file_overlay(path, new_text);
+        }
+    }
+
+    pub fn remove_file_overlay(&mut self, path: &Path) -> Option<VfsFile> {
+        self.find_part_mut(|vfs| vfs.remove_file_overlay(path))
+    }
+
+    pub fn notify_changed(&mut self, path: PathBuf) {
+        for vfs in self.parts.iter_mut() {
+            vfs.notify_changed(path.clone());
+        }
+    }
+
+    pub fn handle_task(&mut self, task: VfsTask) {
+        let VfsTask(part, task) = task;
+        self.parts[part].handle_task(task);
+    }
+
+    pub fn commit_changes(&mut self) -> Vec<VfsChange> {
+        let mut res = Vec::new();
+        for (part, vfs) in self.parts.iter_mut().enumerate() {
+            res.extend(vfs.commit_changes().into_iter().map(|change| tag_change(part, change)));
+        }
+        res
+    }
+
+    /// Calls `f` on the parts, starting with the newest, until it returns a
+    /// file.
+    fn find_part_mut(
+        &mut self,
+        mut f: impl FnMut(&mut ra_vfs::Vfs) -> Option<VfsFile>,
+    ) -> Option<VfsFile> {
+        self.parts
+            .iter_mut()
+            .enumerate()
+            .rev()
+            .find_map(|(part, vfs)| f(vfs).map(|file| VfsFile(tag(part, file.0))))
+    }
+}
+
+fn tag(part: usize, id: u32) -> u32 {
+    assert!(id < 1 << PART_SHIFT, "too many files or roots in the VFS");
+    (part as u32) << PART_SHIFT | id
+}
+
+fn untag(id: u32) -> (usize, u32) {
+    ((id >> PART_SHIFT) as usize, id & ((1 << PART_SHIFT) - 1))
+}
+
+fn tag_change(part: usize, change: VfsChange) -> VfsChange {
+    let root = |root: VfsRoot| VfsRoot(tag(part, root.0));
+    let file = |file: VfsFile| VfsFile(tag(part, file.0));
+    match change {
+        VfsChange::AddRoot { root: r, files } => VfsChange::AddRoot {
+            root: root(r),
+            files: files.into_iter().map(|(f, path, text)| (file(f), path, text)).collect(),
+        },
+        VfsChange::AddFile { root: r, file: f, path, text } => {
+            VfsChange::AddFile { root: root(r), file: file(f), path, text }
+        }
+        VfsChange::RemoveFile { root: r, file: f, path } => {
+            VfsChange::RemoveFile { root: root(r), file: file(f), path }
+        }
+        VfsChange::ChangeFile { file: f, text } => VfsChange::ChangeFile { file: file(f), text },
+    }
+}
@@ -1,6 +1,7 @@
 //! FIXME: write short doc here
 
 use std::{
+    fmt, fs,
     path::{Path, PathBuf},
     sync::Arc,
 };
@@ -10,16 +11,21 @@ use lsp_server::ErrorCode;
 use lsp_types::Url;
 use parking_lot::RwLock;
 use ra_ide::{
-    Analysis, AnalysisChange, AnalysisHost, CrateGraph, FeatureFlags, FileId, LibraryData,
-    SourceRootId,
+    Analysis, AnalysisChange, AnalysisHost, CrateGraph, ExternSourceId, FeatureFlags, FileId,
+    LibraryData, SourceRootId,
 };
+use ra_proc_macro::ProcMacroClient;
 use ra_project_model::{get_rustc_cfg_options, ProjectWorkspace};
-use ra_vfs::{LineEndings, RootEntry, Vfs, VfsChange, VfsFile, VfsRoot, VfsTask, Watch};
-use ra_vfs_glob::{Glob, RustPackageFilterBuilder};
-use relative_path::RelativePathBuf;
+use ra_vfs::{Filter, LineEndings, RootEntry, VfsChange, VfsFile, VfsRoot, Watch};
+use ra_vfs_glob::{
+    is_ignore_file, is_workspace_manifest, Glob, IgnoreFiles, RustPackageFilterBuilder,
+};
+use relative_path::{RelativePath, RelativePathBuf};
+use rustc_hash::{FxHashMap, FxHashSet};
 
 use crate::{
     main_loop::pending_requests::{CompletedRequest, LatestRequests},
+    vfs::{Vfs, VfsTask},
     LspError, Result,
 };
 
@@ -43,10 +49,30 @@ pub struct WorldState {
     pub roots_to_scan: usize,
     pub roots: Vec<PathBuf>,
     pub workspaces: Arc<Vec<ProjectWorkspace>>,
+    /// Set when a workspace manifest (`Cargo.toml`, `Cargo.lock` or
+    /// `rust-project.json`) has changed, and `workspaces` should be reloaded.
+    pub workspaces_changed: bool,
     pub analysis_host: AnalysisHost,
     pub vfs: Arc<RwLock<Vfs>>,
     pub task_receiver: Receiver<VfsTask>,
     pub latest_requests: Arc<RwLock<LatestRequests>>,
+    crate_graph: CrateGraph,
+    /// VFS roots which don't belong to any workspace anymore.
+    removed_roots: FxHashSet<SourceRootId>,
+    /// The ignore rules of the VFS roots, to update the files when an ignore
+    /// file changes.
+    root_filters: Vec<RootFilter>,
+    /// Files in the VFS which were removed from the analysis, because an
+    /// ignore file changed and they are ignored now.
+    ignored_files: FxHashSet<FileId>,
+    /// The texts of the files opened in the editor. The VFS drops the edits of
+    /// ignored files, so they are reapplied when a file isn't ignored anymore.
+    overlays: FxHashMap<PathBuf, String>,
+    extern_source_roots: FxHashMap<PathBuf, ExternSourceId>,
+    target: Option<String>,
+    exclude_globs: Vec<Glob>,
+    use_ignore_files: bool,
+    proc_macro_client: ProcMacroClient,
 }
 
 /// An immutable snapshot of the world's state at a point in time.
@@ -62,65 +88,73 @@ impl WorldState {
     pub fn new(
         folder_roots: Vec<PathBuf>,
         workspaces: Vec<ProjectWorkspace>,
+        target: Option<String>,
         lru_capacity: Option<usize>,
         exclude_globs: &[Glob],
+        use_ignore_files: bool,
         watch: Watch,
         options: Options,
         feature_flags: FeatureFlags,
+        proc_macro_client: ProcMacroClient,
     ) -> WorldState {
         let mut change = AnalysisChange::new();
 
+        // Directories excluded by the workspaces, as absolute paths, so that
+        // folder roots containing them skip them as well.
+        let excluded_dirs = workspaces
+            .iter()
+            .flat_map(|ws| ws.to_roots())
+            .flat_map(|pkg_root| {
+                pkg_root.exclude().iter().map(|dir| pkg_root.path().join(dir)).collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
         let mut roots = Vec::new();
+        let mut root_filters = Vec::new();
         roots.extend(folder_roots.iter().map(|path| {
-            let mut filter = RustPackageFilterBuilder::default().set_member(true);
+            let mut filter =
+                RustPackageFilterBuilder::default().set_member(true).set_include_manifests(true);
             for glob in exclude_globs.iter() {
                 filter = filter.exclude(glob.clone());
             }
-            RootEntry::new(path.clone(), filter.into_vfs_filter())
+            for dir in excluded_dirs.iter().filter_map(|dir| dir.strip_prefix(path).ok()) {
+                filter = filter.exclude_dir(dir);
+            }
+            root_entry(path, filter, use_ignore_files, &mut root_filters)
         }));
         for ws in workspaces.iter() {
             roots.extend(ws.to_roots().into_iter().map(|pkg_root| {
-                let mut filter =
-                    RustPackageFilterBuilder::default().set_member(pkg_root.is_member());
-                for glob in exclude_globs.iter() {
-                    filter = filter.exclude(glob.clone());
-                }
-                RootEntry::new(pkg_root.path().clone(), filter.into_vfs_filter())
+                let filter = package_filter(
+                    pkg_root.is_member(),
+                    pkg_root.exclude().iter().map(PathBuf::as_path),
+                    exclude_globs,
+                );
+                root_entry(pkg_root.path(), filter, use_ignore_files, &mut root_filters)
             }));
         }
         let (task_sender, task_receiver) = unbounded();
-        let task_sender = Box::new(move |t| task_sender.send(t).unwrap());
         let (mut vfs, vfs_roots) = Vfs::new(roots, task_sender, watch);
         let roots_to_scan = vfs_roots.len();
+        let out_dirs = workspaces.iter().flat_map(|ws| ws.out_dirs()).collect::<FxHashSet<_>>();
+        let mut extern_source_roots = FxHashMap::default();
         for r in vfs_roots {
             let vfs_root_path = vfs.root2path(r);
-            let is_local = folder_roots.iter().any(|it| vfs_root_path.starts_with(it));
+            let is_local = is_local_root(&folder_roots, &workspaces, &vfs_root_path);
             change.add_root(SourceRootId(r.0), is_local);
             change.set_debug_root_path(SourceRootId(r.0), vfs_root_path.display().to_string());
-        }
-
-        // FIXME: Read default cfgs from config
-        let default_cfg_options = {
-            let mut opts = get_rustc_cfg_options();
-            opts.insert_atom("test".into());
-            opts.insert_atom("debug_assertion".into());
-            opts
-        };
-
-        // Create crate graph from all the workspaces
-        let mut crate_graph = CrateGraph::default();
-        let mut load = |path: &std::path::Path| {
-            let vfs_file = vfs.load(path);
-            vfs_file.map(|f| FileId(f.0))
-        };
-        for ws in workspaces.iter() {
-            let (graph, crate_names) = ws.to_crate_graph(&default_cfg_options, &mut load);
-            let shift = crate_graph.extend(graph);
-            for (crate_id, name) in crate_names {
-                change.set_debug_crate_name(crate_id.shift(shift), name)
+            if out_dirs.contains(&vfs_root_path) {
+                extern_source_roots.insert(vfs_root_path, ExternSourceId(r.0));
             }
         }
-        change.set_crate_graph(crate_graph);
+
+        let crate_graph = build_crate_graph(
+            &workspaces,
+            target.as_ref().map(String::as_str),
+            &extern_source_roots,
+            &proc_macro_client,
+            &mut vfs,
+            &mut change,
+        );
+        change.set_crate_graph(crate_graph.clone());
 
         let mut analysis_host = AnalysisHost::new(lru_capacity, feature_flags);
         analysis_host.apply_change(change);
@@ -129,29 +163,184 @@ impl WorldState {
             roots_to_scan,
             roots: folder_roots,
             workspaces: Arc::new(workspaces),
+            workspaces_changed: false,
             analysis_host,
             vfs: Arc::new(RwLock::new(vfs)),
             task_receiver,
             latest_requests: Default::default(),
+            crate_graph,
+            removed_roots: FxHashSet::default(),
+            root_filters,
+            ignored_files: FxHashSet::default(),
+            overlays: FxHashMap::default(),
+            extern_source_roots,
+            target,
+            exclude_globs: exclude_globs.to_vec(),
+            use_ignore_files,
+            proc_macro_client,
         }
     }
 
+    /// Replaces the current workspaces with freshly loaded ones.
+    ///
+    /// The package roots of the old and the new workspaces are diffed, and
+    /// the difference is applied together with the crate graph update in a
+    /// single change, so the caches for the unchanged crates stay warm. The
+    /// roots which are gone are removed from the analysis. The new ones are
+    /// loaded into a new part of the VFS, so the ids of the existing files
+    /// don't change. New roots inside of existing ones are skipped, their
+    /// files are in the VFS already.
+    pub fn reload_workspaces(&mut self, workspaces: Vec<ProjectWorkspace>) {
+        let old_roots = package_roots(&self.workspaces);
+        let new_roots = package_roots(&workspaces);
+        let mut change = AnalysisChange::new();
+
+        let removed_paths = old_roots
+            .iter()
+            .filter(|it| !new_roots.contains(it))
+            .map(|(path, _, _)| path)
+            .filter(|path| !self.roots.contains(path))
+            .collect::<FxHashSet<_>>();
+        if !removed_paths.is_empty() {
+            let vfs = self.vfs.read();
+            for root in vfs.roots() {
+                if removed_paths.contains(&vfs.root2path(root)) {
+                    log::info!("workspace root removed: {}", vfs.root2path(root).display());
+                    change.remove_root(SourceRootId(root.0));
+                    self.removed_roots.insert(SourceRootId(root.0));
+                }
+            }
+        }
+
+        let active_roots = {
+            let vfs = self.vfs.read();
+            vfs.roots()
+                .into_iter()
+                .filter(|root| !self.removed_roots.contains(&SourceRootId(root.0)))
+                .map(|root| vfs.root2path(root))
+                .collect::<Vec<_>>()
+        };
+        let mut added_roots = Vec::new();
+        for (path, is_member, exclude) in new_roots.iter().filter(|it| !old_roots.contains(it)) {
+            if active_roots.iter().any(|it| path.starts_with(it)) {
+                continue;
+            }
+            // Existing roots inside of the new one keep their files.
+            let nested_roots = active_roots.iter().filter_map(|it| it.strip_prefix(path).ok());
+            let filter = package_filter(
+                *is_member,
+                exclude.iter().map(PathBuf::as_path).chain(nested_roots),
+                &self.exclude_globs,
+            );
+            added_roots.push(root_entry(
+                path,
+                filter,
+                self.use_ignore_files,
+                &mut self.root_filters,
+            ));
+        }
+        if !added_roots.is_empty() {
+            let vfs_roots = self.vfs.write().add_roots(added_roots);
+            self.roots_to_scan += vfs_roots.len();
+            let out_dirs = workspaces.iter().flat_map(|ws| ws.out_dirs()).collect::<FxHashSet<_>>();
+            let vfs = self.vfs.read();
+            for r in vfs_roots {
+                let vfs_root_path = vfs.root2path(r);
+                log::info!("workspace root added: {}", vfs_root_path.display());
+                let is_local = is_local_root(&self.roots, &workspaces, &vfs_root_path);
+                change.add_root(SourceRootId(r.0), is_local);
+                change.set_debug_root_path(SourceRootId(r.0), vfs_root_path.display().to_string());
+                if out_dirs.contains(&vfs_root_path) {
+                    self.extern_source_roots.insert(vfs_root_path, ExternSourceId(r.0));
+                }
+            }
+        }
+
+        let crate_graph = build_crate_graph(
+            &workspaces,
+            self.target.as_ref().map(String::as_str),
+            &self.extern_source_roots,
+            &self.proc_macro_client,
+            &mut self.vfs.write(),
+            &mut change,
+        );
+        if crate_graph != self.crate_graph {
+            log::info!("crate graph changed");
+            change.set_crate_graph(crate_graph.clone());
+            self.crate_graph = crate_graph;
+        }
+        self.analysis_host.apply_change(change);
+        self.workspaces = Arc::new(workspaces);
+    }
+    /// Sets the text of a file opened in the editor, which takes precedence
+    /// over the text on disk until the file is closed.
+    pub fn add_file_overlay(&mut self, path: &Path, text: String) -> Option<VfsFile> {
+        self.overlays.insert(path.to_path_buf(), text.clone());
+        self.vfs.write().add_file_overlay(path, text)
+    }
+
+    pub fn change_file_overlay(&mut self, path: &Path, text: String) {
+        self.overlays.insert(path.to_path_buf(), text.clone());
+        self.vfs.write().change_file_overlay(path, text);
+    }
+
+    pub fn remove_file_overlay(&mut self, path: &Path) -> Option<VfsFile> {
+        self.overlays.remove(path);
+        self.vfs.write().remove_file_overlay(path)
+    }
+
+    /// Whether `root` belongs to a workspace which was removed.
+    pub fn is_removed_root(&self, root: SourceRootId) -> bool {
+        self.removed_roots.contains(&root)
+    }
+
     /// Returns a vec of libraries
     /// FIXME: better API here
     pub fn process_changes(
         &mut self,
     ) -> Vec<(SourceRootId, Vec<(FileId, RelativePathBuf, Arc<String>)>)> {
-        let changes = self.vfs.write().commit_changes();
+        let mut changes = self.vfs.write().commit_changes();
         if changes.is_empty() {
             return Vec::new();
         }
         let mut libs = Vec::new();
         let mut change = AnalysisChange::new();
+        // Files which are not ignored anymore are loaded into the VFS, which
+        // results in another batch of changes.
+        while !changes.is_empty() {
+            let ignore_dirs = self.apply_vfs_changes(changes, &mut change, &mut libs);
+            if !ignore_dirs.is_empty() {
+                self.reapply_ignore_files(&ignore_dirs, &mut change);
+            }
+            changes = self.vfs.write().commit_changes();
+        }
+        self.analysis_host.apply_change(change);
+        libs
+    }
+
+    /// Adds the `changes` of the VFS to `change`, and the loaded library roots
+    /// to `libs`. Returns the directories of the changed ignore files.
+    fn apply_vfs_changes(
+        &mut self,
+        changes: Vec<VfsChange>,
+        change: &mut AnalysisChange,
+        libs: &mut Vec<(SourceRootId, Vec<(FileId, RelativePathBuf, Arc<String>)>)>,
+    ) -> Vec<PathBuf> {
+        let mut ignore_dirs = Vec::new();
         for c in changes {
             match c {
+                VfsChange::AddRoot { root, .. }
+                    if self.removed_roots.contains(&SourceRootId(root.0)) =>
+                {
+                    // A root of a removed workspace, which finished loading.
+                    self.roots_to_scan -= 1;
+                }
                 VfsChange::AddRoot { root, files } => {
+                    let files = files
+                        .into_iter()
+                        .filter(|(_, path, _)| !is_manifest(path) && !is_ignore(path));
                     let root_path = self.vfs.read().root2path(root);
-                    let is_local = self.roots.iter().any(|r| root_path.starts_with(r));
+                    let is_local = is_local_root(&self.roots, &self.workspaces, &root_path);
                     if is_local {
                         self.roots_to_scan -= 1;
                         for (file, path, text) in files {
@@ -159,25 +348,151 @@ impl WorldState {
                         }
                     } else {
                         let files = files
-                            .into_iter()
                             .map(|(vfsfile, path, text)| (FileId(vfsfile.0), path, text))
                             .collect();
                         libs.push((SourceRootId(root.0), files));
                     }
                 }
                 VfsChange::AddFile { root, file, path, text } => {
+                    if is_manifest(&path) {
+                        self.workspaces_changed = true;
+                        continue;
+                    }
+                    if is_ignore(&path) {
+                        let path = path.to_path(self.vfs.read().root2path(root));
+                        ignore_dirs.extend(path.parent().map(Path::to_path_buf));
+                        continue;
+                    }
                     change.add_file(SourceRootId(root.0), FileId(file.0), path, text);
                 }
                 VfsChange::RemoveFile { root, file, path } => {
+                    if is_manifest(&path) {
+                        self.workspaces_changed = true;
+                        continue;
+                    }
+                    if is_ignore(&path) {
+                        let path = path.to_path(self.vfs.read().root2path(root));
+                        ignore_dirs.extend(path.parent().map(Path::to_path_buf));
+                        continue;
+                    }
                     change.remove_file(SourceRootId(root.0), FileId(file.0), path)
                 }
                 VfsChange::ChangeFile { file, text } => {
+                    let path = self.vfs.read().file2path(file);
+                    let file_name = path.file_name().and_then(|it| it.to_str());
+                    if file_name.map_or(false, is_workspace_manifest) {
+                        self.workspaces_changed = true;
+                        continue;
+                    }
+                    if file_name.map_or(false, is_ignore_file) {
+                        ignore_dirs.extend(path.parent().map(Path::to_path_buf));
+                        continue;
+                    }
                     change.change_file(FileId(file.0), text);
                 }
             }
         }
-        self.analysis_host.apply_change(change);
-        libs
+        ignore_dirs
+    }
+
+    /// Updates the files of the local roots after the ignore files in `dirs`
+    /// have changed: the files which are ignored now are removed from the
+    /// analysis, and the ones which are not ignored anymore are added back.
+    /// Library roots are not updated, their ignore files rarely change.
+    fn reapply_ignore_files(&mut self, dirs: &[PathBuf], change: &mut AnalysisChange) {
+        log::info!("ignore files changed in {:?}", dirs);
+        for root in self.root_filters.iter() {
+            root.ignore_files.reload();
+        }
+        let mut vfs = self.vfs.write();
+        let vfs_roots = vfs
+            .roots()
+            .into_iter()
+            .map(|root| (vfs.root2path(root), SourceRootId(root.0)))
+            .collect::<FxHashMap<_, _>>();
+
+        let mut files = Vec::new();
+        for root in self.root_filters.iter() {
+            let root_id = match vfs_roots.get(&root.path) {
+                Some(&it) => it,
+                None => continue,
+            };
+            if self.removed_roots.contains(&root_id)
+                || !is_local_root(&self.roots, &self.workspaces, &root.path)
+            {
+                continue;
+            }
+            // Files in nested roots belong to those.
+            let nested_roots = vfs_roots
+                .keys()
+                .filter(|it| it.starts_with(&root.path) && **it != root.path)
+                .collect::<Vec<_>>();
+            for dir in dirs {
+                let start = if dir.starts_with(&root.path) {
+                    dir
+                } else if root.path.starts_with(dir) {
+                    &root.path
+                } else {
+                    continue;
+                };
+                let rel_start =
+                    match RelativePathBuf::from_path(start.strip_prefix(&root.path).unwrap()) {
+                        Ok(it) => it,
+                        Err(_) => continue,
+                    };
+                // A directory is ignored if any of its parents is.
+                let is_ignored = rel_start
+                    .components()
+                    .scan(RelativePathBuf::default(), |dir, component| {
+                        dir.push(component.as_str());
+                        Some(root.ignore_files.is_ignored(dir, true))
+                    })
+                    .any(|it| it);
+                walk_root_dir(
+                    root,
+                    &nested_roots,
+                    rel_start,
+                    is_ignored,
+                    &mut |rel_path, ignored| files.push((root_id, rel_path, ignored)),
+                );
+            }
+        }
+
+        for (root_id, rel_path, ignored) in files {
+            let root_path = vfs.root2path(VfsRoot(root_id.0));
+            let path = rel_path.to_path(&root_path);
+            match vfs.path2file(&path) {
+                Some(file) => {
+                    let file_id = FileId(file.0);
+                    if ignored {
+                        if self.ignored_files.insert(file_id) {
+                            change.remove_file(root_id, file_id, rel_path);
+                        }
+                    } else if self.ignored_files.remove(&file_id) {
+                        let text = match self.overlays.get(&path) {
+                            // The VFS dropped the edits while the file was
+                            // ignored, so they are applied to it again.
+                            Some(text) => {
+                                vfs.change_file_overlay(&path, text.clone());
+                                Some(text.clone())
+                            }
+                            // The VFS stores texts with `\n` line endings.
+                            None => fs::read_to_string(&path)
+                                .ok()
+                                .map(|text| text.replace("\r\n", "\n")),
+                        };
+                        if let Some(text) = text {
+                            change.add_file(root_id, file_id, rel_path, Arc::new(text));
+                        }
+                    }
+                }
+                // The `AddFile` change is picked up with the next batch.
+                None if !ignored => {
+                    vfs.load(&path);
+                }
+                None => (),
+            }
+        }
     }
 
     pub fn add_lib(&mut self, data: LibraryData) {
@@ -279,3 +594,253 @@ impl WorldSnapshot {
         self.analysis.feature_flags()
     }
 }
+
+fn is_manifest(path: &RelativePath) -> bool {
+    path.file_name().map_or(false, is_workspace_manifest)
+}
+
+fn is_ignore(path: &RelativePath) -> bool {
+    path.file_name().map_or(false, is_ignore_file)
+}
+
+/// Whether the root at `path` is edited by the user: it is in one of the
+/// folders opened in the client, or contains detached files.
+fn is_local_root(folder_roots: &[PathBuf], workspaces: &[ProjectWorkspace], path: &Path) -> bool {
+    folder_roots.iter().any(|it| path.starts_with(it))
+        || workspaces.iter().any(|ws| match ws {
+            ProjectWorkspace::DetachedFiles { roots, .. } => roots.iter().any(|it| it == path),
+            ProjectWorkspace::Cargo { .. } | ProjectWorkspace::Json { .. } => false,
+        })
+}
+
+/// The ignore rules of a VFS root, and its filter without them.
+struct RootFilter {
+    path: PathBuf,
+    ignore_files: IgnoreFiles,
+    base: Box<dyn Filter>,
+}
+
+impl fmt::Debug for RootFilter {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("RootFilter").field("path", &self.path).finish()
+    }
+}
+
+/// The filter of a package root, `exclude` are directories relative to it.
+fn package_filter<'a>(
+    is_member: bool,
+    exclude: impl Iterator<Item = &'a Path>,
+    exclude_globs: &[Glob],
+) -> RustPackageFilterBuilder {
+    let mut filter =
+        RustPackageFilterBuilder::default().set_member(is_member).set_include_manifests(true);
+    for glob in exclude_globs.iter() {
+        filter = filter.exclude(glob.clone());
+    }
+    for dir in exclude {
+        filter = filter.exclude_dir(dir);
+    }
+    filter
+}
+
+fn root_entry(
+    path: &Path,
+    filter: RustPackageFilterBuilder,
+    use_ignore_files: bool,
+    root_filters: &mut Vec<RootFilter>,
+) -> RootEntry {
+    let filter = if use_ignore_files {
+        let ignore_files = IgnoreFiles::new(path.to_path_buf());
+        root_filters.push(RootFilter {
+            path: path.to_path_buf(),
+            ignore_files: ignore_files.clone(),
+            base: filter.clone().into_vfs_filter(),
+        });
+        filter.respect_ignore_files(ignore_files)
+    } else {
+        filter
+    };
+    RootEntry::new(path.to_path_buf(), filter.into_vfs_filter())
+}
+
+/// Walks the Rust files in `dir` of `root` on disk, reporting whether they
+/// are ignored now. Excluded directories and `nested_roots` are skipped.
+fn walk_root_dir(
+    root: &RootFilter,
+    nested_roots: &[&PathBuf],
+    dir: RelativePathBuf,
+    is_ignored: bool,
+    cb: &mut dyn FnMut(RelativePathBuf, bool),
+) {
+    let entries = match fs::read_dir(dir.to_path(&root.path)) {
+        Ok(it) => it,
+        Err(_) => return,
+    };
+    for entry in entries.filter_map(|it| it.ok()) {
+        let file_type = match entry.file_type() {
+            Ok(it) => it,
+            Err(_) => continue,
+        };
+        let name = entry.file_name();
+        let name = match name.to_str() {
+            Some(it) => it,
+            None => continue,
+        };
+        let path = dir.join(name);
+        if file_type.is_dir() {
+            if !root.base.include_dir(&path)
+                || nested_roots.iter().any(|it| **it == path.to_path(&root.path))
+            {
+                continue;
+            }
+            let is_ignored = is_ignored || root.ignore_files.is_ignored(&path, true);
+            walk_root_dir(root, nested_roots, path, is_ignored, cb);
+        } else if path.extension() == Some("rs") && root.base.include_file(&path) {
+            let is_ignored = is_ignored || root.ignore_files.is_ignored(&path, false);
+            cb(path, is_ignored);
+        }
+    }
+}
+
+fn package_roots(workspaces: &[ProjectWorkspace]) -> Vec<(PathBuf, bool, Vec<PathBuf>)> {
+    let mut res = workspaces
+        .iter()
+        .flat_map(|ws| ws.to_roots())
+        .map(|root| (root.path().clone(), root.is_member(), root.exclude().to_vec()))
+        .collect::<Vec<_>>();
+    res.sort();
+    res.dedup();
+    res
+}
+
+/// Creates the crate graph from all the workspaces, loading crate roots
+/// through the `vfs`.
+fn build_crate_graph(
+    workspaces: &[ProjectWorkspace],
+    target: Option<&str>,
+    extern_source_roots: &FxHashMap<PathBuf, ExternSourceId>,
+    proc_macro_client: &ProcMacroClient,
+    vfs: &mut Vfs,
+    change: &mut AnalysisChange,
+) -> CrateGraph {
+    // FIXME: Read default cfgs from config
+    let default_cfg_options = {
+        let mut opts = get_rustc_cfg_options(target);
+        opts.insert_atom("test".into());
+        opts.insert_atom("debug_assertion".into());
+        opts
+    };
+
+    let mut crate_graph = CrateGraph::default();
+    let mut load = |path: &Path| {
+        let vfs_file = vfs.load(path);
+        vfs_file.map(|f| FileId(f.0))
+    };
+    for ws in workspaces.iter() {
+        let (graph, crate_names) = ws.to_crate_graph(
+            &default_cfg_options,
+            extern_source_roots,
+            proc_macro_client,
+            &mut load,
+        );
+        let shift = crate_graph.extend(graph);
+        for (crate_id, name) in crate_names {
+            change.set_debug_crate_name(crate_id.shift(shift), name)
+        }
+    }
+    crate_graph
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use ra_project_model::{JsonProject, Sysroot};
+    use serde_json::{json, Value};
+
+    use super::*;
+
+    fn json_workspace(roots: &[&Path], crates: Vec<Value>) -> ProjectWorkspace {
+        let project = JsonProject::from_json(json!({ "roots": roots, "crates": crates })).unwrap();
+        ProjectWorkspace::Json { project, sysroot: Sysroot::default() }
+    }
+
+    fn json_crate(root_module: &Path, deps: Value) -> Value {
+        json!({
+            "root_module": root_module,
+            "edition": "2018",
+            "deps": deps,
+            "atom_cfgs": [],
+            "key_value_cfgs": {}
+        })
+    }
+
+    /// Handles the tasks of the VFS until all the roots are loaded.
+    fn load_roots(world: &mut WorldState) {
+        loop {
+            for (root, files) in world.process_changes() {
+                world.add_lib(LibraryData::prepare(root, files));
+            }
+            if world.roots_to_scan == 0 {
+                return;
+            }
+            let task = world.task_receiver.recv_timeout(Duration::from_secs(10)).unwrap();
+            world.vfs.write().handle_task(task);
+        }
+    }
+
+    fn file_id(world: &WorldState, path: &Path) -> FileId {
+        FileId(world.vfs.read().path2file(path).unwrap().0)
+    }
+
+    #[test]
+    fn new_dependency_keeps_the_caches_of_unchanged_crates() {
+        let tmp = tempfile::tempdir().unwrap();
+        let ws_dir = tmp.path().join("ws");
+        let dep_dir = tmp.path().join("dep");
+        let ws_lib = ws_dir.join("src/lib.rs");
+        let dep_lib = dep_dir.join("lib.rs");
+        fs::create_dir_all(ws_lib.parent().unwrap()).unwrap();
+        fs::create_dir_all(&dep_dir).unwrap();
+        fs::write(&ws_lib, "pub fn foo() {}\n").unwrap();
+        fs::write(&dep_lib, "pub fn bar() {}\n").unwrap();
+
+        let options = Options {
+            publish_decorations: false,
+            supports_location_link: false,
+            line_folding_only: false,
+            max_inlay_hint_length: None,
+        };
+        let mut world = WorldState::new(
+            vec![ws_dir.clone()],
+            vec![json_workspace(&[&ws_dir], vec![json_crate(&ws_lib, json!([]))])],
+            None,
+            None,
+            &[],
+            false,
+            Watch(false),
+            options,
+            FeatureFlags::default(),
+            ProcMacroClient::dummy(),
+        );
+        load_roots(&mut world);
+        let ws_file = file_id(&world, &ws_lib);
+        let line_index = world.analysis_host.analysis().file_line_index(ws_file).unwrap();
+
+        world.reload_workspaces(vec![json_workspace(
+            &[&ws_dir, &dep_dir],
+            vec![
+                json_crate(&ws_lib, json!([{ "crate": 1, "name": "dep" }])),
+                json_crate(&dep_lib, json!([])),
+            ],
+        )]);
+        load_roots(&mut world);
+
+        assert_eq!(file_id(&world, &ws_lib), ws_file);
+        let analysis = world.analysis_host.analysis();
+        assert!(Arc::ptr_eq(&analysis.file_line_index(ws_file).unwrap(), &line_index));
+        let dep_file = file_id(&world, &dep_lib);
+        assert_ne!(dep_file, ws_file);
+        assert_eq!(analysis.crate_for(dep_file).unwrap().len(), 1);
+    }
+}
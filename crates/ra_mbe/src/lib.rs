@@ -9,6 +9,8 @@ mod syntax_bridge;
 mod tt_iter;
 mod subtree_source;
 
+use std::fmt;
+
 pub use tt::{Delimiter, Punct};
 
 use crate::{
@@ -28,8 +30,30 @@ pub enum ExpandError {
     BindingError(String),
     ConversionError,
     InvalidRepeat,
+    ProcMacroError(tt::ExpansionError),
+}
+
+impl From<tt::ExpansionError> for ExpandError {
+    fn from(it: tt::ExpansionError) -> Self {
+        ExpandError::ProcMacroError(it)
+    }
 }
 
+impl fmt::Display for ExpandError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ExpandError::NoMatchingRule => f.write_str("no rule matches the input"),
+            ExpandError::UnexpectedToken => f.write_str("unexpected token"),
+            ExpandError::BindingError(it) if it.is_empty() => f.write_str("binding error"),
+            ExpandError::BindingError(it) => f.write_str(it),
+            ExpandError::ConversionError => f.write_str("could not convert tokens"),
+            ExpandError::InvalidRepeat => f.write_str("invalid repetition"),
+            ExpandError::ProcMacroError(it) => write!(f, "proc macro failed: {}", it),
+        }
+    }
+}
+
+pub use crate::mbe_expander::{BindingTrace, ExpansionTrace, RuleOutcome, RuleTrace};
 pub use crate::syntax_bridge::{
     ast_to_token_tree, syntax_node_to_token_tree, token_tree_to_syntax_node, TokenMap,
 };
@@ -117,7 +141,7 @@ impl MacroRules {
         let mut src = TtIter::new(tt);
         let mut rules = Vec::new();
         while src.len() > 0 {
-            let rule = Rule::parse(&mut src)?;
+            let rule = Rule::parse(&mut src, true)?;
             rules.push(rule);
             if let Err(()) = src.expect_char(';') {
                 if src.len() > 0 {
@@ -127,6 +151,37 @@ impl MacroRules {
             }
         }
 
+        MacroRules::new(rules, tt)
+    }
+
+    /// Parses the body of a macros 2.0 `macro` item: either the `{ ... }` of
+    /// `macro m { (...) => { ... }, ... }`, or the parameters followed by the
+    /// body of the single-arm `macro m(...) { ... }`.
+    pub fn parse_macro2(tt: &tt::Subtree) -> Result<MacroRules, ParseError> {
+        let mut src = TtIter::new(tt);
+        let mut rules = Vec::new();
+        if tt.delimiter == tt::Delimiter::Brace {
+            while src.len() > 0 {
+                let rule = Rule::parse(&mut src, true)?;
+                rules.push(rule);
+                if let Err(()) = src.expect_any_char(&[';', ',']) {
+                    if src.len() > 0 {
+                        return Err(ParseError::Expected("expected `,` or `;`".to_string()));
+                    }
+                    break;
+                }
+            }
+        } else {
+            rules.push(Rule::parse(&mut src, false)?);
+            if src.len() > 0 {
+                return Err(ParseError::Expected("unexpected tokens after macro body".to_string()));
+            }
+        }
+
+        MacroRules::new(rules, tt)
+    }
+
+    fn new(rules: Vec<Rule>, tt: &tt::Subtree) -> Result<MacroRules, ParseError> {
         for rule in rules.iter() {
             validate(&rule.lhs)?;
         }
@@ -141,6 +196,14 @@ impl MacroRules {
         mbe_expander::expand(self, &tt)
     }
 
+    /// Expands `tt` like `expand`, but records how the input was matched
+    /// against each of the rules tried.
+    pub fn expand_traced(&self, tt: &tt::Subtree) -> ExpansionTrace {
+        let mut tt = tt.clone();
+        self.shift.shift_all(&mut tt);
+        mbe_expander::expand_traced(self, &tt)
+    }
+
     pub fn map_id_down(&self, id: tt::TokenId) -> tt::TokenId {
         self.shift.shift(id)
     }
@@ -154,14 +217,16 @@ impl MacroRules {
 }
 
 impl Rule {
-    fn parse(src: &mut TtIter) -> Result<Rule, ParseError> {
+    fn parse(src: &mut TtIter, expect_arrow: bool) -> Result<Rule, ParseError> {
         let mut lhs = src
             .expect_subtree()
             .map_err(|()| ParseError::Expected("expected subtree".to_string()))?
             .clone();
         lhs.delimiter = tt::Delimiter::None;
-        src.expect_char('=').map_err(|()| ParseError::Expected("expected `=`".to_string()))?;
-        src.expect_char('>').map_err(|()| ParseError::Expected("expected `>`".to_string()))?;
+        if expect_arrow {
+            src.expect_char('=').map_err(|()| ParseError::Expected("expected `=`".to_string()))?;
+            src.expect_char('>').map_err(|()| ParseError::Expected("expected `>`".to_string()))?;
+        }
         let mut rhs = src
             .expect_subtree()
             .map_err(|()| ParseError::Expected("expected subtree".to_string()))?
@@ -18,11 +18,89 @@ pub(crate) fn expand(
 }
 
 fn expand_rule(rule: &crate::Rule, input: &tt::Subtree) -> Result<tt::Subtree, ExpandError> {
-    let bindings = matcher::match_(&rule.lhs, input)?;
+    let bindings = matcher::match_(&rule.lhs, input, None)?;
     let res = transcriber::transcribe(&rule.rhs, &bindings)?;
     Ok(res)
 }
 
+pub(crate) fn expand_traced(rules: &crate::MacroRules, input: &tt::Subtree) -> ExpansionTrace {
+    let mut trace = ExpansionTrace { rules: Vec::new(), result: Err(ExpandError::NoMatchingRule) };
+    for (idx, rule) in rules.rules.iter().enumerate() {
+        let mut match_trace = matcher::Trace::default();
+        let outcome = match matcher::match_(&rule.lhs, input, Some(&mut match_trace)) {
+            Ok(bindings) => {
+                let expansion = transcriber::transcribe(&rule.rhs, &bindings);
+                let mut bindings: Vec<_> =
+                    bindings.inner.iter().map(|(name, it)| (name.clone(), it.to_trace())).collect();
+                bindings.sort_by(|(a, _), (b, _)| a.cmp(b));
+                RuleOutcome::Matched { bindings, expansion }
+            }
+            Err(error) => {
+                let unmatched = match_trace.unmatched.unwrap_or_default().to_vec();
+                RuleOutcome::NoMatch { error, unmatched }
+            }
+        };
+        if let RuleOutcome::Matched { expansion: Ok(it), .. } = &outcome {
+            trace.result = Ok(it.clone());
+        }
+        trace.rules.push(RuleTrace { rule: idx, lhs: rule.lhs.clone(), outcome });
+        if trace.result.is_ok() {
+            break;
+        }
+    }
+    trace
+}
+
+/// A record of an attempt to expand a macro invocation, produced by
+/// `MacroRules::expand_traced`.
+#[derive(Debug, PartialEq, Eq)]
+pub struct ExpansionTrace {
+    /// Rules in the order they were tried. The last one is the rule which
+    /// was used for expansion, if any.
+    pub rules: Vec<RuleTrace>,
+    pub result: Result<tt::Subtree, ExpandError>,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct RuleTrace {
+    /// Index of the rule in the macro definition
+    pub rule: usize,
+    /// The matcher of the rule, i.e. the part to the left of `=>`
+    pub lhs: tt::Subtree,
+    pub outcome: RuleOutcome,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum RuleOutcome {
+    /// The input did not match the rule. `unmatched` holds the input tokens,
+    /// starting from the one where matching failed.
+    NoMatch { error: ExpandError, unmatched: Vec<tt::TokenTree> },
+    /// The input matched, and the matched fragments were substituted into the
+    /// transcriber, which might still fail.
+    Matched { bindings: Vec<(SmolStr, BindingTrace)>, expansion: Result<tt::Subtree, ExpandError> },
+}
+
+/// What a single `$var` was bound to. Variables inside of repetitions are
+/// bound to a list of values per level of nesting.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BindingTrace {
+    Fragment(tt::TokenTree),
+    Nested(Vec<BindingTrace>),
+    Empty,
+}
+
+impl Binding {
+    fn to_trace(&self) -> BindingTrace {
+        match self {
+            Binding::Fragment(Fragment::Tokens(it)) | Binding::Fragment(Fragment::Ast(it)) => {
+                BindingTrace::Fragment(it.clone())
+            }
+            Binding::Nested(it) => BindingTrace::Nested(it.iter().map(Binding::to_trace).collect()),
+            Binding::Empty => BindingTrace::Empty,
+        }
+    }
+}
+
 /// The actual algorithm for expansion is not too hard, but is pretty tricky.
 /// `Bindings` structure is the key to understanding what we are doing here.
 ///
@@ -64,70 +64,119 @@ macro_rules! bail {
     };
 }
 
-pub(super) fn match_(pattern: &tt::Subtree, src: &tt::Subtree) -> Result<Bindings, ExpandError> {
+/// Where matching failed. It is only recorded if the caller asks for it, so
+/// that normal expansion doesn't pay for it.
+#[derive(Debug, Default)]
+pub(super) struct Trace<'a> {
+    /// The input tokens, starting from the innermost position where matching
+    /// failed.
+    pub(super) unmatched: Option<&'a [tt::TokenTree]>,
+}
+
+pub(super) fn match_<'a>(
+    pattern: &tt::Subtree,
+    src: &'a tt::Subtree,
+    mut trace: Option<&mut Trace<'a>>,
+) -> Result<Bindings, ExpandError> {
     assert!(pattern.delimiter == tt::Delimiter::None);
 
     let mut res = Bindings::default();
     let mut src = TtIter::new(src);
 
-    match_subtree(&mut res, pattern, &mut src)?;
+    match_subtree(&mut res, pattern, &mut src, reborrow(&mut trace))?;
 
     if src.len() > 0 {
+        if let Some(trace) = trace {
+            trace.unmatched = Some(src.inner.as_slice());
+        }
         bail!("leftover tokens");
     }
 
     Ok(res)
 }
 
-fn match_subtree(
+fn reborrow<'t, 'a>(trace: &'t mut Option<&mut Trace<'a>>) -> Option<&'t mut Trace<'a>> {
+    trace.as_mut().map(|it| &mut **it)
+}
+
+/// Matches `src` against `pattern`. If this fails, the position of the
+/// failure is recorded in `trace`.
+fn match_subtree<'a>(
     bindings: &mut Bindings,
     pattern: &tt::Subtree,
-    src: &mut TtIter,
+    src: &mut TtIter<'a>,
+    mut trace: Option<&mut Trace<'a>>,
 ) -> Result<(), ExpandError> {
     for op in parse_pattern(pattern) {
-        match op? {
-            Op::TokenTree(tt::TokenTree::Leaf(lhs)) => {
-                let rhs = src.expect_leaf().map_err(|()| err!("expected leaf: `{}`", lhs))?;
-                match (lhs, rhs) {
-                    (
-                        tt::Leaf::Punct(tt::Punct { char: lhs, .. }),
-                        tt::Leaf::Punct(tt::Punct { char: rhs, .. }),
-                    ) if lhs == rhs => (),
-                    (
-                        tt::Leaf::Ident(tt::Ident { text: lhs, .. }),
-                        tt::Leaf::Ident(tt::Ident { text: rhs, .. }),
-                    ) if lhs == rhs => (),
-                    (
-                        tt::Leaf::Literal(tt::Literal { text: lhs, .. }),
-                        tt::Leaf::Literal(tt::Literal { text: rhs, .. }),
-                    ) if lhs == rhs => (),
-                    _ => Err(ExpandError::UnexpectedToken)?,
+        let op = op?;
+        let before = src.inner.as_slice();
+        if let Some(trace) = reborrow(&mut trace) {
+            trace.unmatched = None;
+        }
+        if let Err(err) = match_op(bindings, op, src, reborrow(&mut trace)) {
+            // Nested subtrees and repeats may have recorded a more precise position
+            if let Some(trace) = trace {
+                if trace.unmatched.is_none() {
+                    trace.unmatched = Some(before);
                 }
             }
-            Op::TokenTree(tt::TokenTree::Subtree(lhs)) => {
-                let rhs = src.expect_subtree().map_err(|()| err!("expected subtree"))?;
-                if lhs.delimiter != rhs.delimiter {
-                    bail!("mismatched delimiter")
-                }
-                let mut src = TtIter::new(rhs);
-                match_subtree(bindings, lhs, &mut src)?;
-                if src.len() > 0 {
-                    bail!("leftover tokens");
-                }
+            return Err(err);
+        }
+    }
+    Ok(())
+}
+
+fn match_op<'a>(
+    bindings: &mut Bindings,
+    op: Op,
+    src: &mut TtIter<'a>,
+    mut trace: Option<&mut Trace<'a>>,
+) -> Result<(), ExpandError> {
+    match op {
+        Op::TokenTree(tt::TokenTree::Leaf(lhs)) => {
+            let rhs = src.expect_leaf().map_err(|()| err!("expected leaf: `{}`", lhs))?;
+            match (lhs, rhs) {
+                (
+                    tt::Leaf::Punct(tt::Punct { char: lhs, .. }),
+                    tt::Leaf::Punct(tt::Punct { char: rhs, .. }),
+                ) if lhs == rhs => (),
+                (
+                    tt::Leaf::Ident(tt::Ident { text: lhs, .. }),
+                    tt::Leaf::Ident(tt::Ident { text: rhs, .. }),
+                ) if lhs == rhs => (),
+                (
+                    tt::Leaf::Literal(tt::Literal { text: lhs, .. }),
+                    tt::Leaf::Literal(tt::Literal { text: rhs, .. }),
+                ) if lhs == rhs => (),
+                _ => Err(ExpandError::UnexpectedToken)?,
             }
-            Op::Var { name, kind } => {
-                let kind = kind.as_ref().ok_or(ExpandError::UnexpectedToken)?;
-                match match_meta_var(kind.as_str(), src)? {
-                    Some(fragment) => {
-                        bindings.inner.insert(name.clone(), Binding::Fragment(fragment));
-                    }
-                    None => bindings.push_optional(name),
+        }
+        Op::TokenTree(tt::TokenTree::Subtree(lhs)) => {
+            let rhs = src.expect_subtree().map_err(|()| err!("expected subtree"))?;
+            if lhs.delimiter != rhs.delimiter {
+                bail!("mismatched delimiter")
+            }
+            let mut src = TtIter::new(rhs);
+            match_subtree(bindings, lhs, &mut src, reborrow(&mut trace))?;
+            if src.len() > 0 {
+                if let Some(trace) = trace {
+                    trace.unmatched = Some(src.inner.as_slice());
                 }
+                bail!("leftover tokens");
             }
-            Op::Repeat { subtree, kind, separator } => {
-                match_repeat(bindings, subtree, kind, separator, src)?
+        }
+        Op::Var { name, kind } => {
+            let kind = kind.as_ref().ok_or(ExpandError::UnexpectedToken)?;
+            match match_meta_var(kind.as_str(), src)? {
+                Some(fragment) => {
+                    bindings.inner.insert(name.clone(), Binding::Fragment(fragment));
+                }
+                None => bindings.push_optional(name),
             }
         }
+        Op::Repeat { subtree, kind, separator } => {
+            match_repeat(bindings, subtree, kind, separator, src, trace)?
+        }
     }
     Ok(())
 }
@@ -228,12 +277,13 @@ impl<'a> TtIter<'a> {
     }
 }
 
-pub(super) fn match_repeat(
+pub(super) fn match_repeat<'a>(
     bindings: &mut Bindings,
     pattern: &tt::Subtree,
     kind: RepeatKind,
     separator: Option<Separator>,
-    src: &mut TtIter,
+    src: &mut TtIter<'a>,
+    mut trace: Option<&mut Trace<'a>>,
 ) -> Result<(), ExpandError> {
     // Dirty hack to make macro-expansion terminate.
     // This should be replaced by a propper macro-by-example implementation
@@ -250,7 +300,7 @@ pub(super) fn match_repeat(
         }
 
         let mut nested = Bindings::default();
-        match match_subtree(&mut nested, pattern, &mut fork) {
+        match match_subtree(&mut nested, pattern, &mut fork, reborrow(&mut trace)) {
             Ok(()) => {
                 limit -= 1;
                 if limit == 0 {
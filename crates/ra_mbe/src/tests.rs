@@ -1422,6 +1422,73 @@ macro_rules! delegate_impl {
     );
 }
 
+#[test]
+fn test_macro2_single_arm() {
+    let rules = create_macro2_rules(
+        r#"
+        macro foo($ i:ident, $ j:ident) {
+            fn foo() { let a = $ i; let b = $j; }
+        }
+"#,
+    );
+    assert_expansion(
+        MacroKind::Items,
+        &rules,
+        "foo! { foo, bar }",
+        "fn foo () {let a = foo ; let b = bar ;}",
+    );
+}
+
+#[test]
+fn test_macro2_multiple_arms() {
+    let rules = create_macro2_rules(
+        r#"
+        macro foo {
+            ($ i:ident) => {
+                fn $ i() {}
+            },
+            () => {
+                struct Foo;
+            };
+            ($ i:ident, $ j:ident) => {
+                mod $ i {}
+                mod $ j {}
+            }
+        }
+"#,
+    );
+    assert_expansion(MacroKind::Items, &rules, "foo! { bar }", "fn bar () {}");
+    assert_expansion(MacroKind::Items, &rules, "foo! {}", "struct Foo ;");
+    assert_expansion(MacroKind::Items, &rules, "foo! { a, b }", "mod a {} mod b {}");
+}
+
+#[test]
+fn test_expand_traced() {
+    let rules = create_rules(
+        r#"
+        macro_rules! foo {
+            ($ i:ident) => (fn $ i() {});
+            ($ ($ i:ident),* ; struct) => ($ (struct $ i;)*);
+        }
+"#,
+    );
+
+    assert_eq!(
+        expand_traced(&rules, "foo! { a, b; enum }"),
+        "\
+#0 `$ i : ident`: no match (BindingError(\"leftover tokens\")) at `, b ; enum`
+#1 `$ ($ i : ident) ,* ; struct`: no match (UnexpectedToken) at `enum`
+result: Err(NoMatchingRule)"
+    );
+    assert_eq!(
+        expand_traced(&rules, "foo! { a, b; struct }"),
+        "\
+#0 `$ i : ident`: no match (BindingError(\"leftover tokens\")) at `, b ; struct`
+#1 `$ ($ i : ident) ,* ; struct`: matched, i = Nested([Fragment(a), Fragment(b)])
+result: Ok(struct a ; struct b ;)"
+    );
+}
+
 pub(crate) fn create_rules(macro_definition: &str) -> MacroRules {
     let source_file = ast::SourceFile::parse(macro_definition).ok().unwrap();
     let macro_definition =
@@ -1431,6 +1498,15 @@ pub(crate) fn create_rules(macro_definition: &str) -> MacroRules {
     crate::MacroRules::parse(&definition_tt).unwrap()
 }
 
+pub(crate) fn create_macro2_rules(macro_definition: &str) -> MacroRules {
+    let source_file = ast::SourceFile::parse(macro_definition).ok().unwrap();
+    let macro_definition =
+        source_file.syntax().descendants().find_map(ast::MacroDef::cast).unwrap();
+
+    let (definition_tt, _) = ast_to_token_tree(&macro_definition.token_tree().unwrap()).unwrap();
+    crate::MacroRules::parse_macro2(&definition_tt).unwrap()
+}
+
 pub(crate) fn expand(rules: &MacroRules, invocation: &str) -> tt::Subtree {
     let source_file = ast::SourceFile::parse(invocation).ok().unwrap();
     let macro_invocation =
@@ -1441,6 +1517,51 @@ pub(crate) fn expand(rules: &MacroRules, invocation: &str) -> tt::Subtree {
     rules.expand(&invocation_tt).unwrap()
 }
 
+/// Renders the trace of expanding `invocation` in a compact, testable form.
+pub(crate) fn expand_traced(rules: &MacroRules, invocation: &str) -> String {
+    let source_file = ast::SourceFile::parse(invocation).ok().unwrap();
+    let macro_invocation =
+        source_file.syntax().descendants().find_map(ast::MacroCall::cast).unwrap();
+
+    let (invocation_tt, _) = ast_to_token_tree(&macro_invocation.token_tree().unwrap()).unwrap();
+
+    let trace = rules.expand_traced(&invocation_tt);
+    let mut res = String::new();
+    for rule in trace.rules.iter() {
+        res += &format!("#{} `{}`: ", rule.rule, rule.lhs);
+        match &rule.outcome {
+            crate::RuleOutcome::NoMatch { error, unmatched } => {
+                let unmatched =
+                    tt::Subtree { delimiter: tt::Delimiter::None, token_trees: unmatched.clone() };
+                res += &format!("no match ({:?}) at `{}`\n", error, unmatched);
+            }
+            crate::RuleOutcome::Matched { bindings, .. } => {
+                res += "matched";
+                for (name, binding) in bindings {
+                    res += &format!(", {} = {}", name, render_binding(binding));
+                }
+                res += "\n";
+            }
+        }
+    }
+    match &trace.result {
+        Ok(it) => res += &format!("result: Ok({})", it),
+        Err(err) => res += &format!("result: Err({:?})", err),
+    }
+    return res;
+
+    fn render_binding(binding: &crate::BindingTrace) -> String {
+        match binding {
+            crate::BindingTrace::Fragment(it) => format!("Fragment({})", it),
+            crate::BindingTrace::Nested(it) => format!(
+                "Nested([{}])",
+                it.iter().map(render_binding).collect::<Vec<_>>().join(", ")
+            ),
+            crate::BindingTrace::Empty => "Empty".to_string(),
+        }
+    }
+}
+
 pub(crate) enum MacroKind {
     Items,
     Stmts,
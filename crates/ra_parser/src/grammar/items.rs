@@ -259,6 +259,7 @@ fn items_without_modifiers(p: &mut Parser, m: Marker) -> Result<(), Marker> {
             adt::union_def(p, m);
         }
         T![enum] => adt::enum_def(p, m),
+        IDENT if p.at_contextual_kw("macro") && la == IDENT => macro_def(p, m),
         T![use] => use_item::use_item(p, m),
         T![const] if (la == IDENT || la == T![_] || la == T![mut]) => consts::const_def(p, m),
         T![static] => consts::static_def(p, m),
@@ -276,6 +277,32 @@ fn items_without_modifiers(p: &mut Parser, m: Marker) -> Result<(), Marker> {
     Ok(())
 }
 
+// test macro_def
+// macro m { ($i:ident) => {} }
+// pub macro m($i:ident) { $i }
+fn macro_def(p: &mut Parser, m: Marker) {
+    assert!(p.at_contextual_kw("macro"));
+    p.bump_remap(T![macro]);
+    name(p);
+    match p.current() {
+        T!['{'] => token_tree(p),
+        T!['('] => {
+            // The parameters and the body of a single-arm macro are wrapped
+            // into one token tree, so that both forms have a single body.
+            let tt = p.start();
+            token_tree(p);
+            if p.at(T!['{']) {
+                token_tree(p);
+            } else {
+                p.error("expected `{`");
+            }
+            tt.complete(p, TOKEN_TREE);
+        }
+        _ => p.error("expected `{` or `(`"),
+    }
+    m.complete(p, MACRO_DEF);
+}
+
 fn extern_crate_item(p: &mut Parser, m: Marker) {
     assert!(p.at(T![extern]));
     p.bump(T![extern]);
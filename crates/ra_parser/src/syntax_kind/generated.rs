@@ -104,6 +104,7 @@ pub enum SyntaxKind {
     DEFAULT_KW,
     EXISTENTIAL_KW,
     UNION_KW,
+    MACRO_KW,
     INT_NUMBER,
     FLOAT_NUMBER,
     CHAR,
@@ -135,6 +136,7 @@ pub enum SyntaxKind {
     IMPL_BLOCK,
     TYPE_ALIAS_DEF,
     MACRO_CALL,
+    MACRO_DEF,
     TOKEN_TREE,
     PAREN_TYPE,
     TUPLE_TYPE,
@@ -251,7 +253,7 @@ impl SyntaxKind {
             | SUPER_KW | IN_KW | WHERE_KW | FOR_KW | LOOP_KW | WHILE_KW | CONTINUE_KW
             | BREAK_KW | IF_KW | ELSE_KW | MATCH_KW | CONST_KW | STATIC_KW | MUT_KW | UNSAFE_KW
             | TYPE_KW | REF_KW | LET_KW | MOVE_KW | RETURN_KW | TRY_KW | BOX_KW | AWAIT_KW
-            | AUTO_KW | DEFAULT_KW | EXISTENTIAL_KW | UNION_KW => true,
+            | AUTO_KW | DEFAULT_KW | EXISTENTIAL_KW | UNION_KW | MACRO_KW => true,
             _ => false,
         }
     }
@@ -640,4 +642,7 @@ macro_rules! T {
     ( union ) => {
         $crate::SyntaxKind::UNION_KW
     };
+    ( macro ) => {
+        $crate::SyntaxKind::MACRO_KW
+    };
 }
@@ -0,0 +1,119 @@
+//! Client-side Proc-Macro crate
+//!
+//! We separate proc-macro expanding logic to an extern program to allow
+//! different implementations (e.g. wasm or dylib loading). And this crate
+//! is used to provide basic infrastructure for communication between two
+//! processes: Client (RA itself), Server (the external program)
+
+mod rpc;
+mod process;
+pub mod msg;
+
+use std::{
+    io,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use ra_syntax::SmolStr;
+
+use crate::process::ProcMacroProcessSrv;
+
+pub use rpc::{ExpansionResult, ExpansionTask, ListMacrosResult, ListMacrosTask, ProcMacroKind};
+
+#[derive(Debug, Clone)]
+pub struct ProcMacroProcessExpander {
+    process: Arc<ProcMacroProcessSrv>,
+    dylib_path: PathBuf,
+    name: SmolStr,
+}
+
+impl Eq for ProcMacroProcessExpander {}
+impl PartialEq for ProcMacroProcessExpander {
+    fn eq(&self, other: &Self) -> bool {
+        self.name == other.name
+            && self.dylib_path == other.dylib_path
+            && Arc::ptr_eq(&self.process, &other.process)
+    }
+}
+
+impl tt::TokenExpander for ProcMacroProcessExpander {
+    fn expand(
+        &self,
+        subtree: &tt::Subtree,
+        attr: Option<&tt::Subtree>,
+    ) -> Result<tt::Subtree, tt::ExpansionError> {
+        let task = ExpansionTask {
+            macro_body: subtree.clone(),
+            macro_name: self.name.to_string(),
+            attributes: attr.cloned(),
+            lib: self.dylib_path.to_path_buf(),
+        };
+
+        let result: ExpansionResult = self.process.send_task(msg::Request::ExpansionMacro(task))?;
+        Ok(result.expansion)
+    }
+}
+
+#[derive(Debug)]
+enum ProcMacroClientKind {
+    Process { process: Arc<ProcMacroProcessSrv> },
+    Dummy,
+}
+
+/// Knows how to load the procedural macros of a `proc-macro` crate, given
+/// the path to its compiled dylib.
+#[derive(Debug)]
+pub struct ProcMacroClient {
+    kind: ProcMacroClientKind,
+}
+
+impl ProcMacroClient {
+    /// Uses the server at `process_path` to load and expand proc macros. The
+    /// server is restarted if it crashes.
+    pub fn extern_process(process_path: &Path) -> io::Result<ProcMacroClient> {
+        let process = ProcMacroProcessSrv::run(process_path)?;
+        Ok(ProcMacroClient { kind: ProcMacroClientKind::Process { process: Arc::new(process) } })
+    }
+
+    /// A client which doesn't load any proc macros.
+    pub fn dummy() -> ProcMacroClient {
+        ProcMacroClient { kind: ProcMacroClientKind::Dummy }
+    }
+
+    pub fn by_dylib_path(
+        &self,
+        dylib_path: &Path,
+    ) -> Vec<(SmolStr, ProcMacroKind, Arc<dyn tt::TokenExpander>)> {
+        match &self.kind {
+            ProcMacroClientKind::Dummy => vec![],
+            ProcMacroClientKind::Process { process } => {
+                let macros = match process.find_proc_macros(dylib_path) {
+                    Err(err) => {
+                        log::error!(
+                            "failed to find proc macros in {}: {}",
+                            dylib_path.display(),
+                            err
+                        );
+                        return vec![];
+                    }
+                    Ok(macros) => macros,
+                };
+
+                macros
+                    .into_iter()
+                    .map(|(name, kind)| {
+                        let name = SmolStr::new(&name);
+                        let expander: Arc<dyn tt::TokenExpander> =
+                            Arc::new(ProcMacroProcessExpander {
+                                process: process.clone(),
+                                name: name.clone(),
+                                dylib_path: dylib_path.into(),
+                            });
+                        (name, kind, expander)
+                    })
+                    .collect()
+            }
+        }
+    }
+}
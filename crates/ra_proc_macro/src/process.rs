@@ -0,0 +1,168 @@
+//! Handle process life-time and message passing for proc-macro client
+
+use std::{
+    convert::{TryFrom, TryInto},
+    io::{self, BufReader},
+    path::{Path, PathBuf},
+    process::{Child, ChildStdin, Command, Stdio},
+    sync::{
+        mpsc::{self, Receiver, RecvTimeoutError},
+        Mutex, PoisonError,
+    },
+    thread,
+    time::Duration,
+};
+
+use tt::ExpansionError;
+
+use crate::{
+    msg::{Message, Request, Response},
+    rpc::{ListMacrosResult, ListMacrosTask, ProcMacroKind},
+};
+
+/// How long to wait for the server to answer a request. A proc macro which
+/// loops forever would otherwise block every other expansion.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How many times in a row the server may die before we stop restarting it.
+const MAX_RESTARTS: u32 = 3;
+
+/// A proc macro server process. Requests are sent one at a time; if the
+/// server dies (e.g. because a proc macro crashed it) or doesn't answer in
+/// time, the request fails and the server is restarted on the next request.
+#[derive(Debug)]
+pub(crate) struct ProcMacroProcessSrv {
+    path: PathBuf,
+    state: Mutex<State>,
+}
+
+#[derive(Debug)]
+struct State {
+    process: Option<Process>,
+    /// The number of times the server died since it last answered a request.
+    failures: u32,
+}
+
+#[derive(Debug)]
+struct Process {
+    child: Child,
+    stdin: ChildStdin,
+    /// Responses are read on a separate thread, so that we can time out.
+    responses: Receiver<io::Result<Option<Response>>>,
+}
+
+impl Drop for Process {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+impl Process {
+    fn run(path: &Path) -> io::Result<Process> {
+        let mut child = Command::new(path)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::inherit())
+            .spawn()?;
+        let stdin = child.stdin.take().expect("stdin is piped");
+        let mut stdout = BufReader::new(child.stdout.take().expect("stdout is piped"));
+        let (sender, responses) = mpsc::channel();
+        thread::spawn(move || loop {
+            let res = Response::read(&mut stdout);
+            let done = match res {
+                Ok(Some(_)) => false,
+                Ok(None) | Err(_) => true,
+            };
+            if sender.send(res).is_err() || done {
+                break;
+            }
+        });
+        Ok(Process { child, stdin, responses })
+    }
+
+    fn send_request(&mut self, req: Request) -> io::Result<Option<Response>> {
+        req.write(&mut self.stdin)?;
+        match self.responses.recv_timeout(REQUEST_TIMEOUT) {
+            Ok(res) => res,
+            Err(RecvTimeoutError::Timeout) => Err(io::Error::new(
+                io::ErrorKind::TimedOut,
+                format!("proc macro server didn't answer in {:?}", REQUEST_TIMEOUT),
+            )),
+            Err(RecvTimeoutError::Disconnected) => Ok(None),
+        }
+    }
+
+    /// Describes how the server exited, after it stopped answering.
+    fn exit_status(&mut self) -> String {
+        match self.child.try_wait() {
+            Ok(Some(status)) => format!("proc macro server exited ({})", status),
+            _ => "proc macro server exited".to_string(),
+        }
+    }
+}
+
+impl ProcMacroProcessSrv {
+    pub fn run(process_path: &Path) -> io::Result<ProcMacroProcessSrv> {
+        // Spawn eagerly, so that a misconfigured server path is reported right away.
+        let process = Process::run(process_path)?;
+        Ok(ProcMacroProcessSrv {
+            path: process_path.to_path_buf(),
+            state: Mutex::new(State { process: Some(process), failures: 0 }),
+        })
+    }
+
+    pub fn find_proc_macros(
+        &self,
+        dylib_path: &Path,
+    ) -> Result<Vec<(String, ProcMacroKind)>, ExpansionError> {
+        let task = ListMacrosTask { lib: dylib_path.to_path_buf() };
+
+        let result: ListMacrosResult = self.send_task(Request::ListMacro(task))?;
+        Ok(result.macros)
+    }
+
+    pub fn send_task<R>(&self, req: Request) -> Result<R, ExpansionError>
+    where
+        R: TryFrom<Response, Error = &'static str>,
+    {
+        let mut state = self.state.lock().unwrap_or_else(PoisonError::into_inner);
+        if state.process.is_none() {
+            if state.failures >= MAX_RESTARTS {
+                return Err(ExpansionError::Unknown(format!(
+                    "proc macro server {} keeps failing, not restarting it",
+                    self.path.display()
+                )));
+            }
+            let restarted =
+                Process::run(&self.path).map_err(|err| ExpansionError::IOError(err.to_string()))?;
+            state.process = Some(restarted);
+        }
+
+        let process = state.process.as_mut().unwrap();
+        let res = match process.send_request(req) {
+            Ok(Some(res)) => res,
+            Ok(None) => {
+                let msg = process.exit_status();
+                log::error!("{}", msg);
+                state.process = None;
+                state.failures += 1;
+                return Err(ExpansionError::Unknown(msg));
+            }
+            Err(err) => {
+                // Most likely, the server crashed or hangs; dropping the
+                // process kills it, and it is restarted on the next request.
+                log::error!("proc macro server failed: {}", err);
+                state.process = None;
+                state.failures += 1;
+                return Err(ExpansionError::IOError(err.to_string()));
+            }
+        };
+        state.failures = 0;
+
+        match res {
+            Response::Error(err) => Err(ExpansionError::ExpansionError(err.message)),
+            _ => res.try_into().map_err(|err: &str| ExpansionError::Unknown(err.to_string())),
+        }
+    }
+}
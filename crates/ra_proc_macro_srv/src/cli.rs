@@ -0,0 +1,64 @@
+//! Driver for the proc macro server: reads requests from stdin and writes
+//! responses to stdout, one JSON message per line.
+
+use std::io::{self, Write};
+
+use ra_proc_macro::msg::{ErrorCode, Message, Request, Response, ResponseError};
+
+use crate::ProcMacroSrv;
+
+pub fn run() -> io::Result<()> {
+    if !cfg!(feature = "proc_macro_bridge") {
+        return Err(io::Error::new(
+            io::ErrorKind::Other,
+            "the proc macro server was built without the `proc_macro_bridge` feature, \
+             rebuild it with a nightly compiler and `--features proc_macro_bridge`",
+        ));
+    }
+
+    let mut srv = ProcMacroSrv::default();
+
+    let stdin = io::stdin();
+    let mut stdin = stdin.lock();
+    let mut stdout = protocol_stdout()?;
+
+    while let Some(req) = Request::read(&mut stdin)? {
+        let res = match req {
+            Request::ListMacro(task) => srv.list_macros(&task).map(Response::ListMacro),
+            Request::ExpansionMacro(task) => srv.expand(&task).map(Response::ExpansionMacro),
+        };
+        let msg = res.unwrap_or_else(|message| {
+            Response::Error(ResponseError { code: ErrorCode::ExpansionError, message })
+        });
+        msg.write(&mut stdout)?;
+    }
+
+    Ok(())
+}
+
+/// Proc macros can print to stdout, which would corrupt the protocol. So the
+/// protocol uses a duplicate of the original stdout, and stdout itself is
+/// redirected to stderr.
+#[cfg(unix)]
+fn protocol_stdout() -> io::Result<Box<dyn Write>> {
+    use std::{fs::File, os::unix::io::FromRawFd};
+
+    // Make sure nothing buffered goes to the wrong place.
+    io::stdout().flush()?;
+    unsafe {
+        let fd = libc::dup(libc::STDOUT_FILENO);
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        if libc::dup2(libc::STDERR_FILENO, libc::STDOUT_FILENO) < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(Box::new(File::from_raw_fd(fd)))
+    }
+}
+
+// FIXME: redirect stdout on other platforms as well
+#[cfg(not(unix))]
+fn protocol_stdout() -> io::Result<Box<dyn Write>> {
+    Ok(Box::new(io::stdout()))
+}
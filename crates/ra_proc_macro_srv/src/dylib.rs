@@ -0,0 +1,210 @@
+//! Handles dynamic library loading for proc macro
+
+use std::{
+    fmt, io,
+    path::{Path, PathBuf},
+};
+
+use goblin::{mach::Mach, Object};
+use ra_proc_macro::ProcMacroKind;
+
+/// The exported symbol of a proc macro dylib which points to the list of its
+/// proc macros, `__rustc_proc_macro_decls_<hash>__`.
+const NEW_REGISTRAR_SYMBOL: &str = "_rustc_proc_macro_decls_";
+
+fn invalid_data_err(e: impl Into<Box<dyn std::error::Error + Send + Sync>>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, e)
+}
+
+fn is_derive_registrar_symbol(symbol: &str) -> bool {
+    symbol.contains(NEW_REGISTRAR_SYMBOL)
+}
+
+fn find_registrar_symbol(file: &Path) -> io::Result<Option<String>> {
+    let buffer = std::fs::read(file)?;
+    let object = Object::parse(&buffer).map_err(invalid_data_err)?;
+
+    let symbols: Vec<String> = match object {
+        Object::Elf(elf) => {
+            let symbols = elf.dynstrtab.to_vec().map_err(invalid_data_err)?;
+            symbols.into_iter().map(|it| it.to_string()).collect()
+        }
+        Object::PE(pe) => {
+            pe.exports.iter().flat_map(|it| it.name).map(|it| it.to_string()).collect()
+        }
+        Object::Mach(Mach::Binary(binary)) => {
+            let exports = binary.exports().map_err(invalid_data_err)?;
+            exports
+                .into_iter()
+                .map(|it| {
+                    // `dlsym` expects the symbol names without the leading underscore
+                    // which Mach-O adds to them.
+                    if it.name.starts_with('_') {
+                        it.name[1..].to_string()
+                    } else {
+                        it.name
+                    }
+                })
+                .collect()
+        }
+        Object::Mach(Mach::Fat(_)) | Object::Archive(_) | Object::Unknown(_) => Vec::new(),
+    };
+
+    Ok(symbols.into_iter().find(|it| is_derive_registrar_symbol(it)))
+}
+
+#[derive(Debug)]
+pub(crate) struct ExpansionError(String);
+
+impl fmt::Display for ExpansionError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+#[cfg(feature = "proc_macro_bridge")]
+mod imp {
+    use std::path::Path;
+
+    use libloading::Library;
+    use proc_macro::bridge::{client::ProcMacro, server::SameThread};
+
+    use super::{find_registrar_symbol, invalid_data_err, ExpansionError, ProcMacroKind};
+    use crate::rustc_server::{Rustc, TokenStream};
+
+    pub(crate) struct ProcMacroLibrary {
+        // The macros point into the library, so it has to outlive them.
+        _lib: Library,
+        exported_macros: Vec<ProcMacro>,
+    }
+
+    impl ProcMacroLibrary {
+        pub(crate) fn open(file: &Path) -> std::io::Result<ProcMacroLibrary> {
+            let symbol_name = find_registrar_symbol(file)?.ok_or_else(|| {
+                invalid_data_err(format!("cannot find registrar symbol in {}", file.display()))
+            })?;
+
+            let lib = Library::new(file)?;
+            let exported_macros = {
+                // Safety: the registrar symbol is a `&&[ProcMacro]` if the
+                // dylib was compiled by the same rustc as this server.
+                let macros: libloading::Symbol<&&[ProcMacro]> =
+                    unsafe { lib.get(symbol_name.as_bytes()) }?;
+                macros.to_vec()
+            };
+
+            Ok(ProcMacroLibrary { _lib: lib, exported_macros })
+        }
+
+        pub(crate) fn expand(
+            &self,
+            macro_name: &str,
+            macro_body: &tt::Subtree,
+            attributes: Option<&tt::Subtree>,
+        ) -> Result<tt::Subtree, ExpansionError> {
+            let parsed_body = TokenStream::with_subtree(macro_body.clone());
+            let parsed_attributes = attributes
+                .map_or_else(TokenStream::new, |it| TokenStream::with_subtree(it.clone()));
+
+            for proc_macro in &self.exported_macros {
+                let res = match proc_macro {
+                    ProcMacro::CustomDerive { trait_name, client, .. }
+                        if *trait_name == macro_name =>
+                    {
+                        client.run(&SameThread, Rustc::default(), parsed_body)
+                    }
+                    ProcMacro::Bang { name, client } if *name == macro_name => {
+                        client.run(&SameThread, Rustc::default(), parsed_body)
+                    }
+                    ProcMacro::Attr { name, client } if *name == macro_name => {
+                        client.run(&SameThread, Rustc::default(), parsed_attributes, parsed_body)
+                    }
+                    _ => continue,
+                };
+                return res.map(|it| it.subtree).map_err(|err| {
+                    ExpansionError(err.as_str().unwrap_or("proc macro panicked").to_string())
+                });
+            }
+
+            Err(ExpansionError(format!("no proc macro named {}", macro_name)))
+        }
+
+        pub(crate) fn list_macros(&self) -> Vec<(String, ProcMacroKind)> {
+            self.exported_macros
+                .iter()
+                .map(|proc_macro| match proc_macro {
+                    ProcMacro::CustomDerive { trait_name, attributes, .. } => {
+                        let helpers = attributes.iter().map(|it| it.to_string()).collect();
+                        (trait_name.to_string(), ProcMacroKind::CustomDerive { helpers })
+                    }
+                    ProcMacro::Bang { name, .. } => (name.to_string(), ProcMacroKind::FuncLike),
+                    ProcMacro::Attr { name, .. } => (name.to_string(), ProcMacroKind::Attr),
+                })
+                .collect()
+        }
+    }
+}
+
+#[cfg(not(feature = "proc_macro_bridge"))]
+mod imp {
+    use std::path::Path;
+
+    use super::{find_registrar_symbol, invalid_data_err, ExpansionError, ProcMacroKind};
+
+    /// Can't be constructed: without the bridge, we can't talk to the dylib.
+    pub(crate) enum ProcMacroLibrary {}
+
+    impl ProcMacroLibrary {
+        pub(crate) fn open(file: &Path) -> std::io::Result<ProcMacroLibrary> {
+            // Still check that this is a proc macro dylib, for a better error message.
+            find_registrar_symbol(file)?.ok_or_else(|| {
+                invalid_data_err(format!("cannot find registrar symbol in {}", file.display()))
+            })?;
+            Err(invalid_data_err(
+                "the proc macro server was built without the `proc_macro_bridge` feature",
+            ))
+        }
+
+        pub(crate) fn expand(
+            &self,
+            _macro_name: &str,
+            _macro_body: &tt::Subtree,
+            _attributes: Option<&tt::Subtree>,
+        ) -> Result<tt::Subtree, ExpansionError> {
+            match *self {}
+        }
+
+        pub(crate) fn list_macros(&self) -> Vec<(String, ProcMacroKind)> {
+            match *self {}
+        }
+    }
+}
+
+/// The proc macros of a single dylib.
+pub(crate) struct Expander {
+    path: PathBuf,
+    inner: imp::ProcMacroLibrary,
+}
+
+impl Expander {
+    pub(crate) fn new(lib: &Path) -> Result<Expander, String> {
+        let inner = imp::ProcMacroLibrary::open(lib)
+            .map_err(|err| format!("cannot load proc macros from {}: {}", lib.display(), err))?;
+        Ok(Expander { path: lib.to_path_buf(), inner })
+    }
+
+    pub(crate) fn expand(
+        &self,
+        macro_name: &str,
+        macro_body: &tt::Subtree,
+        attributes: Option<&tt::Subtree>,
+    ) -> Result<tt::Subtree, String> {
+        self.inner
+            .expand(macro_name, macro_body, attributes)
+            .map_err(|err| format!("{} (in {})", err, self.path.display()))
+    }
+
+    pub(crate) fn list_macros(&self) -> Vec<(String, ProcMacroKind)> {
+        self.inner.list_macros()
+    }
+}
@@ -0,0 +1,127 @@
+//! RA Proc Macro Server
+//!
+//! This library loads compiled proc macro dylibs and expands their macros on
+//! `ra_tt` token trees. It is run in a separate process (see `cli`), so that
+//! crashing proc macros or ABI mismatches don't take down the language server.
+//!
+//! The proc macros in the dylib talk to us via the (unstable) `proc_macro`
+//! bridge: `rustc_server` implements its server side on top of `ra_tt`.
+
+#![cfg_attr(
+    feature = "proc_macro_bridge",
+    feature(proc_macro_internals, proc_macro_diagnostic, proc_macro_span)
+)]
+
+#[cfg(feature = "proc_macro_bridge")]
+extern crate proc_macro;
+
+#[cfg(feature = "proc_macro_bridge")]
+mod rustc_server;
+mod dylib;
+
+pub mod cli;
+
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use ra_proc_macro::{ExpansionResult, ExpansionTask, ListMacrosResult, ListMacrosTask};
+
+#[derive(Default)]
+pub(crate) struct ProcMacroSrv {
+    expanders: HashMap<PathBuf, (DylibStamp, dylib::Expander)>,
+}
+
+impl ProcMacroSrv {
+    pub fn expand(&mut self, task: &ExpansionTask) -> Result<ExpansionResult, String> {
+        let expander = self.expander(&task.lib)?;
+        match expander.expand(&task.macro_name, &task.macro_body, task.attributes.as_ref()) {
+            Ok(expansion) => Ok(ExpansionResult { expansion }),
+            Err(msg) => {
+                Err(format!("cannot perform expansion for {}: error {}", &task.macro_name, msg))
+            }
+        }
+    }
+
+    pub fn list_macros(&mut self, task: &ListMacrosTask) -> Result<ListMacrosResult, String> {
+        let expander = self.expander(&task.lib)?;
+        Ok(ListMacrosResult { macros: expander.list_macros() })
+    }
+
+    fn expander(&mut self, path: &Path) -> Result<&dylib::Expander, String> {
+        cached(&mut self.expanders, path, dylib::Expander::new)
+    }
+}
+
+/// Identifies a build of a dylib. Cargo rebuilds a proc-macro crate into the
+/// same path, so the path alone doesn't tell whether the dylib changed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct DylibStamp {
+    modified: SystemTime,
+    len: u64,
+}
+
+impl DylibStamp {
+    fn new(path: &Path) -> Result<DylibStamp, String> {
+        let metadata = fs::metadata(path)
+            .map_err(|err| format!("Cannot read metadata of {}: {}", path.display(), err))?;
+        let modified = metadata
+            .modified()
+            .map_err(|err| format!("Cannot read metadata of {}: {}", path.display(), err))?;
+        Ok(DylibStamp { modified, len: metadata.len() })
+    }
+}
+
+/// Returns the value loaded from the dylib at `path`, loading it anew if the
+/// dylib changed since it was loaded.
+fn cached<'a, T>(
+    cache: &'a mut HashMap<PathBuf, (DylibStamp, T)>,
+    path: &Path,
+    load: impl FnOnce(&Path) -> Result<T, String>,
+) -> Result<&'a T, String> {
+    let stamp = DylibStamp::new(path)?;
+    match cache.get(path) {
+        Some((loaded, _)) if *loaded == stamp => (),
+        _ => {
+            // The old library is unloaded first, as loading a library with
+            // the same path as a loaded one just returns the loaded one.
+            cache.remove(path);
+            let value = load(path)?;
+            cache.insert(path.to_path_buf(), (stamp, value));
+        }
+    }
+    Ok(&cache[path].1)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::Cell, collections::HashMap, fs, path::Path};
+
+    use super::cached;
+
+    #[test]
+    fn reloads_changed_dylib() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("libfoo.so");
+        let mut cache = HashMap::new();
+        let loads = Cell::new(0);
+        let load = |_: &Path| {
+            loads.set(loads.get() + 1);
+            Ok(loads.get())
+        };
+
+        fs::write(&path, "v1").unwrap();
+        assert_eq!(cached(&mut cache, &path, load), Ok(&1));
+        assert_eq!(cached(&mut cache, &path, load), Ok(&1));
+
+        fs::write(&path, "v2 (rebuilt)").unwrap();
+        assert_eq!(cached(&mut cache, &path, load), Ok(&2));
+        assert_eq!(cached(&mut cache, &path, load), Ok(&2));
+
+        fs::remove_file(&path).unwrap();
+        assert!(cached(&mut cache, &path, load).is_err());
+    }
+}
@@ -0,0 +1,9 @@
+//! The proc macro server binary, which is spawned by `ra_lsp_server` and
+//! talks to it via stdin/stdout.
+
+fn main() {
+    if let Err(err) = ra_proc_macro_srv::cli::run() {
+        eprintln!("proc macro server failed: {}", err);
+        std::process::exit(1);
+    }
+}
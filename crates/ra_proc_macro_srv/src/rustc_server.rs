@@ -0,0 +1,573 @@
+//! Rustc proc-macro server implementation with ra_tt
+//!
+//! Based on idea from https://github.com/fedochet/rust-proc-macro-expander
+//! The lib-proc-macro server backend is `TokenStream`-agnostic, such that
+//! we could provide any TokenStream implementation.
+//! The original idea from fedochet is using proc-macro2 as backend,
+//! we use ra_tt instead for better intergation with RA.
+//!
+//! FIXME: No span and source file information is implemented yet
+
+use std::{collections::HashMap, iter::FromIterator, ops::Bound, vec::IntoIter};
+
+use proc_macro::{
+    bridge::{self, server},
+    Delimiter, Level, LineColumn, Spacing,
+};
+use ra_syntax::{ast, AstNode, SourceFile as SyntaxSourceFile};
+
+type Group = tt::Subtree;
+type TokenTree = tt::TokenTree;
+type Punct = tt::Punct;
+type Literal = tt::Literal;
+type Span = tt::TokenId;
+
+#[derive(Debug, Clone)]
+pub struct TokenStream {
+    pub subtree: tt::Subtree,
+}
+
+impl Default for TokenStream {
+    fn default() -> TokenStream {
+        TokenStream::new()
+    }
+}
+
+impl TokenStream {
+    pub fn new() -> Self {
+        TokenStream {
+            subtree: tt::Subtree { delimiter: tt::Delimiter::None, token_trees: Vec::new() },
+        }
+    }
+
+    /// The delimiter of `subtree`, like the parens of a function-like macro
+    /// call, is not part of the stream.
+    pub fn with_subtree(subtree: tt::Subtree) -> Self {
+        TokenStream {
+            subtree: tt::Subtree {
+                delimiter: tt::Delimiter::None,
+                token_trees: subtree.token_trees,
+            },
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.subtree.token_trees.is_empty()
+    }
+
+    fn from_str(src: &str) -> Option<TokenStream> {
+        // Wrap the text into a macro call, to reuse the conversion of
+        // `ast::TokenTree`s.
+        let text = format!("m!{{{}}}", src);
+        let parse = SyntaxSourceFile::parse(&text);
+        if !parse.errors().is_empty() {
+            return None;
+        }
+        let token_tree = parse.tree().syntax().descendants().find_map(ast::TokenTree::cast)?;
+        let (subtree, _token_map) = mbe::ast_to_token_tree(&token_tree)?;
+        Some(TokenStream {
+            subtree: tt::Subtree {
+                delimiter: tt::Delimiter::None,
+                token_trees: subtree.token_trees,
+            },
+        })
+    }
+}
+
+/// Creates a token stream containing a single token tree.
+impl From<TokenTree> for TokenStream {
+    fn from(tree: TokenTree) -> TokenStream {
+        TokenStream {
+            subtree: tt::Subtree { delimiter: tt::Delimiter::None, token_trees: vec![tree] },
+        }
+    }
+}
+
+/// Collects a number of token trees into a single stream.
+impl FromIterator<TokenTree> for TokenStream {
+    fn from_iter<I: IntoIterator<Item = TokenTree>>(trees: I) -> Self {
+        trees.into_iter().map(TokenStream::from).collect()
+    }
+}
+
+/// A "flattening" operation on token streams, collects token trees
+/// from multiple token streams into a single stream.
+impl FromIterator<TokenStream> for TokenStream {
+    fn from_iter<I: IntoIterator<Item = TokenStream>>(streams: I) -> Self {
+        let mut builder = TokenStreamBuilder::new();
+        streams.into_iter().for_each(|stream| builder.push(stream));
+        builder.build()
+    }
+}
+
+impl Extend<TokenStream> for TokenStream {
+    fn extend<I: IntoIterator<Item = TokenStream>>(&mut self, streams: I) {
+        for stream in streams {
+            for tree in stream.subtree.token_trees {
+                match tree {
+                    // Invisible groups are flattened into the stream
+                    tt::TokenTree::Subtree(subtree) if subtree.delimiter == tt::Delimiter::None => {
+                        self.subtree.token_trees.extend(subtree.token_trees);
+                    }
+                    _ => self.subtree.token_trees.push(tree),
+                }
+            }
+        }
+    }
+}
+
+impl IntoIterator for TokenStream {
+    type Item = TokenTree;
+    type IntoIter = IntoIter<TokenTree>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.subtree.token_trees.into_iter()
+    }
+}
+
+fn to_text(tree: &tt::TokenTree) -> String {
+    match tree {
+        tt::TokenTree::Leaf(tt::Leaf::Ident(ident)) => ident.text.to_string(),
+        tt::TokenTree::Leaf(tt::Leaf::Literal(literal)) => literal.text.to_string(),
+        tt::TokenTree::Leaf(tt::Leaf::Punct(punct)) => punct.char.to_string(),
+        tt::TokenTree::Subtree(subtree) => {
+            let (open, close) = match subtree.delimiter {
+                tt::Delimiter::Brace => ("{", "}"),
+                tt::Delimiter::Bracket => ("[", "]"),
+                tt::Delimiter::Parenthesis => ("(", ")"),
+                tt::Delimiter::None => ("", ""),
+            };
+            format!("{}{}{}", open, trees_to_text(&subtree.token_trees), close)
+        }
+    }
+}
+
+fn trees_to_text(trees: &[tt::TokenTree]) -> String {
+    let mut res = String::new();
+    for tree in trees {
+        res.push_str(&to_text(tree));
+        let is_joint = match tree {
+            tt::TokenTree::Leaf(tt::Leaf::Punct(punct)) => punct.spacing == tt::Spacing::Joint,
+            _ => false,
+        };
+        if !is_joint {
+            res.push(' ');
+        }
+    }
+    res.trim_end().to_string()
+}
+
+/// A diagnostic emitted by a proc macro. For now, we only log them.
+#[derive(Clone, Debug)]
+pub struct Diagnostic {
+    level: Level,
+    message: String,
+}
+
+// The bridge requires idents to be `Copy`, so we intern them.
+#[derive(Hash, Eq, PartialEq, Copy, Clone)]
+pub struct IdentId(u32);
+
+#[derive(Default)]
+struct IdentInterner {
+    idents: HashMap<tt::Ident, u32>,
+    ident_data: Vec<tt::Ident>,
+}
+
+impl IdentInterner {
+    fn intern(&mut self, data: &tt::Ident) -> IdentId {
+        if let Some(index) = self.idents.get(data) {
+            return IdentId(*index);
+        }
+        let index = self.ident_data.len() as u32;
+        self.ident_data.push(data.clone());
+        self.idents.insert(data.clone(), index);
+        IdentId(index)
+    }
+
+    fn get(&self, id: IdentId) -> &tt::Ident {
+        &self.ident_data[id.0 as usize]
+    }
+}
+
+pub struct TokenStreamBuilder {
+    acc: TokenStream,
+}
+
+impl TokenStreamBuilder {
+    fn new() -> TokenStreamBuilder {
+        TokenStreamBuilder { acc: TokenStream::new() }
+    }
+
+    fn push(&mut self, stream: TokenStream) {
+        self.acc.extend(std::iter::once(stream))
+    }
+
+    fn build(self) -> TokenStream {
+        self.acc
+    }
+}
+
+#[derive(Clone)]
+pub struct TokenStreamIter {
+    trees: IntoIter<TokenTree>,
+}
+
+/// We don't track source files yet.
+#[derive(Clone, PartialEq, Eq)]
+pub struct SourceFile;
+
+#[derive(Default)]
+pub struct Rustc {
+    ident_interner: IdentInterner,
+}
+
+impl server::Types for Rustc {
+    type TokenStream = TokenStream;
+    type TokenStreamBuilder = TokenStreamBuilder;
+    type TokenStreamIter = TokenStreamIter;
+    type Group = Group;
+    type Punct = Punct;
+    type Ident = IdentId;
+    type Literal = Literal;
+    type SourceFile = SourceFile;
+    type Diagnostic = Diagnostic;
+    type Span = Span;
+    type MultiSpan = Vec<Span>;
+}
+
+impl server::TokenStream for Rustc {
+    fn new(&mut self) -> Self::TokenStream {
+        TokenStream::new()
+    }
+
+    fn is_empty(&mut self, stream: &Self::TokenStream) -> bool {
+        stream.is_empty()
+    }
+
+    fn from_str(&mut self, src: &str) -> Self::TokenStream {
+        // The bridge has no way to report errors here, so a panic it is, just
+        // like in rustc.
+        TokenStream::from_str(src).unwrap_or_else(|| panic!("cannot parse string: {:?}", src))
+    }
+
+    fn to_string(&mut self, stream: &Self::TokenStream) -> String {
+        trees_to_text(&stream.subtree.token_trees)
+    }
+
+    fn from_token_tree(
+        &mut self,
+        tree: bridge::TokenTree<Self::Group, Self::Punct, Self::Ident, Self::Literal>,
+    ) -> Self::TokenStream {
+        let tree: TokenTree = match tree {
+            bridge::TokenTree::Group(group) => group.into(),
+            bridge::TokenTree::Ident(id) => {
+                tt::Leaf::from(self.ident_interner.get(id).clone()).into()
+            }
+            bridge::TokenTree::Literal(literal) => tt::Leaf::from(literal).into(),
+            bridge::TokenTree::Punct(punct) => tt::Leaf::from(punct).into(),
+        };
+        tree.into()
+    }
+
+    fn into_iter(&mut self, stream: Self::TokenStream) -> Self::TokenStreamIter {
+        TokenStreamIter { trees: stream.into_iter() }
+    }
+}
+
+fn delim_to_internal(d: Delimiter) -> tt::Delimiter {
+    match d {
+        Delimiter::Parenthesis => tt::Delimiter::Parenthesis,
+        Delimiter::Brace => tt::Delimiter::Brace,
+        Delimiter::Bracket => tt::Delimiter::Bracket,
+        Delimiter::None => tt::Delimiter::None,
+    }
+}
+
+fn delim_to_external(d: tt::Delimiter) -> Delimiter {
+    match d {
+        tt::Delimiter::Parenthesis => Delimiter::Parenthesis,
+        tt::Delimiter::Brace => Delimiter::Brace,
+        tt::Delimiter::Bracket => Delimiter::Bracket,
+        tt::Delimiter::None => Delimiter::None,
+    }
+}
+
+fn spacing_to_internal(spacing: Spacing) -> tt::Spacing {
+    match spacing {
+        Spacing::Alone => tt::Spacing::Alone,
+        Spacing::Joint => tt::Spacing::Joint,
+    }
+}
+
+fn spacing_to_external(spacing: tt::Spacing) -> Spacing {
+    match spacing {
+        tt::Spacing::Alone => Spacing::Alone,
+        tt::Spacing::Joint => Spacing::Joint,
+    }
+}
+
+impl server::TokenStreamBuilder for Rustc {
+    fn new(&mut self) -> Self::TokenStreamBuilder {
+        TokenStreamBuilder::new()
+    }
+
+    fn push(&mut self, builder: &mut Self::TokenStreamBuilder, stream: Self::TokenStream) {
+        builder.push(stream)
+    }
+
+    fn build(&mut self, builder: Self::TokenStreamBuilder) -> Self::TokenStream {
+        builder.build()
+    }
+}
+
+impl server::TokenStreamIter for Rustc {
+    fn next(
+        &mut self,
+        iter: &mut Self::TokenStreamIter,
+    ) -> Option<bridge::TokenTree<Self::Group, Self::Punct, Self::Ident, Self::Literal>> {
+        iter.trees.next().map(|tree| match tree {
+            TokenTree::Subtree(group) => bridge::TokenTree::Group(group),
+            TokenTree::Leaf(tt::Leaf::Ident(ident)) => {
+                bridge::TokenTree::Ident(self.ident_interner.intern(&ident))
+            }
+            TokenTree::Leaf(tt::Leaf::Literal(literal)) => bridge::TokenTree::Literal(literal),
+            TokenTree::Leaf(tt::Leaf::Punct(punct)) => bridge::TokenTree::Punct(punct),
+        })
+    }
+}
+
+impl server::Group for Rustc {
+    fn new(&mut self, delimiter: Delimiter, stream: Self::TokenStream) -> Self::Group {
+        tt::Subtree {
+            delimiter: delim_to_internal(delimiter),
+            token_trees: stream.subtree.token_trees,
+        }
+    }
+
+    fn delimiter(&mut self, group: &Self::Group) -> Delimiter {
+        delim_to_external(group.delimiter)
+    }
+
+    fn stream(&mut self, group: &Self::Group) -> Self::TokenStream {
+        TokenStream {
+            subtree: tt::Subtree {
+                delimiter: tt::Delimiter::None,
+                token_trees: group.token_trees.clone(),
+            },
+        }
+    }
+
+    // Delimiters don't carry token ids in `ra_tt`.
+    fn span(&mut self, _group: &Self::Group) -> Self::Span {
+        tt::TokenId::unspecified()
+    }
+
+    fn set_span(&mut self, _group: &mut Self::Group, _span: Self::Span) {}
+
+    fn span_open(&mut self, _group: &Self::Group) -> Self::Span {
+        tt::TokenId::unspecified()
+    }
+
+    fn span_close(&mut self, _group: &Self::Group) -> Self::Span {
+        tt::TokenId::unspecified()
+    }
+}
+
+impl server::Punct for Rustc {
+    fn new(&mut self, ch: char, spacing: Spacing) -> Self::Punct {
+        tt::Punct { char: ch, spacing: spacing_to_internal(spacing) }
+    }
+
+    fn as_char(&mut self, punct: Self::Punct) -> char {
+        punct.char
+    }
+
+    fn spacing(&mut self, punct: Self::Punct) -> Spacing {
+        spacing_to_external(punct.spacing)
+    }
+
+    // Puncts don't carry token ids in `ra_tt`.
+    fn span(&mut self, _punct: Self::Punct) -> Self::Span {
+        tt::TokenId::unspecified()
+    }
+
+    fn with_span(&mut self, punct: Self::Punct, _span: Self::Span) -> Self::Punct {
+        punct
+    }
+}
+
+impl server::Ident for Rustc {
+    fn new(&mut self, string: &str, span: Self::Span, is_raw: bool) -> Self::Ident {
+        let text = if is_raw { format!("r#{}", string) } else { string.to_string() };
+        self.ident_interner.intern(&tt::Ident { text: text.into(), id: span })
+    }
+
+    fn span(&mut self, ident: Self::Ident) -> Self::Span {
+        self.ident_interner.get(ident).id
+    }
+
+    fn with_span(&mut self, ident: Self::Ident, span: Self::Span) -> Self::Ident {
+        let ident = tt::Ident { id: span, ..self.ident_interner.get(ident).clone() };
+        self.ident_interner.intern(&ident)
+    }
+}
+
+fn literal(text: String) -> Literal {
+    tt::Literal { text: text.into() }
+}
+
+impl server::Literal for Rustc {
+    fn debug(&mut self, literal: &Self::Literal) -> String {
+        format!("{:?}", literal)
+    }
+
+    fn integer(&mut self, n: &str) -> Self::Literal {
+        literal(n.to_string())
+    }
+
+    fn typed_integer(&mut self, n: &str, kind: &str) -> Self::Literal {
+        literal(format!("{}{}", n, kind))
+    }
+
+    fn float(&mut self, n: &str) -> Self::Literal {
+        let mut text = n.to_string();
+        if !text.contains('.') {
+            text.push_str(".0");
+        }
+        literal(text)
+    }
+
+    fn f32(&mut self, n: &str) -> Self::Literal {
+        literal(format!("{}f32", n))
+    }
+
+    fn f64(&mut self, n: &str) -> Self::Literal {
+        literal(format!("{}f64", n))
+    }
+
+    fn string(&mut self, string: &str) -> Self::Literal {
+        literal(format!("\"{}\"", string.escape_debug()))
+    }
+
+    fn character(&mut self, ch: char) -> Self::Literal {
+        literal(format!("'{}'", ch.escape_debug()))
+    }
+
+    fn byte_string(&mut self, bytes: &[u8]) -> Self::Literal {
+        let escaped = bytes
+            .iter()
+            .cloned()
+            .flat_map(std::ascii::escape_default)
+            .map(char::from)
+            .collect::<String>();
+        literal(format!("b\"{}\"", escaped))
+    }
+
+    // Literals don't carry token ids in `ra_tt`.
+    fn span(&mut self, _literal: &Self::Literal) -> Self::Span {
+        tt::TokenId::unspecified()
+    }
+
+    fn set_span(&mut self, _literal: &mut Self::Literal, _span: Self::Span) {}
+
+    fn subspan(
+        &mut self,
+        _literal: &Self::Literal,
+        _start: Bound<usize>,
+        _end: Bound<usize>,
+    ) -> Option<Self::Span> {
+        None
+    }
+}
+
+impl server::SourceFile for Rustc {
+    fn eq(&mut self, file1: &Self::SourceFile, file2: &Self::SourceFile) -> bool {
+        file1 == file2
+    }
+
+    fn path(&mut self, _file: &Self::SourceFile) -> String {
+        String::new()
+    }
+
+    fn is_real(&mut self, _file: &Self::SourceFile) -> bool {
+        false
+    }
+}
+
+impl server::Diagnostic for Rustc {
+    fn new(&mut self, level: Level, msg: &str, _spans: Self::MultiSpan) -> Self::Diagnostic {
+        Diagnostic { level, message: msg.to_string() }
+    }
+
+    fn sub(
+        &mut self,
+        _diag: &mut Self::Diagnostic,
+        _level: Level,
+        _msg: &str,
+        _spans: Self::MultiSpan,
+    ) {
+        // FIXME: report diagnostics to the client
+    }
+
+    fn emit(&mut self, diag: Self::Diagnostic) {
+        // FIXME: report diagnostics to the client
+        eprintln!("proc macro {:?}: {}", diag.level, diag.message);
+    }
+}
+
+impl server::Span for Rustc {
+    fn debug(&mut self, span: Self::Span) -> String {
+        format!("{:?}", span.0)
+    }
+
+    fn def_site(&mut self) -> Self::Span {
+        tt::TokenId::unspecified()
+    }
+
+    fn call_site(&mut self) -> Self::Span {
+        tt::TokenId::unspecified()
+    }
+
+    fn source_file(&mut self, _span: Self::Span) -> Self::SourceFile {
+        SourceFile
+    }
+
+    fn parent(&mut self, _span: Self::Span) -> Option<Self::Span> {
+        None
+    }
+
+    fn source(&mut self, span: Self::Span) -> Self::Span {
+        span
+    }
+
+    fn start(&mut self, _span: Self::Span) -> LineColumn {
+        LineColumn { line: 0, column: 0 }
+    }
+
+    fn end(&mut self, _span: Self::Span) -> LineColumn {
+        LineColumn { line: 0, column: 0 }
+    }
+
+    fn join(&mut self, _first: Self::Span, _second: Self::Span) -> Option<Self::Span> {
+        None
+    }
+
+    fn resolved_at(&mut self, span: Self::Span, _at: Self::Span) -> Self::Span {
+        span
+    }
+
+    fn source_text(&mut self, _span: Self::Span) -> Option<String> {
+        None
+    }
+}
+
+impl server::MultiSpan for Rustc {
+    fn new(&mut self) -> Self::MultiSpan {
+        Vec::new()
+    }
+
+    fn push(&mut self, spans: &mut Self::MultiSpan, span: Self::Span) {
+        spans.push(span)
+    }
+}
@@ -1,11 +1,16 @@
 //! FIXME: write short doc here
 
-use std::path::{Path, PathBuf};
+use std::{
+    io::{BufRead, BufReader},
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+};
 
-use cargo_metadata::{CargoOpt, MetadataCommand};
+use cargo_metadata::{CargoOpt, MetadataCommand, PackageId};
 use ra_arena::{impl_arena_id, Arena, RawId};
 use ra_db::Edition;
 use rustc_hash::FxHashMap;
+use serde::Deserialize;
 
 use crate::Result;
 
@@ -23,6 +28,39 @@ pub struct CargoWorkspace {
     pub(crate) workspace_root: PathBuf,
 }
 
+#[derive(Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(rename_all = "camelCase", default)]
+pub struct CargoConfig {
+    /// Run `cargo check` to collect the outputs of build scripts, like
+    /// `OUT_DIR`, cfgs and environment variables.
+    pub load_out_dirs_from_check: bool,
+
+    /// Do not activate the `default` feature.
+    pub no_default_features: bool,
+
+    /// Activate all available features.
+    pub all_features: bool,
+
+    /// List of features to activate.
+    /// This will be ignored if `all_features` is set.
+    pub features: Vec<String>,
+
+    /// Target triple to analyze the workspace for, instead of the host one.
+    pub target: Option<String>,
+}
+
+impl Default for CargoConfig {
+    fn default() -> Self {
+        CargoConfig {
+            load_out_dirs_from_check: false,
+            no_default_features: false,
+            all_features: true,
+            features: Vec::new(),
+            target: None,
+        }
+    }
+}
+
 #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
 pub struct Package(RawId);
 impl_arena_id!(Package);
@@ -33,6 +71,7 @@ impl_arena_id!(Target);
 
 #[derive(Debug, Clone)]
 struct PackageData {
+    id: PackageId,
     name: String,
     manifest: PathBuf,
     targets: Vec<Target>,
@@ -40,6 +79,10 @@ struct PackageData {
     dependencies: Vec<PackageDependency>,
     edition: Edition,
     features: Vec<String>,
+    out_dir: Option<PathBuf>,
+    cfgs: Vec<String>,
+    envs: Vec<(String, String)>,
+    proc_macro_dylib_path: Option<PathBuf>,
 }
 
 #[derive(Debug, Clone)]
@@ -98,6 +141,22 @@ impl Package {
     pub fn features(self, ws: &CargoWorkspace) -> &[String] {
         &ws.packages[self].features
     }
+    /// The `OUT_DIR` of the build script, if any.
+    pub fn out_dir(self, ws: &CargoWorkspace) -> Option<&Path> {
+        ws.packages[self].out_dir.as_ref().map(PathBuf::as_path)
+    }
+    /// cfgs set by the build script, in `rustc --print cfg` format.
+    pub fn cfgs(self, ws: &CargoWorkspace) -> &[String] {
+        &ws.packages[self].cfgs
+    }
+    /// Environment variables set by the build script via `cargo:rustc-env`.
+    pub fn envs(self, ws: &CargoWorkspace) -> &[(String, String)] {
+        &ws.packages[self].envs
+    }
+    /// The compiled dylib of the proc-macro target of this package, if any.
+    pub fn proc_macro_dylib_path(self, ws: &CargoWorkspace) -> Option<&Path> {
+        ws.packages[self].proc_macro_dylib_path.as_ref().map(PathBuf::as_path)
+    }
     pub fn targets<'a>(self, ws: &'a CargoWorkspace) -> impl Iterator<Item = Target> + 'a {
         ws.packages[self].targets.iter().cloned()
     }
@@ -132,9 +191,24 @@ impl Target {
 }
 
 impl CargoWorkspace {
-    pub fn from_cargo_metadata(cargo_toml: &Path) -> Result<CargoWorkspace> {
+    pub fn from_cargo_metadata(
+        cargo_toml: &Path,
+        cargo_config: &CargoConfig,
+    ) -> Result<CargoWorkspace> {
         let mut meta = MetadataCommand::new();
-        meta.manifest_path(cargo_toml).features(CargoOpt::AllFeatures);
+        meta.manifest_path(cargo_toml);
+        if cargo_config.all_features {
+            meta.features(CargoOpt::AllFeatures);
+        } else {
+            if cargo_config.no_default_features {
+                meta.features(CargoOpt::NoDefaultFeatures);
+            }
+            if !cargo_config.features.is_empty() {
+                // `NoDefaultFeatures` is mutually exclusive with `SomeFeatures`,
+                // see https://github.com/oli-obk/cargo_metadata/issues/79
+                meta.other_options(vec!["--features".to_string(), cargo_config.features.join(" ")]);
+            }
+        }
         if let Some(parent) = cargo_toml.parent() {
             meta.current_dir(parent);
         }
@@ -150,6 +224,7 @@ impl CargoWorkspace {
             let is_member = ws_members.contains(&id);
             let edition = edition.parse::<Edition>()?;
             let pkg = packages.alloc(PackageData {
+                id: id.clone(),
                 name,
                 manifest: manifest_path,
                 targets: Vec::new(),
@@ -157,6 +232,10 @@ impl CargoWorkspace {
                 edition,
                 dependencies: Vec::new(),
                 features: Vec::new(),
+                out_dir: None,
+                cfgs: Vec::new(),
+                envs: Vec::new(),
+                proc_macro_dylib_path: None,
             });
             let pkg_data = &mut packages[pkg];
             pkg_by_id.insert(id, pkg);
@@ -192,4 +271,172 @@ impl CargoWorkspace {
     pub fn target_by_root(&self, root: &Path) -> Option<Target> {
         self.packages().filter_map(|pkg| pkg.targets(self).find(|it| it.root(self) == root)).next()
     }
+
+    /// Runs `cargo check` to execute the build scripts of the workspace and
+    /// collects their outputs, as well as the compiled proc-macro dylibs.
+    ///
+    /// This can take a long time, so it is not done by `from_cargo_metadata`.
+    pub fn load_build_outputs(&self, cargo_config: &CargoConfig) -> Result<BuildOutputs> {
+        load_build_outputs(&self.workspace_root.join("Cargo.toml"), cargo_config).map(
+            |(packages, error)| BuildOutputs {
+                workspace_root: self.workspace_root.clone(),
+                packages,
+                error,
+            },
+        )
+    }
+
+    /// Applies the outputs of `load_build_outputs`. Returns `false` if they
+    /// are for another workspace.
+    pub fn set_build_outputs(&mut self, outputs: &BuildOutputs) -> bool {
+        if outputs.workspace_root != self.workspace_root {
+            return false;
+        }
+        for (_, pkg) in self.packages.iter_mut() {
+            let BuildOutput { out_dir, cfgs, env, proc_macro_dylib_path } =
+                outputs.packages.get(&pkg.id).cloned().unwrap_or_default();
+            pkg.out_dir = out_dir;
+            pkg.cfgs = cfgs;
+            pkg.envs = env;
+            pkg.proc_macro_dylib_path = proc_macro_dylib_path;
+        }
+        true
+    }
+}
+
+/// The outputs of the build scripts of a workspace, loaded by
+/// `CargoWorkspace::load_build_outputs`.
+#[derive(Debug, Clone)]
+pub struct BuildOutputs {
+    workspace_root: PathBuf,
+    packages: FxHashMap<PackageId, BuildOutput>,
+    error: Option<String>,
+}
+
+impl BuildOutputs {
+    pub fn workspace_root(&self) -> &Path {
+        &self.workspace_root
+    }
+
+    /// Why `cargo check` failed, if it did. The outputs of the build scripts
+    /// which did run are still available.
+    pub fn error(&self) -> Option<&str> {
+        self.error.as_ref().map(String::as_str)
+    }
+}
+
+/// What we know about a package after running `cargo check`.
+#[derive(Debug, Clone, Default)]
+struct BuildOutput {
+    out_dir: Option<PathBuf>,
+    cfgs: Vec<String>,
+    env: Vec<(String, String)>,
+    proc_macro_dylib_path: Option<PathBuf>,
+}
+
+/// The parts of `cargo check --message-format=json` messages we are
+/// interested in. Messages with other reasons fail to deserialize and are
+/// skipped.
+#[derive(Deserialize)]
+#[serde(tag = "reason", rename_all = "kebab-case")]
+enum CargoMessage {
+    BuildScriptExecuted {
+        package_id: PackageId,
+        #[serde(default)]
+        out_dir: Option<PathBuf>,
+        #[serde(default)]
+        cfgs: Vec<String>,
+        #[serde(default)]
+        env: Vec<(String, String)>,
+    },
+    CompilerArtifact {
+        package_id: PackageId,
+        target: ArtifactTarget,
+        #[serde(default)]
+        filenames: Vec<PathBuf>,
+    },
+}
+
+#[derive(Deserialize)]
+struct ArtifactTarget {
+    kind: Vec<String>,
+}
+
+fn is_dylib(path: &Path) -> bool {
+    match path.extension().and_then(|ext| ext.to_str()) {
+        Some("so") | Some("dylib") | Some("dll") => true,
+        _ => false,
+    }
+}
+
+/// Returns the outputs collected from `cargo check` and, if it failed, why.
+fn load_build_outputs(
+    cargo_toml: &Path,
+    cargo_config: &CargoConfig,
+) -> Result<(FxHashMap<PackageId, BuildOutput>, Option<String>)> {
+    let mut res = FxHashMap::default();
+    let mut cmd = Command::new("cargo");
+    cmd.args(&["check", "--message-format=json", "--all-targets", "--manifest-path"])
+        .arg(cargo_toml);
+    if cargo_config.all_features {
+        cmd.arg("--all-features");
+    } else {
+        if cargo_config.no_default_features {
+            cmd.arg("--no-default-features");
+        }
+        if !cargo_config.features.is_empty() {
+            cmd.arg("--features").arg(cargo_config.features.join(" "));
+        }
+    }
+    if let Some(target) = &cargo_config.target {
+        cmd.args(&["--target", target]);
+    }
+    let mut child = cmd
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .stdin(Stdio::null())
+        .spawn()
+        .map_err(|e| format!("failed to run cargo check: {}", e))?;
+    let mut error = None;
+    if let Some(stdout) = child.stdout.take() {
+        for line in BufReader::new(stdout).lines() {
+            let line = match line {
+                Ok(line) => line,
+                Err(e) => {
+                    error = Some(format!("failed to read cargo check output: {}", e));
+                    break;
+                }
+            };
+            let message = match serde_json::from_str::<CargoMessage>(&line) {
+                Ok(message) => message,
+                Err(_) => continue,
+            };
+            match message {
+                CargoMessage::BuildScriptExecuted { package_id, out_dir, cfgs, env } => {
+                    let output: &mut BuildOutput = res.entry(package_id).or_default();
+                    output.out_dir = out_dir;
+                    output.cfgs = cfgs;
+                    output.env = env;
+                }
+                CargoMessage::CompilerArtifact { package_id, target, filenames } => {
+                    if target.kind.iter().any(|kind| kind == "proc-macro") {
+                        if let Some(dylib) = filenames.into_iter().find(|it| is_dylib(it)) {
+                            let output: &mut BuildOutput = res.entry(package_id).or_default();
+                            output.proc_macro_dylib_path = Some(dylib);
+                        }
+                    }
+                }
+            }
+        }
+    }
+    match child.wait() {
+        Ok(status) if status.success() => (),
+        Ok(status) => {
+            error.get_or_insert_with(|| format!("cargo check failed ({})", status));
+        }
+        Err(e) => {
+            error.get_or_insert_with(|| format!("failed to wait for cargo check: {}", e));
+        }
+    }
+    Ok((res, error))
 }
@@ -1,9 +1,15 @@
-//! FIXME: write short doc here
+//! `rust-project.json` is a way to describe a project to rust-analyzer without
+//! Cargo, for example when it is built with Bazel or Buck.
 
-use std::path::PathBuf;
+use std::{
+    error::Error,
+    fmt,
+    path::{Path, PathBuf},
+};
 
 use rustc_hash::{FxHashMap, FxHashSet};
 use serde::Deserialize;
+use serde_json::Value;
 
 /// A root points to the directory which contains Rust crates. rust-analyzer watches all files in
 /// all roots. Roots might be nested.
@@ -17,11 +23,34 @@ pub struct Root {
 /// useful in creating the crate graph.
 #[derive(Clone, Debug, Deserialize)]
 pub struct Crate {
+    /// Name used to refer to the crate in the UI, defaults to the name under which
+    /// other crates depend on it.
+    #[serde(default)]
+    pub(crate) display_name: Option<String>,
     pub(crate) root_module: PathBuf,
     pub(crate) edition: Edition,
     pub(crate) deps: Vec<Dep>,
     pub(crate) atom_cfgs: FxHashSet<String>,
     pub(crate) key_value_cfgs: FxHashMap<String, String>,
+    /// Environment variables visible to `env!` in the crate.
+    #[serde(default)]
+    pub(crate) env: FxHashMap<String, String>,
+    /// Directories with the source code of the crate, in addition to `roots`.
+    #[serde(default)]
+    pub(crate) source: Option<CrateSource>,
+    #[serde(default)]
+    pub(crate) is_proc_macro: bool,
+    /// Path to the compiled proc-macro dylib, required if `is_proc_macro` is set.
+    #[serde(default)]
+    pub(crate) proc_macro_dylib_path: Option<PathBuf>,
+}
+
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct CrateSource {
+    #[serde(default)]
+    pub(crate) include_dirs: Vec<PathBuf>,
+    #[serde(default)]
+    pub(crate) exclude_dirs: Vec<PathBuf>,
 }
 
 #[derive(Clone, Copy, Debug, Deserialize)]
@@ -49,6 +78,362 @@ pub struct Dep {
 /// Roots and crates that compose this Rust project.
 #[derive(Clone, Debug, Deserialize)]
 pub struct JsonProject {
+    /// Path to the source of the standard library. If set, the sysroot crates are
+    /// added to the project and every crate implicitly depends on them.
+    #[serde(default)]
+    pub(crate) sysroot_src: Option<PathBuf>,
     pub(crate) roots: Vec<Root>,
     pub(crate) crates: Vec<Crate>,
 }
+
+impl JsonProject {
+    /// Parses and validates `rust-project.json`. Errors point to the offending
+    /// field, like `crates[1].edition`. Unknown fields are ignored with a
+    /// warning, as generators may add their own metadata.
+    pub fn from_json(json: Value) -> Result<JsonProject, SchemaError> {
+        let (project, ignored) = parse_project(json)?;
+        for path in ignored {
+            log::warn!("ignoring unknown field in rust-project.json: `{}`", path);
+        }
+        Ok(project)
+    }
+
+    /// Adds the crates and roots of `other` which are not yet present in this
+    /// project. Crates are identified by their root module.
+    pub fn merge(&mut self, other: &JsonProject) {
+        let mut ids = Vec::with_capacity(other.crates.len());
+        let mut next_id = self.crates.len();
+        for krate in &other.crates {
+            match self.crates.iter().position(|it| it.root_module == krate.root_module) {
+                Some(idx) => ids.push((CrateId(idx), false)),
+                None => {
+                    ids.push((CrateId(next_id), true));
+                    next_id += 1;
+                }
+            }
+        }
+        for (krate, &(_, is_new)) in other.crates.iter().zip(ids.iter()) {
+            if !is_new {
+                continue;
+            }
+            let mut krate = krate.clone();
+            for dep in krate.deps.iter_mut() {
+                dep.krate = ids[dep.krate.0].0;
+            }
+            self.crates.push(krate);
+        }
+        for root in &other.roots {
+            if !self.roots.iter().any(|it| it.path == root.path) {
+                self.roots.push(root.clone());
+            }
+        }
+        if self.sysroot_src.is_none() {
+            self.sysroot_src = other.sysroot_src.clone();
+        }
+    }
+
+    /// Returns the directories under `root`, relative to it, which are excluded
+    /// by all the crates with sources in `root`. A directory excluded by one
+    /// crate stays visible if another crate in the same root still needs it.
+    pub(crate) fn excluded_dirs(&self, root: &Path) -> Vec<PathBuf> {
+        let crates = self
+            .crates
+            .iter()
+            .filter(|krate| {
+                krate.root_module.starts_with(root)
+                    || krate.source.as_ref().map_or(false, |source| {
+                        source
+                            .include_dirs
+                            .iter()
+                            .any(|dir| dir.starts_with(root) || root.starts_with(dir))
+                    })
+            })
+            .collect::<Vec<_>>();
+        let mut res = Vec::new();
+        for krate in crates.iter() {
+            let source = match &krate.source {
+                Some(it) => it,
+                None => continue,
+            };
+            for dir in source.exclude_dirs.iter() {
+                let rel_dir = match dir.strip_prefix(root) {
+                    Ok(it) => it,
+                    Err(_) => continue,
+                };
+                let excluded_by_all = crates.iter().all(|krate| {
+                    krate.source.as_ref().map_or(false, |source| {
+                        source.exclude_dirs.iter().any(|it| dir.starts_with(it))
+                    })
+                });
+                if excluded_by_all && !res.iter().any(|it: &PathBuf| it == rel_dir) {
+                    res.push(rel_dir.to_path_buf());
+                }
+            }
+        }
+        res
+    }
+
+    /// Returns the name of the crate with the given index for display purposes.
+    pub(crate) fn crate_display_name(&self, id: CrateId) -> Option<&str> {
+        if let Some(name) = &self.crates[id.0].display_name {
+            return Some(name.as_str());
+        }
+        self.crates.iter().flat_map(|krate| krate.deps.iter()).find_map(|dep| {
+            if dep.krate == id {
+                Some(dep.name.as_str())
+            } else {
+                None
+            }
+        })
+    }
+}
+
+/// An error in `rust-project.json`, with the path to the field which caused it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SchemaError {
+    pub path: String,
+    pub message: String,
+}
+
+impl SchemaError {
+    fn new(path: impl Into<String>, message: impl Into<String>) -> SchemaError {
+        SchemaError { path: path.into(), message: message.into() }
+    }
+}
+
+impl fmt::Display for SchemaError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.path.is_empty() {
+            write!(f, "invalid rust-project.json: {}", self.message)
+        } else {
+            write!(f, "invalid rust-project.json: `{}`: {}", self.path, self.message)
+        }
+    }
+}
+
+impl Error for SchemaError {}
+
+/// Parses and validates the project, returning the paths of the unknown fields.
+fn parse_project(json: Value) -> Result<(JsonProject, Vec<String>), SchemaError> {
+    let mut ignored = Vec::new();
+    let mut on_ignored = |path: serde_ignored::Path| ignored.push(field_path(&path));
+    let deserializer = serde_ignored::Deserializer::new(json, &mut on_ignored);
+    let project: JsonProject = serde_path_to_error::deserialize(deserializer).map_err(|e| {
+        let path = e.path().to_string();
+        // The path of the top-level value is displayed as `.`.
+        let path = if path == "." { String::new() } else { path };
+        SchemaError::new(path, e.into_inner().to_string())
+    })?;
+    validate_project(&project)?;
+    Ok((project, ignored))
+}
+
+/// Formats `path` like the paths of `SchemaError`s.
+fn field_path(path: &serde_ignored::Path) -> String {
+    match path {
+        serde_ignored::Path::Root => String::new(),
+        serde_ignored::Path::Seq { parent, index } => format!("{}[{}]", field_path(parent), index),
+        serde_ignored::Path::Map { parent, key } => match field_path(parent) {
+            parent if parent.is_empty() => key.clone(),
+            parent => format!("{}.{}", parent, key),
+        },
+        serde_ignored::Path::Some { parent }
+        | serde_ignored::Path::NewtypeStruct { parent }
+        | serde_ignored::Path::NewtypeVariant { parent } => field_path(parent),
+    }
+}
+
+fn validate_project(project: &JsonProject) -> Result<(), SchemaError> {
+    let n_crates = project.crates.len();
+    for (idx, krate) in project.crates.iter().enumerate() {
+        for (dep_idx, dep) in krate.deps.iter().enumerate() {
+            if dep.krate.0 >= n_crates {
+                return Err(SchemaError::new(
+                    format!("crates[{}].deps[{}].crate", idx, dep_idx),
+                    format!(
+                        "crate index {} is out of bounds, there are {} crates",
+                        dep.krate.0, n_crates
+                    ),
+                ));
+            }
+        }
+        if krate.is_proc_macro && krate.proc_macro_dylib_path.is_none() {
+            return Err(SchemaError::new(
+                format!("crates[{}].proc_macro_dylib_path", idx),
+                "is required if `is_proc_macro` is set",
+            ));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn check_error(json: Value, path: &str, message: &str) {
+        let err = JsonProject::from_json(json).unwrap_err();
+        assert_eq!(err, SchemaError::new(path, message));
+    }
+
+    fn krate() -> Value {
+        json!({
+            "root_module": "/src/lib.rs",
+            "edition": "2018",
+            "deps": [],
+            "atom_cfgs": [],
+            "key_value_cfgs": {}
+        })
+    }
+
+    #[test]
+    fn parses_extended_schema() {
+        let mut proc_macro = krate();
+        proc_macro["display_name"] = json!("my_macros");
+        proc_macro["is_proc_macro"] = json!(true);
+        proc_macro["proc_macro_dylib_path"] = json!("/target/libmy_macros.so");
+        let mut lib = krate();
+        lib["deps"] = json!([{ "crate": 0, "name": "macros" }]);
+        lib["env"] = json!({ "CARGO_PKG_NAME": "lib" });
+        lib["source"] = json!({ "include_dirs": ["/src"], "exclude_dirs": ["/src/gen"] });
+        let project = JsonProject::from_json(json!({
+            "sysroot_src": "/rust/src",
+            "roots": ["/src"],
+            "crates": [proc_macro, lib]
+        }))
+        .unwrap();
+
+        assert_eq!(project.sysroot_src, Some(PathBuf::from("/rust/src")));
+        assert!(project.crates[0].is_proc_macro);
+        assert_eq!(project.crates[1].env["CARGO_PKG_NAME"], "lib");
+        assert_eq!(project.crate_display_name(CrateId(0)), Some("my_macros"));
+        assert_eq!(project.crate_display_name(CrateId(1)), None);
+    }
+
+    #[test]
+    fn display_name_defaults_to_dep_name() {
+        let mut lib = krate();
+        lib["deps"] = json!([{ "crate": 0, "name": "foo" }]);
+        let project =
+            JsonProject::from_json(json!({ "roots": [], "crates": [krate(), lib] })).unwrap();
+        assert_eq!(project.crate_display_name(CrateId(0)), Some("foo"));
+    }
+
+    #[test]
+    fn merge_deduplicates_crates() {
+        let mut foo = krate();
+        foo["root_module"] = json!("/foo/lib.rs");
+        let mut bar = krate();
+        bar["root_module"] = json!("/bar/lib.rs");
+        bar["deps"] = json!([{ "crate": 0, "name": "foo" }]);
+        let mut baz = krate();
+        baz["root_module"] = json!("/baz/lib.rs");
+        baz["deps"] = json!([{ "crate": 1, "name": "foo" }]);
+
+        let mut project = JsonProject::from_json(
+            json!({ "roots": ["/foo", "/bar"], "crates": [foo.clone(), bar] }),
+        )
+        .unwrap();
+        let other =
+            JsonProject::from_json(json!({ "roots": ["/foo", "/baz"], "crates": [baz, foo] }))
+                .unwrap();
+        project.merge(&other);
+
+        let roots = project.roots.iter().map(|it| it.path.clone()).collect::<Vec<_>>();
+        assert_eq!(
+            roots,
+            vec![PathBuf::from("/foo"), PathBuf::from("/bar"), PathBuf::from("/baz")]
+        );
+        assert_eq!(project.crates.len(), 3);
+        assert_eq!(project.crates[2].root_module, PathBuf::from("/baz/lib.rs"));
+        assert_eq!(project.crates[2].deps[0].krate, CrateId(0));
+    }
+
+    #[test]
+    fn exclude_dirs_apply_per_crate() {
+        let mut foo = krate();
+        foo["root_module"] = json!("/src/foo/lib.rs");
+        foo["source"] = json!({ "exclude_dirs": ["/src/gen", "/src/foo/tests"] });
+        let mut bar = krate();
+        bar["root_module"] = json!("/src/bar/lib.rs");
+        bar["source"] = json!({ "exclude_dirs": ["/src/gen"] });
+        let mut baz = krate();
+        baz["root_module"] = json!("/other/lib.rs");
+        baz["source"] = json!({ "exclude_dirs": ["/src/bar"] });
+        let project =
+            JsonProject::from_json(json!({ "roots": ["/src"], "crates": [foo, bar, baz] }))
+                .unwrap();
+
+        assert_eq!(project.excluded_dirs(Path::new("/src")), vec![PathBuf::from("gen")]);
+        assert_eq!(project.excluded_dirs(Path::new("/src/foo")), vec![PathBuf::from("tests")]);
+        assert!(project.excluded_dirs(Path::new("/other")).is_empty());
+    }
+
+    #[test]
+    fn unknown_fields_are_ignored() {
+        let mut lib = krate();
+        lib["generator_id"] = json!(92);
+        lib["deps"] = json!([{ "crate": 0, "name": "foo", "kind": "normal" }]);
+        let (project, ignored) = parse_project(json!({
+            "roots": [],
+            "crates": [krate(), lib],
+            "sysroot": "/rust/src",
+            "metadata": { "generator": "buck" }
+        }))
+        .unwrap();
+
+        assert_eq!(project.crates.len(), 2);
+        assert_eq!(
+            ignored,
+            vec![
+                "crates[1].deps[0].kind".to_string(),
+                "crates[1].generator_id".to_string(),
+                "metadata".to_string(),
+                "sysroot".to_string(),
+            ]
+        );
+    }
+
+    #[test]
+    fn errors_point_to_the_field() {
+        check_error(json!({ "crates": [] }), "", "missing field `roots`");
+        check_error(
+            json!({ "roots": [1], "crates": [] }),
+            "roots[0]",
+            "invalid type: integer `1`, expected path string",
+        );
+        let mut bad_edition = krate();
+        bad_edition["edition"] = json!("2021");
+        check_error(
+            json!({ "roots": [], "crates": [krate(), bad_edition] }),
+            "crates[1].edition",
+            "unknown variant `2021`, expected `2015` or `2018`",
+        );
+
+        let mut bad_dep = krate();
+        bad_dep["deps"] = json!([{ "crate": 92, "name": "foo" }]);
+        check_error(
+            json!({ "roots": [], "crates": [bad_dep] }),
+            "crates[0].deps[0].crate",
+            "crate index 92 is out of bounds, there are 1 crates",
+        );
+
+        let mut bad_env = krate();
+        bad_env["env"] = json!({ "FOO": 1 });
+        check_error(
+            json!({ "roots": [], "crates": [bad_env] }),
+            "crates[0].env.FOO",
+            "invalid type: integer `1`, expected a string",
+        );
+
+        let mut missing_dylib = krate();
+        missing_dylib["is_proc_macro"] = json!(true);
+        check_error(
+            json!({ "roots": [], "crates": [missing_dylib] }),
+            "crates[0].proc_macro_dylib_path",
+            "is required if `is_proc_macro` is set",
+        );
+    }
+}
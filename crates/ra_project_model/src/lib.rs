@@ -6,20 +6,26 @@ mod sysroot;
 
 use std::{
     error::Error,
-    fs::File,
+    fs::{self, File},
     io::BufReader,
     path::{Path, PathBuf},
     process::Command,
 };
 
 use ra_cfg::CfgOptions;
-use ra_db::{CrateGraph, CrateId, Edition, Env, FileId};
+use ra_db::{
+    CrateGraph, CrateId, Edition, Env, ExternSource, ExternSourceId, FileId, ProcMacro,
+    ProcMacroKind,
+};
+use ra_proc_macro::ProcMacroClient;
 use rustc_hash::FxHashMap;
 use serde_json::from_reader;
 
+use crate::sysroot::SysrootCrate;
+
 pub use crate::{
-    cargo_workspace::{CargoWorkspace, Package, Target, TargetKind},
-    json_project::JsonProject,
+    cargo_workspace::{BuildOutputs, CargoConfig, CargoWorkspace, Package, Target, TargetKind},
+    json_project::{JsonProject, SchemaError},
     sysroot::Sysroot,
 };
 
@@ -31,7 +37,11 @@ pub enum ProjectWorkspace {
     /// Project workspace was discovered by running `cargo metadata` and `rustc --print sysroot`.
     Cargo { cargo: CargoWorkspace, sysroot: Sysroot },
     /// Project workspace was manually specified using a `rust-project.json` file.
-    Json { project: JsonProject },
+    Json { project: JsonProject, sysroot: Sysroot },
+    /// Standalone files which don't belong to any other workspace. Each of them
+    /// is a separate crate, which depends only on the sysroot crates. `roots`
+    /// are the directories of the files which are outside of all other roots.
+    DetachedFiles { files: Vec<PathBuf>, roots: Vec<PathBuf>, sysroot: Sysroot },
 }
 
 /// `PackageRoot` describes a package root folder.
@@ -43,11 +53,13 @@ pub struct PackageRoot {
     path: PathBuf,
     /// Is a member of the current workspace
     is_member: bool,
+    /// Directories, relative to `path`, which should be skipped
+    exclude: Vec<PathBuf>,
 }
 
 impl PackageRoot {
     pub fn new(path: PathBuf, is_member: bool) -> PackageRoot {
-        PackageRoot { path, is_member }
+        PackageRoot { path, is_member, exclude: Vec::new() }
     }
 
     pub fn path(&self) -> &PathBuf {
@@ -57,23 +69,32 @@ impl PackageRoot {
     pub fn is_member(&self) -> bool {
         self.is_member
     }
+
+    pub fn exclude(&self) -> &[PathBuf] {
+        &self.exclude
+    }
 }
 
 impl ProjectWorkspace {
-    pub fn discover(path: &Path) -> Result<ProjectWorkspace> {
-        ProjectWorkspace::discover_with_sysroot(path, true)
+    pub fn discover(path: &Path, cargo_config: &CargoConfig) -> Result<ProjectWorkspace> {
+        ProjectWorkspace::discover_with_sysroot(path, true, cargo_config)
     }
 
-    pub fn discover_with_sysroot(path: &Path, with_sysroot: bool) -> Result<ProjectWorkspace> {
+    pub fn discover_with_sysroot(
+        path: &Path,
+        with_sysroot: bool,
+        cargo_config: &CargoConfig,
+    ) -> Result<ProjectWorkspace> {
         match find_rust_project_json(path) {
             Some(json_path) => {
                 let file = File::open(json_path)?;
                 let reader = BufReader::new(file);
-                Ok(ProjectWorkspace::Json { project: from_reader(reader)? })
+                let project = JsonProject::from_json(from_reader(reader)?)?;
+                ProjectWorkspace::from_json_project(project, with_sysroot)
             }
             None => {
                 let cargo_toml = find_cargo_toml(path)?;
-                let cargo = CargoWorkspace::from_cargo_metadata(&cargo_toml)?;
+                let cargo = CargoWorkspace::from_cargo_metadata(&cargo_toml, cargo_config)?;
                 let sysroot =
                     if with_sysroot { Sysroot::discover(&cargo_toml)? } else { Sysroot::default() };
                 Ok(ProjectWorkspace::Cargo { cargo, sysroot })
@@ -81,15 +102,88 @@ impl ProjectWorkspace {
         }
     }
 
+    /// Discovers the workspace by running a user-provided `command` once for
+    /// all of the `paths`.
+    pub fn discover_with_command(
+        command: &[String],
+        paths: &[PathBuf],
+        with_sysroot: bool,
+    ) -> Result<ProjectWorkspace> {
+        let project = run_discover_command(command, paths)?;
+        ProjectWorkspace::from_json_project(project, with_sysroot)
+    }
+
+    pub fn from_json_project(project: JsonProject, with_sysroot: bool) -> Result<ProjectWorkspace> {
+        let sysroot = match &project.sysroot_src {
+            Some(sysroot_src) if with_sysroot => Sysroot::load(sysroot_src)?,
+            _ => Sysroot::default(),
+        };
+        Ok(ProjectWorkspace::Json { project, sysroot })
+    }
+
+    /// Merges `project` into this workspace, if it was created from a
+    /// `rust-project.json`. The sysroot of the workspace is kept as is.
+    pub fn merge_json_project(&mut self, project: &JsonProject) -> bool {
+        match self {
+            ProjectWorkspace::Json { project: it, .. } => {
+                it.merge(project);
+                true
+            }
+            ProjectWorkspace::Cargo { .. } | ProjectWorkspace::DetachedFiles { .. } => false,
+        }
+    }
+
+    pub fn detached_files(
+        files: Vec<PathBuf>,
+        roots: Vec<PathBuf>,
+        with_sysroot: bool,
+    ) -> Result<ProjectWorkspace> {
+        let sysroot = match files.first().and_then(|file| file.parent()) {
+            Some(dir) if with_sysroot => Sysroot::discover_in_dir(dir)?,
+            _ => Sysroot::default(),
+        };
+        Ok(ProjectWorkspace::DetachedFiles { files, roots, sysroot })
+    }
+
+    /// Adds `file` to this workspace, if it is a workspace of detached files.
+    /// `root` is the directory to watch for the file, if it is not in any
+    /// other root.
+    pub fn add_detached_file(&mut self, file: &Path, root: Option<&Path>) -> bool {
+        match self {
+            ProjectWorkspace::DetachedFiles { files, roots, .. } => {
+                if !files.iter().any(|it| it == file) {
+                    files.push(file.to_path_buf());
+                }
+                if let Some(root) = root {
+                    if !roots.iter().any(|it| it == root) {
+                        roots.push(root.to_path_buf());
+                    }
+                }
+                true
+            }
+            ProjectWorkspace::Cargo { .. } | ProjectWorkspace::Json { .. } => false,
+        }
+    }
+
     /// Returns the roots for the current `ProjectWorkspace`
     /// The return type contains the path and whether or not
     /// the root is a member of the current workspace
     pub fn to_roots(&self) -> Vec<PackageRoot> {
         match self {
-            ProjectWorkspace::Json { project } => {
+            ProjectWorkspace::Json { project, sysroot } => {
+                let include_dirs = project
+                    .crates
+                    .iter()
+                    .filter_map(|krate| krate.source.as_ref())
+                    .flat_map(|source| source.include_dirs.iter());
                 let mut roots = Vec::with_capacity(project.roots.len());
-                for root in &project.roots {
-                    roots.push(PackageRoot::new(root.path.clone(), true));
+                for path in project.roots.iter().map(|root| &root.path).chain(include_dirs) {
+                    let mut root = PackageRoot::new(path.clone(), true);
+                    root.exclude = project.excluded_dirs(path);
+                    roots.push(root);
+                }
+                for krate in sysroot.crates() {
+                    roots.push(PackageRoot::new(krate.root_dir(&sysroot).to_path_buf(), false))
                 }
                 roots
             }
@@ -99,33 +193,97 @@ impl ProjectWorkspace {
                     let root = pkg.root(&cargo).to_path_buf();
                     let member = pkg.is_member(&cargo);
                     roots.push(PackageRoot::new(root, member));
+                    if let Some(out_dir) = pkg.out_dir(&cargo) {
+                        roots.push(PackageRoot::new(out_dir.to_path_buf(), false));
+                    }
                 }
                 for krate in sysroot.crates() {
                     roots.push(PackageRoot::new(krate.root_dir(&sysroot).to_path_buf(), false))
                 }
                 roots
             }
+            // Files inside of other roots are already in the VFS, the others
+            // are watched through the roots of their directories.
+            ProjectWorkspace::DetachedFiles { roots, sysroot, .. } => {
+                roots
+                    .iter()
+                    .map(|root| PackageRoot::new(root.clone(), true))
+                    .chain(sysroot.crates().map(|krate| {
+                        PackageRoot::new(krate.root_dir(&sysroot).to_path_buf(), false)
+                    }))
+                    .collect()
+            }
+        }
+    }
+
+    /// Runs `cargo check` to load the outputs of the build scripts, for a
+    /// cargo workspace.
+    pub fn load_build_outputs(&self, cargo_config: &CargoConfig) -> Option<Result<BuildOutputs>> {
+        match self {
+            ProjectWorkspace::Cargo { cargo, .. } => Some(cargo.load_build_outputs(cargo_config)),
+            ProjectWorkspace::Json { .. } | ProjectWorkspace::DetachedFiles { .. } => None,
+        }
+    }
+
+    /// Applies the outputs of `load_build_outputs`. Returns `false` if they
+    /// are for another workspace.
+    pub fn set_build_outputs(&mut self, outputs: &BuildOutputs) -> bool {
+        match self {
+            ProjectWorkspace::Cargo { cargo, .. } => cargo.set_build_outputs(outputs),
+            ProjectWorkspace::Json { .. } | ProjectWorkspace::DetachedFiles { .. } => false,
+        }
+    }
+
+    /// Returns the `OUT_DIR`s of the build scripts in the workspace.
+    pub fn out_dirs(&self) -> Vec<PathBuf> {
+        match self {
+            ProjectWorkspace::Json { .. } | ProjectWorkspace::DetachedFiles { .. } => Vec::new(),
+            ProjectWorkspace::Cargo { cargo, .. } => cargo
+                .packages()
+                .filter_map(|pkg| pkg.out_dir(&cargo))
+                .map(Path::to_path_buf)
+                .collect(),
         }
     }
 
     pub fn n_packages(&self) -> usize {
         match self {
-            ProjectWorkspace::Json { project } => project.crates.len(),
+            ProjectWorkspace::Json { project, sysroot } => {
+                project.crates.len() + sysroot.crates().len()
+            }
             ProjectWorkspace::Cargo { cargo, sysroot } => {
                 cargo.packages().len() + sysroot.crates().len()
             }
+            ProjectWorkspace::DetachedFiles { files, sysroot, .. } => {
+                files.len() + sysroot.crates().len()
+            }
         }
     }
 
     pub fn to_crate_graph(
         &self,
         default_cfg_options: &CfgOptions,
+        extern_source_roots: &FxHashMap<PathBuf, ExternSourceId>,
+        proc_macro_client: &ProcMacroClient,
         load: &mut dyn FnMut(&Path) -> Option<FileId>,
     ) -> (CrateGraph, FxHashMap<CrateId, String>) {
         let mut crate_graph = CrateGraph::default();
         let mut names = FxHashMap::default();
         match self {
-            ProjectWorkspace::Json { project } => {
+            ProjectWorkspace::Json { project, sysroot } => {
+                let sysroot_crates = sysroot_to_crate_graph(
+                    &mut crate_graph,
+                    &mut names,
+                    sysroot,
+                    default_cfg_options,
+                    load,
+                );
+                let libcore = sysroot.core().and_then(|it| sysroot_crates.get(&it).copied());
+                let liballoc = sysroot.alloc().and_then(|it| sysroot_crates.get(&it).copied());
+                let libstd = sysroot.std().and_then(|it| sysroot_crates.get(&it).copied());
+                let libproc_macro =
+                    sysroot.proc_macro().and_then(|it| sysroot_crates.get(&it).copied());
+
                 let mut crates = FxHashMap::default();
                 for (id, krate) in project.crates.iter().enumerate() {
                     let crate_id = json_project::CrateId(id);
@@ -144,25 +302,62 @@ impl ProjectWorkspace {
                             }
                             opts
                         };
-                        crates.insert(
-                            crate_id,
-                            crate_graph.add_crate_root(
-                                file_id,
-                                edition,
-                                cfg_options,
-                                Env::default(),
-                            ),
+                        let mut env = Env::default();
+                        for (key, value) in &krate.env {
+                            env.set(key, value.clone());
+                        }
+                        let proc_macros = if krate.is_proc_macro {
+                            krate
+                                .proc_macro_dylib_path
+                                .as_ref()
+                                .map(|it| load_proc_macros(proc_macro_client, it))
+                                .unwrap_or_default()
+                        } else {
+                            Vec::new()
+                        };
+                        let graph_crate_id = crate_graph.add_crate_root(
+                            file_id,
+                            edition,
+                            cfg_options,
+                            env,
+                            ExternSource::default(),
+                            proc_macros,
                         );
+                        if let Some(name) = project.crate_display_name(crate_id) {
+                            names.insert(graph_crate_id, name.to_string());
+                        }
+                        crates.insert(crate_id, graph_crate_id);
                     }
                 }
 
                 for (id, krate) in project.crates.iter().enumerate() {
+                    let from_crate_id = json_project::CrateId(id);
+                    let from = match crates.get(&from_crate_id) {
+                        Some(&it) => it,
+                        None => continue,
+                    };
+                    // Like with Cargo, every crate depends on the standard
+                    // library, if the project specifies one.
+                    let sysroot_deps = [
+                        ("core", libcore),
+                        ("alloc", liballoc),
+                        ("std", libstd),
+                        ("proc_macro", libproc_macro.filter(|_| krate.is_proc_macro)),
+                    ];
+                    for &(name, to) in sysroot_deps.iter() {
+                        if let Some(to) = to {
+                            if let Err(_) = crate_graph.add_dep(from, name.into(), to) {
+                                log::error!(
+                                    "cyclic dependency on {} for {:?}",
+                                    name,
+                                    from_crate_id
+                                );
+                            }
+                        }
+                    }
                     for dep in &krate.deps {
-                        let from_crate_id = json_project::CrateId(id);
                         let to_crate_id = dep.krate;
-                        if let (Some(&from), Some(&to)) =
-                            (crates.get(&from_crate_id), crates.get(&to_crate_id))
-                        {
+                        if let Some(&to) = crates.get(&to_crate_id) {
                             if let Err(_) = crate_graph.add_dep(from, dep.name.clone().into(), to) {
                                 log::error!(
                                     "cyclic dependency {:?} -> {:?}",
@@ -175,38 +370,13 @@ impl ProjectWorkspace {
                 }
             }
             ProjectWorkspace::Cargo { cargo, sysroot } => {
-                let mut sysroot_crates = FxHashMap::default();
-                for krate in sysroot.crates() {
-                    if let Some(file_id) = load(krate.root(&sysroot)) {
-                        // Crates from sysroot have `cfg(test)` disabled
-                        let cfg_options = {
-                            let mut opts = default_cfg_options.clone();
-                            opts.remove_atom("test");
-                            opts
-                        };
-
-                        let crate_id = crate_graph.add_crate_root(
-                            file_id,
-                            Edition::Edition2018,
-                            cfg_options,
-                            Env::default(),
-                        );
-                        sysroot_crates.insert(krate, crate_id);
-                        names.insert(crate_id, krate.name(&sysroot).to_string());
-                    }
-                }
-                for from in sysroot.crates() {
-                    for to in from.deps(&sysroot) {
-                        let name = to.name(&sysroot);
-                        if let (Some(&from), Some(&to)) =
-                            (sysroot_crates.get(&from), sysroot_crates.get(&to))
-                        {
-                            if let Err(_) = crate_graph.add_dep(from, name.into(), to) {
-                                log::error!("cyclic dependency between sysroot crates")
-                            }
-                        }
-                    }
-                }
+                let sysroot_crates = sysroot_to_crate_graph(
+                    &mut crate_graph,
+                    &mut names,
+                    sysroot,
+                    default_cfg_options,
+                    load,
+                );
 
                 let libcore = sysroot.core().and_then(|it| sysroot_crates.get(&it).copied());
                 let liballoc = sysroot.alloc().and_then(|it| sysroot_crates.get(&it).copied());
@@ -226,13 +396,40 @@ impl ProjectWorkspace {
                             let cfg_options = {
                                 let mut opts = default_cfg_options.clone();
                                 opts.insert_features(pkg.features(&cargo).iter().map(Into::into));
+                                for cfg in pkg.cfgs(&cargo) {
+                                    insert_cfg_line(&mut opts, cfg);
+                                }
                                 opts
                             };
+                            let mut env = Env::default();
+                            let mut extern_source = ExternSource::default();
+                            if let Some(out_dir) = pkg.out_dir(&cargo) {
+                                // FIXME: We probably mangle non UTF-8 paths here, figure out a better solution
+                                env.set("OUT_DIR", out_dir.to_string_lossy().to_string());
+                                if let Some(&extern_source_id) = extern_source_roots.get(out_dir) {
+                                    extern_source.set_extern_path(
+                                        &out_dir.to_string_lossy(),
+                                        extern_source_id,
+                                    );
+                                }
+                            }
+                            for (key, value) in pkg.envs(&cargo) {
+                                env.set(key, value.clone());
+                            }
+                            let proc_macros = if tgt.is_proc_macro(&cargo) {
+                                pkg.proc_macro_dylib_path(&cargo)
+                                    .map(|it| load_proc_macros(proc_macro_client, it))
+                                    .unwrap_or_default()
+                            } else {
+                                Vec::new()
+                            };
                             let crate_id = crate_graph.add_crate_root(
                                 file_id,
                                 edition,
                                 cfg_options,
-                                Env::default(),
+                                env,
+                                extern_source,
+                                proc_macros,
                             );
                             names.insert(crate_id, pkg.name(&cargo).to_string());
                             if tgt.kind(&cargo) == TargetKind::Lib {
@@ -312,6 +509,41 @@ impl ProjectWorkspace {
                     }
                 }
             }
+            ProjectWorkspace::DetachedFiles { files, sysroot, .. } => {
+                let sysroot_crates = sysroot_to_crate_graph(
+                    &mut crate_graph,
+                    &mut names,
+                    sysroot,
+                    default_cfg_options,
+                    load,
+                );
+                let sysroot_deps =
+                    [("core", sysroot.core()), ("alloc", sysroot.alloc()), ("std", sysroot.std())];
+                for file in files {
+                    let file_id = match load(file) {
+                        Some(it) => it,
+                        None => continue,
+                    };
+                    let crate_id = crate_graph.add_crate_root(
+                        file_id,
+                        Edition::Edition2018,
+                        default_cfg_options.clone(),
+                        Env::default(),
+                        ExternSource::default(),
+                        Vec::new(),
+                    );
+                    if let Some(name) = file.file_stem() {
+                        names.insert(crate_id, name.to_string_lossy().to_string());
+                    }
+                    for &(name, krate) in sysroot_deps.iter() {
+                        if let Some(&to) = krate.and_then(|it| sysroot_crates.get(&it)) {
+                            if let Err(_) = crate_graph.add_dep(crate_id, name.into(), to) {
+                                log::error!("cyclic dependency on {} for {}", name, file.display());
+                            }
+                        }
+                    }
+                }
+            }
         }
         (crate_graph, names)
     }
@@ -321,12 +553,99 @@ impl ProjectWorkspace {
             ProjectWorkspace::Cargo { cargo, .. } => {
                 Some(cargo.workspace_root.as_ref()).filter(|root| path.starts_with(root))
             }
-            ProjectWorkspace::Json { project: JsonProject { roots, .. } } => roots
+            ProjectWorkspace::Json { project: JsonProject { roots, .. }, .. } => roots
                 .iter()
                 .find(|root| path.starts_with(&root.path))
                 .map(|root| root.path.as_ref()),
+            ProjectWorkspace::DetachedFiles { .. } => None,
+        }
+    }
+}
+
+fn load_proc_macros(proc_macro_client: &ProcMacroClient, dylib_path: &Path) -> Vec<ProcMacro> {
+    let dylib_modified = fs::metadata(dylib_path).and_then(|it| it.modified()).ok();
+    proc_macro_client
+        .by_dylib_path(dylib_path)
+        .into_iter()
+        .map(|(name, kind, expander)| {
+            let kind = match kind {
+                ra_proc_macro::ProcMacroKind::CustomDerive { helpers } => {
+                    ProcMacroKind::CustomDerive {
+                        helpers: helpers.into_iter().map(Into::into).collect(),
+                    }
+                }
+                ra_proc_macro::ProcMacroKind::FuncLike => ProcMacroKind::FuncLike,
+                ra_proc_macro::ProcMacroKind::Attr => ProcMacroKind::Attr,
+            };
+            ProcMacro { name, kind, dylib_path: dylib_path.to_path_buf(), dylib_modified, expander }
+        })
+        .collect()
+}
+
+fn sysroot_to_crate_graph(
+    crate_graph: &mut CrateGraph,
+    names: &mut FxHashMap<CrateId, String>,
+    sysroot: &Sysroot,
+    default_cfg_options: &CfgOptions,
+    load: &mut dyn FnMut(&Path) -> Option<FileId>,
+) -> FxHashMap<SysrootCrate, CrateId> {
+    let mut sysroot_crates = FxHashMap::default();
+    for krate in sysroot.crates() {
+        if let Some(file_id) = load(krate.root(&sysroot)) {
+            // Crates from sysroot have `cfg(test)` disabled
+            let cfg_options = {
+                let mut opts = default_cfg_options.clone();
+                opts.remove_atom("test");
+                opts
+            };
+
+            let crate_id = crate_graph.add_crate_root(
+                file_id,
+                Edition::Edition2018,
+                cfg_options,
+                Env::default(),
+                ExternSource::default(),
+                Vec::new(),
+            );
+            sysroot_crates.insert(krate, crate_id);
+            names.insert(crate_id, krate.name(&sysroot).to_string());
+        }
+    }
+    for from in sysroot.crates() {
+        for to in from.deps(&sysroot) {
+            let name = to.name(&sysroot);
+            if let (Some(&from), Some(&to)) = (sysroot_crates.get(&from), sysroot_crates.get(&to)) {
+                if let Err(_) = crate_graph.add_dep(from, name.into(), to) {
+                    log::error!("cyclic dependency between sysroot crates")
+                }
+            }
         }
     }
+    sysroot_crates
+}
+
+/// Runs the project discovery `command` with `paths` as the trailing
+/// arguments, and parses its output as `rust-project.json`.
+pub fn run_discover_command(command: &[String], paths: &[PathBuf]) -> Result<JsonProject> {
+    if paths.is_empty() {
+        Err("no paths to run the project discovery command for")?;
+    }
+    let (program, args) = command.split_first().ok_or("project discovery command is empty")?;
+    let output = Command::new(program)
+        .args(args)
+        .args(paths)
+        .output()
+        .map_err(|e| format!("failed to run `{}`: {}", program, e))?;
+    if !output.status.success() {
+        Err(format!(
+            "`{}` failed for {} paths: {}",
+            command.join(" "),
+            paths.len(),
+            String::from_utf8_lossy(&output.stderr).trim()
+        ))?;
+    }
+    let json = serde_json::from_slice(&output.stdout)?;
+    Ok(JsonProject::from_json(json)?)
 }
 
 fn find_rust_project_json(path: &Path) -> Option<PathBuf> {
@@ -361,7 +680,7 @@ fn find_cargo_toml(path: &Path) -> Result<PathBuf> {
     Err(format!("can't find Cargo.toml at {}", path.display()))?
 }
 
-pub fn get_rustc_cfg_options() -> CfgOptions {
+pub fn get_rustc_cfg_options(target: Option<&str>) -> CfgOptions {
     let mut cfg_options = CfgOptions::default();
 
     // Some nightly-only cfgs, which are required for stdlib
@@ -374,7 +693,12 @@ pub fn get_rustc_cfg_options() -> CfgOptions {
 
     match (|| -> Result<_> {
         // `cfg(test)` and `cfg(debug_assertion)` are handled outside, so we suppress them here.
-        let output = Command::new("rustc").args(&["--print", "cfg", "-O"]).output()?;
+        let mut cmd = Command::new("rustc");
+        cmd.args(&["--print", "cfg", "-O"]);
+        if let Some(target) = target {
+            cmd.args(&["--target", target]);
+        }
+        let output = cmd.output()?;
         if !output.status.success() {
             Err("failed to get rustc cfgs")?;
         }
@@ -382,14 +706,7 @@ pub fn get_rustc_cfg_options() -> CfgOptions {
     })() {
         Ok(rustc_cfgs) => {
             for line in rustc_cfgs.lines() {
-                match line.find('=') {
-                    None => cfg_options.insert_atom(line.into()),
-                    Some(pos) => {
-                        let key = &line[..pos];
-                        let value = line[pos + 1..].trim_matches('"');
-                        cfg_options.insert_key_value(key.into(), value.into());
-                    }
-                }
+                insert_cfg_line(&mut cfg_options, line);
             }
         }
         Err(e) => log::error!("failed to get rustc cfgs: {}", e),
@@ -397,3 +714,15 @@ pub fn get_rustc_cfg_options() -> CfgOptions {
 
     cfg_options
 }
+
+/// Parses a cfg in `rustc --print cfg` format, either `atom` or `key="value"`.
+fn insert_cfg_line(cfg_options: &mut CfgOptions, line: &str) {
+    match line.find('=') {
+        None => cfg_options.insert_atom(line.into()),
+        Some(pos) => {
+            let key = &line[..pos];
+            let value = line[pos + 1..].trim_matches('"');
+            cfg_options.insert_key_value(key.into(), value.into());
+        }
+    }
+}
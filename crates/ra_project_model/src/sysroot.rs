@@ -48,8 +48,18 @@ impl Sysroot {
     }
 
     pub fn discover(cargo_toml: &Path) -> Result<Sysroot> {
-        let src = try_find_src_path(cargo_toml)?;
+        Sysroot::discover_in_dir(cargo_toml.parent().unwrap())
+    }
+
+    /// Finds the sysroot of the toolchain used in `dir`.
+    pub fn discover_in_dir(dir: &Path) -> Result<Sysroot> {
+        let src = try_find_src_path(dir)?;
+        Sysroot::load(&src)
+    }
 
+    /// Loads the standard library crates from the `src` directory of a Rust
+    /// checkout or of the `rust-src` component.
+    pub fn load(src: &Path) -> Result<Sysroot> {
         if !src.exists() {
             Err(format!(
                 "can't load standard library from sysroot\n\
@@ -90,15 +100,13 @@ impl Sysroot {
     }
 }
 
-fn try_find_src_path(cargo_toml: &Path) -> Result<PathBuf> {
+fn try_find_src_path(dir: &Path) -> Result<PathBuf> {
     if let Ok(path) = env::var("RUST_SRC_PATH") {
         return Ok(path.into());
     }
 
-    let rustc_output = Command::new("rustc")
-        .current_dir(cargo_toml.parent().unwrap())
-        .args(&["--print", "sysroot"])
-        .output()?;
+    let rustc_output =
+        Command::new("rustc").current_dir(dir).args(&["--print", "sysroot"]).output()?;
     if !rustc_output.status.success() {
         Err("failed to locate sysroot")?;
     }
@@ -39,6 +39,16 @@ impl ast::RefExpr {
     }
 }
 
+impl ast::LambdaExpr {
+    pub fn is_move(&self) -> bool {
+        self.syntax().children_with_tokens().any(|n| n.kind() == T![move])
+    }
+
+    pub fn is_async(&self) -> bool {
+        self.syntax().children_with_tokens().any(|n| n.kind() == T![async])
+    }
+}
+
 #[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
 pub enum PrefixOp {
     /// The `*` operator for dereferencing
@@ -353,6 +363,43 @@ impl ast::BlockExpr {
             _ => true,
         }
     }
+
+    /// Whether the block declares items (including `macro_rules!` macros),
+    /// which are visible in the whole block.
+    ///
+    /// ```not_rust
+    /// fn foo() {
+    ///     struct S;
+    ///     macro_rules! m { () => {} }
+    /// }
+    /// ```
+    pub fn has_items(&self) -> bool {
+        let block = match self.block() {
+            Some(it) => it,
+            None => return false,
+        };
+        block.syntax().children().any(|child| {
+            if ast::ModuleItem::can_cast(child.kind()) {
+                return true;
+            }
+            let macro_call = match ast::ExprStmt::cast(child.clone()) {
+                Some(stmt) => match stmt.expr() {
+                    Some(ast::Expr::MacroCall(it)) => it,
+                    _ => return false,
+                },
+                None => match ast::MacroCall::cast(child) {
+                    Some(it) => it,
+                    None => return false,
+                },
+            };
+            ast::NameOwner::name(&macro_call).is_some()
+        })
+    }
+
+    /// Whether this is an `async` block (`async { ... }` or `async move { ... }`).
+    pub fn is_async(&self) -> bool {
+        self.syntax().children_with_tokens().any(|n| n.kind() == T![async])
+    }
 }
 
 #[test]
@@ -35,6 +35,10 @@ fn text_of_first_token(node: &SyntaxNode) -> &SmolStr {
     node.green().children().next().and_then(|it| it.into_token()).unwrap().text()
 }
 
+fn child_token(node: &SyntaxNode, kind: SyntaxKind) -> Option<SyntaxToken> {
+    node.children_with_tokens().filter_map(|it| it.into_token()).find(|it| it.kind() == kind)
+}
+
 impl ast::Attr {
     pub fn as_simple_atom(&self) -> Option<SmolStr> {
         match self.input() {
@@ -285,6 +289,34 @@ impl ast::BindPat {
     }
 }
 
+impl ast::LiteralPat {
+    pub fn is_negative(&self) -> bool {
+        self.syntax().children_with_tokens().any(|n| n.kind() == T![-])
+    }
+}
+
+impl ast::RangePat {
+    pub fn start(&self) -> Option<ast::Pat> {
+        self.syntax()
+            .children_with_tokens()
+            .take_while(|it| !is_range_pat_op(it.kind()))
+            .filter_map(|it| it.into_node())
+            .find_map(ast::Pat::cast)
+    }
+
+    pub fn end(&self) -> Option<ast::Pat> {
+        self.syntax()
+            .children_with_tokens()
+            .skip_while(|it| !is_range_pat_op(it.kind()))
+            .filter_map(|it| it.into_node())
+            .find_map(ast::Pat::cast)
+    }
+}
+
+fn is_range_pat_op(kind: SyntaxKind) -> bool {
+    kind == T![...] || kind == T![..=] || kind == T![..]
+}
+
 impl ast::PointerType {
     pub fn is_mut(&self) -> bool {
         self.syntax().children_with_tokens().any(|n| n.kind() == T![mut])
@@ -338,28 +370,37 @@ impl ast::SelfParam {
 
 impl ast::LifetimeParam {
     pub fn lifetime_token(&self) -> Option<SyntaxToken> {
-        self.syntax()
-            .children_with_tokens()
-            .filter_map(|it| it.into_token())
-            .find(|it| it.kind() == LIFETIME)
+        child_token(self.syntax(), LIFETIME)
     }
 }
 
 impl ast::TypeParam {
     pub fn colon_token(&self) -> Option<SyntaxToken> {
-        self.syntax()
-            .children_with_tokens()
-            .filter_map(|it| it.into_token())
-            .find(|it| it.kind() == T![:])
+        child_token(self.syntax(), T![:])
     }
 }
 
 impl ast::WherePred {
     pub fn lifetime_token(&self) -> Option<SyntaxToken> {
-        self.syntax()
-            .children_with_tokens()
-            .filter_map(|it| it.into_token())
-            .find(|it| it.kind() == LIFETIME)
+        child_token(self.syntax(), LIFETIME)
+    }
+}
+
+impl ast::Label {
+    pub fn lifetime_token(&self) -> Option<SyntaxToken> {
+        child_token(self.syntax(), LIFETIME)
+    }
+}
+
+impl ast::BreakExpr {
+    pub fn lifetime_token(&self) -> Option<SyntaxToken> {
+        child_token(self.syntax(), LIFETIME)
+    }
+}
+
+impl ast::ContinueExpr {
+    pub fn lifetime_token(&self) -> Option<SyntaxToken> {
+        child_token(self.syntax(), LIFETIME)
     }
 }
 
@@ -387,17 +428,11 @@ impl ast::TypeBound {
     }
 
     fn lifetime(&self) -> Option<SyntaxToken> {
-        self.syntax()
-            .children_with_tokens()
-            .filter_map(|it| it.into_token())
-            .find(|it| it.kind() == LIFETIME)
+        child_token(self.syntax(), LIFETIME)
     }
 
     pub fn question_mark_token(&self) -> Option<SyntaxToken> {
-        self.syntax()
-            .children_with_tokens()
-            .filter_map(|it| it.into_token())
-            .find(|it| it.kind() == T![?])
+        child_token(self.syntax(), T![?])
     }
     pub fn has_question_mark(&self) -> bool {
         self.question_mark_token().is_some()
@@ -409,3 +444,31 @@ impl ast::TraitDef {
         self.syntax().children_with_tokens().any(|t| t.kind() == T![auto])
     }
 }
+
+pub enum VisibilityKind {
+    In(ast::Path),
+    PubCrate,
+    PubSuper,
+    PubSelf,
+    Pub,
+}
+
+impl ast::Visibility {
+    pub fn kind(&self) -> VisibilityKind {
+        if let Some(path) = children(self).next() {
+            VisibilityKind::In(path)
+        } else if self.has_token(T![crate]) {
+            VisibilityKind::PubCrate
+        } else if self.has_token(T![super]) {
+            VisibilityKind::PubSuper
+        } else if self.has_token(T![self]) {
+            VisibilityKind::PubSelf
+        } else {
+            VisibilityKind::Pub
+        }
+    }
+
+    fn has_token(&self, kind: SyntaxKind) -> bool {
+        self.syntax().children_with_tokens().any(|it| it.kind() == kind)
+    }
+}
@@ -0,0 +1,2 @@
+macro m { ($i:ident) => {} }
+pub macro m($i:ident) { $i }
@@ -14,7 +14,10 @@ macro_rules! impl_froms {
     }
 }
 
-use std::fmt;
+use std::{
+    fmt::{self, Debug},
+    panic::RefUnwindSafe,
+};
 
 use smol_str::SmolStr;
 
@@ -167,3 +170,31 @@ impl Subtree {
 }
 
 pub mod buffer;
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum ExpansionError {
+    IOError(String),
+    JsonError(String),
+    Unknown(String),
+    ExpansionError(String),
+}
+
+impl fmt::Display for ExpansionError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ExpansionError::IOError(it) => write!(f, "io error: {}", it),
+            ExpansionError::JsonError(it) => write!(f, "json error: {}", it),
+            ExpansionError::Unknown(it) => write!(f, "unknown error: {}", it),
+            ExpansionError::ExpansionError(it) => write!(f, "expansion error: {}", it),
+        }
+    }
+}
+
+/// An expander living outside of rust-analyzer, like a procedural macro
+/// loaded into a separate process.
+pub trait TokenExpander: Debug + Send + Sync + RefUnwindSafe {
+    /// Expands `subtree`. `attrs` are the arguments of an attribute macro,
+    /// e.g. `(GET, "/")` in `#[route(GET, "/")]`.
+    fn expand(&self, subtree: &Subtree, attrs: Option<&Subtree>)
+        -> Result<Subtree, ExpansionError>;
+}
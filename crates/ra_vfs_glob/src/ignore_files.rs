@@ -0,0 +1,90 @@
+//! Support for `.gitignore`, `.ignore` and `.git/info/exclude` files.
+//!
+//! Ignore files are read lazily, when the vfs asks about a file or directory
+//! inside the directory which contains them. The ignore files of the
+//! directories above the root apply as well, up to the root of the enclosing
+//! git repository. As in git, the last matching pattern wins, and patterns from
+//! deeper directories take precedence over shallower ones.
+
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::{Arc, RwLock},
+};
+
+use ignore::{
+    gitignore::{Gitignore, GitignoreBuilder},
+    Match,
+};
+use ra_vfs::RelativePath;
+
+/// Names of the ignore files, which are read in every directory, in the order
+/// of increasing precedence.
+pub(crate) const IGNORE_FILES: &[&str] = &[".gitignore", ".ignore"];
+
+/// The ignore rules of a vfs root. Clones share the rules, which are cached
+/// until `reload` is called.
+#[derive(Debug, Clone)]
+pub struct IgnoreFiles {
+    inner: Arc<IgnoreFilesInner>,
+}
+
+#[derive(Debug)]
+struct IgnoreFilesInner {
+    root: PathBuf,
+    /// The root of the git repository which contains `root`, if any.
+    repo_root: Option<PathBuf>,
+    rules: RwLock<HashMap<PathBuf, Arc<Gitignore>>>,
+}
+
+impl IgnoreFiles {
+    pub fn new(root: PathBuf) -> IgnoreFiles {
+        let repo_root =
+            root.ancestors().find(|dir| dir.join(".git").exists()).map(Path::to_path_buf);
+        IgnoreFiles {
+            inner: Arc::new(IgnoreFilesInner { root, repo_root, rules: Default::default() }),
+        }
+    }
+
+    /// Checks if `path`, relative to the root, is ignored. Parent directories
+    /// are not checked.
+    pub fn is_ignored(&self, path: &RelativePath, is_dir: bool) -> bool {
+        let root = &self.inner.root;
+        let path = path.to_path(root);
+        // The ancestors of `path` which can contain ignore files, deepest first.
+        let parent_dirs = path.ancestors().skip(1).take_while(|dir| match &self.inner.repo_root {
+            Some(repo_root) => dir.starts_with(repo_root),
+            None => dir.starts_with(root),
+        });
+        for dir in parent_dirs {
+            match self.rules_for_dir(dir).matched(&path, is_dir) {
+                Match::None => (),
+                Match::Ignore(_) => return true,
+                Match::Whitelist(_) => return false,
+            }
+        }
+        false
+    }
+
+    /// Drops the cached rules, so that the ignore files are read anew.
+    pub fn reload(&self) {
+        self.inner.rules.write().unwrap().clear();
+    }
+
+    fn rules_for_dir(&self, dir: &Path) -> Arc<Gitignore> {
+        if let Some(rules) = self.inner.rules.read().unwrap().get(dir) {
+            return Arc::clone(rules);
+        }
+        let mut builder = GitignoreBuilder::new(dir);
+        // Missing files and invalid patterns are skipped.
+        if self.inner.repo_root.as_ref().map_or(false, |it| it == dir) {
+            builder.add(dir.join(".git/info/exclude"));
+        }
+        for name in IGNORE_FILES {
+            builder.add(dir.join(name));
+        }
+        let rules = Arc::new(builder.build().unwrap_or_else(|_| Gitignore::empty()));
+        self.inner.rules.write().unwrap().insert(dir.to_path_buf(), Arc::clone(&rules));
+        rules
+    }
+}
@@ -1,26 +1,55 @@
 //! `ra_vfs_glob` crate implements exclusion rules for vfs.
 //!
 //! By default, we include only `.rs` files, and skip some know offenders like
-//! `/target` or `/node_modules` altogether.
+//! `/target` or `/node_modules` altogether. Member packages can additionally
+//! include the workspace manifests, so that changes to them are noticed.
 //!
-//! It's also possible to add custom exclusion globs.
+//! It's also possible to add custom exclusion globs, and to respect
+//! `.gitignore`, `.ignore` and `.git/info/exclude` files.
+
+mod ignore_files;
+
+use std::path::Path;
 
 use globset::{GlobSet, GlobSetBuilder};
 use ra_vfs::{Filter, RelativePath};
 
+use crate::ignore_files::IGNORE_FILES;
+
+pub use crate::ignore_files::IgnoreFiles;
 pub use globset::{Glob, GlobBuilder};
 
 const ALWAYS_IGNORED: &[&str] = &["target/**", "**/node_modules/**", "**/.git/**"];
 const IGNORED_FOR_NON_MEMBERS: &[&str] = &["examples/**", "tests/**", "benches/**"];
+const WORKSPACE_MANIFESTS: &[&str] = &["Cargo.toml", "Cargo.lock", "rust-project.json"];
+
+/// Checks if `file_name` is one of the files which define the shape of the
+/// workspace, like `Cargo.toml`.
+pub fn is_workspace_manifest(file_name: &str) -> bool {
+    WORKSPACE_MANIFESTS.contains(&file_name)
+}
 
+/// Checks if `file_name` is an ignore file, like `.gitignore`.
+pub fn is_ignore_file(file_name: &str) -> bool {
+    IGNORE_FILES.contains(&file_name)
+}
+
+#[derive(Clone)]
 pub struct RustPackageFilterBuilder {
     is_member: bool,
+    include_manifests: bool,
     exclude: GlobSetBuilder,
+    ignore_files: Option<IgnoreFiles>,
 }
 
 impl Default for RustPackageFilterBuilder {
     fn default() -> RustPackageFilterBuilder {
-        RustPackageFilterBuilder { is_member: false, exclude: GlobSetBuilder::new() }
+        RustPackageFilterBuilder {
+            is_member: false,
+            include_manifests: false,
+            exclude: GlobSetBuilder::new(),
+            ignore_files: None,
+        }
     }
 }
 
@@ -29,12 +58,33 @@ impl RustPackageFilterBuilder {
         self.is_member = is_member;
         self
     }
+    /// Includes workspace manifests of member packages in addition to `.rs` files.
+    pub fn set_include_manifests(mut self, include_manifests: bool) -> RustPackageFilterBuilder {
+        self.include_manifests = include_manifests;
+        self
+    }
     pub fn exclude(mut self, glob: Glob) -> RustPackageFilterBuilder {
         self.exclude.add(glob);
         self
     }
+    /// Excludes a directory, given relative to the root, with everything inside.
+    pub fn exclude_dir(self, dir: &Path) -> RustPackageFilterBuilder {
+        let dir = dir.iter().map(|it| it.to_string_lossy()).collect::<Vec<_>>().join("/");
+        match Glob::new(&format!("{}/**", dir)) {
+            Ok(glob) => self.exclude(glob),
+            Err(_) => self,
+        }
+    }
+    /// Excludes files ignored by `ignore_files`, which should be created for
+    /// the root this filter is used for. If manifests are included, ignore
+    /// files are included as well, so that changes to them are noticed.
+    pub fn respect_ignore_files(mut self, ignore_files: IgnoreFiles) -> RustPackageFilterBuilder {
+        self.ignore_files = Some(ignore_files);
+        self
+    }
     pub fn into_vfs_filter(self) -> Box<dyn Filter> {
-        let RustPackageFilterBuilder { is_member, mut exclude } = self;
+        let RustPackageFilterBuilder { is_member, include_manifests, mut exclude, ignore_files } =
+            self;
         for &glob in ALWAYS_IGNORED {
             exclude.add(Glob::new(glob).unwrap());
         }
@@ -43,21 +93,45 @@ impl RustPackageFilterBuilder {
                 exclude.add(Glob::new(glob).unwrap());
             }
         }
-        Box::new(RustPackageFilter { exclude: exclude.build().unwrap() })
+        Box::new(RustPackageFilter {
+            exclude: exclude.build().unwrap(),
+            include_manifests: include_manifests && is_member,
+            ignore_files,
+        })
     }
 }
 
 struct RustPackageFilter {
     exclude: GlobSet,
+    include_manifests: bool,
+    ignore_files: Option<IgnoreFiles>,
+}
+
+impl RustPackageFilter {
+    fn is_ignored(&self, path: &RelativePath, is_dir: bool) -> bool {
+        match &self.ignore_files {
+            Some(ignore_files) => ignore_files.is_ignored(path, is_dir),
+            None => false,
+        }
+    }
 }
 
 impl Filter for RustPackageFilter {
     fn include_dir(&self, dir_path: &RelativePath) -> bool {
-        !self.exclude.is_match(dir_path.as_str())
+        !self.exclude.is_match(dir_path.as_str()) && !self.is_ignored(dir_path, true)
     }
 
     fn include_file(&self, file_path: &RelativePath) -> bool {
-        file_path.extension() == Some("rs")
+        if file_path.extension() == Some("rs") {
+            return !self.is_ignored(file_path, false);
+        }
+        let file_name = match file_path.file_name() {
+            Some(it) => it,
+            None => return false,
+        };
+        self.include_manifests
+            && (is_workspace_manifest(file_name)
+                || (self.ignore_files.is_some() && is_ignore_file(file_name)))
     }
 }
 
@@ -91,4 +165,77 @@ fn test_globs() {
         .into_vfs_filter();
 
     assert!(!filter.include_dir(RelativePath::new("src/llvm-project/clang")));
+
+    let filter = RustPackageFilterBuilder::default()
+        .set_member(true)
+        .exclude_dir(Path::new("src/generated"))
+        .into_vfs_filter();
+
+    assert!(filter.include_dir(RelativePath::new("src")));
+    assert!(!filter.include_dir(RelativePath::new("src/generated")));
+    assert!(!filter.include_dir(RelativePath::new("src/generated/foo")));
+}
+
+#[test]
+fn test_manifests() {
+    let filter = RustPackageFilterBuilder::default().set_member(true).into_vfs_filter();
+    assert!(filter.include_file(RelativePath::new("src/lib.rs")));
+    assert!(!filter.include_file(RelativePath::new("Cargo.toml")));
+
+    let filter = RustPackageFilterBuilder::default()
+        .set_member(true)
+        .set_include_manifests(true)
+        .into_vfs_filter();
+    assert!(filter.include_file(RelativePath::new("src/lib.rs")));
+    assert!(filter.include_file(RelativePath::new("Cargo.toml")));
+    assert!(filter.include_file(RelativePath::new("Cargo.lock")));
+    assert!(filter.include_file(RelativePath::new("rust-project.json")));
+    assert!(!filter.include_file(RelativePath::new("README.md")));
+
+    let filter = RustPackageFilterBuilder::default()
+        .set_member(false)
+        .set_include_manifests(true)
+        .into_vfs_filter();
+    assert!(!filter.include_file(RelativePath::new("Cargo.toml")));
+}
+
+#[test]
+fn test_ignore_files() {
+    use std::fs;
+
+    let dir = tempfile::tempdir().unwrap();
+    let write = |path: &str, text: &str| {
+        let path = dir.path().join(path);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, text).unwrap();
+    };
+    write(".git/info/exclude", "excluded.rs\n");
+    write(".gitignore", "# generated code\n*.gen.rs\n!keep.gen.rs\n/project/tests/\n");
+    write("project/.ignore", "/local.rs\n/generated/\n");
+    write("project/src/.gitignore", "foo/bar.rs\n!a.gen.rs\n");
+
+    let ignore_files = IgnoreFiles::new(dir.path().join("project"));
+    let check = |path: &str, is_dir: bool| ignore_files.is_ignored(RelativePath::new(path), is_dir);
+
+    assert!(check("excluded.rs", false));
+    assert!(check("src/b/excluded.rs", false));
+    assert!(check("b.gen.rs", false));
+    assert!(!check("src/keep.gen.rs", false));
+    assert!(check("tests", true));
+    assert!(!check("src/tests", true));
+    assert!(check("generated", true));
+    assert!(!check("generated", false));
+    assert!(check("local.rs", false));
+    assert!(!check("src/local.rs", false));
+    assert!(check("src/foo/bar.rs", false));
+    assert!(!check("src/b/foo/bar.rs", false));
+    assert!(!check("src/a.gen.rs", false));
+    assert!(check("a.gen.rs", false));
+    assert!(!check("src/lib.rs", false));
+
+    write("project/src/.gitignore", "lib.rs\n");
+    assert!(!check("src/lib.rs", false));
+    ignore_files.reload();
+    assert!(check("src/lib.rs", false));
+    assert!(check("src/a.gen.rs", false));
 }
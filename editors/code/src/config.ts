@@ -15,6 +15,12 @@ export interface CargoWatchOptions {
     ignore: string[];
 }
 
+export interface CargoFeatures {
+    noDefaultFeatures: boolean;
+    allFeatures: boolean;
+    features: string[];
+}
+
 export class Config {
     public highlightingOn = true;
     public rainbowHighlightingOn = false;
@@ -25,7 +31,17 @@ export class Config {
     public maxInlayHintLength: null | number = null;
     public excludeGlobs = [];
     public useClientWatching = false;
+    public useIgnoreFiles = true;
     public featureFlags = {};
+    public loadOutDirsFromCheck = false;
+    public cargoFeatures: CargoFeatures = {
+        noDefaultFeatures: false,
+        allFeatures: true,
+        features: [],
+    };
+    public cargoTarget: null | string = null;
+    public discoverProjectCommand: null | string[] = null;
+    public procMacroServerPath: null | string = null;
     // for internal use
     public withSysroot: null | boolean = null;
     public cargoWatchOptions: CargoWatchOptions = {
@@ -37,6 +53,7 @@ export class Config {
     };
 
     private prevEnhancedTyping: null | boolean = null;
+    private prevWorkspaceOptions: null | string = null;
 
     constructor() {
         vscode.workspace.onDidChangeConfiguration(_ =>
@@ -147,11 +164,63 @@ export class Config {
         if (config.has('useClientWatching')) {
             this.useClientWatching = config.get('useClientWatching') || false;
         }
+        if (config.has('useIgnoreFiles')) {
+            this.useIgnoreFiles = config.get('useIgnoreFiles') as boolean;
+        }
         if (config.has('featureFlags')) {
             this.featureFlags = config.get('featureFlags') || {};
         }
+        if (config.has('loadOutDirsFromCheck')) {
+            this.loadOutDirsFromCheck =
+                config.get('loadOutDirsFromCheck') || false;
+        }
+        if (config.has('procMacroServerPath')) {
+            this.procMacroServerPath =
+                config.get('procMacroServerPath') || null;
+        }
         if (config.has('withSysroot')) {
             this.withSysroot = config.get('withSysroot') || false;
         }
+        if (config.has('cargoFeatures.noDefaultFeatures')) {
+            this.cargoFeatures.noDefaultFeatures = config.get(
+                'cargoFeatures.noDefaultFeatures',
+                false,
+            );
+        }
+        if (config.has('cargoFeatures.allFeatures')) {
+            this.cargoFeatures.allFeatures = config.get(
+                'cargoFeatures.allFeatures',
+                true,
+            );
+        }
+        if (config.has('cargoFeatures.features')) {
+            this.cargoFeatures.features = config.get(
+                'cargoFeatures.features',
+                [],
+            );
+        }
+        if (config.has('cargoTarget')) {
+            this.cargoTarget = config.get('cargoTarget') || null;
+        }
+        if (config.has('discoverProjectCommand')) {
+            this.discoverProjectCommand =
+                config.get('discoverProjectCommand') || null;
+        }
+
+        // The crate graph and the set of analyzed files depend on these
+        // options, so the workspace has to be reloaded when they change.
+        const workspaceOptions = JSON.stringify({
+            useIgnoreFiles: this.useIgnoreFiles,
+            features: this.cargoFeatures,
+            target: this.cargoTarget,
+            discoverProjectCommand: this.discoverProjectCommand,
+        });
+        if (
+            this.prevWorkspaceOptions !== null &&
+            this.prevWorkspaceOptions !== workspaceOptions
+        ) {
+            vscode.commands.executeCommand('rust-analyzer.reload');
+        }
+        this.prevWorkspaceOptions = workspaceOptions;
     }
 }
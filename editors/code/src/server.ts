@@ -57,8 +57,19 @@ export class Server {
                 maxInlayHintLength: Server.config.maxInlayHintLength,
                 excludeGlobs: Server.config.excludeGlobs,
                 useClientWatching: Server.config.useClientWatching,
+                useIgnoreFiles: Server.config.useIgnoreFiles,
                 featureFlags: Server.config.featureFlags,
                 withSysroot: Server.config.withSysroot,
+                cargo: {
+                    loadOutDirsFromCheck: Server.config.loadOutDirsFromCheck,
+                    noDefaultFeatures:
+                        Server.config.cargoFeatures.noDefaultFeatures,
+                    allFeatures: Server.config.cargoFeatures.allFeatures,
+                    features: Server.config.cargoFeatures.features,
+                    target: Server.config.cargoTarget,
+                },
+                discoverProjectCommand: Server.config.discoverProjectCommand,
+                procMacroSrv: Server.config.procMacroServerPath,
             },
             traceOutputChannel,
         };